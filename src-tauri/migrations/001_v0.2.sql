@@ -0,0 +1,8 @@
+-- embedding.rs
+CREATE TABLE item_vectors (
+    item_id INTEGER PRIMARY KEY,
+    vector BLOB NOT NULL
+);
+CREATE TRIGGER trg_items_delete_vectors AFTER DELETE ON items BEGIN
+    DELETE FROM item_vectors WHERE item_id = old.id;
+END;
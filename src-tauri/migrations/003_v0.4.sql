@@ -0,0 +1,19 @@
+-- indexer.rs
+-- trigram 分词保持原来 LIKE '%query%' 的子串匹配语义，中文不需要额外分词
+CREATE VIRTUAL TABLE items_fts USING fts5(
+    content,
+    content = 'items',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+INSERT INTO items_fts (items_fts) VALUES ('rebuild');
+CREATE TRIGGER trg_items_insert_fts AFTER INSERT ON items BEGIN
+    INSERT INTO items_fts (rowid, content) VALUES (new.id, new.content);
+END;
+CREATE TRIGGER trg_items_delete_fts AFTER DELETE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, content) VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER trg_items_update_fts AFTER UPDATE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, content) VALUES ('delete', old.id, old.content);
+    INSERT INTO items_fts (rowid, content) VALUES (new.id, new.content);
+END;
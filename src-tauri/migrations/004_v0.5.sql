@@ -0,0 +1,19 @@
+-- indexer.rs, tokenizer.rs
+-- jieba 分词后按空格切分，用于整词的相关度排序。只存倒排索引，不存原文
+CREATE VIRTUAL TABLE items_words USING fts5(
+    content,
+    content = '',
+    contentless_delete = 1,
+    tokenize = 'unicode61'
+);
+INSERT INTO items_words (rowid, content) SELECT id, jieba_segment(content) FROM items;
+CREATE TRIGGER trg_items_insert_words AFTER INSERT ON items BEGIN
+    INSERT INTO items_words (rowid, content) VALUES (new.id, jieba_segment(new.content));
+END;
+CREATE TRIGGER trg_items_delete_words AFTER DELETE ON items BEGIN
+    DELETE FROM items_words WHERE rowid = old.id;
+END;
+CREATE TRIGGER trg_items_update_words AFTER UPDATE ON items BEGIN
+    DELETE FROM items_words WHERE rowid = old.id;
+    INSERT INTO items_words (rowid, content) VALUES (new.id, jieba_segment(new.content));
+END;
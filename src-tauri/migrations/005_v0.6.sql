@@ -0,0 +1,6 @@
+-- indexer.rs
+-- 已有文件的大小未知，保持 NULL，文件下次重新索引时补上
+ALTER TABLE files ADD COLUMN size INTEGER;
+ALTER TABLE files ADD COLUMN extension TEXT NOT NULL DEFAULT '';
+UPDATE files SET extension = file_extension(name);
+CREATE INDEX idx_files_extension ON files (extension);
@@ -0,0 +1,11 @@
+-- history.rs
+CREATE TABLE search_history (
+    id INTEGER PRIMARY KEY,
+    query TEXT NOT NULL,
+    fuzzy INTEGER NOT NULL,
+    sort TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (query)
+);
+CREATE INDEX idx_search_history_created_at ON search_history (created_at);
@@ -0,0 +1,12 @@
+-- saved_search.rs
+CREATE TABLE saved_searches (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    fuzzy INTEGER NOT NULL,
+    sort TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    UNIQUE (name)
+);
@@ -0,0 +1,9 @@
+-- similar.rs
+-- 已有文件的向量在第一次查找相似文件时补算
+CREATE TABLE file_vectors (
+    file_id INTEGER PRIMARY KEY,
+    vector BLOB NOT NULL
+);
+CREATE TRIGGER trg_files_delete_vectors AFTER DELETE ON files BEGIN
+    DELETE FROM file_vectors WHERE file_id = old.id;
+END;
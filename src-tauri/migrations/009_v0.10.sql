@@ -0,0 +1,9 @@
+-- indexer.rs
+-- SQLite 的 LIKE 只对 ASCII 字母不区分大小写，另存一份折叠大小写后的名称
+ALTER TABLE directories ADD COLUMN name_folded TEXT NOT NULL DEFAULT '';
+UPDATE directories SET name_folded = fold_case(name);
+ALTER TABLE files ADD COLUMN name_folded TEXT NOT NULL DEFAULT '';
+UPDATE files SET name_folded = fold_case(name);
+-- history.rs, saved_search.rs
+ALTER TABLE search_history ADD COLUMN case_sensitive INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE saved_searches ADD COLUMN case_sensitive INTEGER NOT NULL DEFAULT 0;
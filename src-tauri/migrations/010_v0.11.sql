@@ -0,0 +1,6 @@
+-- indexer.rs
+-- 已有内容的位置未知，保持 NULL，文件下次重新索引时补上
+ALTER TABLE items ADD COLUMN page INTEGER;
+ALTER TABLE items ADD COLUMN paragraph INTEGER;
+ALTER TABLE items ADD COLUMN sheet_row INTEGER;
+ALTER TABLE items ADD COLUMN line INTEGER;
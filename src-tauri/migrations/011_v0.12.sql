@@ -0,0 +1,3 @@
+-- indexer.rs, reader.rs
+-- 压缩包内文件的虚拟路径，普通文件为 NULL
+ALTER TABLE items ADD COLUMN entry TEXT;
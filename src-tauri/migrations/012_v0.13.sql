@@ -0,0 +1,3 @@
+-- indexer.rs, reader.rs
+-- 视频字幕的开始时间，其他内容为 NULL
+ALTER TABLE items ADD COLUMN time_ms INTEGER;
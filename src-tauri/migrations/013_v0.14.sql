@@ -0,0 +1,3 @@
+-- indexer.rs, reader.rs
+-- xlsx 的单元格引用，其他内容为 NULL
+ALTER TABLE items ADD COLUMN cell TEXT;
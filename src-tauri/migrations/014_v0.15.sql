@@ -0,0 +1,3 @@
+-- indexer.rs, worker.rs
+-- 没有读取内容的原因，如超过大小上限，正常读取的文件为 NULL
+ALTER TABLE files ADD COLUMN skip_reason TEXT;
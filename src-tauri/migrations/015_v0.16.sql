@@ -0,0 +1,3 @@
+-- indexer.rs, reader.rs
+-- Markdown 中内容所在的标题，其他内容为 NULL
+ALTER TABLE items ADD COLUMN heading TEXT;
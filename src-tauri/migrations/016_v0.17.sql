@@ -0,0 +1,5 @@
+-- worker.rs
+-- 失败次数和原因，暂时性的错误在 retry_at（毫秒时间戳）之后重试
+ALTER TABLE tasks ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tasks ADD COLUMN error TEXT;
+ALTER TABLE tasks ADD COLUMN retry_at INTEGER;
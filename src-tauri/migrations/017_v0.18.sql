@@ -0,0 +1,27 @@
+-- roots.rs, indexer.rs, worker.rs
+-- 文件和任务所在的索引目录，用于按目录统计进度
+CREATE TABLE roots (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    UNIQUE (path)
+);
+ALTER TABLE files ADD COLUMN root_id INTEGER;
+ALTER TABLE tasks ADD COLUMN root_id INTEGER;
+CREATE INDEX idx_files_root_id ON files (root_id);
+CREATE INDEX idx_tasks_root_id ON tasks (root_id);
+-- 已有的文件和任务按设置中的索引目录补上，嵌套时取最近的一个
+INSERT INTO roots (path)
+    SELECT value FROM json_each((SELECT value FROM config WHERE key = 'IndexDirPaths'));
+UPDATE files SET root_id = (
+    SELECT roots.id FROM roots, directories
+    WHERE directories.id = files.directory_id
+        AND (directories.path = roots.path
+            OR substr(directories.path, 1, length(roots.path) + 1) IN (roots.path || '/', roots.path || '\'))
+    ORDER BY length(roots.path) DESC LIMIT 1
+);
+UPDATE tasks SET root_id = (
+    SELECT roots.id FROM roots
+    WHERE tasks.path = roots.path
+        OR substr(tasks.path, 1, length(roots.path) + 1) IN (roots.path || '/', roots.path || '\')
+    ORDER BY length(roots.path) DESC LIMIT 1
+);
@@ -0,0 +1,3 @@
+-- worker.rs
+-- 超过大小阈值的文件由单独的线程处理，已有的任务仍按普通任务处理
+ALTER TABLE tasks ADD COLUMN slow INTEGER NOT NULL DEFAULT 0;
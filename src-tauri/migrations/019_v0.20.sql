@@ -0,0 +1,18 @@
+-- worker.rs
+-- 重试后仍然失败的任务移出任务队列，可以单独重试
+CREATE TABLE failed_files (
+    id INTEGER PRIMARY KEY,
+    path_type TEXT NOT NULL,
+    path TEXT NOT NULL,
+    task_type TEXT NOT NULL,
+    root_id INTEGER,
+    attempts INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    failed_at TEXT NOT NULL,
+    UNIQUE (path_type, path)
+);
+CREATE INDEX idx_failed_files_root_id ON failed_files (root_id);
+INSERT INTO failed_files (path_type, path, task_type, root_id, attempts, error, failed_at)
+    SELECT path_type, path, task_type, root_id, attempts, coalesce(error, ''), updated_at
+    FROM tasks WHERE status = 'Failed';
+DELETE FROM tasks WHERE status = 'Failed';
@@ -0,0 +1,3 @@
+-- worker.rs
+-- 需要识别文字的图片由单独的 OCR 线程处理，已有的任务仍按普通任务处理
+ALTER TABLE tasks ADD COLUMN ocr INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- worker.rs
+-- 目录任务由索引线程逐层扫描，记录需要强制重新索引的扩展名
+ALTER TABLE tasks ADD COLUMN force_extension TEXT;
@@ -0,0 +1,3 @@
+-- indexer.rs
+-- 记录文件的创建时间，已有的文件重新索引后才有
+ALTER TABLE files ADD COLUMN created_time TEXT;
@@ -0,0 +1,8 @@
+-- indexer.rs
+-- 记录上级目录，列出和删除子目录不再按路径前缀匹配。name 是路径的最后一段，去掉 name 和分隔符就是上级目录
+ALTER TABLE directories ADD COLUMN parent_id INTEGER;
+CREATE INDEX idx_directories_parent_id ON directories (parent_id);
+UPDATE directories SET parent_id = (
+    SELECT parent.id FROM directories AS parent
+    WHERE parent.path = substr(directories.path, 1, length(directories.path) - length(directories.name) - 1)
+);
@@ -0,0 +1,40 @@
+-- indexer.rs, compress.rs
+-- 内容可以压缩成 BLOB 保存，全文索引通过 item_text() 读取解压后的文字。
+-- 只改变压缩方式的更新不重新分词
+DROP TRIGGER trg_items_insert_fts;
+DROP TRIGGER trg_items_delete_fts;
+DROP TRIGGER trg_items_update_fts;
+DROP TRIGGER trg_items_insert_words;
+DROP TRIGGER trg_items_delete_words;
+DROP TRIGGER trg_items_update_words;
+DROP TABLE items_fts;
+CREATE VIEW items_text AS SELECT id, item_text(content) AS content FROM items;
+CREATE VIRTUAL TABLE items_fts USING fts5(
+    content,
+    content = 'items_text',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+INSERT INTO items_fts (items_fts) VALUES ('rebuild');
+CREATE TRIGGER trg_items_insert_fts AFTER INSERT ON items BEGIN
+    INSERT INTO items_fts (rowid, content) VALUES (new.id, item_text(new.content));
+END;
+CREATE TRIGGER trg_items_delete_fts AFTER DELETE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, content) VALUES ('delete', old.id, item_text(old.content));
+END;
+CREATE TRIGGER trg_items_update_fts AFTER UPDATE ON items
+WHEN item_text(old.content) IS NOT item_text(new.content) BEGIN
+    INSERT INTO items_fts (items_fts, rowid, content) VALUES ('delete', old.id, item_text(old.content));
+    INSERT INTO items_fts (rowid, content) VALUES (new.id, item_text(new.content));
+END;
+CREATE TRIGGER trg_items_insert_words AFTER INSERT ON items BEGIN
+    INSERT INTO items_words (rowid, content) VALUES (new.id, jieba_segment(item_text(new.content)));
+END;
+CREATE TRIGGER trg_items_delete_words AFTER DELETE ON items BEGIN
+    DELETE FROM items_words WHERE rowid = old.id;
+END;
+CREATE TRIGGER trg_items_update_words AFTER UPDATE ON items
+WHEN item_text(old.content) IS NOT item_text(new.content) BEGIN
+    DELETE FROM items_words WHERE rowid = old.id;
+    INSERT INTO items_words (rowid, content) VALUES (new.id, jieba_segment(item_text(new.content)));
+END;
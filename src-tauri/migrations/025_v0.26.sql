@@ -0,0 +1,8 @@
+-- indexer.rs
+-- 相同内容的文件共享一份 items，items.file_id 指向保存内容的文件，
+-- 其他文件的 content_file_id 指向它。已有的文件没有哈希，重新读取后才参与共享
+ALTER TABLE files ADD COLUMN content_hash TEXT;
+ALTER TABLE files ADD COLUMN content_file_id INTEGER;
+UPDATE files SET content_file_id = id;
+CREATE INDEX idx_files_content_hash ON files (content_hash);
+CREATE INDEX idx_files_content_file_id ON files (content_file_id);
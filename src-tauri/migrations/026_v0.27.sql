@@ -0,0 +1,5 @@
+-- indexer.rs
+-- fold_case 先统一成 NFC，macOS 上 NFD 的名称也能用 NFC 的查询搜到。
+-- 已有的路径和内容保持原样，重新索引时统一
+UPDATE directories SET name_folded = fold_case(name);
+UPDATE files SET name_folded = fold_case(name);
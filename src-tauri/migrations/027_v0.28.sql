@@ -0,0 +1,4 @@
+-- worker.rs
+-- 取消任务时按路径范围删除，UNIQUE (path_type, path) 的索引不能只按 path 查找
+CREATE INDEX idx_tasks_path ON tasks (path);
+CREATE INDEX idx_failed_files_path ON failed_files (path);
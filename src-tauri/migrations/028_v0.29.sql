@@ -0,0 +1,3 @@
+-- indexer.rs
+-- 只有大小相同的文件才可能共享内容，按大小查找后再计算哈希
+CREATE INDEX idx_files_size ON files (size);
@@ -0,0 +1,4 @@
+-- worker.rs
+-- 因为省电推迟的 OCR 任务，接通电源后按这个标记重新排队，不依赖错误信息的文字
+ALTER TABLE tasks ADD COLUMN deferred INTEGER NOT NULL DEFAULT 0;
+UPDATE tasks SET deferred = 1 WHERE error LIKE '%使用电池供电，OCR 推迟到接通电源后进行';
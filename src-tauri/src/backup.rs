@@ -0,0 +1,275 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime};
+use log::{error, info};
+use rusqlite::backup::Backup;
+use rusqlite::OpenFlags;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::dirs::get_index_dir;
+use crate::encryption;
+use crate::sqlite::{get_conn, lock_whole_db};
+use crate::transfer;
+
+// 检查是否到了自动备份时间的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+// 自动备份的文件名前缀，清理旧备份时只删除这些文件
+const AUTO_PREFIX: &str = "auto-";
+const BACKUP_EXTENSION: &str = "db";
+// 自动备份文件名中的时间，按文件名排序就是按时间排序
+const AUTO_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// 一个备份文件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub path: String,
+    pub created_at: String,
+    pub bytes: u64,
+    /// 定时自动生成的备份，超过保留个数时会被删除
+    pub auto: bool,
+}
+
+/// 自动备份保存的目录，没有设置时在索引目录下的 backups 中
+pub fn get_backup_dir() -> Result<PathBuf> {
+    Ok(match Config::get_backup_policy()?.dir {
+        Some(dir) => PathBuf::from(dir),
+        None => get_index_dir().join("backups"),
+    })
+}
+
+fn is_auto_backup(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(AUTO_PREFIX))
+}
+
+fn backup_info(path: &Path) -> Result<BackupInfo> {
+    let metadata = fs::metadata(path)?;
+    Ok(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        created_at: DateTime::<Local>::from(metadata.modified()?).to_rfc3339(),
+        bytes: metadata.len(),
+        auto: is_auto_backup(path),
+    })
+}
+
+/// 用 SQLite 的在线备份把索引复制到 dest，备份期间可以继续索引。
+/// 先写到临时文件，检查通过后再改名，中途失败不会留下不完整的备份。
+/// 加密的索引备份后仍然用当时的密码加密，修改密码后旧的备份只能用原来的密码恢复
+pub fn backup_index(dest: &Path) -> Result<BackupInfo> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = dest.with_extension("tmp");
+    if temp_path.exists() {
+        fs::remove_file(&temp_path)?;
+    }
+    info!("备份索引: {}", dest.display());
+    let _whole_db = lock_whole_db();
+    let copied = (|| -> Result<()> {
+        let source = get_conn()?;
+        let mut target = encryption::open(
+            &temp_path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+        )?;
+        // 一次复制所有页。分步复制时其他连接的写入会让备份从头开始，索引期间可能一直完成不了
+        Backup::new(&source, &mut target)?.run_to_completion(-1, Duration::ZERO, None)?;
+        // 恢复后和原来的数据库一样使用 WAL
+        target.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        drop(target);
+        transfer::check_database(&temp_path)?;
+        Ok(())
+    })();
+    if let Err(e) = copied {
+        if temp_path.exists() {
+            fs::remove_file(&temp_path)?;
+        }
+        return Err(e);
+    }
+    fs::rename(&temp_path, dest)?;
+    info!("备份索引完成: {}", dest.display());
+    backup_info(dest)
+}
+
+/// 备份目录中的备份，新的在前
+pub fn list_backups() -> Result<Vec<BackupInfo>> {
+    let dir = get_backup_dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == BACKUP_EXTENSION) {
+            backups.push(backup_info(&path)?);
+        }
+    }
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+/// 检查备份后复制到索引目录，下次启动时替换当前的索引，和导入索引一样原来的索引保留为 .bak
+pub fn restore_index(path: &Path) -> Result<()> {
+    let version = transfer::stage_database(&mut File::open(path)?, None)?;
+    info!(
+        "恢复的索引已准备好，重启后生效: {}, 版本: {version}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// 自动备份和文件名中记录的备份时间，新的在前。复制过的文件修改时间会变，不用修改时间判断
+fn auto_backups() -> Result<Vec<(PathBuf, SystemTime)>> {
+    let mut backups = Vec::new();
+    for backup in list_backups()? {
+        let path = PathBuf::from(backup.path);
+        let time = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(AUTO_PREFIX))
+            .and_then(|time| NaiveDateTime::parse_from_str(time, AUTO_TIME_FORMAT).ok())
+            .and_then(|time| time.and_local_timezone(Local).earliest());
+        if let Some(time) = time {
+            backups.push((path, SystemTime::from(time)));
+        }
+    }
+    backups.sort_by_key(|backup| Reverse(backup.1));
+    Ok(backups)
+}
+
+/// 删除超过保留个数的自动备份，返回删除的个数
+fn prune_auto_backups(keep: usize) -> Result<usize> {
+    let stale: Vec<PathBuf> = auto_backups()?
+        .into_iter()
+        .skip(keep)
+        .map(|(path, _)| path)
+        .collect();
+    for path in &stale {
+        info!("删除旧的自动备份: {}", path.display());
+        fs::remove_file(path)?;
+    }
+    Ok(stale.len())
+}
+
+/// 距离最近一次自动备份超过设置的间隔时备份，并清理旧的自动备份。返回新的备份
+pub fn run_scheduled_backup(now: SystemTime) -> Result<Option<BackupInfo>> {
+    let policy = Config::get_backup_policy()?;
+    if !policy.enabled {
+        return Ok(None);
+    }
+    let interval = Duration::from_secs(policy.interval_hours * 3600);
+    let last = auto_backups()?.first().map(|(_, time)| *time);
+    if last.is_some_and(|last| now.duration_since(last).unwrap_or_default() < interval) {
+        return Ok(None);
+    }
+    let name = format!(
+        "{AUTO_PREFIX}{}.{BACKUP_EXTENSION}",
+        DateTime::<Local>::from(now).format(AUTO_TIME_FORMAT)
+    );
+    let backup = backup_index(&get_backup_dir()?.join(name))?;
+    prune_auto_backups(policy.keep.max(1))?;
+    Ok(Some(backup))
+}
+
+pub fn start_process() -> Result<()> {
+    thread::Builder::new()
+        .name("index-backup".to_string())
+        .spawn(|| loop {
+            if let Err(e) = run_scheduled_backup(SystemTime::now()) {
+                error!("自动备份索引失败: {e:?}");
+            }
+            thread::sleep(CHECK_INTERVAL);
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::config::BackupPolicy;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+    use crate::transfer::has_pending_import;
+
+    #[test]
+    fn test_backup_and_restore_index() {
+        let env = TestEnv::new();
+        let file = env.temp_dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let items = vec![Item {
+            content: "备份的内容".into(),
+            ..Default::default()
+        }];
+        Indexer::new()
+            .unwrap()
+            .write_file_items(&file, items)
+            .unwrap();
+
+        let dest = env.temp_dir.path().join("manual.db");
+        let backup = backup_index(&dest).unwrap();
+        assert!(!backup.auto);
+        assert!(backup.bytes > 0);
+        let conn = Connection::open_with_flags(&dest, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let content: String = conn
+            .query_row("SELECT content FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(content, "备份的内容");
+        drop(conn);
+
+        restore_index(&dest).unwrap();
+        assert!(has_pending_import());
+        // 备份文件保留
+        assert!(dest.exists());
+        // 不是数据库的文件不恢复
+        assert!(restore_index(&file).is_err());
+    }
+
+    #[test]
+    fn test_run_scheduled_backup() {
+        let env = TestEnv::new();
+        let now = SystemTime::now();
+        assert!(run_scheduled_backup(now).unwrap().is_none());
+
+        Config::set_backup_policy(&BackupPolicy {
+            enabled: true,
+            interval_hours: 24,
+            keep: 2,
+            dir: Some(
+                env.temp_dir
+                    .path()
+                    .join("backups")
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+        })
+        .unwrap();
+        assert!(run_scheduled_backup(now).unwrap().unwrap().auto);
+        // 没到间隔时不备份
+        assert!(run_scheduled_backup(now).unwrap().is_none());
+
+        // 手动备份不计入自动备份的个数，也不会被清理
+        backup_index(&get_backup_dir().unwrap().join("manual.db")).unwrap();
+        let day = Duration::from_secs(24 * 3600);
+        for days in 1..=3 {
+            assert!(run_scheduled_backup(now + day * days).unwrap().is_some());
+        }
+        let backups = list_backups().unwrap();
+        assert_eq!(backups.iter().filter(|backup| backup.auto).count(), 2);
+        assert_eq!(backups.iter().filter(|backup| !backup.auto).count(), 1);
+        // 保留的是最近的两个
+        let kept: Vec<SystemTime> = auto_backups()
+            .unwrap()
+            .into_iter()
+            .map(|(_, time)| time)
+            .collect();
+        assert!(kept[1] > now + day);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_8};
+use std::path::Path;
+
+use crate::config::Config;
+
+// 猜测编码时最多使用的字节数，足够区分 GBK、Big5、Shift-JIS 等常见编码
+const DETECT_LIMIT: usize = 64 * 1024;
+
+/// 按名称查找编码，如 `gbk`、`big5`、`shift_jis`，名称不区分大小写
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| anyhow!("未知的编码: {label}"))
+}
+
+/// 文件所在索引目录指定的编码，索引目录嵌套时以最深的为准
+pub fn root_encoding(path: &Path) -> Result<Option<&'static Encoding>> {
+    Config::get_text_encodings()?
+        .iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.len())
+        .map(|(_, label)| parse_encoding(label))
+        .transpose()
+}
+
+/// 设置索引目录的编码，encoding 为空时恢复自动检测
+pub fn set_root_encoding(root: &str, encoding: Option<&str>) -> Result<()> {
+    let mut encodings = Config::get_text_encodings()?;
+    match encoding {
+        Some(label) => {
+            let encoding = parse_encoding(label)?;
+            encodings.insert(root.to_string(), encoding.name().to_string());
+        }
+        None => {
+            encodings.remove(root);
+        }
+    }
+    Config::set_text_encodings(&encodings)
+}
+
+/// 依次按 BOM、合法的 UTF-8、内容统计判断编码
+pub fn detect_encoding(data: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return encoding;
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return UTF_8;
+    }
+    let sample = &data[..data.len().min(DETECT_LIMIT)];
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(sample, sample.len() == data.len());
+    detector.guess(None, Utf8Detection::Allow)
+}
+
+/// 转换为 UTF-8 文本。有 BOM 时以 BOM 为准，否则使用指定的编码，未指定时自动检测
+pub fn decode_text(data: &[u8], encoding: Option<&'static Encoding>) -> String {
+    let (encoding, bom_len) = Encoding::for_bom(data)
+        .unwrap_or_else(|| (encoding.unwrap_or_else(|| detect_encoding(data)), 0));
+    encoding
+        .decode_without_bom_handling(&data[bom_len..])
+        .0
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+    use encoding_rs::{BIG5, GBK, SHIFT_JIS};
+
+    #[test]
+    fn test_decode_text() {
+        let chinese =
+            "这是一个测试文档，用来检查文本文件的编码能否被正确识别。\n第二行内容也是中文。\n";
+        let traditional = "這是一個測試文件，用來檢查文字檔案的編碼能否被正確識別。\n";
+        let japanese = "これはテスト用の文書です。文字コードを正しく判定できるか確認します。\n";
+        for (text, encoding) in [(chinese, GBK), (traditional, BIG5), (japanese, SHIFT_JIS)] {
+            let (data, _, _) = encoding.encode(text);
+            assert_eq!(detect_encoding(&data), encoding);
+            assert_eq!(decode_text(&data, None), text);
+        }
+
+        // BOM 优先于指定的编码
+        let data = [b"\xEF\xBB\xBF".as_slice(), chinese.as_bytes()].concat();
+        assert_eq!(decode_text(&data, Some(GBK)), chinese);
+    }
+
+    #[test]
+    fn test_root_encoding() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("old");
+        set_root_encoding(env.temp_dir.path().to_str().unwrap(), Some("utf-8")).unwrap();
+        set_root_encoding(root.to_str().unwrap(), Some("GBK")).unwrap();
+        assert_eq!(
+            Config::get_text_encodings().unwrap()[root.to_str().unwrap()],
+            "GBK"
+        );
+        assert_eq!(root_encoding(&root.join("a.txt")).unwrap(), Some(GBK));
+        assert_eq!(
+            root_encoding(&env.temp_dir.path().join("a.txt")).unwrap(),
+            Some(UTF_8)
+        );
+        assert_eq!(root_encoding(Path::new("/other/a.txt")).unwrap(), None);
+        assert!(set_root_encoding(root.to_str().unwrap(), Some("unknown")).is_err());
+
+        set_root_encoding(root.to_str().unwrap(), None).unwrap();
+        assert_eq!(root_encoding(&root.join("a.txt")).unwrap(), Some(UTF_8));
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{bail, Result};
+
+use crate::config::{Chunking, Config};
+use crate::reader::Item;
+
+// 在分段末尾的这一部分中查找标点或空白断开，避免从句子中间切断
+const BREAK_SEARCH_RATIO: usize = 5;
+
+fn is_break_char(c: char) -> bool {
+    c.is_whitespace() || "。！？；，、.!?;,".contains(c)
+}
+
+/// 把过长的内容按字符数拆分成有重叠的多段，每段保留原来的位置。
+/// 整个文件只有一条内容的 reader（如 PDF 的一页）拆分后搜索结果的摘要更准确
+pub fn chunk_items(items: Vec<Item>, chunking: &Chunking) -> Vec<Item> {
+    if chunking.max_chars == 0 {
+        return items;
+    }
+    let mut result = Vec::with_capacity(items.len());
+    for item in items {
+        // 每个字符的字节位置，最后加上结尾
+        let bounds: Vec<usize> = item
+            .content
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([item.content.len()])
+            .collect();
+        let len = bounds.len() - 1;
+        if len <= chunking.max_chars {
+            result.push(item);
+            continue;
+        }
+
+        let mut start = 0;
+        loop {
+            let mut end = (start + chunking.max_chars).min(len);
+            if end < len {
+                let min_end = end - chunking.max_chars / BREAK_SEARCH_RATIO;
+                if let Some(pos) = (min_end..end)
+                    .rev()
+                    .find(|&i| item.content[bounds[i]..].starts_with(is_break_char))
+                {
+                    end = pos + 1;
+                }
+            }
+            let content = item.content[bounds[start]..bounds[end]].trim();
+            if !content.is_empty() {
+                result.push(Item {
+                    content: content.to_string(),
+                    position: item.position.clone(),
+                });
+            }
+            if end == len {
+                break;
+            }
+            start = end.saturating_sub(chunking.overlap_chars).max(start + 1);
+        }
+    }
+    result
+}
+
+pub fn set_chunking(chunking: &Chunking) -> Result<()> {
+    if chunking.max_chars > 0 && chunking.overlap_chars >= chunking.max_chars {
+        bail!("重叠的字符数必须小于每段的字符数");
+    }
+    Config::set_chunking(chunking)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ItemPosition;
+    use crate::test::test_mod::TestEnv;
+
+    fn item(content: &str) -> Item {
+        Item {
+            content: content.to_string(),
+            position: ItemPosition {
+                page: Some(3),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_chunk_items() {
+        let chunking = Chunking {
+            max_chars: 10,
+            overlap_chars: 3,
+        };
+        let items = chunk_items(
+            vec![item("短内容"), item("一二三四五六七八九十甲乙丙丁戊")],
+            &chunking,
+        );
+        let contents: Vec<&str> = items.iter().map(|item| item.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec!["短内容", "一二三四五六七八九十", "八九十甲乙丙丁戊"]
+        );
+        assert_eq!(items[2].position.page, Some(3));
+
+        // 优先在标点和空白处断开
+        let items = chunk_items(vec![item("第一句话已经说完了。第二句话")], &chunking);
+        assert_eq!(items[0].content, "第一句话已经说完了。");
+        assert_eq!(items[1].content, "完了。第二句话");
+        let items = chunk_items(vec![item("alphabet gamma delta")], &chunking);
+        assert_eq!(items[0].content, "alphabet");
+
+        let disabled = Chunking {
+            max_chars: 0,
+            overlap_chars: 0,
+        };
+        assert_eq!(
+            chunk_items(vec![item(&"长".repeat(100))], &disabled).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_set_chunking() {
+        let _env = TestEnv::new();
+        let chunking = Chunking {
+            max_chars: 500,
+            overlap_chars: 50,
+        };
+        set_chunking(&chunking).unwrap();
+        assert_eq!(Config::get_chunking().unwrap(), chunking);
+        assert!(set_chunking(&Chunking {
+            max_chars: 500,
+            overlap_chars: 500,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use log::{error, info};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, TransactionBehavior};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::sqlite::get_conn;
+
+// 短内容压缩后几乎不会变小
+const MIN_COMPRESS_BYTES: usize = 256;
+// zstd 压缩级别，0 为默认级别
+const COMPRESS_LEVEL: i32 = 0;
+// 后台转换时每批处理的行数和两批之间的间隔，不长时间占用写锁
+const BATCH_SIZE: usize = 500;
+const BATCH_PAUSE: Duration = Duration::from_millis(50);
+
+// 有没有等待转换的请求，启动时设置和已有内容转换成的设置不同时请求一次
+static REQUESTED: Mutex<bool> = Mutex::new(false);
+static REQUESTED_CHANGED: Condvar = Condvar::new();
+static CONVERTING: AtomicBool = AtomicBool::new(false);
+
+/// 内容压缩的设置和后台转换的情况
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CompressStatus {
+    pub enabled: bool,
+    /// 正在把已有的内容转换成当前设置
+    pub converting: bool,
+}
+
+/// 写入 items.content 的值，压缩时为 zstd 压缩后的 BLOB，否则为原文
+pub fn encode_content(text: &str, compress: bool) -> Value {
+    if compress && text.len() >= MIN_COMPRESS_BYTES {
+        if let Ok(compressed) = zstd::bulk::compress(text.as_bytes(), COMPRESS_LEVEL) {
+            if compressed.len() < text.len() {
+                return Value::Blob(compressed);
+            }
+        }
+    }
+    Value::Text(text.to_string())
+}
+
+/// 读取 items.content 的文字，SQL 中通过 item_text() 调用
+pub fn decode_content(value: ValueRef) -> Result<String> {
+    match value {
+        ValueRef::Text(text) => Ok(String::from_utf8(text.to_vec())?),
+        ValueRef::Blob(blob) => Ok(String::from_utf8(zstd::stream::decode_all(blob)?)?),
+        ValueRef::Null => Ok(String::new()),
+        other => Err(anyhow!("内容的类型不正确: {:?}", other.data_type())),
+    }
+}
+
+/// 把 after_id 之后的一批内容转换成当前设置，返回这批最后的 id，没有更多内容时返回 None
+fn convert_batch(after_id: i64, compress: bool) -> Result<Option<i64>> {
+    let mut conn = get_conn()?;
+    // 立即占用写锁，读取和写回之间内容不会被索引线程替换
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let rows = {
+        let mut stmt =
+            tx.prepare("SELECT id, content FROM items WHERE id > ?1 ORDER BY id LIMIT ?2")?;
+        let rows = stmt.query_map(params![after_id, BATCH_SIZE], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Value>(1)?))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    let Some((last_id, _)) = rows.last() else {
+        return Ok(None);
+    };
+    let last_id = *last_id;
+    let mut stmt = tx.prepare("UPDATE items SET content = ?2 WHERE id = ?1")?;
+    for (id, content) in rows {
+        let converted = match (&content, compress) {
+            (Value::Text(text), true) => encode_content(text, true),
+            (Value::Blob(blob), false) => Value::Text(decode_content(ValueRef::Blob(blob))?),
+            _ => continue,
+        };
+        if converted != content {
+            stmt.execute(params![id, converted])?;
+        }
+    }
+    drop(stmt);
+    tx.commit()?;
+    Ok(Some(last_id))
+}
+
+/// 把所有内容转换成当前设置，转换期间设置改变时从头开始
+pub fn convert_all() -> Result<()> {
+    let mut compress = Config::get_compress_content()?;
+    info!("开始转换内容, 压缩: {compress}");
+    let mut after_id = 0;
+    loop {
+        let current = Config::get_compress_content()?;
+        if current != compress {
+            info!("压缩设置改变, 重新转换, 压缩: {current}");
+            compress = current;
+            after_id = 0;
+        }
+        match convert_batch(after_id, compress)? {
+            Some(last_id) => after_id = last_id,
+            None => break,
+        }
+        thread::sleep(BATCH_PAUSE);
+    }
+    Config::set_converted_compress(Some(compress))?;
+    info!("内容转换完成, 压缩: {compress}");
+    Ok(())
+}
+
+/// 上次转换没有完成，或者转换后设置又改变了
+fn needs_convert() -> Result<bool> {
+    Ok(Config::get_converted_compress()? != Some(Config::get_compress_content()?))
+}
+
+/// 修改压缩设置，已有的内容在后台转换
+pub fn set_enabled(enabled: bool) -> Result<()> {
+    // 先清除转换完成的记录，转换中途退出时下次启动接着转换
+    Config::set_converted_compress(None)?;
+    Config::set_compress_content(enabled)?;
+    request_convert();
+    Ok(())
+}
+
+/// 请求后台线程把已有的内容转换成当前设置
+fn request_convert() {
+    *REQUESTED.lock().unwrap_or_else(PoisonError::into_inner) = true;
+    REQUESTED_CHANGED.notify_all();
+}
+
+pub fn get_status() -> Result<CompressStatus> {
+    Ok(CompressStatus {
+        enabled: Config::get_compress_content()?,
+        converting: CONVERTING.load(Ordering::SeqCst),
+    })
+}
+
+pub fn start_process() -> Result<()> {
+    if needs_convert()? {
+        request_convert();
+    }
+    thread::Builder::new()
+        .name("content-compress".to_string())
+        .spawn(|| loop {
+            {
+                let mut requested = REQUESTED.lock().unwrap_or_else(PoisonError::into_inner);
+                while !*requested {
+                    requested = REQUESTED_CHANGED
+                        .wait(requested)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                *requested = false;
+            }
+            CONVERTING.store(true, Ordering::SeqCst);
+            if let Err(e) = convert_all() {
+                error!("转换内容失败: {e:?}");
+            }
+            CONVERTING.store(false, Ordering::SeqCst);
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::indexer::{Indexer, SearchFilter, SearchSort};
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+
+    fn content_types() -> Vec<String> {
+        get_conn()
+            .unwrap()
+            .prepare("SELECT typeof(content) FROM items ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compress_content() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let long = format!("鸭子索引{}", "压缩的内容".repeat(100));
+        let file = env.temp_dir.path().join("1.txt");
+        fs::write(&file, &long).unwrap();
+        let items = vec![
+            Item {
+                content: long.clone(),
+                ..Default::default()
+            },
+            Item {
+                content: "short".into(),
+                ..Default::default()
+            },
+        ];
+        Config::set_compress_content(true).unwrap();
+        indexer.write_file_items(&file, items).unwrap();
+        assert_eq!(content_types(), vec!["blob", "text"]);
+
+        let search = || {
+            indexer
+                .search_item(
+                    "鸭子索引",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items
+        };
+        let result = search();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].content, long);
+
+        // 关闭后转换回原文，全文索引不变
+        Config::set_compress_content(false).unwrap();
+        convert_all().unwrap();
+        assert_eq!(content_types(), vec!["text", "text"]);
+        assert_eq!(search().len(), 1);
+        assert!(!needs_convert().unwrap());
+
+        // 修改设置后转换完成前，下次启动时需要接着转换
+        set_enabled(true).unwrap();
+        assert!(needs_convert().unwrap());
+        convert_all().unwrap();
+        assert!(!needs_convert().unwrap());
+        assert_eq!(content_types(), vec!["blob", "text"]);
+        assert_eq!(search()[0].content, long);
+        assert!(crate::integrity::verify_index(false).unwrap().is_ok());
+    }
+}
@@ -1,10 +1,14 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::info;
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use strum::Display;
 use strum::EnumString;
 
+use crate::embedding::EmbeddingModelInfo;
+use crate::ignore::DEFAULT_IGNORE_PATTERNS;
+use crate::share::NetworkShare;
 use crate::sqlite::get_conn;
 
 pub struct Config {}
@@ -15,6 +19,208 @@ enum ConfigKey {
     IndexDirPaths,
     #[strum(to_string = "ExtensionWhitelist")]
     ExtensionWhitelist,
+    #[strum(to_string = "OcrLanguages")]
+    OcrLanguages,
+    #[strum(to_string = "EmbeddingModels")]
+    EmbeddingModels,
+    #[strum(to_string = "ActiveEmbeddingModel")]
+    ActiveEmbeddingModel,
+    #[strum(to_string = "NetworkShares")]
+    NetworkShares,
+    #[strum(to_string = "SyncDir")]
+    SyncDir,
+    #[strum(to_string = "SyncMachineId")]
+    SyncMachineId,
+    #[strum(to_string = "SyncExportedSeq")]
+    SyncExportedSeq,
+    #[strum(to_string = "SyncAppliedSeqs")]
+    SyncAppliedSeqs,
+    #[strum(to_string = "SyncRootMap")]
+    SyncRootMap,
+    #[strum(to_string = "TextEncodings")]
+    TextEncodings,
+    #[strum(to_string = "MaxFileSize")]
+    MaxFileSize,
+    #[strum(to_string = "ExternalReaders")]
+    ExternalReaders,
+    #[strum(to_string = "Chunking")]
+    Chunking,
+    #[strum(to_string = "DocumentPasswords")]
+    DocumentPasswords,
+    #[strum(to_string = "LowImpact")]
+    LowImpact,
+    #[strum(to_string = "BatteryPolicy")]
+    BatteryPolicy,
+    #[strum(to_string = "SlowLaneThresholdMb")]
+    SlowLaneThresholdMb,
+    #[strum(to_string = "IoRateLimitMb")]
+    IoRateLimitMb,
+    #[strum(to_string = "OcrConcurrency")]
+    OcrConcurrency,
+    #[strum(to_string = "TaskTimeoutSecs")]
+    TaskTimeoutSecs,
+    #[strum(to_string = "IgnorePatterns")]
+    IgnorePatterns,
+    #[strum(to_string = "WatchModes")]
+    WatchModes,
+    #[strum(to_string = "PollIntervalSecs")]
+    PollIntervalSecs,
+    #[strum(to_string = "ShallowRoots")]
+    ShallowRoots,
+    #[strum(to_string = "ReconciledAt")]
+    ReconciledAt,
+    #[strum(to_string = "JournalCursors")]
+    JournalCursors,
+    #[strum(to_string = "CompressContent")]
+    CompressContent,
+    #[strum(to_string = "ConvertedCompress")]
+    ConvertedCompress,
+    #[strum(to_string = "BackupPolicy")]
+    BackupPolicy,
+    #[strum(to_string = "MaintainedAt")]
+    MaintainedAt,
+}
+
+const DEFAULT_EXTENSION_WHITELIST: &str = r#"[{"label":"文档","is_extension":false,"children":[{"label":"txt","is_extension":true,"enabled":true},{"label":"md","is_extension":true,"enabled":true},{"label":"markdown","is_extension":true,"enabled":true},{"label":"docx","is_extension":true,"enabled":true},{"label":"pptx","is_extension":true,"enabled":true},{"label":"pdf","is_extension":true,"enabled":true},{"label":"odt","is_extension":true,"enabled":true},{"label":"odp","is_extension":true,"enabled":true},{"label":"html","is_extension":true,"enabled":true},{"label":"htm","is_extension":true,"enabled":true},{"label":"xhtml","is_extension":true,"enabled":true},{"label":"one","is_extension":true,"enabled":true}]}, {"label":"邮件","is_extension":false,"children":[{"label":"eml","is_extension":true,"enabled":true},{"label":"mbox","is_extension":true,"enabled":true}]}, {"label":"数据","is_extension":false,"children":[{"label":"xlsx","is_extension":true,"enabled":false},{"label":"ods","is_extension":true,"enabled":false},{"label":"json","is_extension":true,"enabled":false},{"label":"yaml","is_extension":true,"enabled":false},{"label":"yml","is_extension":true,"enabled":false},{"label":"toml","is_extension":true,"enabled":false},{"label":"xml","is_extension":true,"enabled":false}]}, {"label":"压缩包","is_extension":false,"children":[{"label":"zip","is_extension":true,"enabled":false},{"label":"7z","is_extension":true,"enabled":false},{"label":"tar","is_extension":true,"enabled":false},{"label":"tgz","is_extension":true,"enabled":false},{"label":"gz","is_extension":true,"enabled":false}]}, {"label":"视频","is_extension":false,"children":[{"label":"mkv","is_extension":true,"enabled":false},{"label":"mp4","is_extension":true,"enabled":false}]}, {"label":"图片","is_extension":false,"children":[{"label":"jpg","is_extension":true,"enabled":true},{"label":"jpeg","is_extension":true,"enabled":true},{"label":"png","is_extension":true,"enabled":true},{"label":"tif","is_extension":true,"enabled":true},{"label":"tiff","is_extension":true,"enabled":true},{"label":"gif","is_extension":true,"enabled":true},{"label":"webp","is_extension":true,"enabled":true}]}, {"label":"代码","is_extension":false,"children":[{"label":"Rust","is_extension":false,"children":[{"label":"rs","is_extension":true,"enabled":false}]},{"label":"Python","is_extension":false,"children":[{"label":"py","is_extension":true,"enabled":false}]},{"label":"JavaScript","is_extension":false,"children":[{"label":"js","is_extension":true,"enabled":false},{"label":"jsx","is_extension":true,"enabled":false},{"label":"mjs","is_extension":true,"enabled":false},{"label":"cjs","is_extension":true,"enabled":false}]},{"label":"TypeScript","is_extension":false,"children":[{"label":"ts","is_extension":true,"enabled":false},{"label":"tsx","is_extension":true,"enabled":false}]},{"label":"Java","is_extension":false,"children":[{"label":"java","is_extension":true,"enabled":false}]},{"label":"Kotlin","is_extension":false,"children":[{"label":"kt","is_extension":true,"enabled":false},{"label":"kts","is_extension":true,"enabled":false}]},{"label":"Go","is_extension":false,"children":[{"label":"go","is_extension":true,"enabled":false}]},{"label":"C/C++","is_extension":false,"children":[{"label":"c","is_extension":true,"enabled":false},{"label":"h","is_extension":true,"enabled":false},{"label":"cpp","is_extension":true,"enabled":false},{"label":"cc","is_extension":true,"enabled":false},{"label":"cxx","is_extension":true,"enabled":false},{"label":"hpp","is_extension":true,"enabled":false}]},{"label":"C#","is_extension":false,"children":[{"label":"cs","is_extension":true,"enabled":false}]},{"label":"Ruby","is_extension":false,"children":[{"label":"rb","is_extension":true,"enabled":false}]},{"label":"PHP","is_extension":false,"children":[{"label":"php","is_extension":true,"enabled":false}]},{"label":"Swift","is_extension":false,"children":[{"label":"swift","is_extension":true,"enabled":false}]},{"label":"Shell","is_extension":false,"children":[{"label":"sh","is_extension":true,"enabled":false},{"label":"bash","is_extension":true,"enabled":false},{"label":"ps1","is_extension":true,"enabled":false}]},{"label":"SQL","is_extension":false,"children":[{"label":"sql","is_extension":true,"enabled":false}]},{"label":"Lua","is_extension":false,"children":[{"label":"lua","is_extension":true,"enabled":false}]},{"label":"Vue","is_extension":false,"children":[{"label":"vue","is_extension":true,"enabled":false}]},{"label":"CSS","is_extension":false,"children":[{"label":"css","is_extension":true,"enabled":false},{"label":"scss","is_extension":true,"enabled":false},{"label":"less","is_extension":true,"enabled":false}]}]}]"#;
+
+impl ConfigKey {
+    /// 数据库中还没有该配置项时使用的默认值（JSON）
+    fn default_value(&self) -> &'static str {
+        match self {
+            ConfigKey::IndexDirPaths => "[]",
+            ConfigKey::ExtensionWhitelist => DEFAULT_EXTENSION_WHITELIST,
+            ConfigKey::OcrLanguages => r#"["eng","chi_sim"]"#,
+            ConfigKey::EmbeddingModels => "[]",
+            ConfigKey::ActiveEmbeddingModel => "null",
+            ConfigKey::NetworkShares => "[]",
+            ConfigKey::SyncDir => "null",
+            ConfigKey::SyncMachineId => "null",
+            ConfigKey::SyncExportedSeq => "0",
+            ConfigKey::SyncAppliedSeqs => "{}",
+            ConfigKey::SyncRootMap => "{}",
+            ConfigKey::TextEncodings => "{}",
+            // 视频只读取字幕轨道，不会读取整个文件
+            ConfigKey::MaxFileSize => r#"{"default_mb":200,"extensions":{"mkv":0,"mp4":0}}"#,
+            ConfigKey::ExternalReaders => "{}",
+            ConfigKey::Chunking => r#"{"max_chars":1000,"overlap_chars":100}"#,
+            ConfigKey::DocumentPasswords => "[]",
+            ConfigKey::LowImpact => {
+                r#"{"enabled":false,"auto":true,"sleep_ms":200,"max_read_mb_per_sec":10}"#
+            }
+            ConfigKey::BatteryPolicy => {
+                r#"{"enabled":true,"threshold_percent":50,"action":"Throttle"}"#
+            }
+            ConfigKey::SlowLaneThresholdMb => "50",
+            ConfigKey::IoRateLimitMb => "0",
+            ConfigKey::OcrConcurrency => "1",
+            ConfigKey::TaskTimeoutSecs => "600",
+            ConfigKey::IgnorePatterns => "{}",
+            ConfigKey::WatchModes => "{}",
+            ConfigKey::PollIntervalSecs => "60",
+            ConfigKey::ShallowRoots => "[]",
+            ConfigKey::ReconciledAt => "{}",
+            ConfigKey::JournalCursors => "{}",
+            ConfigKey::CompressContent => "false",
+            ConfigKey::ConvertedCompress => "null",
+            ConfigKey::BackupPolicy => {
+                r#"{"enabled":false,"interval_hours":24,"keep":3,"dir":null}"#
+            }
+            ConfigKey::MaintainedAt => "null",
+        }
+    }
+}
+
+/// 读取内容的文件大小上限，单位为 MB，0 表示不限制。超过上限的文件只索引文件名
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct MaxFileSize {
+    pub default_mb: u64,
+    /// 按扩展名（小写，不带点）单独设置的上限，优先于 default_mb
+    pub extensions: HashMap<String, u64>,
+}
+
+impl MaxFileSize {
+    /// 扩展名对应的上限，单位为字节，不限制时为 None
+    pub fn limit_bytes(&self, extension: &str) -> Option<u64> {
+        let mb = self
+            .extensions
+            .get(&extension.to_lowercase())
+            .copied()
+            .unwrap_or(self.default_mb);
+        (mb > 0).then(|| mb * 1024 * 1024)
+    }
+}
+
+/// 过长的内容拆分成多段后写入索引，max_chars 为 0 时不拆分
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Chunking {
+    pub max_chars: usize,
+    /// 相邻两段重叠的字符数，避免跨段的词搜不到
+    pub overlap_chars: usize,
+}
+
+/// 低负载索引模式，降低索引线程优先级，每个文件之间休息并限制读取速度
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct LowImpact {
+    /// 始终使用低负载模式
+    pub enabled: bool,
+    /// 最近有搜索时自动切换到低负载模式
+    pub auto: bool,
+    /// 每个文件处理完后休息的毫秒数
+    pub sleep_ms: u64,
+    /// 平均读取速度上限，0 表示不限制
+    pub max_read_mb_per_sec: u64,
+}
+
+/// 电量低于阈值时对索引的处理
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BatteryAction {
+    /// 推迟 OCR，其余文件按低负载模式索引
+    Throttle,
+    /// 暂停索引和向量计算
+    Pause,
+}
+
+/// 使用电池供电时的索引策略，接通电源后恢复
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BatteryPolicy {
+    pub enabled: bool,
+    /// 电量低于该百分比时生效，100 表示只要使用电池就生效
+    pub threshold_percent: u8,
+    pub action: BatteryAction,
+}
+
+/// 定时自动备份索引，只保留最近的 keep 个自动备份，手动备份的文件不会被清理
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BackupPolicy {
+    pub enabled: bool,
+    pub interval_hours: u64,
+    pub keep: usize,
+    /// 备份保存的目录，为空时保存在索引目录下的 backups 中
+    pub dir: Option<String>,
+}
+
+/// 索引目录监听文件变化的方式
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WatchMode {
+    /// 网络目录轮询，本地磁盘使用系统通知
+    Auto,
+    /// 系统的文件变化通知
+    Native,
+    /// 定时扫描目录，SMB/NFS 等收不到可靠通知的目录使用
+    Poll,
+}
+
+fn default_external_timeout_secs() -> u64 {
+    60
+}
+
+/// 用外部命令读取的扩展名，如 `pandoc --to plain`，命令的标准输出按行索引
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ExternalReader {
+    /// 可执行文件和参数，参数中的 `{file}` 替换为要读取的文件，没有 `{file}` 时文件作为最后一个参数
+    pub command: Vec<String>,
+    #[serde(default = "default_external_timeout_secs")]
+    pub timeout_secs: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -31,11 +237,12 @@ impl Config {
         T: serde::de::DeserializeOwned,
     {
         let conn = get_conn()?;
-        let value: String = conn.query_one(
-            "SELECT value FROM config WHERE key = ?1",
-            params![key.to_string()],
-            |row| row.get(0),
-        )?;
+        // 扫描时每个文件都会读取设置，缓存预编译的语句
+        let value: Option<String> = conn
+            .prepare_cached("SELECT value FROM config WHERE key = ?1")?
+            .query_row(params![key.to_string()], |row| row.get(0))
+            .optional()?;
+        let value = value.unwrap_or_else(|| key.default_value().to_string());
         let v: T = serde_json::from_str(&value)?;
         Ok(v)
     }
@@ -47,7 +254,7 @@ impl Config {
         let v = serde_json::to_string(value)?;
         let conn = get_conn()?;
         conn.execute(
-            "UPDATE config SET value = ?2 WHERE key = ?1",
+            "INSERT INTO config (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = ?2",
             params![key.to_string(), v],
         )?;
         Ok(())
@@ -101,6 +308,300 @@ impl Config {
     fn set_extension_whitelist(extension_whitelist: &Vec<ExtensionConfigTree>) -> Result<()> {
         Self::set_key(&ConfigKey::ExtensionWhitelist, &extension_whitelist)
     }
+
+    pub fn get_ocr_languages() -> Result<Vec<String>> {
+        Self::get_key(&ConfigKey::OcrLanguages)
+    }
+
+    pub fn set_ocr_languages(ocr_languages: &Vec<String>) -> Result<()> {
+        Self::set_key(&ConfigKey::OcrLanguages, ocr_languages)
+    }
+
+    pub fn get_embedding_models() -> Result<Vec<EmbeddingModelInfo>> {
+        Self::get_key(&ConfigKey::EmbeddingModels)
+    }
+
+    pub fn set_embedding_models(embedding_models: &Vec<EmbeddingModelInfo>) -> Result<()> {
+        Self::set_key(&ConfigKey::EmbeddingModels, embedding_models)
+    }
+
+    pub fn get_active_embedding_model() -> Result<Option<String>> {
+        Self::get_key(&ConfigKey::ActiveEmbeddingModel)
+    }
+
+    pub fn set_active_embedding_model(name: &Option<String>) -> Result<()> {
+        Self::set_key(&ConfigKey::ActiveEmbeddingModel, name)
+    }
+
+    pub fn get_network_shares() -> Result<Vec<NetworkShare>> {
+        Self::get_key(&ConfigKey::NetworkShares)
+    }
+
+    pub fn set_network_shares(network_shares: &Vec<NetworkShare>) -> Result<()> {
+        Self::set_key(&ConfigKey::NetworkShares, network_shares)
+    }
+
+    pub fn get_sync_dir() -> Result<Option<String>> {
+        Self::get_key(&ConfigKey::SyncDir)
+    }
+
+    pub fn set_sync_dir(sync_dir: &Option<String>) -> Result<()> {
+        Self::set_key(&ConfigKey::SyncDir, sync_dir)
+    }
+
+    pub fn get_sync_machine_id() -> Result<Option<String>> {
+        Self::get_key(&ConfigKey::SyncMachineId)
+    }
+
+    pub fn set_sync_machine_id(machine_id: &str) -> Result<()> {
+        Self::set_key(&ConfigKey::SyncMachineId, machine_id)
+    }
+
+    pub fn get_sync_exported_seq() -> Result<i64> {
+        Self::get_key(&ConfigKey::SyncExportedSeq)
+    }
+
+    pub fn set_sync_exported_seq(seq: i64) -> Result<()> {
+        Self::set_key(&ConfigKey::SyncExportedSeq, &seq)
+    }
+
+    /// 已应用的其他机器的变更序号，key 为机器 ID
+    pub fn get_sync_applied_seqs() -> Result<HashMap<String, i64>> {
+        Self::get_key(&ConfigKey::SyncAppliedSeqs)
+    }
+
+    pub fn set_sync_applied_seqs(seqs: &HashMap<String, i64>) -> Result<()> {
+        Self::set_key(&ConfigKey::SyncAppliedSeqs, seqs)
+    }
+
+    /// 其他机器的索引根目录对应的本机索引根目录，key 为其他机器上的路径
+    pub fn get_sync_root_map() -> Result<HashMap<String, String>> {
+        Self::get_key(&ConfigKey::SyncRootMap)
+    }
+
+    pub fn set_sync_root_map(root_map: &HashMap<String, String>) -> Result<()> {
+        Self::set_key(&ConfigKey::SyncRootMap, root_map)
+    }
+
+    /// 索引目录指定的文本文件编码，key 为索引目录，未指定的目录自动检测
+    pub fn get_text_encodings() -> Result<HashMap<String, String>> {
+        Self::get_key(&ConfigKey::TextEncodings)
+    }
+
+    pub fn set_text_encodings(encodings: &HashMap<String, String>) -> Result<()> {
+        Self::set_key(&ConfigKey::TextEncodings, encodings)
+    }
+
+    /// 索引目录的监听方式，key 为索引目录，未设置的目录为 Auto
+    pub fn get_watch_modes() -> Result<HashMap<String, WatchMode>> {
+        Self::get_key(&ConfigKey::WatchModes)
+    }
+
+    pub fn set_watch_modes(modes: &HashMap<String, WatchMode>) -> Result<()> {
+        Self::set_key(&ConfigKey::WatchModes, modes)
+    }
+
+    /// 轮询目录变化的间隔（秒），重启后生效
+    pub fn get_poll_interval_secs() -> Result<u64> {
+        Self::get_key(&ConfigKey::PollIntervalSecs)
+    }
+
+    pub fn set_poll_interval_secs(interval_secs: u64) -> Result<()> {
+        if interval_secs == 0 {
+            bail!("轮询间隔至少为 1 秒");
+        }
+        Self::set_key(&ConfigKey::PollIntervalSecs, &interval_secs)
+    }
+
+    /// 只索引第一层文件、不进入子目录的索引目录
+    pub fn get_shallow_roots() -> Result<Vec<String>> {
+        Self::get_key(&ConfigKey::ShallowRoots)
+    }
+
+    pub fn set_root_recursive(root: &str, recursive: bool) -> Result<()> {
+        let mut roots = Self::get_shallow_roots()?;
+        roots.retain(|r| r != root);
+        if !recursive {
+            roots.push(root.to_string());
+        }
+        Self::set_key(&ConfigKey::ShallowRoots, &roots)
+    }
+
+    /// 各索引目录确认与磁盘一致的时间（Unix 秒），启动时只检查这之后的变化
+    pub fn get_reconciled_at() -> Result<HashMap<String, i64>> {
+        Self::get_key(&ConfigKey::ReconciledAt)
+    }
+
+    /// time 为 None 时删除，下次启动完整扫描
+    pub fn set_reconciled_at(root: &str, time: Option<i64>) -> Result<()> {
+        let mut reconciled = Self::get_reconciled_at()?;
+        match time {
+            Some(time) => reconciled.insert(root.to_string(), time),
+            None => reconciled.remove(root),
+        };
+        Self::set_key(&ConfigKey::ReconciledAt, &reconciled)
+    }
+
+    /// 各索引目录在系统变化日志（USN、FSEvents）中的位置，格式由平台决定
+    pub fn get_journal_cursors() -> Result<HashMap<String, String>> {
+        Self::get_key(&ConfigKey::JournalCursors)
+    }
+
+    pub fn set_journal_cursor(root: &str, cursor: Option<String>) -> Result<()> {
+        let mut cursors = Self::get_journal_cursors()?;
+        match cursor {
+            Some(cursor) => cursors.insert(root.to_string(), cursor),
+            None => cursors.remove(root),
+        };
+        Self::set_key(&ConfigKey::JournalCursors, &cursors)
+    }
+
+    /// 是否用 zstd 压缩读取出的内容，切换后已有的内容在后台逐批转换
+    pub fn get_compress_content() -> Result<bool> {
+        Self::get_key(&ConfigKey::CompressContent)
+    }
+
+    pub fn set_compress_content(enabled: bool) -> Result<()> {
+        Self::set_key(&ConfigKey::CompressContent, &enabled)
+    }
+
+    /// 已有的内容全部转换成的压缩设置，修改设置后还没有转换完成时为空
+    pub fn get_converted_compress() -> Result<Option<bool>> {
+        Self::get_key(&ConfigKey::ConvertedCompress)
+    }
+
+    pub fn set_converted_compress(converted: Option<bool>) -> Result<()> {
+        Self::set_key(&ConfigKey::ConvertedCompress, &converted)
+    }
+
+    pub fn get_backup_policy() -> Result<BackupPolicy> {
+        Self::get_key(&ConfigKey::BackupPolicy)
+    }
+
+    pub fn set_backup_policy(policy: &BackupPolicy) -> Result<()> {
+        Self::set_key(&ConfigKey::BackupPolicy, policy)
+    }
+
+    /// 上次维护数据库的时间，Unix 时间戳（秒）
+    pub fn get_maintained_at() -> Result<Option<i64>> {
+        Self::get_key(&ConfigKey::MaintainedAt)
+    }
+
+    pub fn set_maintained_at(time: i64) -> Result<()> {
+        Self::set_key(&ConfigKey::MaintainedAt, &Some(time))
+    }
+
+    pub fn get_max_file_size() -> Result<MaxFileSize> {
+        Self::get_key(&ConfigKey::MaxFileSize)
+    }
+
+    pub fn set_max_file_size(max_file_size: &MaxFileSize) -> Result<()> {
+        Self::set_key(&ConfigKey::MaxFileSize, max_file_size)
+    }
+
+    pub fn get_chunking() -> Result<Chunking> {
+        Self::get_key(&ConfigKey::Chunking)
+    }
+
+    pub fn set_chunking(chunking: &Chunking) -> Result<()> {
+        Self::set_key(&ConfigKey::Chunking, chunking)
+    }
+
+    /// 外部命令读取的扩展名，key 为小写、不带点的扩展名
+    pub fn get_external_readers() -> Result<HashMap<String, ExternalReader>> {
+        Self::get_key(&ConfigKey::ExternalReaders)
+    }
+
+    pub fn set_external_readers(readers: &HashMap<String, ExternalReader>) -> Result<()> {
+        Self::set_key(&ConfigKey::ExternalReaders, readers)
+    }
+
+    /// 设置了文档密码的文件或目录，密码本身保存在系统钥匙串中
+    pub fn get_document_passwords() -> Result<Vec<String>> {
+        Self::get_key(&ConfigKey::DocumentPasswords)
+    }
+
+    pub fn set_document_passwords(paths: &Vec<String>) -> Result<()> {
+        Self::set_key(&ConfigKey::DocumentPasswords, paths)
+    }
+
+    pub fn get_low_impact() -> Result<LowImpact> {
+        Self::get_key(&ConfigKey::LowImpact)
+    }
+
+    pub fn set_low_impact(low_impact: &LowImpact) -> Result<()> {
+        Self::set_key(&ConfigKey::LowImpact, low_impact)
+    }
+
+    /// 超过该大小（MB）的文件由单独的大文件线程处理，0 表示不区分
+    pub fn get_slow_lane_threshold_mb() -> Result<u64> {
+        Self::get_key(&ConfigKey::SlowLaneThresholdMb)
+    }
+
+    pub fn set_slow_lane_threshold_mb(threshold_mb: u64) -> Result<()> {
+        Self::set_key(&ConfigKey::SlowLaneThresholdMb, &threshold_mb)
+    }
+
+    /// 所有索引线程合计每秒读取的上限（MB），0 表示不限制
+    pub fn get_io_rate_limit_mb() -> Result<u64> {
+        Self::get_key(&ConfigKey::IoRateLimitMb)
+    }
+
+    pub fn set_io_rate_limit_mb(limit_mb: u64) -> Result<()> {
+        Self::set_key(&ConfigKey::IoRateLimitMb, &limit_mb)
+    }
+
+    /// 识别图片文字的线程数，重启后生效
+    pub fn get_ocr_concurrency() -> Result<usize> {
+        Self::get_key(&ConfigKey::OcrConcurrency)
+    }
+
+    pub fn set_ocr_concurrency(concurrency: usize) -> Result<()> {
+        if concurrency == 0 {
+            bail!("OCR 线程数至少为 1");
+        }
+        Self::set_key(&ConfigKey::OcrConcurrency, &concurrency)
+    }
+
+    /// 读取一个文件的最长时间（秒），超时的任务标记为失败，0 表示不限制
+    pub fn get_task_timeout_secs() -> Result<u64> {
+        Self::get_key(&ConfigKey::TaskTimeoutSecs)
+    }
+
+    pub fn set_task_timeout_secs(timeout_secs: u64) -> Result<()> {
+        Self::set_key(&ConfigKey::TaskTimeoutSecs, &timeout_secs)
+    }
+
+    /// 各索引目录不索引的文件和目录规则，键为索引目录
+    pub fn get_all_ignore_patterns() -> Result<HashMap<String, Vec<String>>> {
+        Self::get_key(&ConfigKey::IgnorePatterns)
+    }
+
+    /// 索引目录的排除规则，还没有设置过时使用默认规则
+    pub fn get_ignore_patterns(root: &str) -> Result<Vec<String>> {
+        Ok(Self::get_all_ignore_patterns()?
+            .remove(root)
+            .unwrap_or_else(|| {
+                DEFAULT_IGNORE_PATTERNS
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect()
+            }))
+    }
+
+    pub fn set_ignore_patterns(root: &str, patterns: Vec<String>) -> Result<()> {
+        let mut all = Self::get_all_ignore_patterns()?;
+        all.insert(root.to_string(), patterns);
+        Self::set_key(&ConfigKey::IgnorePatterns, &all)
+    }
+
+    pub fn get_battery_policy() -> Result<BatteryPolicy> {
+        Self::get_key(&ConfigKey::BatteryPolicy)
+    }
+
+    pub fn set_battery_policy(policy: &BatteryPolicy) -> Result<()> {
+        Self::set_key(&ConfigKey::BatteryPolicy, policy)
+    }
 }
 
 #[cfg(test)]
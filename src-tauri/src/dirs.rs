@@ -27,6 +27,30 @@ pub fn get_index_dir() -> PathBuf {
     path
 }
 
+pub fn get_tessdata_dir() -> PathBuf {
+    let path = get_project_dirs().join("tessdata");
+    if !path.exists() {
+        std::fs::create_dir_all(&path).unwrap();
+    }
+    path
+}
+
+pub fn get_model_dir() -> PathBuf {
+    let path = get_project_dirs().join("models");
+    if !path.exists() {
+        std::fs::create_dir_all(&path).unwrap();
+    }
+    path
+}
+
+pub fn get_plugin_dir() -> PathBuf {
+    let path = get_project_dirs().join("plugins");
+    if !path.exists() {
+        std::fs::create_dir_all(&path).unwrap();
+    }
+    path
+}
+
 pub fn get_log_dir() -> PathBuf {
     let path = get_project_dirs().join("log");
     if !path.exists() {
@@ -49,6 +73,27 @@ mod tests {
         assert!(index_dir.exists());
     }
 
+    #[test]
+    fn test_get_tessdata_dir() {
+        let _env = TestEnv::new();
+        let tessdata_dir = get_tessdata_dir();
+        assert!(tessdata_dir.exists());
+    }
+
+    #[test]
+    fn test_get_model_dir() {
+        let _env = TestEnv::new();
+        let model_dir = get_model_dir();
+        assert!(model_dir.exists());
+    }
+
+    #[test]
+    fn test_get_plugin_dir() {
+        let _env = TestEnv::new();
+        let plugin_dir = get_plugin_dir();
+        assert!(plugin_dir.exists());
+    }
+
     #[test]
     fn test_get_log_dir() {
         let _env = TestEnv::new();
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// git 对象的 sha1，即 `sha1("blob {len}\0" + content)`，GitHub API 返回的 sha 就是它
+    GitBlobSha1(String),
+    Sha256(String),
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+fn hash_file<D: Digest>(path: &Path, mut hasher: D, stop: impl Fn() -> bool) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        if stop() {
+            bail!("计算哈希已取消: {}", path.display());
+        }
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// 文件内容的 sha256，十六进制小写
+pub fn sha256_file(path: &Path) -> Result<String> {
+    hash_file(path, Sha256::new(), || false)
+}
+
+/// 文件内容的 sha256，每读取一块检查一次 stop，返回 true 时放弃
+pub fn sha256_file_until(path: &Path, stop: impl Fn() -> bool) -> Result<String> {
+    hash_file(path, Sha256::new(), stop)
+}
+
+fn file_checksum(path: &Path, checksum: &Checksum) -> Result<String> {
+    match checksum {
+        Checksum::GitBlobSha1(_) => {
+            let len = fs::metadata(path)?.len();
+            let mut hasher = Sha1::new();
+            hasher.update(format!("blob {len}\0").as_bytes());
+            hash_file(path, hasher, || false)
+        }
+        Checksum::Sha256(_) => sha256_file(path),
+    }
+}
+
+pub fn verify_checksum(path: &Path, checksum: &Checksum) -> Result<()> {
+    let expected = match checksum {
+        Checksum::GitBlobSha1(sha) | Checksum::Sha256(sha) => sha,
+    };
+    let actual = file_checksum(path, checksum)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, found {}",
+            path.display(),
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
+
+/// 下载文件到 dest。
+///
+/// 数据先写入 `dest.part`，中断后再次调用会用 Range 请求续传；
+/// 下载完成并校验通过后才重命名为 dest。on_progress 参数为 (已下载字节数, 总字节数)。
+pub fn download_file<F>(
+    url: &str,
+    dest: &Path,
+    checksum: Option<&Checksum>,
+    mut on_progress: F,
+) -> Result<()>
+where
+    F: FnMut(u64, Option<u64>),
+{
+    let part = part_path(dest);
+    let mut downloaded = if part.exists() {
+        fs::metadata(&part)?.len()
+    } else {
+        0
+    };
+
+    let mut request = ureq::get(url);
+    if downloaded > 0 {
+        info!("续传下载: {url}, 已下载 {downloaded} 字节");
+        request = request.header("Range", &format!("bytes={downloaded}-"));
+    }
+    let mut response = request
+        .call()
+        .with_context(|| format!("Failed to request {url}"))?;
+
+    let resumed = response.status().as_u16() == 206;
+    if !resumed && downloaded > 0 {
+        debug!("服务器不支持续传，重新下载: {url}");
+        downloaded = 0;
+    }
+    let total = response
+        .body()
+        .content_length()
+        .map(|remaining| remaining + downloaded);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)?;
+    let mut reader = response.body_mut().as_reader();
+    let mut buf = vec![0; 64 * 1024];
+    on_progress(downloaded, total);
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n])?;
+        downloaded += n as u64;
+        on_progress(downloaded, total);
+    }
+    file.flush()?;
+    drop(file);
+
+    if let Some(checksum) = checksum {
+        if let Err(e) = verify_checksum(&part, checksum) {
+            // 校验失败的数据不能用于续传
+            fs::remove_file(&part)?;
+            return Err(e);
+        }
+    }
+    fs::rename(&part, dest)?;
+    info!("下载完成: {url} -> {}", dest.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("hello.txt");
+        fs::write(&file, "hello\n").unwrap();
+
+        // git hash-object hello.txt
+        let checksum = Checksum::GitBlobSha1("ce013625030ba8dba906f756967f9e9ca394464a".into());
+        verify_checksum(&file, &checksum).unwrap();
+
+        let checksum = Checksum::GitBlobSha1("0000000000000000000000000000000000000000".into());
+        assert!(verify_checksum(&file, &checksum).is_err());
+
+        // sha256sum hello.txt
+        let checksum = Checksum::Sha256(
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03".into(),
+        );
+        verify_checksum(&file, &checksum).unwrap();
+    }
+
+    #[test]
+    fn test_part_path() {
+        let dest = Path::new("tessdata").join("eng.traineddata");
+        assert_eq!(
+            part_path(&dest),
+            Path::new("tessdata").join("eng.traineddata.part")
+        );
+    }
+}
@@ -0,0 +1,778 @@
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use rusqlite::{params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{BatteryAction, Config};
+use crate::dirs::get_model_dir;
+use crate::download::{download_file, Checksum};
+use crate::events::emit;
+use crate::indexer::{
+    build_snippet, item_position, sanitize_query, SearchFilter, SearchPage, SearchResultItem,
+    SqlParams, ITEM_POSITION_COLUMNS,
+};
+use crate::power;
+use crate::reader::ItemPosition;
+use crate::sqlite::get_conn;
+use crate::tokenizer::cut_for_query;
+use crate::worker;
+
+pub const EMBEDDING_PROGRESS_EVENT: &str = "embedding-progress";
+pub const MODEL_DOWNLOAD_PROGRESS_EVENT: &str = "embedding-model-download-progress";
+const EMBED_BATCH_SIZE: usize = 256;
+// 相近内容搜索每批扫描的向量数，按 item_id 分段读取，不长时间占用一个读事务
+const SCAN_BATCH_SIZE: usize = 5000;
+
+pub trait EmbeddingModel: Send + Sync {
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum EmbeddingModelSource {
+    /// 内置的字符 n-gram 哈希模型，无需下载
+    HashedNgram,
+    /// fastText `.vec` 文本格式的静态词向量，需要下载
+    StaticVectors { url: String, sha256: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingModelInfo {
+    pub name: String,
+    pub dims: usize,
+    pub source: EmbeddingModelSource,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingModelStatus {
+    #[serde(flatten)]
+    pub info: EmbeddingModelInfo,
+    pub installed: bool,
+    pub active: bool,
+    /// 内置的哈希模型只比较字符片段，找到的是字面相近的内容，不理解意思，是没有词向量模型时的后备
+    pub lexical: bool,
+}
+
+/// 一条内容的相似度，分数相同时 id 小的在前
+struct ScoredItem {
+    score: f32,
+    item_id: i64,
+    file_id: i64,
+}
+
+impl Ord for ScoredItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| (other.item_id, other.file_id).cmp(&(self.item_id, self.file_id)))
+    }
+}
+
+impl PartialOrd for ScoredItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ScoredItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredItem {}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingProgress {
+    pub model: Option<String>,
+    pub embedded: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ModelDownloadProgress {
+    name: String,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+type ActiveModel = Option<(String, Arc<dyn EmbeddingModel>)>;
+
+// 当前使用的模型，切换模型时清空
+static ACTIVE_MODEL: OnceCell<Mutex<ActiveModel>> = OnceCell::new();
+// 切换模型和写入向量互斥，避免把旧模型的向量写进新模型
+static EMBEDDING_LOCK: OnceCell<Mutex<()>> = OnceCell::new();
+// 有新的待计算内容或模型变化时唤醒后台线程
+static EMBEDDING_WAKER: OnceCell<(Mutex<bool>, Condvar)> = OnceCell::new();
+
+fn get_active_model_cell() -> &'static Mutex<ActiveModel> {
+    ACTIVE_MODEL.get_or_init(|| Mutex::new(None))
+}
+
+fn get_embedding_lock() -> &'static Mutex<()> {
+    EMBEDDING_LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn get_embedding_waker() -> &'static (Mutex<bool>, Condvar) {
+    EMBEDDING_WAKER.get_or_init(|| (Mutex::new(false), Condvar::new()))
+}
+
+/// 字符 n-gram 特征哈希到固定维度，中文按字切分也能工作
+struct HashedNgramModel {
+    dims: usize,
+}
+
+impl HashedNgramModel {
+    // 向量会持久化，不能使用每次运行结果都不同的 DefaultHasher
+    fn fnv1a(chars: &[char]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for c in chars {
+            for b in (*c as u32).to_le_bytes() {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+}
+
+impl EmbeddingModel for HashedNgramModel {
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dims];
+        let chars: Vec<char> = text.to_lowercase().chars().collect();
+        for n in 1..=3 {
+            for gram in chars.windows(n) {
+                if gram.iter().all(|c| c.is_whitespace()) {
+                    continue;
+                }
+                let hash = Self::fnv1a(gram);
+                let index = (hash % self.dims as u64) as usize;
+                let sign = if hash >> 63 == 1 { -1.0 } else { 1.0 };
+                vector[index] += sign * n as f32;
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+/// 静态词向量：文本向量为所有已知词向量的平均值
+struct StaticVectorsModel {
+    dims: usize,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl StaticVectorsModel {
+    fn load(path: &Path, dims: usize) -> Result<Self> {
+        info!("加载词向量模型: {}", path.display());
+        let reader = BufReader::new(File::open(path)?);
+        let mut vectors = HashMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let Some(word) = parts.next() else {
+                continue;
+            };
+            let vector: Vec<f32> = parts.filter_map(|v| v.parse().ok()).collect();
+            // 第一行是 "词数 维度"，会因维度不符被跳过
+            if vector.len() == dims {
+                vectors.insert(word.to_string(), vector);
+            }
+        }
+        if vectors.is_empty() {
+            return Err(anyhow!(
+                "No {}-dimensional vectors found in {}",
+                dims,
+                path.display()
+            ));
+        }
+        Ok(StaticVectorsModel { dims, vectors })
+    }
+}
+
+impl EmbeddingModel for StaticVectorsModel {
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dims];
+        for token in tokenize(text) {
+            if let Some(v) = self.vectors.get(&token) {
+                vector.iter_mut().zip(v).for_each(|(a, b)| *a += b);
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c)
+        || ('\u{3040}'..='\u{30ff}').contains(&c)
+        || ('\u{ac00}'..='\u{d7af}').contains(&c)
+}
+
+/// 英文等按单词切分并转小写，中日韩文字按字切分
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            tokens.push(c.to_string());
+        } else if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+pub fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// 向量都已归一化，点积即余弦相似度
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn builtin_models() -> Vec<EmbeddingModelInfo> {
+    [256, 1024]
+        .into_iter()
+        .map(|dims| EmbeddingModelInfo {
+            name: format!("hash-ngram-{dims}"),
+            dims,
+            source: EmbeddingModelSource::HashedNgram,
+        })
+        .collect()
+}
+
+fn model_path(info: &EmbeddingModelInfo) -> PathBuf {
+    get_model_dir().join(format!("{}.vec", info.name))
+}
+
+fn is_installed(info: &EmbeddingModelInfo) -> bool {
+    match info.source {
+        EmbeddingModelSource::HashedNgram => true,
+        EmbeddingModelSource::StaticVectors { .. } => model_path(info).is_file(),
+    }
+}
+
+fn get_models() -> Result<Vec<EmbeddingModelInfo>> {
+    let mut models = builtin_models();
+    models.extend(Config::get_embedding_models()?);
+    Ok(models)
+}
+
+fn find_model(name: &str) -> Result<EmbeddingModelInfo> {
+    get_models()?
+        .into_iter()
+        .find(|m| m.name == name)
+        .with_context(|| format!("Embedding model '{name}' not found"))
+}
+
+pub fn list_models() -> Result<Vec<EmbeddingModelStatus>> {
+    let active = Config::get_active_embedding_model()?;
+    Ok(get_models()?
+        .into_iter()
+        .map(|info| EmbeddingModelStatus {
+            installed: is_installed(&info),
+            active: active.as_deref() == Some(info.name.as_str()),
+            lexical: info.source == EmbeddingModelSource::HashedNgram,
+            info,
+        })
+        .collect())
+}
+
+/// 添加一个可下载的词向量模型
+pub fn register_model(info: EmbeddingModelInfo) -> Result<()> {
+    if info.name.is_empty()
+        || !info
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(anyhow!("Invalid embedding model name: {}", info.name));
+    }
+    if info.dims == 0 {
+        return Err(anyhow!("Embedding model dims must be positive"));
+    }
+    if !matches!(info.source, EmbeddingModelSource::StaticVectors { .. }) {
+        return Err(anyhow!("Only static vector models can be registered"));
+    }
+    if builtin_models().iter().any(|m| m.name == info.name) {
+        return Err(anyhow!("Embedding model '{}' is built in", info.name));
+    }
+
+    let mut models = Config::get_embedding_models()?;
+    models.retain(|m| m.name != info.name);
+    models.push(info);
+    Config::set_embedding_models(&models)
+}
+
+pub fn download_model(name: &str) -> Result<()> {
+    let info = find_model(name)?;
+    let EmbeddingModelSource::StaticVectors { url, sha256 } = &info.source else {
+        return Ok(());
+    };
+    download_file(
+        url,
+        &model_path(&info),
+        Some(&Checksum::Sha256(sha256.clone())),
+        |downloaded, total| {
+            emit(
+                MODEL_DOWNLOAD_PROGRESS_EVENT,
+                ModelDownloadProgress {
+                    name: name.to_string(),
+                    downloaded,
+                    total,
+                },
+            )
+        },
+    )
+}
+
+fn load_model(info: &EmbeddingModelInfo) -> Result<Arc<dyn EmbeddingModel>> {
+    Ok(match info.source {
+        EmbeddingModelSource::HashedNgram => Arc::new(HashedNgramModel { dims: info.dims }),
+        EmbeddingModelSource::StaticVectors { .. } => {
+            Arc::new(StaticVectorsModel::load(&model_path(info), info.dims)?)
+        }
+    })
+}
+
+/// 当前启用的模型，未启用相近内容搜索时返回 None
+pub fn get_active_model() -> Result<Option<Arc<dyn EmbeddingModel>>> {
+    let Some(name) = Config::get_active_embedding_model()? else {
+        return Ok(None);
+    };
+    let mut active = get_active_model_cell()
+        .lock()
+        .map_err(|e| anyhow!("获取模型锁失败: {}", e))?;
+    if let Some((active_name, model)) = active.as_ref() {
+        if *active_name == name {
+            return Ok(Some(model.clone()));
+        }
+    }
+    let model = load_model(&find_model(&name)?)?;
+    *active = Some((name, model.clone()));
+    Ok(Some(model))
+}
+
+/// 切换模型后已有向量全部失效，由后台线程重新计算
+pub fn set_active_model(name: Option<String>) -> Result<()> {
+    if let Some(name) = &name {
+        let info = find_model(name)?;
+        if !is_installed(&info) {
+            return Err(anyhow!("Embedding model '{}' is not installed", name));
+        }
+    }
+    {
+        let _lock = get_embedding_lock()
+            .lock()
+            .map_err(|e| anyhow!("获取向量锁失败: {}", e))?;
+        if Config::get_active_embedding_model()? == name {
+            return Ok(());
+        }
+        info!("切换向量模型: {name:?}");
+        Config::set_active_embedding_model(&name)?;
+        get_conn()?.execute("DELETE FROM item_vectors", [])?;
+        *get_active_model_cell()
+            .lock()
+            .map_err(|e| anyhow!("获取模型锁失败: {}", e))? = None;
+    }
+    notify_embedding();
+    Ok(())
+}
+
+pub fn get_progress() -> Result<EmbeddingProgress> {
+    let conn = get_conn()?;
+    let embedded: i64 =
+        conn.query_one("SELECT COUNT(*) FROM item_vectors", [], |row| row.get(0))?;
+    let total: i64 = conn.query_one("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
+    Ok(EmbeddingProgress {
+        model: Config::get_active_embedding_model()?,
+        embedded: embedded as usize,
+        total: total as usize,
+    })
+}
+
+/// 为还没有向量的内容计算向量，返回本次处理的条数
+fn embed_pending_items(limit: usize) -> Result<usize> {
+    let _lock = get_embedding_lock()
+        .lock()
+        .map_err(|e| anyhow!("获取向量锁失败: {}", e))?;
+    let Some(model) = get_active_model()? else {
+        return Ok(0);
+    };
+
+    let mut conn = get_conn()?;
+    let pending = {
+        let mut stmt = conn.prepare(
+            r"SELECT items.id, item_text(items.content) FROM items
+            LEFT OUTER JOIN item_vectors ON items.id = item_vectors.item_id
+            WHERE item_vectors.item_id IS NULL
+            LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let tx = conn.transaction()?;
+    for (id, content) in &pending {
+        let vector = model.embed(content);
+        tx.execute(
+            "INSERT OR REPLACE INTO item_vectors (item_id, vector) VALUES (?1, ?2)",
+            params![id, vector_to_blob(&vector)],
+        )?;
+    }
+    tx.commit()?;
+    Ok(pending.len())
+}
+
+/// 相近内容搜索：用当前模型计算查询的向量，与内容的向量逐一比较，返回最相似的内容。
+/// 模型比较的是字符片段或词向量，找到的是字词相近的内容，不理解句子的意思。
+/// 向量按 item_id 分批扫描，只保留到当前页为止分数最高的结果，不对全部内容排序。
+/// 还没有计算出向量的内容不会出现在结果中
+pub fn search_similar_text(
+    query: &str,
+    offset: usize,
+    limit: usize,
+    filter: &SearchFilter,
+) -> Result<SearchPage<SearchResultItem>> {
+    let query = sanitize_query(query)?;
+    let model = get_active_model()?.context("Similar text search is not enabled")?;
+    let query_vector = model.embed(&query);
+
+    let mut params = SqlParams::new();
+    let filter = filter.to_sql("directories.path", "files.modified_time", &mut params)?;
+    // 相同内容的文件共享内容和向量，每个文件各占一条结果
+    let joins = r"JOIN files ON files.content_file_id = items.file_id
+        LEFT OUTER JOIN directories ON files.directory_id = directories.id";
+
+    let scan_sql = format!(
+        r"SELECT item_vectors.item_id, files.id, item_vectors.vector FROM item_vectors
+        JOIN items ON items.id = item_vectors.item_id
+        {joins}
+        WHERE {filter} AND item_vectors.item_id > ? AND item_vectors.item_id <= ?"
+    );
+
+    // 最小堆中保留到当前页为止分数最高的结果，堆顶是其中最低的
+    let keep = offset + limit;
+    let mut top = BinaryHeap::with_capacity(keep + 1);
+    let mut total = 0;
+    let mut after_id = 0;
+    loop {
+        // 每批重新取连接，批次之间不持有读事务
+        let conn = get_conn()?;
+        let batch_end: Option<i64> = conn.query_row(
+            "SELECT MAX(item_id) FROM (SELECT item_id FROM item_vectors WHERE item_id > ?1 ORDER BY item_id LIMIT ?2)",
+            params![after_id, SCAN_BATCH_SIZE],
+            |row| row.get(0),
+        )?;
+        let Some(batch_end) = batch_end else {
+            break;
+        };
+        let mut batch_params = params.clone();
+        batch_params.push(after_id.into());
+        batch_params.push(batch_end.into());
+        let mut stmt = conn.prepare_cached(&scan_sql)?;
+        let mut rows = stmt.query(params_from_iter(batch_params))?;
+        while let Some(row) = rows.next()? {
+            let vector = blob_to_vector(row.get_ref(2)?.as_blob()?);
+            // 切换模型期间可能残留其他维度的向量
+            if vector.len() != query_vector.len() {
+                continue;
+            }
+            total += 1;
+            top.push(Reverse(ScoredItem {
+                score: cosine_similarity(&query_vector, &vector),
+                item_id: row.get(0)?,
+                file_id: row.get(1)?,
+            }));
+            if top.len() > keep {
+                top.pop();
+            }
+        }
+        after_id = batch_end;
+    }
+
+    let terms = cut_for_query(&query);
+    let conn = get_conn()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT item_text(items.content), files.name, directories.path, {ITEM_POSITION_COLUMNS}
+        FROM items {joins} WHERE items.id = ?1 AND files.id = ?2"
+    ))?;
+    let mut result = Vec::new();
+    for Reverse(scored) in top.into_sorted_vec().into_iter().skip(offset).take(limit) {
+        let ScoredItem {
+            score,
+            item_id: id,
+            file_id,
+        } = scored;
+        let (content, file, path, position): (String, String, String, ItemPosition) = stmt
+            .query_row(params![id, file_id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    item_position(row, 3)?,
+                ))
+            })?;
+        let (snippet, highlights) = build_snippet(&content, &terms, false);
+        result.push(SearchResultItem {
+            content,
+            file,
+            path,
+            snippet,
+            highlights,
+            score: score as f64,
+            position,
+        });
+    }
+    Ok(SearchPage::new(result, total, offset, limit))
+}
+
+/// 唤醒后台向量线程，如有新内容写入或切换了模型
+pub fn notify_embedding() {
+    let (pending, condvar) = get_embedding_waker();
+    if let Ok(mut pending) = pending.lock() {
+        *pending = true;
+        condvar.notify_all();
+    }
+}
+
+fn wait_for_embedding(timeout: Duration) {
+    let (pending, condvar) = get_embedding_waker();
+    if let Ok(guard) = pending.lock() {
+        if let Ok((mut guard, _)) = condvar.wait_timeout_while(guard, timeout, |p| !*p) {
+            *guard = false;
+        }
+    }
+}
+
+pub fn start_process() -> Result<()> {
+    thread::Builder::new()
+        .name("embedding-worker".to_string())
+        .spawn(|| loop {
+            if worker::is_paused()
+                || power::battery_action().ok().flatten() == Some(BatteryAction::Pause)
+            {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            match embed_pending_items(EMBED_BATCH_SIZE) {
+                Ok(0) => wait_for_embedding(Duration::from_secs(10)),
+                Ok(_) => match get_progress() {
+                    Ok(progress) => emit(EMBEDDING_PROGRESS_EVENT, progress),
+                    Err(e) => error!("获取向量进度失败: {e}"),
+                },
+                Err(e) => {
+                    error!("计算向量失败: {e}");
+                    wait_for_embedding(Duration::from_secs(10));
+                }
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+    use std::fs;
+
+    #[test]
+    fn test_hashed_ngram_model() {
+        let model = HashedNgramModel { dims: 256 };
+        let a = model.embed("季度销售报告");
+        let b = model.embed("销售报告");
+        let c = model.embed("hello world");
+        assert_eq!(a.len(), 256);
+        assert!((cosine_similarity(&a, &a) - 1.0).abs() < 1e-5);
+        assert!(cosine_similarity(&a, &b) > cosine_similarity(&a, &c));
+        assert_eq!(a, model.embed("季度销售报告"));
+        assert_eq!(blob_to_vector(&vector_to_blob(&a)), a);
+    }
+
+    #[test]
+    fn test_static_vectors_model() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.vec");
+        fs::write(&path, "3 2\nhello 1 0\nworld 0 1\n报 1 1\n").unwrap();
+
+        let model = StaticVectorsModel::load(&path, 2).unwrap();
+        assert_eq!(model.vectors.len(), 3);
+        let v = model.embed("Hello, unknown");
+        assert!((v[0] - 1.0).abs() < 1e-5);
+        assert!(v[1].abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Hello, 世界 abc1"),
+            vec!["hello", "世", "界", "abc1"]
+        );
+    }
+
+    #[test]
+    fn test_register_model() {
+        let _env = TestEnv::new();
+        let info = EmbeddingModelInfo {
+            name: "custom-300".into(),
+            dims: 300,
+            source: EmbeddingModelSource::StaticVectors {
+                url: "https://example.com/custom.vec".into(),
+                sha256: "00".into(),
+            },
+        };
+        register_model(info.clone()).unwrap();
+
+        let models = list_models().unwrap();
+        let custom = models.iter().find(|m| m.info == info).unwrap();
+        assert!(!custom.installed);
+        assert!(!custom.lexical);
+        // 内置的哈希模型标为字面相似的后备
+        assert!(models
+            .iter()
+            .filter(|m| m.info.source == EmbeddingModelSource::HashedNgram)
+            .all(|m| m.lexical));
+        assert!(set_active_model(Some("custom-300".into())).is_err());
+
+        let mut builtin = info.clone();
+        builtin.name = "hash-ngram-256".into();
+        assert!(register_model(builtin).is_err());
+    }
+
+    #[test]
+    fn test_search_similar_text() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let file = env.temp_dir.path().join("notes.txt");
+        fs::write(&file, "").unwrap();
+        indexer
+            .write_file_items(
+                &file,
+                vec![
+                    Item {
+                        content: "今年第三季度的销售报告".into(),
+                        ..Default::default()
+                    },
+                    Item {
+                        content: "weekend hiking trip".into(),
+                        ..Default::default()
+                    },
+                ],
+            )
+            .unwrap();
+
+        // 未启用模型时报错
+        assert!(search_similar_text("销售", 0, 10, &SearchFilter::default()).is_err());
+
+        set_active_model(Some("hash-ngram-1024".into())).unwrap();
+        embed_pending_items(10).unwrap();
+        let page = search_similar_text("季度销售情况", 0, 10, &SearchFilter::default()).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].content, "今年第三季度的销售报告");
+        assert_eq!(page.items[0].file, "notes.txt");
+        assert!(page.items[0].score > page.items[1].score);
+        assert!(!page.items[0].highlights.is_empty());
+
+        let page = search_similar_text("季度销售情况", 1, 10, &SearchFilter::default()).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].content, "weekend hiking trip");
+        // 只保留到当前页为止的结果，总数仍是参与比较的内容数
+        let page = search_similar_text("季度销售情况", 0, 1, &SearchFilter::default()).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].content, "今年第三季度的销售报告");
+
+        let filter = SearchFilter {
+            extensions: vec!["pdf".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            search_similar_text("季度", 0, 10, &filter).unwrap().total,
+            0
+        );
+    }
+
+    #[test]
+    fn test_embed_pending_items() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let file = Path::new("../test_data/indexer")
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer
+            .write_file_items(
+                &file,
+                vec![
+                    Item {
+                        content: "Hello, world!".into(),
+                        ..Default::default()
+                    },
+                    Item {
+                        content: "This is a test.".into(),
+                        ..Default::default()
+                    },
+                ],
+            )
+            .unwrap();
+
+        // 未启用模型时不计算
+        assert_eq!(embed_pending_items(10).unwrap(), 0);
+
+        set_active_model(Some("hash-ngram-256".into())).unwrap();
+        assert_eq!(embed_pending_items(1).unwrap(), 1);
+        assert_eq!(embed_pending_items(10).unwrap(), 1);
+        assert_eq!(
+            get_progress().unwrap(),
+            EmbeddingProgress {
+                model: Some("hash-ngram-256".into()),
+                embedded: 2,
+                total: 2,
+            }
+        );
+
+        // 切换模型后重新计算
+        set_active_model(Some("hash-ngram-1024".into())).unwrap();
+        assert_eq!(get_progress().unwrap().embedded, 0);
+        assert_eq!(embed_pending_items(10).unwrap(), 2);
+
+        // 删除文件后向量一起删除
+        indexer.delete_file(&file).unwrap();
+        assert_eq!(get_progress().unwrap().embedded, 0);
+    }
+}
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::dirs::get_index_dir;
+use crate::sqlite::{is_pool_ready, lock_whole_db, with_drained_pool, DB_FILE};
+use crate::transfer;
+use crate::worker::{self, Worker};
+
+// 修改密码前等待正在处理的任务结束的最长时间
+const REKEY_WAIT: Duration = Duration::from_secs(30);
+
+// 未加密的 SQLite 数据库文件的开头，加密后整个文件都是密文
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+// 启动时输入的密码，连接池的每个连接打开后先用它解密
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// 索引的加密情况
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionStatus {
+    pub encrypted: bool,
+    /// 数据库已经打开，加密的索引输入密码后才打开
+    pub unlocked: bool,
+}
+
+fn db_path() -> PathBuf {
+    get_index_dir().join(DB_FILE)
+}
+
+/// 修改密码时导出完整的数据库，替换当前的数据库后删除
+pub fn pending_rekey_path() -> PathBuf {
+    get_index_dir().join(format!("{DB_FILE}.rekey"))
+}
+
+pub fn get_passphrase() -> Option<String> {
+    PASSPHRASE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+fn is_encrypted_file(path: &Path) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    File::open(path)?
+        .take(SQLITE_HEADER.len() as u64)
+        .read_to_end(&mut header)?;
+    // 空文件由 SQLite 当作新数据库初始化
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+/// 索引的数据库是否加密，不存在时按未加密处理，新建的数据库不加密
+pub fn is_encrypted() -> Result<bool> {
+    is_encrypted_file(&db_path())
+}
+
+/// 不经过连接池打开数据库文件，索引加密时用当前的密码解密
+pub fn open(path: &Path, flags: OpenFlags) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    if let Some(passphrase) = get_passphrase() {
+        conn.pragma_update(None, "key", passphrase)?;
+    }
+    Ok(conn)
+}
+
+/// 密码不正确时 SQLCipher 在读取第一页时才报错
+fn check_passphrase(path: &Path, passphrase: &str) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.pragma_update(None, "key", passphrase)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|_| anyhow!("密码不正确"))?;
+    Ok(())
+}
+
+/// 检查密码后保存，之后打开连接池
+pub fn unlock(passphrase: &str) -> Result<()> {
+    check_passphrase(&db_path(), passphrase)?;
+    *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) = Some(passphrase.to_string());
+    info!("索引已解密");
+    Ok(())
+}
+
+/// 用新的密码导出一份数据库并替换当前的数据库，没有密码时导出为不加密的数据库。
+/// 期间暂停索引，等正在处理的任务结束、连接都归还后再导出，导出和替换之间不会有新的写入
+pub fn set_passphrase(passphrase: Option<&str>) -> Result<()> {
+    if transfer::has_pending_import() {
+        bail!("有导入的索引等待重启后生效，请先重启应用");
+    }
+    // 等正在进行的维护、备份和导出完成，之后它们等修改密码完成
+    let _whole_db = lock_whole_db();
+    let was_paused = worker::is_paused();
+    worker::set_paused(true);
+    let result = Worker::wait_running_tasks(REKEY_WAIT)
+        .and_then(|_| with_drained_pool(|| rekey(passphrase)));
+    worker::set_paused(was_paused);
+    result
+}
+
+fn rekey(passphrase: Option<&str>) -> Result<()> {
+    let temp_path = get_index_dir().join(format!("{DB_FILE}.rekey.tmp"));
+    if temp_path.exists() {
+        fs::remove_file(&temp_path)?;
+    }
+    let temp_str = temp_path
+        .to_str()
+        .with_context(|| format!("路径不是有效的 UTF-8: {}", temp_path.display()))?;
+    let conn = open(&db_path(), OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    // KEY 为空字符串时附加的数据库不加密
+    conn.execute(
+        "ATTACH DATABASE ?1 AS target KEY ?2",
+        params![temp_str, passphrase.unwrap_or_default()],
+    )?;
+    let exported = (|| -> Result<()> {
+        // 这两个设置不随 sqlcipher_export 复制
+        conn.execute_batch("PRAGMA target.auto_vacuum = FULL")?;
+        conn.query_row("SELECT sqlcipher_export('target')", [], |_| Ok(()))?;
+        conn.query_row("PRAGMA target.journal_mode = WAL", [], |_| Ok(()))?;
+        Ok(())
+    })();
+    conn.execute_batch("DETACH DATABASE target")?;
+    drop(conn);
+    if let Err(e) = exported {
+        fs::remove_file(&temp_path)?;
+        return Err(e);
+    }
+    // 导出完整后才改名，替换中途退出时下次启动由 apply_pending_rekey 接着替换
+    fs::rename(&temp_path, pending_rekey_path())?;
+    apply_pending_rekey()?;
+    *PASSPHRASE.lock().unwrap_or_else(PoisonError::into_inner) = passphrase.map(str::to_string);
+    info!("已修改索引密码, 加密: {}", passphrase.is_some());
+    Ok(())
+}
+
+/// 有修改密码时导出的数据库时替换当前的数据库，修改密码时和启动时在打开数据库之前调用。
+/// 原来的数据库和导入索引留下的备份可能是明文，一起删除
+pub fn apply_pending_rekey() -> Result<bool> {
+    let pending = pending_rekey_path();
+    if !pending.exists() {
+        return Ok(false);
+    }
+    let dir = get_index_dir();
+    for name in [DB_FILE.to_string(), format!("{DB_FILE}.bak")] {
+        for suffix in ["", "-wal", "-shm"] {
+            let path = dir.join(format!("{name}{suffix}"));
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    // 上次导出中途退出时留下的临时文件
+    let temp_path = dir.join(format!("{DB_FILE}.rekey.tmp"));
+    if temp_path.exists() {
+        fs::remove_file(&temp_path)?;
+    }
+    fs::rename(&pending, db_path())?;
+    info!("已替换为修改密码后的数据库");
+    Ok(true)
+}
+
+pub fn get_status() -> Result<EncryptionStatus> {
+    Ok(EncryptionStatus {
+        encrypted: is_encrypted()?,
+        unlocked: is_pool_ready(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::sqlite::close_pool;
+    use crate::test::test_mod::TestEnv;
+
+    fn read_content(path: &Path, passphrase: Option<&str>, keyword: &str) -> Result<String> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        if let Some(passphrase) = passphrase {
+            conn.pragma_update(None, "key", passphrase)?;
+        }
+        Ok(conn.query_row(
+            "SELECT content FROM items WHERE id IN (SELECT rowid FROM items_fts WHERE items_fts MATCH ?1)",
+            params![keyword],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn write_content(env: &TestEnv, name: &str, content: &str) {
+        let file = env.temp_dir.path().join(name);
+        fs::write(&file, "a").unwrap();
+        let items = vec![Item {
+            content: content.into(),
+            ..Default::default()
+        }];
+        Indexer::new()
+            .unwrap()
+            .write_file_items(&file, items)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_set_passphrase() {
+        let env = TestEnv::new();
+        write_content(&env, "a.txt", "机密的文件");
+        assert!(!is_encrypted().unwrap());
+
+        set_passphrase(Some("密码")).unwrap();
+        assert!(is_encrypted().unwrap());
+        assert!(!pending_rekey_path().exists());
+        assert!(check_passphrase(&db_path(), "错误").is_err());
+        assert!(read_content(&db_path(), None, "机密的").is_err());
+        assert_eq!(
+            read_content(&db_path(), Some("密码"), "机密的").unwrap(),
+            "机密的文件"
+        );
+        assert_eq!(
+            get_status().unwrap(),
+            EncryptionStatus {
+                encrypted: true,
+                unlocked: true,
+            }
+        );
+
+        // 修改密码后的写入保存在新的数据库中
+        write_content(&env, "b.txt", "之后的文件");
+        set_passphrase(Some("新密码")).unwrap();
+        assert!(check_passphrase(&db_path(), "密码").is_err());
+        write_content(&env, "c.txt", "最后的文件");
+
+        close_pool();
+        assert!(!apply_pending_rekey().unwrap());
+        assert!(unlock("密码").is_err());
+        unlock("新密码").unwrap();
+        let conn = open(&db_path(), OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let journal_mode: String = conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        drop(conn);
+        for (keyword, content) in [
+            ("机密的", "机密的文件"),
+            ("之后的", "之后的文件"),
+            ("最后的", "最后的文件"),
+        ] {
+            assert_eq!(
+                read_content(&db_path(), Some("新密码"), keyword).unwrap(),
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove_passphrase() {
+        let env = TestEnv::new();
+        write_content(&env, "a.txt", "机密的文件");
+        set_passphrase(Some("密码")).unwrap();
+        set_passphrase(None).unwrap();
+        assert!(!is_encrypted().unwrap());
+        assert_eq!(get_passphrase(), None);
+        write_content(&env, "b.txt", "之后的文件");
+        assert_eq!(
+            read_content(&db_path(), None, "之后的").unwrap(),
+            "之后的文件"
+        );
+    }
+
+    #[test]
+    fn test_apply_pending_rekey() {
+        let env = TestEnv::new();
+        write_content(&env, "a.txt", "机密的文件");
+        close_pool();
+        // 导出中途退出时只留下临时文件，不替换当前的数据库
+        let temp_path = get_index_dir().join(format!("{DB_FILE}.rekey.tmp"));
+        fs::write(&temp_path, "不完整").unwrap();
+        assert!(!apply_pending_rekey().unwrap());
+        assert!(temp_path.exists());
+
+        // 导出完整后退出时接着替换
+        fs::copy(db_path(), pending_rekey_path()).unwrap();
+        assert!(apply_pending_rekey().unwrap());
+        assert!(!temp_path.exists());
+        assert!(!pending_rekey_path().exists());
+        assert_eq!(
+            read_content(&db_path(), None, "机密的").unwrap(),
+            "机密的文件"
+        );
+    }
+}
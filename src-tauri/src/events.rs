@@ -0,0 +1,23 @@
+use log::error;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+// 后台线程没有 AppHandle，启动时保存一份用于向前端推送事件
+static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
+
+pub fn set_app_handle(app_handle: AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+}
+
+/// 向前端推送事件。tauri 未启动（如测试中）时直接忽略。
+pub fn emit<S>(event: &str, payload: S)
+where
+    S: Serialize + Clone,
+{
+    if let Some(app_handle) = APP_HANDLE.get() {
+        if let Err(e) = app_handle.emit(event, payload) {
+            error!("推送事件失败: {event}, {e}");
+        }
+    }
+}
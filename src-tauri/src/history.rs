@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use rusqlite::{params, Params};
+use serde::{Deserialize, Serialize};
+
+use crate::indexer::{MatchOptions, SearchFilter, SearchSort};
+use crate::sqlite::get_conn;
+
+// 只保留最近的记录
+const MAX_HISTORY_ENTRIES: usize = 200;
+
+/// 一条搜索历史，保存完整的搜索条件，重新执行时结果一致
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SearchHistoryEntry {
+    pub id: i64,
+    pub query: String,
+    #[serde(flatten)]
+    pub options: MatchOptions,
+    pub sort: SearchSort,
+    pub filter: SearchFilter,
+    pub created_at: String,
+}
+
+/// 记录一次搜索。相同的查询只保留一条，更新为最新的条件和时间
+pub fn add(
+    query: &str,
+    options: MatchOptions,
+    sort: SearchSort,
+    filter: &SearchFilter,
+) -> Result<()> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(());
+    }
+    let mut conn = get_conn()?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        r"INSERT INTO search_history (query, fuzzy, case_sensitive, sort, filter, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(query) DO UPDATE SET fuzzy = ?2, case_sensitive = ?3, sort = ?4, filter = ?5, created_at = ?6",
+        params![
+            query,
+            options.fuzzy,
+            options.case_sensitive,
+            serde_json::to_string(&sort)?,
+            serde_json::to_string(filter)?,
+            Local::now().to_rfc3339()
+        ],
+    )?;
+    tx.execute(
+        r"DELETE FROM search_history WHERE id NOT IN (
+            SELECT id FROM search_history ORDER BY created_at DESC, id DESC LIMIT ?1
+        )",
+        params![MAX_HISTORY_ENTRIES as i64],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn query_entries<P: Params>(condition: &str, params: P) -> Result<Vec<SearchHistoryEntry>> {
+    let conn = get_conn()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, query, fuzzy, case_sensitive, sort, filter, created_at FROM search_history {condition}"
+    ))?;
+    let rows = stmt.query_map(params, |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, bool>(2)?,
+            row.get::<_, bool>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    })?;
+    let mut entries = Vec::new();
+    for row in rows {
+        let (id, query, fuzzy, case_sensitive, sort, filter, created_at) = row?;
+        entries.push(SearchHistoryEntry {
+            id,
+            query,
+            options: MatchOptions {
+                fuzzy,
+                case_sensitive,
+            },
+            sort: serde_json::from_str(&sort)?,
+            filter: serde_json::from_str(&filter)?,
+            created_at,
+        });
+    }
+    Ok(entries)
+}
+
+/// 最近的搜索在前
+pub fn list(limit: usize) -> Result<Vec<SearchHistoryEntry>> {
+    query_entries(
+        "ORDER BY created_at DESC, id DESC LIMIT ?1",
+        params![limit as i64],
+    )
+}
+
+pub fn get(id: i64) -> Result<SearchHistoryEntry> {
+    query_entries("WHERE id = ?1", params![id])?
+        .into_iter()
+        .next()
+        .with_context(|| format!("Search history {id} not found"))
+}
+
+pub fn clear() -> Result<()> {
+    get_conn()?.execute("DELETE FROM search_history", [])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::SortBy;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_search_history() {
+        let _env = TestEnv::new();
+        add(
+            "report",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &SearchFilter::default(),
+        )
+        .unwrap();
+        let filter = SearchFilter {
+            extensions: vec!["pdf".into()],
+            ..Default::default()
+        };
+        let sort = SearchSort {
+            by: SortBy::Modified,
+            order: None,
+        };
+        let options = MatchOptions {
+            fuzzy: true,
+            case_sensitive: true,
+        };
+        add("budget", options, sort, &filter).unwrap();
+        // 重复的查询只更新条件，排到最前
+        add(" report ", options, sort, &filter).unwrap();
+        add(
+            "  ",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &SearchFilter::default(),
+        )
+        .unwrap();
+
+        let entries = list(10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].query, "report");
+        assert_eq!(entries[0].options, options);
+        assert_eq!(entries[0].sort, sort);
+        assert_eq!(entries[0].filter, filter);
+        assert_eq!(entries[1].query, "budget");
+        assert_eq!(get(entries[1].id).unwrap(), entries[1]);
+
+        clear().unwrap();
+        assert!(list(10).unwrap().is_empty());
+        assert!(get(entries[0].id).is_err());
+    }
+
+    #[test]
+    fn test_search_history_limit() {
+        let _env = TestEnv::new();
+        for i in 0..MAX_HISTORY_ENTRIES + 5 {
+            add(
+                &format!("query {i}"),
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        }
+        let entries = list(MAX_HISTORY_ENTRIES * 2).unwrap();
+        assert_eq!(entries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(
+            entries[0].query,
+            format!("query {}", MAX_HISTORY_ENTRIES + 4)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::config::Config;
+use crate::roots::nearest_root;
+
+/// 还没有设置过规则的索引目录使用的默认规则
+pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[".git/", "node_modules/", "target/", "*.tmp"];
+
+struct Rule {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// 一个索引目录下不索引的文件和目录，规则写法与 .gitignore 相同：
+/// 不含 `/` 的规则匹配任意层级的名称，含 `/` 的规则相对索引目录，
+/// 以 `/` 结尾的只匹配目录，`!` 开头的规则重新包含前面排除的路径，后面的规则优先
+pub struct IgnoreRules {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Self> {
+        let mut rules = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.trim();
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let glob = if pattern.contains('/') {
+                pattern.trim_start_matches('/').to_string()
+            } else {
+                format!("**/{pattern}")
+            };
+            let matcher = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .case_insensitive(cfg!(windows))
+                .build()
+                .with_context(|| format!("排除规则格式错误: {pattern}"))?
+                .compile_matcher();
+            rules.push(Rule {
+                matcher,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(IgnoreRules {
+            root: root.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// 路径是否被排除，上级目录被排除时其中的文件和目录也排除
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let names: Vec<_> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect();
+        (1..=names.len()).any(|depth| {
+            let is_dir = is_dir || depth < names.len();
+            self.matches(&names[..depth].join("/"), is_dir)
+        })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.matcher.is_match(relative))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+/// 按配置编译好的各索引目录的规则
+struct CompiledRules {
+    roots: Vec<String>,
+    patterns: HashMap<String, Vec<String>>,
+    rules: Vec<IgnoreRules>,
+}
+
+// 配置变化时重新编译
+static RULES: Mutex<Option<CompiledRules>> = Mutex::new(None);
+
+/// 路径是否被所在索引目录的规则排除，不在索引目录下时不排除
+pub fn is_ignored(path: &Path) -> Result<bool> {
+    let roots = Config::get_index_dir_paths()?;
+    let patterns = Config::get_all_ignore_patterns()?;
+    let Some(root) = nearest_root(&roots, path) else {
+        return Ok(false);
+    };
+    let mut cached = RULES.lock().unwrap_or_else(PoisonError::into_inner);
+    if !cached
+        .as_ref()
+        .is_some_and(|cached| cached.roots == roots && cached.patterns == patterns)
+    {
+        let rules = roots
+            .iter()
+            .map(|root| IgnoreRules::new(Path::new(root), &Config::get_ignore_patterns(root)?))
+            .collect::<Result<Vec<_>>>()?;
+        *cached = Some(CompiledRules {
+            roots: roots.clone(),
+            patterns,
+            rules,
+        });
+    }
+    let Some(compiled) = cached.as_ref() else {
+        return Ok(false);
+    };
+    Ok(compiled
+        .rules
+        .iter()
+        .find(|rules| rules.root == Path::new(root))
+        .is_some_and(|rules| rules.is_ignored(path, path.is_dir())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(patterns: &[&str]) -> IgnoreRules {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        IgnoreRules::new(Path::new("/root"), &patterns).unwrap()
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = rules(&[
+            "# 注释",
+            "node_modules/",
+            "*.tmp",
+            "/build",
+            "docs/*.log",
+            "!keep.tmp",
+        ]);
+        let ignored = |path: &str, is_dir| rules.is_ignored(Path::new(path), is_dir);
+
+        // 任意层级的名称，其中的文件也排除
+        assert!(ignored("/root/node_modules", true));
+        assert!(ignored("/root/web/node_modules/a/index.js", false));
+        assert!(!ignored("/root/node_modules", false));
+        assert!(ignored("/root/a/b.tmp", false));
+        assert!(!ignored("/root/a/keep.tmp", false));
+
+        // 含 / 的规则相对索引目录
+        assert!(ignored("/root/build/out.txt", false));
+        assert!(!ignored("/root/src/build", true));
+        assert!(ignored("/root/docs/a.log", false));
+        assert!(!ignored("/root/docs/sub/a.log", false));
+
+        assert!(!ignored("/root/src/main.rs", false));
+        assert!(!ignored("/other/a.tmp", false));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let patterns = vec!["a[".to_string()];
+        assert!(IgnoreRules::new(Path::new("/root"), &patterns).is_err());
+    }
+}
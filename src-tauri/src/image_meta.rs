@@ -0,0 +1,267 @@
+use exif::{In, Tag, Value};
+use std::io::Cursor;
+
+// IPTC 中建立索引的数据集（记录 2），同一属性可能有多个值
+const IPTC_HEADLINE: u8 = 105;
+const IPTC_CAPTION: u8 = 120;
+const IPTC_KEYWORDS: u8 = 25;
+// 地点从小到大排列：子地点、城市、省份、国家
+const IPTC_PLACE: [u8; 4] = [92, 90, 95, 101];
+
+/// 图片的拍摄信息和说明，按 `camera`、`taken`、`gps`、`place`、`headline`、`caption`、`keywords` 的顺序输出
+pub fn image_properties(data: &[u8]) -> Vec<(&'static str, String)> {
+    let mut properties = exif_properties(data);
+    if let Some(iim) = jpeg_iptc(data) {
+        properties.extend(iptc_properties(&iim));
+    }
+    properties
+}
+
+fn ascii_field(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+/// 度分秒转换为十进制的度数，南纬和西经为负数
+fn gps_coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag, negative: &str) -> Option<f64> {
+    let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let degrees = dms
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(value, unit)| value.to_f64() / unit)
+        .sum::<f64>();
+    if !degrees.is_finite() {
+        return None;
+    }
+    let negative = ascii_field(exif, ref_tag).is_some_and(|r| r.eq_ignore_ascii_case(negative));
+    Some(if negative { -degrees } else { degrees })
+}
+
+fn exif_properties(data: &[u8]) -> Vec<(&'static str, String)> {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return Vec::new();
+    };
+    let mut properties = Vec::new();
+
+    let make = ascii_field(&exif, Tag::Make);
+    let model = ascii_field(&exif, Tag::Model);
+    let camera = match (make, model) {
+        // 型号中通常已经带有厂商名称
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(model)
+        }
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+    if let Some(camera) = camera {
+        properties.push(("camera", camera));
+    }
+
+    if let Some(taken) =
+        ascii_field(&exif, Tag::DateTimeOriginal).or_else(|| ascii_field(&exif, Tag::DateTime))
+    {
+        // EXIF 的日期格式为 2024:05:01 10:00:00
+        let taken = match taken.split_once(' ') {
+            Some((date, time)) => format!("{} {time}", date.replace(':', "-")),
+            None => taken,
+        };
+        properties.push(("taken", taken));
+    }
+
+    let latitude = gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S");
+    let longitude = gps_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W");
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        properties.push(("gps", format!("{latitude:.6}, {longitude:.6}")));
+    }
+    properties
+}
+
+/// JPEG 的 APP13 段中 Photoshop 资源 0x0404 保存的 IPTC IIM 数据
+fn jpeg_iptc(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        // SOS 之后是图像数据
+        if marker == 0xDA {
+            break;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xED {
+            if let Some(resources) = segment.strip_prefix(b"Photoshop 3.0\0") {
+                if let Some(iim) = photoshop_resource(resources, 0x0404) {
+                    return Some(iim.to_vec());
+                }
+            }
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// 8BIM 资源块：类型、编号、Pascal 字符串名称、长度和数据，名称和数据都按偶数长度对齐
+fn photoshop_resource(mut data: &[u8], id: u16) -> Option<&[u8]> {
+    while data.len() >= 12 && data.starts_with(b"8BIM") {
+        let resource_id = u16::from_be_bytes([data[4], data[5]]);
+        let name_len = data[6] as usize;
+        let name_end = 6 + ((name_len + 2) & !1);
+        let size_bytes = data.get(name_end..name_end + 4)?;
+        let size = u32::from_be_bytes(size_bytes.try_into().ok()?) as usize;
+        let content = data.get(name_end + 4..name_end + 4 + size)?;
+        if resource_id == id {
+            return Some(content);
+        }
+        data = data.get(name_end + 4 + ((size + 1) & !1)..)?;
+    }
+    None
+}
+
+/// IIM 数据集依次为 0x1C、记录号、数据集号、两字节长度和数据。按 UTF-8 解码
+fn iptc_properties(mut data: &[u8]) -> Vec<(&'static str, String)> {
+    let mut values: Vec<(u8, String)> = Vec::new();
+    while data.len() >= 5 && data[0] == 0x1C {
+        let record = data[1];
+        let dataset = data[2];
+        let len = u16::from_be_bytes([data[3], data[4]]) as usize;
+        // 最高位表示扩展长度，只用于超大的数据，这里不需要
+        if len & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = data.get(5..5 + len) else {
+            break;
+        };
+        if record == 2 {
+            let value = String::from_utf8_lossy(value).trim().to_string();
+            if !value.is_empty() {
+                values.push((dataset, value));
+            }
+        }
+        data = &data[5 + len..];
+    }
+
+    let join = |datasets: &[u8]| {
+        let mut joined = Vec::new();
+        for dataset in datasets {
+            for (d, value) in &values {
+                if d == dataset && !joined.contains(&value.as_str()) {
+                    joined.push(value.as_str());
+                }
+            }
+        }
+        joined.join(", ")
+    };
+    [
+        ("place", join(&IPTC_PLACE)),
+        ("headline", join(&[IPTC_HEADLINE])),
+        ("caption", join(&[IPTC_CAPTION])),
+        ("keywords", join(&[IPTC_KEYWORDS])),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_empty())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+
+    fn iim(dataset: u8, value: &str) -> Vec<u8> {
+        let mut data = vec![0x1C, 2, dataset];
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value.as_bytes());
+        data
+    }
+
+    fn segment(marker: u8, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, marker];
+        data.extend_from_slice(&(content.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(content);
+        data
+    }
+
+    #[test]
+    fn test_image_properties() {
+        let ascii = |tag, value: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let dms = |d, m, s| {
+            Value::Rational(vec![
+                Rational { num: d, denom: 1 },
+                Rational { num: m, denom: 1 },
+                Rational { num: s, denom: 100 },
+            ])
+        };
+        let fields = [
+            ascii(Tag::Make, "Canon"),
+            ascii(Tag::Model, "Canon EOS 5D"),
+            ascii(Tag::DateTimeOriginal, "2024:05:01 10:00:00"),
+            ascii(Tag::GPSLatitudeRef, "N"),
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: dms(31, 13, 4944),
+            },
+            ascii(Tag::GPSLongitudeRef, "W"),
+            Field {
+                tag: Tag::GPSLongitude,
+                ifd_num: In::PRIMARY,
+                value: dms(121, 28, 2532),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+
+        let mut iim_data = Vec::new();
+        for (dataset, value) in [
+            (25, "外滩"),
+            (25, "夜景"),
+            (90, "上海"),
+            (101, "中国"),
+            (120, "黄浦江边的夜景"),
+        ] {
+            iim_data.extend(iim(dataset, value));
+        }
+        let mut photoshop = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+        photoshop.extend_from_slice(&(iim_data.len() as u32).to_be_bytes());
+        photoshop.extend_from_slice(&iim_data);
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend(segment(
+            0xE1,
+            &[b"Exif\0\0".as_slice(), tiff.get_ref()].concat(),
+        ));
+        jpeg.extend(segment(0xED, &photoshop));
+        jpeg.extend([0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+
+        assert_eq!(
+            image_properties(&jpeg),
+            vec![
+                ("camera", "Canon EOS 5D".to_string()),
+                ("taken", "2024-05-01 10:00:00".to_string()),
+                ("gps", "31.230400, -121.473700".to_string()),
+                ("place", "上海, 中国".to_string()),
+                ("caption", "黄浦江边的夜景".to_string()),
+                ("keywords", "外滩, 夜景".to_string()),
+            ]
+        );
+        assert!(image_properties(b"not an image").is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::events::emit;
+use crate::indexer::ChangeOp;
+
+pub const INDEX_CHANGED_EVENT: &str = "index-changed";
+
+// 推送的最小间隔，批量索引时合并成一次推送
+const EMIT_INTERVAL: Duration = Duration::from_secs(1);
+// 一次推送最多列出的路径数，超过时前端直接重新搜索
+const MAX_PATHS: usize = 1000;
+
+/// 索引发生的变化，前端据此刷新已经显示的搜索结果
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct IndexChanged {
+    /// 新增或更新索引的文件
+    pub updated: Vec<String>,
+    /// 从索引中删除的文件和目录，删除目录时其中的文件不单独列出
+    pub removed: Vec<String>,
+    /// 变化太多，没有列出全部路径
+    pub truncated: bool,
+}
+
+struct PendingChanges {
+    updated: BTreeSet<String>,
+    removed: BTreeSet<String>,
+    truncated: bool,
+    last_emit: Option<Instant>,
+}
+
+static PENDING: Mutex<PendingChanges> = Mutex::new(PendingChanges {
+    updated: BTreeSet::new(),
+    removed: BTreeSet::new(),
+    truncated: false,
+    last_emit: None,
+});
+
+/// 记录一个变化，写入数据库时调用，提交后再由 flush 推送
+pub fn record(op: ChangeOp, path: &str) {
+    let mut pending = PENDING.lock().unwrap_or_else(PoisonError::into_inner);
+    if pending.updated.len() + pending.removed.len() >= MAX_PATHS {
+        pending.truncated = true;
+        return;
+    }
+    let path = path.to_string();
+    match op {
+        ChangeOp::IndexFile => {
+            pending.removed.remove(&path);
+            pending.updated.insert(path);
+        }
+        ChangeOp::DeleteFile | ChangeOp::DeleteDirectory => {
+            pending.updated.remove(&path);
+            pending.removed.insert(path);
+        }
+    }
+}
+
+/// 取出待推送的变化，force 为 false 时距离上次推送不足间隔则继续等待
+fn take(force: bool) -> Option<IndexChanged> {
+    let mut pending = PENDING.lock().unwrap_or_else(PoisonError::into_inner);
+    if pending.updated.is_empty() && pending.removed.is_empty() && !pending.truncated {
+        return None;
+    }
+    if !force
+        && pending
+            .last_emit
+            .is_some_and(|last| last.elapsed() < EMIT_INTERVAL)
+    {
+        return None;
+    }
+    pending.last_emit = Some(Instant::now());
+    let truncated = std::mem::take(&mut pending.truncated);
+    Some(IndexChanged {
+        updated: std::mem::take(&mut pending.updated).into_iter().collect(),
+        removed: std::mem::take(&mut pending.removed).into_iter().collect(),
+        truncated,
+    })
+}
+
+/// 推送记录的变化，队列处理完等需要立即通知时 force 为 true
+pub fn flush(force: bool) {
+    if let Some(changed) = take(force) {
+        emit(INDEX_CHANGED_EVENT, changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_changes() {
+        take(true);
+        assert_eq!(take(true), None);
+
+        record(ChangeOp::IndexFile, "/root/a.txt");
+        record(ChangeOp::DeleteFile, "/root/a.txt");
+        record(ChangeOp::IndexFile, "/root/b.txt");
+        record(ChangeOp::DeleteDirectory, "/root/old");
+        assert_eq!(
+            take(true),
+            Some(IndexChanged {
+                updated: vec!["/root/b.txt".to_string()],
+                removed: vec!["/root/a.txt".to_string(), "/root/old".to_string()],
+                truncated: false,
+            })
+        );
+
+        // 刚推送过，不强制时等到下一个间隔
+        record(ChangeOp::IndexFile, "/root/c.txt");
+        assert_eq!(take(false), None);
+        assert_eq!(take(true).unwrap().updated, vec!["/root/c.txt".to_string()]);
+
+        for i in 0..=MAX_PATHS {
+            record(ChangeOp::IndexFile, &format!("/root/{i}.txt"));
+        }
+        let changed = take(true).unwrap();
+        assert_eq!(changed.updated.len(), MAX_PATHS);
+        assert!(changed.truncated);
+    }
+}
@@ -1,14 +1,23 @@
-use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, Local};
-use log::{debug, info};
-use rusqlite::params;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use log::info;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, ToSql};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::{Path, MAIN_SEPARATOR};
-
-use crate::reader::Item;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use strum::{Display, EnumString};
+
+use crate::compress::{decode_content, encode_content};
+use crate::config::Config;
+use crate::download::sha256_file;
+use crate::index_events;
+use crate::reader::{Item, ItemPosition};
+use crate::roots::find_root_id;
+use crate::similar::write_document_vector;
 use crate::sqlite::get_conn;
-use crate::utils::{filename_to_str, parent_to_str, path_to_str};
+use crate::tokenizer::{cut_for_query, segment};
+use crate::utils::{filename_to_str, parent_to_str, path_to_str, subpath_range, to_nfc};
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct SearchResultDirectory {
@@ -24,11 +33,374 @@ pub struct SearchResultFile {
     pub modified_time: String,
 }
 
+/// 超过大小上限等原因没有读取内容的文件
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub name: String,
+    pub path: String,
+    pub reason: String,
+}
+
+/// 大小相同、可能和新文件内容相同的已索引的文件
+#[derive(Debug, Clone)]
+pub struct SameSizeFile {
+    pub id: i64,
+    pub path: PathBuf,
+    pub modified_time: String,
+    pub content_hash: Option<String>,
+}
+
+/// 准备写入的文件，文件信息在打开事务前读取，多个文件可以在同一个事务中写入
+#[derive(Debug)]
+pub struct FileWrite {
+    file: PathBuf,
+    items: Vec<Item>,
+    skip_reason: Option<String>,
+    modified_time: String,
+    created_time: Option<String>,
+    size: i64,
+    root_id: Option<i64>,
+    compress: bool,
+    content_hash: Option<String>,
+    shared: bool,
+    changelog: bool,
+}
+
+impl FileWrite {
+    pub fn path(&self) -> &Path {
+        &self.file
+    }
+
+    /// 读取的文件内容的哈希，之后相同内容的文件共享这次读取的内容
+    pub fn with_content_hash(mut self, hash: String) -> Self {
+        self.content_hash = Some(hash);
+        self
+    }
+
+    /// 写入批次前补算的哈希，内容已经读取
+    pub fn set_content_hash(&mut self, hash: String) {
+        self.content_hash = Some(hash);
+    }
+
+    /// 不读取文件，写入时共享相同内容的文件已经读取的内容
+    pub fn sharing_content(mut self, hash: String) -> Self {
+        self.content_hash = Some(hash);
+        self.shared = true;
+        self
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
+    /// 读取了内容、可以被相同内容的文件共享，但还没有计算哈希
+    pub fn needs_content_hash(&self, size: i64, extension: &str) -> bool {
+        self.size == size
+            && self.content_hash.is_none()
+            && self.skip_reason.is_none()
+            && filename_to_str(&self.file).is_ok_and(|name| file_extension(name) == extension)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SearchResultItem {
     pub content: String,
     pub file: String,
     pub path: String,
+    /// 命中位置附近的片段，过长时首尾用省略号截断
+    pub snippet: String,
+    pub highlights: Vec<Highlight>,
+    /// 相关度，越大越相关
+    pub score: f64,
+    pub position: ItemPosition,
+}
+
+/// items 表中保存位置的列，与 item_position 读取的顺序一致
+pub const ITEM_POSITION_COLUMNS: &str =
+    "items.page, items.paragraph, items.sheet_row, items.line, items.entry, items.time_ms, items.cell, items.heading";
+
+/// 从 start 列开始读取 ITEM_POSITION_COLUMNS
+pub fn item_position(row: &Row, start: usize) -> rusqlite::Result<ItemPosition> {
+    Ok(ItemPosition {
+        page: row.get(start)?,
+        paragraph: row.get(start + 1)?,
+        sheet_row: row.get(start + 2)?,
+        line: row.get(start + 3)?,
+        entry: row.get(start + 4)?,
+        time_ms: row.get(start + 5)?,
+        cell: row.get(start + 6)?,
+        heading: row.get(start + 7)?,
+    })
+}
+
+/// 搜索结果的排序字段
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// 相关度，默认最相关的在前
+    #[default]
+    Relevance,
+    /// 名称，目录是目录名，文件和内容是文件名，默认升序
+    Name,
+    /// 修改时间，默认从新到旧
+    Modified,
+    /// 所在路径，默认升序
+    Path,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn reverse(self) -> Self {
+        match self {
+            SortOrder::Asc => SortOrder::Desc,
+            SortOrder::Desc => SortOrder::Asc,
+        }
+    }
+
+    fn as_sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// 名称和内容的匹配方式
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchOptions {
+    /// 按编辑距离模糊匹配，只用于目录和文件名，总是不区分大小写
+    pub fuzzy: bool,
+    /// 区分大小写。不区分时按 Unicode 规则折叠大小写，不只是 ASCII 字母
+    pub case_sensitive: bool,
+}
+
+/// 搜索结果的排序方式，order 为空时使用字段的默认顺序
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSort {
+    pub by: SortBy,
+    pub order: Option<SortOrder>,
+}
+
+/// 排序字段对应的 SQL 表达式
+struct SortColumns<'a> {
+    /// 越相关越靠前时的顺序
+    relevance: (&'a str, SortOrder),
+    name: &'a str,
+    modified: &'a str,
+    path: &'a str,
+    /// 值相同时按 id 保持稳定的顺序
+    id: &'a str,
+}
+
+impl SearchSort {
+    fn to_order_sql(self, columns: &SortColumns) -> String {
+        let (column, default_order) = match self.by {
+            SortBy::Relevance => columns.relevance,
+            SortBy::Name => (columns.name, SortOrder::Asc),
+            SortBy::Modified => (columns.modified, SortOrder::Desc),
+            SortBy::Path => (columns.path, SortOrder::Asc),
+        };
+        // 相关度的“升序”是最不相关的在前，需要反转表达式本身的顺序
+        let order = match (self.by, self.order) {
+            (_, None) => default_order,
+            (SortBy::Relevance, Some(SortOrder::Asc)) => default_order.reverse(),
+            (SortBy::Relevance, Some(SortOrder::Desc)) => default_order,
+            (_, Some(order)) => order,
+        };
+        format!("{column} {}, {}", order.as_sql(), columns.id)
+    }
+}
+
+/// snippet 中命中的区间，按字符（Unicode 码点）计算，左闭右开
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// search_all 合并后的一条结果，序列化时用 type 字段区分类型
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SearchHit {
+    Directory(SearchResultDirectory),
+    File(SearchResultFile),
+    // 内容结果的位置字段较多，装箱后其他两类结果不必占用同样的空间
+    Item(Box<SearchResultItem>),
+}
+
+/// 三类搜索各自第一页合并排序后的结果，以及各自的总数
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SearchAllResult {
+    pub hits: Vec<SearchHit>,
+    pub directory_total: usize,
+    pub file_total: usize,
+    pub item_total: usize,
+}
+
+/// 一页搜索结果和分页信息
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SearchPage<T> {
+    pub items: Vec<T>,
+    /// 满足条件的结果总数
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// 这一页之后是否还有结果
+    pub has_more: bool,
+}
+
+impl<T> SearchPage<T> {
+    pub fn new(items: Vec<T>, total: usize, offset: usize, limit: usize) -> Self {
+        let has_more = offset + items.len() < total;
+        SearchPage {
+            items,
+            total,
+            offset,
+            limit,
+            has_more,
+        }
+    }
+}
+
+/// 搜索的过滤条件，没有设置的条件不生效
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchFilter {
+    /// 扩展名，不区分大小写，开头的点可有可无
+    pub extensions: Vec<String>,
+    /// 修改时间不早于该时间，RFC 3339 格式，或者 YYYY-MM-DD 表示当天本地时间零点
+    pub modified_after: Option<String>,
+    /// 修改时间早于该时间，格式同上
+    pub modified_before: Option<String>,
+    /// 创建时间不早于该时间，格式同上，没有记录创建时间的文件不符合
+    pub created_after: Option<String>,
+    /// 创建时间早于该时间，格式同上
+    pub created_before: Option<String>,
+    /// 文件大小下限，单位字节，包含边界
+    pub min_size: Option<u64>,
+    /// 文件大小上限，单位字节，包含边界
+    pub max_size: Option<u64>,
+    /// 只搜索该目录及其子目录
+    pub root: Option<String>,
+}
+
+impl SearchFilter {
+    /// 扩展名、大小和创建时间只有文件才有，目录搜索设置了这些条件时没有结果
+    fn has_file_conditions(&self) -> bool {
+        !self.extensions.is_empty()
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.created_after.is_some()
+            || self.created_before.is_some()
+    }
+
+    /// 转成 WHERE 条件，没有条件时返回 "1"。
+    /// path_column 是所在目录的路径列，modified_column 是修改时间列，文件条件固定使用 files 表
+    pub fn to_sql(
+        &self,
+        path_column: &str,
+        modified_column: &str,
+        params: &mut SqlParams,
+    ) -> Result<String> {
+        let mut clauses = Vec::new();
+        if !self.extensions.is_empty() {
+            let placeholders = vec!["?"; self.extensions.len()].join(", ");
+            for extension in &self.extensions {
+                params.push(extension.trim_start_matches('.').to_lowercase().into());
+            }
+            clauses.push(format!("files.extension IN ({placeholders})"));
+        }
+        if let Some(after) = &self.modified_after {
+            params.push(parse_filter_time(after)?.into());
+            clauses.push(format!("julianday({modified_column}) >= julianday(?)"));
+        }
+        if let Some(before) = &self.modified_before {
+            params.push(parse_filter_time(before)?.into());
+            clauses.push(format!("julianday({modified_column}) < julianday(?)"));
+        }
+        if let Some(after) = &self.created_after {
+            params.push(parse_filter_time(after)?.into());
+            clauses.push("julianday(files.created_time) >= julianday(?)".to_string());
+        }
+        if let Some(before) = &self.created_before {
+            params.push(parse_filter_time(before)?.into());
+            clauses.push("julianday(files.created_time) < julianday(?)".to_string());
+        }
+        if let Some(min_size) = self.min_size {
+            params.push((min_size as i64).into());
+            clauses.push("files.size >= ?".to_string());
+        }
+        if let Some(max_size) = self.max_size {
+            params.push((max_size as i64).into());
+            clauses.push("files.size <= ?".to_string());
+        }
+        if let Some(root) = &self.root {
+            // 按范围查询，区分大小写，可以使用 path 的索引
+            let (lower, upper) = subpath_range(root);
+            params.push(root.clone().into());
+            params.push(lower.into());
+            params.push(upper.into());
+            clauses.push(format!(
+                "({path_column} = ? OR ({path_column} >= ? AND {path_column} < ?))"
+            ));
+        }
+        if clauses.is_empty() {
+            return Ok("1".to_string());
+        }
+        Ok(clauses.join(" AND "))
+    }
+}
+
+/// 把搜索限定在一个索引根目录下，root 必须是设置中添加的索引目录。
+/// 过滤条件中已有更深的 root 时保留，与根目录不相交时报错
+pub fn scope_to_root(mut filter: SearchFilter, root: Option<String>) -> Result<SearchFilter> {
+    let Some(root) = root else {
+        return Ok(filter);
+    };
+    if !Config::get_index_dir_paths()?.contains(&root) {
+        return Err(anyhow!("Path {} is not an indexed root", root));
+    }
+    filter.root = match filter.root {
+        Some(inner) if Path::new(&inner).starts_with(&root) => Some(inner),
+        Some(outer) if !Path::new(&root).starts_with(&outer) => {
+            return Err(anyhow!(
+                "Filter root {} is outside of indexed root {}",
+                outer,
+                root
+            ));
+        }
+        _ => Some(root),
+    };
+    Ok(filter)
+}
+
+fn parse_filter_time(value: &str) -> Result<String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.to_rfc3339());
+    }
+    let time = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|time| time.and_local_timezone(Local).earliest())
+        .with_context(|| format!("Invalid time in search filter: {value}"))?;
+    Ok(time.to_rfc3339())
+}
+
+/// 文件的扩展名，小写，没有时为空字符串
+pub fn file_extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -38,11 +410,674 @@ pub struct IndexStatusStat {
     pub items: usize,
 }
 
-pub struct Indexer {}
+// items_fts 使用 trigram 分词
+const FTS_MIN_QUERY_CHARS: usize = 3;
+
+// 目录 ?1 和各级子目录，depth 是相对 ?1 的层数
+const SUBTREE: &str = r"WITH RECURSIVE subtree(id, path, depth) AS (
+    SELECT id, path, 0 FROM directories WHERE path = ?1
+    UNION ALL
+    SELECT directories.id, directories.path, subtree.depth + 1
+    FROM directories JOIN subtree ON directories.parent_id = subtree.id
+)";
+
+/// 删除文件或者替换文件的内容前，释放 released(id) 中的文件保存的内容。
+/// 还有相同内容的其他文件共享时，内容转给其中 id 最小的文件，否则删除。
+/// released 是定义 released(id) 的 WITH 子句，执行的语句数和文件数无关
+pub fn release_contents(conn: &Connection, released: &str, params: &[&dyn ToSql]) -> Result<()> {
+    conn.execute(
+        &format!(
+            r"{released} UPDATE items SET file_id = (
+                SELECT MIN(id) FROM files
+                WHERE content_file_id = items.file_id AND id NOT IN (SELECT id FROM released)
+            )
+            WHERE file_id IN (SELECT id FROM released) AND EXISTS (
+                SELECT 1 FROM files
+                WHERE content_file_id = items.file_id AND id NOT IN (SELECT id FROM released)
+            )"
+        ),
+        params,
+    )?;
+    // 先更新新的所有者以外的文件。新的所有者本身不变，逐行更新时子查询的 MIN 结果也不变
+    let new_owner = r"SELECT MIN(shared.id) FROM files AS shared
+        WHERE shared.content_file_id = files.content_file_id AND shared.id NOT IN (SELECT id FROM released)";
+    conn.execute(
+        &format!(
+            r"{released} UPDATE files SET content_file_id = ({new_owner})
+            WHERE content_file_id IN (SELECT id FROM released) AND id NOT IN (SELECT id FROM released)
+            AND id != ({new_owner})"
+        ),
+        params,
+    )?;
+    conn.execute(
+        &format!(
+            r"{released} UPDATE files SET content_file_id = id
+            WHERE content_file_id IN (SELECT id FROM released) AND id NOT IN (SELECT id FROM released)"
+        ),
+        params,
+    )?;
+    conn.execute(
+        &format!("{released} DELETE FROM items WHERE file_id IN (SELECT id FROM released)"),
+        params,
+    )?;
+    // 文件向量由后台按新的内容所有者补算
+    conn.execute(
+        &format!("{released} DELETE FROM file_vectors WHERE file_id IN (SELECT id FROM released)"),
+        params,
+    )?;
+    Ok(())
+}
+
+/// 内容相同、扩展名相同并且保存了内容的其他文件。扩展名不同时读取的方式不同，不共享内容
+fn find_content_owner(
+    conn: &Connection,
+    hash: &str,
+    extension: &str,
+    file_id: i64,
+) -> Result<Option<i64>> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM files WHERE content_hash = ?1 AND extension = ?2 AND content_file_id = id AND id != ?3 LIMIT 1",
+            params![hash, extension, file_id],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+// 超长的查询没有意义，只会拖慢 LIKE 扫描
+const MAX_QUERY_CHARS: usize = 256;
+
+/// 所有搜索入口共用：去掉首尾空白和控制字符，限制长度，空查询报错
+pub fn sanitize_query(query: &str) -> Result<String> {
+    let query: String = query
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    // 保存的名称和内容都是 NFC，查询也要统一
+    let query: String = to_nfc(query.trim()).chars().take(MAX_QUERY_CHARS).collect();
+    if query.is_empty() {
+        return Err(anyhow!("Search query is empty"));
+    }
+    Ok(query)
+}
+
+/// 转义 LIKE 通配符，配合 `ESCAPE '\'` 使用
+fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// LIKE 子串匹配的参数
+fn like_pattern(query: &str) -> String {
+    format!("%{}%", escape_like(query))
+}
+
+/// 统一成 NFC 后逐个字符折叠大小写，NFC 的文本字符数不变。SQLite 的 LIKE 和 lower() 只处理 ASCII，
+/// 目录和文件名另存一份折叠后的 name_folded 列，内容用同名的 SQL 函数
+pub fn fold_str(text: &str) -> String {
+    to_nfc(text).chars().map(fold_case).collect()
+}
+
+/// 子串匹配条件。区分大小写时用 instr 比较原文，否则用折叠后的 folded_column 做 LIKE
+fn substring_match_sql(
+    column: &str,
+    folded_column: &str,
+    term: &str,
+    case_sensitive: bool,
+    params: &mut SqlParams,
+) -> String {
+    if case_sensitive {
+        params.push(term.to_string().into());
+        format!("instr({column}, ?) > 0")
+    } else {
+        params.push(like_pattern(&fold_str(term)).into());
+        format!(r"{folded_column} LIKE ? ESCAPE '\'")
+    }
+}
+
+/// 把用户输入转成 FTS5 的短语查询，整体作为子串匹配
+fn fts_phrase(query: &str) -> String {
+    format!("\"{}\"", query.replace('"', "\"\""))
+}
+
+/// 搜索语句解析后的语法树。相邻的词之间默认是 AND
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum QueryNode {
+    /// 不带引号的词，各个词分别匹配
+    Term(String),
+    /// 双引号括起来的短语，空白、括号和运算符都按原样连续匹配
+    Phrase(String),
+    And(Box<QueryNode>, Box<QueryNode>),
+    Or(Box<QueryNode>, Box<QueryNode>),
+    Not(Box<QueryNode>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum QueryToken {
+    Term(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// 按空白和括号切分，只有大写的 AND/OR/NOT 是运算符。
+/// 双引号内是短语，`""` 表示引号本身，缺少结尾引号时短语到查询末尾为止
+fn tokenize_query(query: &str) -> Vec<QueryToken> {
+    fn flush(word: &mut String, tokens: &mut Vec<QueryToken>) {
+        if word.is_empty() {
+            return;
+        }
+        tokens.push(match word.as_str() {
+            "AND" => QueryToken::And,
+            "OR" => QueryToken::Or,
+            "NOT" => QueryToken::Not,
+            _ => QueryToken::Term(word.clone()),
+        });
+        word.clear();
+    }
+
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                flush(&mut word, &mut tokens);
+                let mut phrase = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    phrase.push(c);
+                }
+                if !phrase.is_empty() {
+                    tokens.push(QueryToken::Phrase(phrase));
+                }
+            }
+            '(' | ')' => {
+                flush(&mut word, &mut tokens);
+                tokens.push(if c == '(' {
+                    QueryToken::LParen
+                } else {
+                    QueryToken::RParen
+                });
+            }
+            c if c.is_whitespace() => flush(&mut word, &mut tokens),
+            c => word.push(c),
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+/// 递归下降解析，优先级 NOT > AND > OR
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<QueryToken> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<QueryNode> {
+        let mut node = self.parse_and()?;
+        while self.peek() == Some(&QueryToken::Or) {
+            self.next();
+            node = QueryNode::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<QueryNode> {
+        let mut node = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(QueryToken::And) => {
+                    self.next();
+                }
+                Some(
+                    QueryToken::Term(_)
+                    | QueryToken::Phrase(_)
+                    | QueryToken::Not
+                    | QueryToken::LParen,
+                ) => {}
+                _ => break,
+            }
+            node = QueryNode::And(Box::new(node), Box::new(self.parse_unary()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryNode> {
+        if self.peek() == Some(&QueryToken::Not) {
+            self.next();
+            return Ok(QueryNode::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<QueryNode> {
+        match self.next() {
+            Some(QueryToken::Term(term)) => Ok(QueryNode::Term(term)),
+            Some(QueryToken::Phrase(phrase)) => Ok(QueryNode::Phrase(phrase)),
+            Some(QueryToken::LParen) => {
+                let node = self.parse_or()?;
+                if self.next() != Some(QueryToken::RParen) {
+                    return Err(anyhow!("Unbalanced parentheses in query"));
+                }
+                Ok(node)
+            }
+            Some(token) => Err(anyhow!("Unexpected {:?} in query", token)),
+            None => Err(anyhow!("Unexpected end of query")),
+        }
+    }
+}
+
+pub fn parse_query(query: &str) -> Result<QueryNode> {
+    let mut parser = QueryParser {
+        tokens: tokenize_query(query),
+        pos: 0,
+    };
+    let node = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        return Err(anyhow!("Unexpected {:?} in query", token));
+    }
+    Ok(node)
+}
+
+/// 正在构建的 SQL 参数，按 SQL 中 `?` 出现的顺序追加
+pub type SqlParams = Vec<rusqlite::types::Value>;
+
+impl QueryNode {
+    /// 不在 NOT 下的词，用于高亮和打分
+    pub fn positive_terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_positive_terms(&mut terms);
+        terms
+    }
+
+    fn collect_positive_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            QueryNode::Term(term) | QueryNode::Phrase(term) => terms.push(term.clone()),
+            QueryNode::And(a, b) | QueryNode::Or(a, b) => {
+                a.collect_positive_terms(terms);
+                b.collect_positive_terms(terms);
+            }
+            QueryNode::Not(_) => {}
+        }
+    }
+
+    /// 转成 FTS5 查询。FTS5 的 NOT 只能是二元的，
+    /// trigram 也匹配不了太短的词，这两种情况返回 None
+    fn to_fts(&self) -> Option<String> {
+        match self {
+            // 词本身就是按子串匹配的，trigram 下词和短语都生成 FTS 短语
+            QueryNode::Term(term) | QueryNode::Phrase(term) => {
+                (term.chars().count() >= FTS_MIN_QUERY_CHARS).then(|| fts_phrase(term))
+            }
+            QueryNode::And(a, b) => match (a.as_ref(), b.as_ref()) {
+                (QueryNode::Not(_), QueryNode::Not(_)) => None,
+                (a, QueryNode::Not(b)) | (QueryNode::Not(b), a) => {
+                    Some(format!("({} NOT {})", a.to_fts()?, b.to_fts()?))
+                }
+                (a, b) => Some(format!("({} AND {})", a.to_fts()?, b.to_fts()?)),
+            },
+            QueryNode::Or(a, b) => Some(format!("({} OR {})", a.to_fts()?, b.to_fts()?)),
+            QueryNode::Not(_) => None,
+        }
+    }
+
+    /// 转成对 column 的匹配条件，fuzzy 时按编辑距离匹配，否则按子串匹配。
+    /// folded_column 是 column 折叠大小写后的列或表达式
+    fn to_match_sql(
+        &self,
+        column: &str,
+        folded_column: &str,
+        options: MatchOptions,
+        params: &mut SqlParams,
+    ) -> String {
+        match self {
+            QueryNode::Term(term) | QueryNode::Phrase(term) => {
+                if options.fuzzy {
+                    params.push(term.clone().into());
+                    params.push(fuzzy_max_distance(term).into());
+                    format!("fuzzy_distance({column}, ?) <= ?")
+                } else {
+                    substring_match_sql(column, folded_column, term, options.case_sensitive, params)
+                }
+            }
+            QueryNode::And(a, b) => format!(
+                "({} AND {})",
+                a.to_match_sql(column, folded_column, options, params),
+                b.to_match_sql(column, folded_column, options, params)
+            ),
+            QueryNode::Or(a, b) => format!(
+                "({} OR {})",
+                a.to_match_sql(column, folded_column, options, params),
+                b.to_match_sql(column, folded_column, options, params)
+            ),
+            QueryNode::Not(node) => format!(
+                "(NOT {})",
+                node.to_match_sql(column, folded_column, options, params)
+            ),
+        }
+    }
+}
+
+// 整词命中的 bm25 权重高于子串命中
+const WORDS_SCORE_WEIGHT: f64 = 2.0;
+
+/// 查询词经 jieba 分词后在 items_words 上的 FTS 查询，任意一个词命中即可
+fn words_fts_query(terms: &[String]) -> Option<String> {
+    let mut words: Vec<String> = terms.iter().flat_map(|t| cut_for_query(t)).collect();
+    words.sort();
+    words.dedup();
+    if words.is_empty() {
+        return None;
+    }
+    Some(
+        words
+            .iter()
+            .map(|w| fts_phrase(w))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    )
+}
+
+/// 模糊匹配的排序依据：各个词的编辑距离之和，越小越接近
+fn fuzzy_distance_sql(column: &str, terms: &[String], params: &mut SqlParams) -> String {
+    if terms.is_empty() {
+        return "0".to_string();
+    }
+    terms
+        .iter()
+        .map(|term| {
+            params.push(term.clone().into());
+            format!("fuzzy_distance({column}, ?)")
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// 不能用 FTS 时的打分：各个词在 column 中出现的次数之和
+fn occurrence_score_sql(
+    column: &str,
+    terms: &[String],
+    case_sensitive: bool,
+    params: &mut SqlParams,
+) -> String {
+    if terms.is_empty() {
+        return "0.0".to_string();
+    }
+    // fold_case 不改变字符数，可以直接和原文的长度相减
+    let haystack = if case_sensitive {
+        column.to_string()
+    } else {
+        format!("fold_case({column})")
+    };
+    terms
+        .iter()
+        .map(|term| {
+            let term = if case_sensitive {
+                term.clone()
+            } else {
+                fold_str(term)
+            };
+            params.push(term.clone().into());
+            params.push(term.into());
+            format!("(length({column}) - length(replace({haystack}, ?, ''))) * 1.0 / length(?)")
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+const SNIPPET_MAX_CHARS: usize = 120;
+// 第一个命中位置之前保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+const SNIPPET_ELLIPSIS: char = '…';
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// 模糊匹配允许的编辑距离，短词要求更精确
+fn fuzzy_max_distance(query: &str) -> i64 {
+    match query.chars().count() {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+/// query 与 text 中最相近的子串之间的编辑距离，不区分大小写
+pub fn fuzzy_distance(text: &str, query: &str) -> usize {
+    let text: Vec<char> = text.chars().map(fold_case).collect();
+    let query: Vec<char> = query.chars().map(fold_case).collect();
+    // prev[j]：query 的前 i 个字符与 text 中以第 j 个字符结尾的子串的最小距离，
+    // 子串可以从任意位置开始，所以第 0 行全是 0
+    let mut prev = vec![0usize; text.len() + 1];
+    for (i, qc) in query.iter().enumerate() {
+        let mut cur = vec![i + 1; text.len() + 1];
+        for (j, tc) in text.iter().enumerate() {
+            let cost = usize::from(qc != tc);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev.into_iter().min().unwrap_or(0)
+}
+
+/// 名称和查询完全相同排最前，其次是以查询开头，内容命中和其他名称命中排在后面
+fn name_match_tier(name: &str, query: &str) -> u8 {
+    let name = fold_str(name);
+    let query = fold_str(query);
+    if name == query {
+        0
+    } else if name.starts_with(&query) {
+        1
+    } else {
+        2
+    }
+}
+
+/// 分页前的结果总数，与查询使用相同的 WHERE 条件
+fn count_rows(conn: &Connection, sql: &str, params: SqlParams) -> Result<usize> {
+    let total: i64 = conn.query_row(sql, params_from_iter(params), |row| row.get(0))?;
+    Ok(total as usize)
+}
+
+/// 连接池中每个连接初始化时注册搜索用到的 SQL 函数
+pub fn register_sql_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "fuzzy_distance",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let text = ctx.get::<String>(0)?;
+            let query = ctx.get::<String>(1)?;
+            Ok(fuzzy_distance(&text, &query) as i64)
+        },
+    )?;
+    // items_words 的触发器中使用
+    conn.create_scalar_function(
+        "jieba_segment",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let text = ctx.get::<String>(0)?;
+            Ok(segment(&text))
+        },
+    )?;
+    // files 表升级时补全扩展名
+    conn.create_scalar_function(
+        "file_extension",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let name = ctx.get::<String>(0)?;
+            Ok(file_extension(&name))
+        },
+    )?;
+    // 读取可能压缩过的 items.content
+    conn.create_scalar_function(
+        "item_text",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            decode_content(ctx.get_raw(0)).map_err(|e| rusqlite::Error::UserFunctionError(e.into()))
+        },
+    )?;
+    // 不区分大小写的内容匹配，以及升级时补全 name_folded
+    conn.create_scalar_function(
+        "fold_case",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let text = ctx.get::<String>(0)?;
+            Ok(fold_str(&text))
+        },
+    )
+}
+
+/// 查找所有不重叠的命中区间
+fn find_matches(content: &[char], query: &[char], case_sensitive: bool) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    if query.is_empty() || query.len() > content.len() {
+        return matches;
+    }
+    let mut i = 0;
+    while i + query.len() <= content.len() {
+        if content[i..i + query.len()]
+            .iter()
+            .zip(query)
+            .all(|(a, b)| a == b || !case_sensitive && fold_case(*a) == fold_case(*b))
+        {
+            matches.push((i, i + query.len()));
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+    matches
+}
+
+/// 截取第一个命中位置附近的片段，并给出片段中各个词的命中区间
+pub fn build_snippet(
+    content: &str,
+    terms: &[String],
+    case_sensitive: bool,
+) -> (String, Vec<Highlight>) {
+    let chars: Vec<char> = content.chars().collect();
+    let mut all_matches: Vec<(usize, usize)> = terms
+        .iter()
+        .flat_map(|term| find_matches(&chars, &term.chars().collect::<Vec<_>>(), case_sensitive))
+        .collect();
+    all_matches.sort();
+    // 多个词的命中区间重叠时只保留靠前的
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in all_matches {
+        if matches.last().is_none_or(|(_, last_end)| s >= *last_end) {
+            matches.push((s, e));
+        }
+    }
+
+    let mut start = matches
+        .first()
+        .map_or(0, |(s, _)| s.saturating_sub(SNIPPET_CONTEXT_CHARS));
+    let end = (start + SNIPPET_MAX_CHARS).min(chars.len());
+    start = start.min(end.saturating_sub(SNIPPET_MAX_CHARS));
+
+    let mut snippet = String::new();
+    let mut offset = start;
+    if start > 0 {
+        snippet.push(SNIPPET_ELLIPSIS);
+        offset -= 1;
+    }
+    snippet.extend(&chars[start..end]);
+    if end < chars.len() {
+        snippet.push(SNIPPET_ELLIPSIS);
+    }
+
+    let highlights = matches
+        .into_iter()
+        .filter(|(s, e)| *s >= start && *e <= end)
+        .map(|(s, e)| Highlight {
+            start: s - offset,
+            end: e - offset,
+        })
+        .collect();
+    (snippet, highlights)
+}
+
+/// 写入 changelog 的变更类型，供 sync.rs 导出给其他机器
+#[derive(Debug, PartialEq, Eq, Clone, Copy, EnumString, Display)]
+pub enum ChangeOp {
+    #[strum(to_string = "IndexFile")]
+    IndexFile,
+    #[strum(to_string = "DeleteFile")]
+    DeleteFile,
+    #[strum(to_string = "DeleteDirectory")]
+    DeleteDirectory,
+}
+
+pub struct Indexer {
+    record_changes: bool,
+}
 
 impl Indexer {
     pub fn new() -> Result<Self> {
-        Ok(Indexer {})
+        Ok(Indexer {
+            record_changes: true,
+        })
+    }
+
+    /// 应用其他机器同步过来的变更时使用，避免变更被再次导出
+    pub fn new_without_changelog() -> Result<Self> {
+        Ok(Indexer {
+            record_changes: false,
+        })
+    }
+
+    /// 配置了同步目录时才写入 changelog，没有同步时不会有人导出，写入的记录也不会被清理
+    fn changelog_enabled(&self) -> Result<bool> {
+        Ok(self.record_changes && Config::get_sync_dir()?.is_some())
+    }
+
+    fn record_change(
+        &self,
+        conn: &Connection,
+        changelog: bool,
+        op: ChangeOp,
+        path: &str,
+    ) -> Result<()> {
+        if changelog {
+            conn.execute(
+                "INSERT INTO changelog (op, path, created_at) VALUES (?1, ?2, ?3)",
+                params![op.to_string(), path, Local::now().to_rfc3339()],
+            )?;
+        }
+        Ok(())
     }
 
     fn check_is_absolute(&self, path: &Path) -> Result<()> {
@@ -58,25 +1093,63 @@ impl Indexer {
     }
 
     pub fn write_directory(&self, directory: &Path) -> Result<i64> {
+        let conn = get_conn()?;
+        self.write_directory_with(&conn, directory)
+    }
+
+    fn write_directory_with(&self, conn: &Connection, directory: &Path) -> Result<i64> {
         self.check_is_absolute(directory)?;
         let dir_name = filename_to_str(directory)?;
         let dir_path = path_to_str(directory)?;
         let modified_time = self.get_modified_time(directory)?;
 
-        let directory_id = get_conn()?.query_row(
-            "INSERT INTO directories (name, path, modified_time) VALUES (?1, ?2, ?3) ON CONFLICT(path) DO UPDATE SET modified_time = ?3 RETURNING id",
-            params![&dir_name, &dir_path, &modified_time],
-            |row| row.get(0)
-        )?;
+        let directory_id: Option<i64> = conn
+            .prepare_cached(
+                "UPDATE directories SET modified_time = ?2 WHERE path = ?1 RETURNING id",
+            )?
+            .query_row(params![&dir_path, &modified_time], |row| row.get(0))
+            .optional()?;
+        if let Some(directory_id) = directory_id {
+            return Ok(directory_id);
+        }
+        let directory_id = conn
+            .prepare_cached(
+                "INSERT INTO directories (name, path, modified_time, name_folded) VALUES (?1, ?2, ?3, ?4) RETURNING id",
+            )?
+            .query_row(
+                params![&dir_name, &dir_path, &modified_time, fold_str(dir_name)],
+                |row| row.get(0),
+            )?;
+        self.link_directory(conn, directory_id, directory)?;
         Ok(directory_id)
     }
 
+    /// 设置目录的上级目录，并接上先于它写入的子目录。
+    /// 文件可能先于所在目录的上级目录写入，上级目录没有索引时 parent_id 为空，写入上级目录时再补上
+    fn link_directory(&self, conn: &Connection, directory_id: i64, directory: &Path) -> Result<()> {
+        if let Some(parent) = directory.parent() {
+            conn.execute(
+                "UPDATE directories SET parent_id = (SELECT id FROM directories WHERE path = ?2) WHERE id = ?1",
+                params![directory_id, path_to_str(parent)?],
+            )?;
+        }
+        let dir_path = path_to_str(directory)?;
+        let (lower, upper) = subpath_range(dir_path);
+        conn.execute(
+            r"UPDATE directories SET parent_id = ?1
+            WHERE path >= ?2 AND path < ?3 AND parent_id IS NULL
+                AND substr(path, 1, length(path) - length(name) - 1) = ?4",
+            params![directory_id, lower, upper, dir_path],
+        )?;
+        Ok(())
+    }
+
     pub fn get_directory(&self, directory: &Path) -> Result<SearchResultDirectory> {
         self.check_is_absolute(directory)?;
         let dir_path = path_to_str(directory)?;
         let conn = get_conn()?;
-        let mut stmt =
-            conn.prepare("SELECT name, path, modified_time FROM directories WHERE path = ?1")?;
+        let mut stmt = conn
+            .prepare_cached("SELECT name, path, modified_time FROM directories WHERE path = ?1")?;
         let row = stmt.query_row(params![dir_path], |row| {
             Ok(SearchResultDirectory {
                 name: row.get(0)?,
@@ -92,7 +1165,7 @@ impl Indexer {
         let file_path = parent_to_str(file)?;
         let file_name = filename_to_str(file)?;
         let conn = get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             r"SELECT files.name, directories.path, files.modified_time 
             FROM files
             join directories
@@ -109,51 +1182,346 @@ impl Indexer {
         Ok(row)
     }
 
-    pub fn write_file_items(&self, file: &Path, items: Vec<Item>) -> Result<i64> {
+    /// 索引中是否有这个文件
+    pub fn has_file(&self, file: &Path) -> Result<bool> {
         self.check_is_absolute(file)?;
-        let parent_dir = file.parent().with_context(|| {
-            format!(
-                "Failed to get parent directory from file: {}",
-                file.display()
+        let conn = get_conn()?;
+        let found = conn
+            .prepare_cached(
+                r"SELECT 1 FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.path = ?1 AND files.name = ?2",
+            )?
+            .query_row(
+                params![parent_to_str(file)?, filename_to_str(file)?],
+                |_| Ok(()),
             )
-        })?;
-        let directory_id = self.write_directory(parent_dir)?;
+            .optional()?;
+        Ok(found.is_some())
+    }
 
+    pub fn get_file_items(&self, file: &Path) -> Result<Vec<Item>> {
+        self.check_is_absolute(file)?;
+        let file_path = parent_to_str(file)?;
         let file_name = filename_to_str(file)?;
-        let modified_time = self.get_modified_time(file)?;
+        let conn = get_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            r"SELECT item_text(items.content), {ITEM_POSITION_COLUMNS}
+                FROM items
+                join files on items.file_id = files.content_file_id
+                join directories on files.directory_id = directories.id
+                WHERE directories.path = ?1 and files.name = ?2
+                ORDER BY items.id"
+        ))?;
+        let items = stmt
+            .query_map(params![file_path, file_name], |row| {
+                Ok(Item {
+                    content: row.get(0)?,
+                    position: item_position(row, 1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
 
-        let mut conn = get_conn()?;
-        let tx = conn.transaction()?;
-        let file_id: i64 = tx.query_row(
-            "INSERT INTO files (directory_id, name, modified_time) VALUES (?1, ?2, ?3) ON CONFLICT(directory_id, name) DO UPDATE SET modified_time = ?3 RETURNING id",
-            params![&directory_id, file_name, &modified_time],
-            |row| row.get(0),
-        )?;
-        // println!("write_file_items File ID: {}", file_id);
+    /// 是否有相同内容的其他文件已经读取过内容，有时可以共享它的内容，不用再读取
+    pub fn has_shared_content(&self, hash: &str, file: &Path) -> Result<bool> {
+        self.check_is_absolute(file)?;
+        let file_name = filename_to_str(file)?;
+        let conn = get_conn()?;
+        let file_id: Option<i64> = conn
+            .query_row(
+                r"SELECT files.id FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.path = ?1 AND files.name = ?2",
+                params![parent_to_str(file)?, file_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(find_content_owner(
+            &conn,
+            hash,
+            &file_extension(file_name),
+            file_id.unwrap_or(0),
+        )?
+        .is_some())
+    }
+
+    /// 索引中记录的该文件内容的哈希，大小或修改时间和磁盘上的不一致时为空
+    pub fn indexed_content_hash(
+        &self,
+        file: &Path,
+        size: i64,
+        modified_time: &str,
+    ) -> Result<Option<String>> {
+        self.check_is_absolute(file)?;
+        let conn = get_conn()?;
+        let hash = conn
+            .prepare_cached(
+                r"SELECT files.content_hash FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.path = ?1 AND files.name = ?2 AND files.size = ?3 AND files.modified_time = ?4",
+            )?
+            .query_row(
+                params![parent_to_str(file)?, filename_to_str(file)?, size, modified_time],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?;
+        Ok(hash.flatten())
+    }
+
+    /// 索引中这个文件的大小和内容哈希，没有哈希时计算后补上。
+    /// 没有索引或者磁盘上的文件在索引后改过时为空
+    pub fn indexed_file_content(&self, file: &Path) -> Result<Option<(i64, String)>> {
+        self.check_is_absolute(file)?;
+        let row: Option<(i64, Option<i64>, String, Option<String>)> = get_conn()?
+            .query_row(
+                r"SELECT files.id, files.size, files.modified_time, files.content_hash FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.path = ?1 AND files.name = ?2",
+                params![parent_to_str(file)?, filename_to_str(file)?],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        let Some((id, size, modified_time, hash)) = row else {
+            return Ok(None);
+        };
+        if let (Some(size), Some(hash)) = (size, hash) {
+            return Ok(Some((size, hash)));
+        }
+        let Ok(metadata) = fs::metadata(file) else {
+            return Ok(None);
+        };
+        let disk_size = metadata.len() as i64;
+        if size.is_some_and(|size| size != disk_size)
+            || self.get_modified_time(file)? != modified_time
+        {
+            return Ok(None);
+        }
+        let hash = sha256_file(file)?;
+        self.set_content_hash(id, &hash)?;
+        Ok(Some((disk_size, hash)))
+    }
+
+    /// 大小和扩展名相同、保存了读取的内容的其他文件，最多 limit 个，只有它们可能和 file 共享内容
+    pub fn same_size_files(
+        &self,
+        file: &Path,
+        size: i64,
+        limit: usize,
+    ) -> Result<Vec<SameSizeFile>> {
+        self.check_is_absolute(file)?;
+        let file_name = filename_to_str(file)?;
+        let conn = get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            r"SELECT files.id, directories.path, files.name, files.modified_time, files.content_hash
+            FROM files
+            JOIN directories ON files.directory_id = directories.id
+            WHERE files.size = ?1 AND files.extension = ?2 AND files.content_file_id = files.id
+                AND files.skip_reason IS NULL AND NOT (directories.path = ?3 AND files.name = ?4)
+            LIMIT ?5",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                size,
+                file_extension(file_name),
+                parent_to_str(file)?,
+                file_name,
+                limit as i64
+            ],
+            |row| {
+                Ok(SameSizeFile {
+                    id: row.get(0)?,
+                    path: Path::new(&row.get::<_, String>(1)?).join(row.get::<_, String>(2)?),
+                    modified_time: row.get(3)?,
+                    content_hash: row.get(4)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// 补上之前没有计算的内容哈希
+    pub fn set_content_hash(&self, file_id: i64, hash: &str) -> Result<()> {
+        get_conn()?.execute(
+            "UPDATE files SET content_hash = ?2 WHERE id = ?1",
+            params![file_id, hash],
+        )?;
+        Ok(())
+    }
+
+    pub fn write_file_items(&self, file: &Path, items: Vec<Item>) -> Result<i64> {
+        self.write_file(file, items, None)
+    }
+
+    /// 没有读取内容的文件及原因
+    pub fn get_skipped_files(&self) -> Result<Vec<SkippedFile>> {
+        let conn = get_conn()?;
+        let mut stmt = conn.prepare(
+            r"SELECT directories.path, files.name, files.skip_reason
+            FROM files
+            JOIN directories ON files.directory_id = directories.id
+            WHERE files.skip_reason IS NOT NULL
+            ORDER BY directories.path, files.name",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SkippedFile {
+                path: row.get(0)?,
+                name: row.get(1)?,
+                reason: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    fn write_file(&self, file: &Path, items: Vec<Item>, skip_reason: Option<&str>) -> Result<i64> {
+        let file_write = self.prepare_file(file, items, skip_reason)?;
+        let file_path = path_to_str(file)?;
+        let mut conn = get_conn()?;
+        let tx = conn.transaction()?;
+        let file_id = self.write_prepared_file(&tx, &file_write)?;
+        tx.commit()?;
+        index_events::record(ChangeOp::IndexFile, file_path);
+        Ok(file_id)
+    }
+
+    /// 读取文件信息，准备在之后的事务中写入。
+    /// 会用到其他数据库连接，不能在打开写事务后调用
+    pub fn prepare_file(
+        &self,
+        file: &Path,
+        items: Vec<Item>,
+        skip_reason: Option<&str>,
+    ) -> Result<FileWrite> {
+        self.check_is_absolute(file)?;
+        let metadata = fs::metadata(file)?;
+        Ok(FileWrite {
+            file: file.to_path_buf(),
+            items: items
+                .into_iter()
+                .map(|item| Item {
+                    content: to_nfc(&item.content),
+                    ..item
+                })
+                .collect(),
+            skip_reason: skip_reason.map(str::to_string),
+            modified_time: self.get_modified_time(file)?,
+            // 部分文件系统不支持创建时间
+            created_time: metadata
+                .created()
+                .ok()
+                .map(|created| DateTime::<Local>::from(created).to_rfc3339()),
+            size: metadata.len() as i64,
+            root_id: find_root_id(file)?,
+            compress: Config::get_compress_content()?,
+            content_hash: None,
+            shared: false,
+            changelog: self.changelog_enabled()?,
+        })
+    }
+
+    /// 在调用方的事务中写入文件，替换该文件已有的内容。调用方提交后再记录给前端推送的变化
+    pub fn write_prepared_file(&self, tx: &Connection, file_write: &FileWrite) -> Result<i64> {
+        let file = file_write.file.as_path();
+        let items = &file_write.items;
+        let parent_dir = file.parent().with_context(|| {
+            format!(
+                "Failed to get parent directory from file: {}",
+                file.display()
+            )
+        })?;
+        let directory_id = self.write_directory_with(tx, parent_dir)?;
+
+        let file_name = filename_to_str(file)?;
+        let extension = file_extension(file_name);
+
+        let file_id: i64 = tx.query_row(
+            "INSERT INTO files (directory_id, name, modified_time, size, extension, name_folded, skip_reason, root_id, created_time, content_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) ON CONFLICT(directory_id, name) DO UPDATE SET modified_time = ?3, size = ?4, extension = ?5, skip_reason = ?7, root_id = ?8, created_time = ?9, content_hash = ?10 RETURNING id",
+            params![&directory_id, file_name, &file_write.modified_time, file_write.size, &extension, fold_str(file_name), &file_write.skip_reason, file_write.root_id, &file_write.created_time, &file_write.content_hash],
+            |row| row.get(0),
+        )?;
+        release_contents(tx, "WITH released(id) AS (SELECT ?1)", params![file_id])?;
+        self.record_change(
+            tx,
+            file_write.changelog,
+            ChangeOp::IndexFile,
+            path_to_str(file)?,
+        )?;
+
+        let owner = match &file_write.content_hash {
+            Some(hash) => find_content_owner(tx, hash, &extension, file_id)?,
+            None => None,
+        };
+        match owner {
+            Some(owner) if file_write.shared => {
+                tx.execute(
+                    "UPDATE files SET content_file_id = ?2 WHERE id = ?1",
+                    params![file_id, owner],
+                )?;
+                return Ok(file_id);
+            }
+            Some(owner) => {
+                // 重新读取的内容替换相同内容的文件共享的旧内容，如读取器升级后重新索引
+                tx.execute("DELETE FROM items WHERE file_id = ?1", params![owner])?;
+                tx.execute(
+                    "DELETE FROM file_vectors WHERE file_id = ?1",
+                    params![owner],
+                )?;
+                tx.execute(
+                    "UPDATE files SET content_file_id = ?2 WHERE content_file_id = ?1",
+                    params![owner, file_id],
+                )?;
+            }
+            None if file_write.shared => {
+                bail!("相同内容的文件已经不在索引中: {}", file.display());
+            }
+            None => {}
+        }
+        tx.execute(
+            "UPDATE files SET content_file_id = id WHERE id = ?1",
+            params![file_id],
+        )?;
+        // println!("write_file_items File ID: {}", file_id);
 
         for chunk in items.chunks(1000) {
-            let mut query = String::from("INSERT INTO items (file_id, content) VALUES ");
+            let mut query = String::from(
+                "INSERT INTO items (file_id, content, page, paragraph, sheet_row, line, entry, time_ms, cell, heading) VALUES ",
+            );
 
-            // 构建 VALUES 部分 (?, ?, ?, ?), (?, ?, ?, ?), ...
+            // 构建 VALUES 部分 (?, ?, ?, ?, ?, ?, ?, ?, ?, ?), (?, ?, ?, ?, ?, ?, ?, ?, ?, ?), ...
             let values: Vec<String> = (0..chunk.len())
                 .map(|i| {
-                    let base = i * 2 + 1; // 每个 item 有 2 个参数
-                    format!("(?{}, ?{})", base, base + 1)
+                    let base = i * 10 + 1; // 每个 item 有 10 个参数
+                    let placeholders: Vec<String> =
+                        (base..base + 10).map(|n| format!("?{n}")).collect();
+                    format!("({})", placeholders.join(", "))
                 })
                 .collect();
             query.push_str(&values.join(", "));
 
             // 准备所有参数
+            let contents: Vec<_> = chunk
+                .iter()
+                .map(|item| encode_content(&item.content, file_write.compress))
+                .collect();
             let mut params = Vec::new();
-            for item in chunk.iter() {
+            for (item, content) in chunk.iter().zip(&contents) {
                 params.push(&file_id as &dyn rusqlite::ToSql);
-                params.push(&item.content as &dyn rusqlite::ToSql);
+                params.push(content as &dyn rusqlite::ToSql);
+                params.push(&item.position.page as &dyn rusqlite::ToSql);
+                params.push(&item.position.paragraph as &dyn rusqlite::ToSql);
+                params.push(&item.position.sheet_row as &dyn rusqlite::ToSql);
+                params.push(&item.position.line as &dyn rusqlite::ToSql);
+                params.push(&item.position.entry as &dyn rusqlite::ToSql);
+                params.push(&item.position.time_ms as &dyn rusqlite::ToSql);
+                params.push(&item.position.cell as &dyn rusqlite::ToSql);
+                params.push(&item.position.heading as &dyn rusqlite::ToSql);
             }
 
             // 执行批量插入
             tx.execute(&query, params.as_slice())?;
         }
-        tx.commit()?;
+        write_document_vector(tx, file_id, items)?;
         Ok(file_id)
     }
 
@@ -169,21 +1537,18 @@ impl Indexer {
         let dir_path = path_to_str(directory)?;
         let conn = get_conn()?;
         let mut stmt = conn.prepare(
-            "SELECT name, path, modified_time FROM directories WHERE path LIKE ?1 AND path NOT LIKE ?2",
-        )?;
-        let rows = stmt.query_map(
-            params![
-                format!("{}{}%", dir_path, MAIN_SEPARATOR),
-                format!("{}{}%{}%", dir_path, MAIN_SEPARATOR, MAIN_SEPARATOR)
-            ],
-            |row| {
-                Ok(SearchResultDirectory {
-                    name: row.get(0)?,
-                    path: row.get(1)?,
-                    modified_time: row.get(2)?,
-                })
-            },
+            r"SELECT children.name, children.path, children.modified_time
+            FROM directories AS children
+            JOIN directories ON children.parent_id = directories.id
+            WHERE directories.path = ?1",
         )?;
+        let rows = stmt.query_map(params![dir_path], |row| {
+            Ok(SearchResultDirectory {
+                name: row.get(0)?,
+                path: row.get(1)?,
+                modified_time: row.get(2)?,
+            })
+        })?;
 
         for row in rows {
             dirs.push(row?);
@@ -216,15 +1581,63 @@ impl Indexer {
         content: &str,
         offset: usize,
         limit: usize,
-    ) -> Result<Vec<SearchResultDirectory>> {
-        let mut result = Vec::new();
+        options: MatchOptions,
+        sort: SearchSort,
+        filter: &SearchFilter,
+    ) -> Result<SearchPage<SearchResultDirectory>> {
+        let content = sanitize_query(content)?;
+        if filter.has_file_conditions() {
+            return Ok(SearchPage::new(Vec::new(), 0, offset, limit));
+        }
         let conn = get_conn()?;
 
-        let sql = format!(
-            "SELECT name, path, modified_time FROM directories WHERE name LIKE '%{content}%' ORDER BY id LIMIT {limit} OFFSET {offset}"
-        );
-        let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map([], |row| {
+        let mut where_params = SqlParams::new();
+        let mut order_params = SqlParams::new();
+        let (matcher, relevance) = if options.fuzzy {
+            where_params.push(content.clone().into());
+            where_params.push(fuzzy_max_distance(&content).into());
+            if sort.by == SortBy::Relevance {
+                order_params.push(content.clone().into());
+            }
+            (
+                "fuzzy_distance(name, ?) <= ?".to_string(),
+                "fuzzy_distance(name, ?)",
+            )
+        } else {
+            let matcher = substring_match_sql(
+                "name",
+                "name_folded",
+                &content,
+                options.case_sensitive,
+                &mut where_params,
+            );
+            (matcher, "id")
+        };
+        let order = sort.to_order_sql(&SortColumns {
+            relevance: (relevance, SortOrder::Asc),
+            name: "name",
+            modified: "julianday(modified_time)",
+            path: "path",
+            id: "id",
+        });
+        let filter = filter.to_sql("path", "modified_time", &mut where_params)?;
+        let condition = format!("{matcher} AND {filter}");
+
+        let total = count_rows(
+            &conn,
+            &format!("SELECT COUNT(*) FROM directories WHERE {condition}"),
+            where_params.clone(),
+        )?;
+
+        let mut params = where_params;
+        params.extend(order_params);
+        params.push((limit as i64).into());
+        params.push((offset as i64).into());
+        let mut stmt = conn.prepare(&format!(
+            r"SELECT name, path, modified_time FROM directories
+            WHERE {condition} ORDER BY {order} LIMIT ? OFFSET ?"
+        ))?;
+        let rows = stmt.query_map(params_from_iter(params), |row| {
             Ok(SearchResultDirectory {
                 name: row.get(0)?,
                 path: row.get(1)?,
@@ -232,10 +1645,11 @@ impl Indexer {
             })
         })?;
 
+        let mut result = Vec::new();
         for row in rows {
             result.push(row.context("Failed to map row to SearchResultDirectory")?);
         }
-        Ok(result)
+        Ok(SearchPage::new(result, total, offset, limit))
     }
 
     pub fn search_file(
@@ -243,19 +1657,54 @@ impl Indexer {
         content: &str,
         offset: usize,
         limit: usize,
-    ) -> Result<Vec<SearchResultFile>> {
-        let mut result = Vec::new();
+        options: MatchOptions,
+        sort: SearchSort,
+        filter: &SearchFilter,
+    ) -> Result<SearchPage<SearchResultFile>> {
+        let content = sanitize_query(content)?;
+        let node = parse_query(&content)?;
         let conn = get_conn()?;
 
-        let sql = format!(
-            r"SELECT files.name, directories.path, files.modified_time
-            FROM files
-            left outer join directories
-            on files.directory_id = directories.id
-            WHERE files.name LIKE '%{content}%' ORDER BY files.id LIMIT {limit} OFFSET {offset}"
+        let mut where_params = SqlParams::new();
+        let matcher = node.to_match_sql(
+            "files.name",
+            "files.name_folded",
+            options,
+            &mut where_params,
         );
-        let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map([], |row| {
+        let filter = filter.to_sql("directories.path", "files.modified_time", &mut where_params)?;
+        let condition = format!("{matcher} AND {filter}");
+        let source = r"files
+            left outer join directories
+            on files.directory_id = directories.id";
+
+        let total = count_rows(
+            &conn,
+            &format!("SELECT COUNT(*) FROM {source} WHERE {condition}"),
+            where_params.clone(),
+        )?;
+
+        let mut params = where_params;
+        let relevance = if options.fuzzy && sort.by == SortBy::Relevance {
+            fuzzy_distance_sql("files.name", &node.positive_terms(), &mut params)
+        } else {
+            "files.id".to_string()
+        };
+        let order = sort.to_order_sql(&SortColumns {
+            relevance: (&relevance, SortOrder::Asc),
+            name: "files.name",
+            modified: "julianday(files.modified_time)",
+            path: "directories.path",
+            id: "files.id",
+        });
+        params.push((limit as i64).into());
+        params.push((offset as i64).into());
+        let mut stmt = conn.prepare(&format!(
+            r"SELECT files.name, directories.path, files.modified_time
+            FROM {source}
+            WHERE {condition} ORDER BY {order} LIMIT ? OFFSET ?"
+        ))?;
+        let rows = stmt.query_map(params_from_iter(params), |row| {
             Ok(SearchResultFile {
                 name: row.get(0)?,
                 path: row.get(1)?,
@@ -263,10 +1712,11 @@ impl Indexer {
             })
         })?;
 
+        let mut result = Vec::new();
         for row in rows {
             result.push(row.context("Failed to map row to SearchResultFile")?);
         }
-        Ok(result)
+        Ok(SearchPage::new(result, total, offset, limit))
     }
 
     pub fn search_item(
@@ -274,42 +1724,187 @@ impl Indexer {
         content: &str,
         offset: usize,
         limit: usize,
-    ) -> Result<Vec<SearchResultItem>> {
-        let mut result = Vec::new();
+        case_sensitive: bool,
+        sort: SearchSort,
+        filter: &SearchFilter,
+    ) -> Result<SearchPage<SearchResultItem>> {
+        let content = sanitize_query(content)?;
+        let node = parse_query(&content)?;
+        let terms = node.positive_terms();
         let conn = get_conn()?;
 
+        // 参数按在 SQL 中出现的位置分开收集，最后按顺序拼接
+        let mut score_params = SqlParams::new();
+        let mut where_params = SqlParams::new();
+        let options = MatchOptions {
+            fuzzy: false,
+            case_sensitive,
+        };
+        let (score, source, matcher) = if let Some(fts) = node.to_fts() {
+            // bm25 越小越相关，取负数让 score 越大越相关
+            where_params.push(fts.into());
+            // trigram 分词不区分大小写，区分时再用原文过滤一次
+            let matcher = if case_sensitive {
+                format!(
+                    "items_fts MATCH ? AND {}",
+                    node.to_match_sql(
+                        "item_text(items.content)",
+                        "fold_case(item_text(items.content))",
+                        options,
+                        &mut where_params
+                    )
+                )
+            } else {
+                "items_fts MATCH ?".to_string()
+            };
+            (
+                "-bm25(items_fts)".to_string(),
+                "items_fts JOIN items ON items.id = items_fts.rowid",
+                matcher,
+            )
+        } else {
+            // 有太短的词或者单独的 NOT，只能扫描，按出现次数打分
+            let score = occurrence_score_sql(
+                "item_text(items.content)",
+                &terms,
+                case_sensitive,
+                &mut score_params,
+            );
+            let matcher = node.to_match_sql(
+                "item_text(items.content)",
+                "fold_case(item_text(items.content))",
+                options,
+                &mut where_params,
+            );
+            (score, "items", matcher)
+        };
+        let filter = filter.to_sql("directories.path", "files.modified_time", &mut where_params)?;
+        let condition = format!("{matcher} AND {filter}");
+        // 相同内容的文件共享同一份内容，每个文件各占一条结果
+        let joins = r"JOIN files ON files.content_file_id = items.file_id
+            LEFT OUTER JOIN directories ON files.directory_id = directories.id";
+
+        let total = count_rows(
+            &conn,
+            &format!("SELECT COUNT(*) FROM {source} {joins} WHERE {condition}"),
+            where_params.clone(),
+        )?;
+
+        // 命中范围仍由子串匹配决定，按 jieba 分词后的词再算一次 bm25，
+        // 整词命中的排在只是子串命中的前面
+        let mut words_params = SqlParams::new();
+        let (words_score, words_join) = match words_fts_query(&terms) {
+            Some(words) => {
+                words_params.push(words.into());
+                (
+                    format!("coalesce(words.score * {WORDS_SCORE_WEIGHT}, 0)"),
+                    r"LEFT OUTER JOIN (
+                    SELECT rowid, -bm25(items_words) AS score FROM items_words WHERE items_words MATCH ?
+                ) AS words ON words.rowid = items.id",
+                )
+            }
+            None => ("0".to_string(), ""),
+        };
+
+        let mut params = score_params;
+        params.extend(words_params);
+        params.extend(where_params);
+        params.push((limit as i64).into());
+        params.push((offset as i64).into());
+        let order = sort.to_order_sql(&SortColumns {
+            relevance: ("score", SortOrder::Desc),
+            name: "files.name",
+            modified: "julianday(files.modified_time)",
+            path: "directories.path",
+            id: "items.id",
+        });
         let sql = format!(
-            r"SELECT items.content, files.name, directories.path
-            FROM items
-            LEFT OUTER JOIN files ON items.file_id = files.id
-            LEFT OUTER JOIN directories ON files.directory_id = directories.id
-            WHERE items.content LIKE '%{content}%' ORDER BY items.id LIMIT {limit} OFFSET {offset}"
+            r"SELECT item_text(items.content), files.name, directories.path,
+            ({score}) + {words_score} AS score, {ITEM_POSITION_COLUMNS}
+            FROM {source}
+            {words_join}
+            {joins}
+            WHERE {condition} ORDER BY {order} LIMIT ? OFFSET ?"
         );
         let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map([], |row| {
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            let item_content: String = row.get(0)?;
+            let (snippet, highlights) = build_snippet(&item_content, &terms, case_sensitive);
             Ok(SearchResultItem {
-                content: row.get(0)?,
+                content: item_content,
                 file: row.get(1)?,
                 path: row.get(2)?,
+                snippet,
+                highlights,
+                score: row.get(3)?,
+                position: item_position(row, 4)?,
             })
         })?;
 
+        let mut result = Vec::new();
         for row in rows {
             result.push(row.context("Failed to map row to SearchResultItem")?);
         }
-        Ok(result)
+        Ok(SearchPage::new(result, total, offset, limit))
+    }
+
+    /// 同时搜索目录、文件和内容，每类取前 limit 条合并。
+    /// 各类的分数不能直接比较，先按名称匹配程度分档，同一档内按各自的名次交替排列
+    pub fn search_all(
+        &self,
+        content: &str,
+        limit: usize,
+        options: MatchOptions,
+        sort: SearchSort,
+        filter: &SearchFilter,
+    ) -> Result<SearchAllResult> {
+        let query = sanitize_query(content)?;
+        let directories = self.search_directory(&query, 0, limit, options, sort, filter)?;
+        let files = self.search_file(&query, 0, limit, options, sort, filter)?;
+        let items = self.search_item(&query, 0, limit, options.case_sensitive, sort, filter)?;
+
+        let mut ranked: Vec<((u8, usize, u8), SearchHit)> = Vec::new();
+        for (rank, directory) in directories.items.into_iter().enumerate() {
+            let tier = name_match_tier(&directory.name, &query);
+            ranked.push(((tier, rank, 0), SearchHit::Directory(directory)));
+        }
+        for (rank, file) in files.items.into_iter().enumerate() {
+            let tier = name_match_tier(&file.name, &query);
+            ranked.push(((tier, rank, 1), SearchHit::File(file)));
+        }
+        for (rank, item) in items.items.into_iter().enumerate() {
+            ranked.push(((2, rank, 2), SearchHit::Item(Box::new(item))));
+        }
+        // 按相关度以外的字段排序时保留各自的顺序，只按名次交替
+        if sort.by != SortBy::Relevance {
+            for ((tier, _, _), _) in ranked.iter_mut() {
+                *tier = 0;
+            }
+        }
+        ranked.sort_by_key(|(key, _)| *key);
+
+        Ok(SearchAllResult {
+            hits: ranked.into_iter().map(|(_, hit)| hit).collect(),
+            directory_total: directories.total,
+            file_total: files.total,
+            item_total: items.total,
+        })
     }
 
     pub fn delete_file(&self, file: &Path) -> Result<()> {
         self.check_is_absolute(file)?;
         let file_name = filename_to_str(file)?;
         let directory_path = parent_to_str(file)?;
+        let file_path = path_to_str(file)?;
+        let changelog = self.changelog_enabled()?;
         let mut conn = get_conn()?;
         let tx = conn.transaction()?;
 
-        tx.execute(
-            r"DELETE FROM items WHERE file_id in 
-            (SELECT id FROM files WHERE name = ?1 and directory_id in (SELECT id FROM directories WHERE path = ?2))",
+        release_contents(
+            &tx,
+            r"WITH released(id) AS (
+                SELECT id FROM files WHERE name = ?1 and directory_id in (SELECT id FROM directories WHERE path = ?2)
+            )",
             params![&file_name, &directory_path],
         )?;
 
@@ -318,233 +1913,1920 @@ impl Indexer {
             and directory_id in (SELECT id FROM directories WHERE path = ?2)",
             params![&file_name, &directory_path],
         )?;
+        self.record_change(&tx, changelog, ChangeOp::DeleteFile, file_path)?;
         tx.commit()?;
+        index_events::record(ChangeOp::DeleteFile, file_path);
 
         Ok(())
     }
 
-    pub fn delete_directory(&self, directory: &Path) -> Result<()> {
-        self.check_is_absolute(directory)?;
+    /// 在一个事务中删除目录和各级子目录中的文件，子目录按 parent_id 逐级查找，
+    /// 执行的语句数和目录大小无关
+    pub fn delete_directory(&self, directory: &Path) -> Result<()> {
+        self.check_is_absolute(directory)?;
+        let dir_path = path_to_str(directory)?;
+        let changelog = self.changelog_enabled()?;
+        let mut conn = get_conn()?;
+        let tx = conn.transaction()?;
+        let deleted = self.delete_directory_with(&tx, dir_path, changelog)?;
+        tx.commit()?;
+        // 推送给前端时删除目录包括其中的文件
+        index_events::record(ChangeOp::DeleteDirectory, dir_path);
+
+        info!("删除目录记录: {}, 目录数: {deleted}", directory.display());
+        Ok(())
+    }
+
+    /// 在调用方的事务中删除目录和各级子目录中的文件，返回删除的目录数
+    fn delete_directory_with(
+        &self,
+        tx: &Connection,
+        dir_path: &str,
+        changelog: bool,
+    ) -> Result<usize> {
+        // 变化记录同样用一条语句写入，语句数和目录大小无关。先记录文件，再从下往上记录目录
+        if changelog {
+            let created_at = Local::now().to_rfc3339();
+            tx.execute(
+                &format!(
+                    r"{SUBTREE} INSERT INTO changelog (op, path, created_at)
+                    SELECT ?2, subtree.path || ?3 || files.name, ?4
+                    FROM files JOIN subtree ON files.directory_id = subtree.id"
+                ),
+                params![
+                    dir_path,
+                    ChangeOp::DeleteFile.to_string(),
+                    MAIN_SEPARATOR.to_string(),
+                    created_at
+                ],
+            )?;
+            let dirs = tx.execute(
+                &format!(
+                    r"{SUBTREE} INSERT INTO changelog (op, path, created_at)
+                    SELECT ?2, path, ?3 FROM subtree ORDER BY depth DESC"
+                ),
+                params![dir_path, ChangeOp::DeleteDirectory.to_string(), created_at],
+            )?;
+            if dirs == 0 {
+                // 没有索引的目录也记录删除，同步到其他设备时一样处理
+                self.record_change(tx, changelog, ChangeOp::DeleteDirectory, dir_path)?;
+            }
+        }
+        release_contents(
+            tx,
+            &format!("{SUBTREE}, released(id) AS (SELECT files.id FROM files JOIN subtree ON files.directory_id = subtree.id)"),
+            params![dir_path],
+        )?;
+        tx.execute(
+            &format!("{SUBTREE} DELETE FROM files WHERE directory_id IN (SELECT id FROM subtree)"),
+            params![dir_path],
+        )?;
+        let deleted = tx.execute(
+            &format!("{SUBTREE} DELETE FROM directories WHERE id IN (SELECT id FROM subtree)"),
+            params![dir_path],
+        )?;
+        Ok(deleted)
+    }
+
+    /// 目录下（包括各级子目录）已经索引的目录和文件
+    pub fn get_paths_under(&self, directory: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        self.check_is_absolute(directory)?;
+        let dir_path = path_to_str(directory)?;
+        let (lower, upper) = subpath_range(dir_path);
+        let conn = get_conn()?;
+        let mut stmt =
+            conn.prepare("SELECT path FROM directories WHERE path >= ?1 AND path < ?2")?;
+        let dirs = stmt
+            .query_map(params![lower, upper], |row| {
+                Ok(PathBuf::from(row.get::<_, String>(0)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut stmt = conn.prepare(
+            r"SELECT directories.path, files.name FROM files
+            JOIN directories ON files.directory_id = directories.id
+            WHERE directories.path = ?1 OR (directories.path >= ?2 AND directories.path < ?3)",
+        )?;
+        let files = stmt
+            .query_map(params![dir_path, lower, upper], |row| {
+                Ok(Path::new(&row.get::<_, String>(0)?).join(row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((dirs, files))
+    }
+
+    /// 文件重命名或移动后只更新路径，保留已经读取的内容。原路径没有索引时返回 false
+    pub fn move_file(&self, from: &Path, to: &Path) -> Result<bool> {
+        self.check_is_absolute(from)?;
+        self.check_is_absolute(to)?;
+        let from_name = filename_to_str(from)?;
+        let from_dir = parent_to_str(from)?;
+        let from_path = path_to_str(from)?;
+        let to_path = path_to_str(to)?;
+        let to_name = filename_to_str(to)?;
+        let to_dir = to.parent().with_context(|| {
+            format!("Failed to get parent directory from file: {}", to.display())
+        })?;
+        let root_id = find_root_id(to)?;
+        let changelog = self.changelog_enabled()?;
+        let mut conn = get_conn()?;
+        let tx = conn.transaction()?;
+
+        let file_id: Option<i64> = tx
+            .query_row(
+                r"SELECT files.id FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.path = ?1 AND files.name = ?2",
+                params![from_dir, from_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(file_id) = file_id else {
+            return Ok(false);
+        };
+        let directory_id = self.write_directory_with(&tx, to_dir)?;
+        // 目标位置原来的文件被覆盖
+        release_contents(
+            &tx,
+            "WITH released(id) AS (SELECT id FROM files WHERE directory_id = ?1 AND name = ?2 AND id != ?3)",
+            params![directory_id, to_name, file_id],
+        )?;
+        tx.execute(
+            "DELETE FROM files WHERE directory_id = ?1 AND name = ?2 AND id != ?3",
+            params![directory_id, to_name, file_id],
+        )?;
+        tx.execute(
+            "UPDATE files SET directory_id = ?1, name = ?2, name_folded = ?3, extension = ?4, root_id = ?5 WHERE id = ?6",
+            params![directory_id, to_name, fold_str(to_name), file_extension(to_name), root_id, file_id],
+        )?;
+        self.record_change(&tx, changelog, ChangeOp::DeleteFile, from_path)?;
+        self.record_change(&tx, changelog, ChangeOp::IndexFile, to_path)?;
+        tx.commit()?;
+        index_events::record(ChangeOp::DeleteFile, from_path);
+        index_events::record(ChangeOp::IndexFile, to_path);
+        Ok(true)
+    }
+
+    /// 目录重命名或移动后更新目录和所有子目录的路径，文件的内容不用重新读取。
+    /// 原路径没有索引时返回 false
+    pub fn move_directory(&self, from: &Path, to: &Path) -> Result<bool> {
+        self.check_is_absolute(from)?;
+        self.check_is_absolute(to)?;
+        let from_path = path_to_str(from)?;
+        let to_path = path_to_str(to)?;
+        let to_name = filename_to_str(to)?;
+        let root_id = find_root_id(to)?;
+        let (from_lower, from_upper) = subpath_range(from_path);
+        let (to_lower, to_upper) = subpath_range(to_path);
+        let changelog = self.changelog_enabled()?;
+        let mut conn = get_conn()?;
+        let tx = conn.transaction()?;
+        let indexed = |path: &str| -> rusqlite::Result<bool> {
+            tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM directories WHERE path = ?1)",
+                params![path],
+                |row| row.get(0),
+            )
+        };
+        if !indexed(from_path)? {
+            return Ok(false);
+        }
+        // 目标位置原来的目录被覆盖，和移动在同一个事务中删除
+        let overwritten = indexed(to_path)?;
+        if overwritten {
+            self.delete_directory_with(&tx, to_path, changelog)?;
+        }
+
+        // 按范围比较前缀，不用 LIKE，路径中的 % 和 _ 不需要转义，可以使用 path 的索引
+        tx.execute(
+            "UPDATE directories SET path = ?3 || substr(path, length(?1) + 1) WHERE path >= ?1 AND path < ?2",
+            params![from_lower, from_upper, to_lower],
+        )?;
+        let directory_id: i64 = tx.query_row(
+            "UPDATE directories SET path = ?2, name = ?3, name_folded = ?4 WHERE path = ?1 RETURNING id",
+            params![from_path, to_path, to_name, fold_str(to_name)],
+            |row| row.get(0),
+        )?;
+        // 子目录的 parent_id 不变，只需要更新移动的目录
+        self.link_directory(&tx, directory_id, to)?;
+        let moved_dirs = "SELECT id FROM directories WHERE path = ?1 OR (path >= ?2 AND path < ?3)";
+        tx.execute(
+            &format!("UPDATE files SET root_id = ?4 WHERE directory_id IN ({moved_dirs})"),
+            params![to_path, to_lower, to_upper, root_id],
+        )?;
+        let files = {
+            let mut stmt = tx.prepare(&format!(
+                r"SELECT directories.path, files.name FROM files
+                JOIN directories ON files.directory_id = directories.id
+                WHERE directories.id IN ({moved_dirs})"
+            ))?;
+            let rows = stmt.query_map(params![to_path, to_lower, to_upper], |row| {
+                Ok(Path::new(&row.get::<_, String>(0)?).join(row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let file_paths = files
+            .iter()
+            .map(|file| path_to_str(file))
+            .collect::<Result<Vec<_>>>()?;
+        self.record_change(&tx, changelog, ChangeOp::DeleteDirectory, from_path)?;
+        for file_path in &file_paths {
+            self.record_change(&tx, changelog, ChangeOp::IndexFile, file_path)?;
+        }
+        tx.commit()?;
+        // 提交后才记录给前端推送的变化
+        if overwritten {
+            index_events::record(ChangeOp::DeleteDirectory, to_path);
+        }
+        index_events::record(ChangeOp::DeleteDirectory, from_path);
+        for file_path in file_paths {
+            index_events::record(ChangeOp::IndexFile, file_path);
+        }
+        info!(
+            "移动目录: {} -> {}, 文件数: {}",
+            from.display(),
+            to.display(),
+            files.len()
+        );
+        Ok(true)
+    }
+
+    pub fn get_index_status(&self) -> Result<IndexStatusStat> {
+        let conn = get_conn()?;
+        let total_directories: i64 =
+            conn.query_one("SELECT COUNT(*) FROM directories", [], |row| row.get(0))?;
+        let total_files: i64 =
+            conn.query_one("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
+        let indexed_files: i64 =
+            conn.query_one("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
+        Ok(IndexStatusStat {
+            directories: total_directories as usize,
+            files: total_files as usize,
+            items: indexed_files as usize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+    use std::time::{Duration, SystemTime};
+
+    const TEST_DATA_DIR: &str = "../test_data/indexer";
+    const FUZZY: MatchOptions = MatchOptions {
+        fuzzy: true,
+        case_sensitive: false,
+    };
+
+    #[test]
+    fn test_get_index() {
+        let _env = TestEnv::new();
+        let _ = Indexer::new().unwrap();
+    }
+
+    #[test]
+    fn test_write_directory() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let path = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        indexer.write_directory(&path).unwrap();
+    }
+
+    #[test]
+    fn test_get_directory() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let path = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        indexer.write_directory(&path).unwrap();
+
+        let dir = indexer.get_directory(&path).unwrap();
+        assert_eq!(dir.name, "indexer");
+        assert_eq!(dir.path, path.canonicalize().unwrap().to_str().unwrap());
+    }
+
+    #[test]
+    fn test_write_file_items() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(file.parent().unwrap()).unwrap();
+
+        let items = vec![
+            Item {
+                content: "Hello, world!".into(),
+                ..Default::default()
+            },
+            Item {
+                content: "This is a test.".into(),
+                ..Default::default()
+            },
+        ];
+        indexer.write_file_items(&file, items).unwrap();
+    }
+
+    #[test]
+    fn test_move_file_and_directory() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let old_dir = env.temp_dir.path().join("old");
+        fs::create_dir_all(old_dir.join("sub")).unwrap();
+        fs::write(old_dir.join("a.txt"), "a").unwrap();
+        fs::write(old_dir.join("sub").join("b.txt"), "b").unwrap();
+        let item = |content: &str| Item {
+            content: content.into(),
+            ..Default::default()
+        };
+        indexer
+            .write_file_items(&old_dir.join("a.txt"), vec![item("内容 a")])
+            .unwrap();
+        indexer
+            .write_file_items(&old_dir.join("sub").join("b.txt"), vec![item("内容 b")])
+            .unwrap();
+
+        // 目录移动后子目录和文件都在新路径下，内容不变
+        let new_dir = env.temp_dir.path().join("new");
+        fs::rename(&old_dir, &new_dir).unwrap();
+        assert!(indexer.move_directory(&old_dir, &new_dir).unwrap());
+        assert!(indexer.get_directory(&old_dir).is_err());
+        assert!(indexer
+            .get_file(&old_dir.join("sub").join("b.txt"))
+            .is_err());
+        assert_eq!(indexer.get_directory(&new_dir).unwrap().name, "new");
+        let items = indexer
+            .get_file_items(&new_dir.join("sub").join("b.txt"))
+            .unwrap();
+        assert_eq!(items[0].content, "内容 b");
+
+        // 文件重命名
+        fs::rename(new_dir.join("a.txt"), new_dir.join("c.txt")).unwrap();
+        assert!(indexer
+            .move_file(&new_dir.join("a.txt"), &new_dir.join("c.txt"))
+            .unwrap());
+        assert!(indexer.get_file(&new_dir.join("a.txt")).is_err());
+        let items = indexer.get_file_items(&new_dir.join("c.txt")).unwrap();
+        assert_eq!(items[0].content, "内容 a");
+
+        // 原路径没有索引时不处理
+        assert!(!indexer
+            .move_file(&new_dir.join("a.txt"), &new_dir.join("d.txt"))
+            .unwrap());
+        assert!(!indexer.move_directory(&old_dir, &new_dir).unwrap());
+
+        // 目标位置已经索引的目录被覆盖
+        let other_dir = env.temp_dir.path().join("other");
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::write(other_dir.join("x.txt"), "x").unwrap();
+        indexer
+            .write_file_items(&other_dir.join("x.txt"), vec![item("内容 x")])
+            .unwrap();
+        fs::remove_dir_all(&other_dir).unwrap();
+        fs::rename(&new_dir, &other_dir).unwrap();
+        assert!(indexer.move_directory(&new_dir, &other_dir).unwrap());
+        assert!(indexer.get_file(&other_dir.join("x.txt")).is_err());
+        let items = indexer
+            .get_file_items(&other_dir.join("sub").join("b.txt"))
+            .unwrap();
+        assert_eq!(items[0].content, "内容 b");
+        let (dirs, files) = indexer.get_paths_under(&other_dir).unwrap();
+        assert_eq!(dirs, vec![other_dir.join("sub")]);
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn test_write_skipped_file() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        // 没有读取内容的文件只索引文件名，并记录原因，与其他文件在同一个事务中写入
+        let other = file.parent().unwrap().join("office").join("test.docx");
+        let batch = vec![
+            indexer
+                .prepare_file(&file, Vec::new(), Some("文件大小 2 MB 超过上限 1 MB"))
+                .unwrap(),
+            indexer.prepare_file(&other, Vec::new(), None).unwrap(),
+        ];
+        let mut conn = get_conn().unwrap();
+        let tx = conn.transaction().unwrap();
+        for file_write in &batch {
+            indexer.write_prepared_file(&tx, file_write).unwrap();
+        }
+        tx.commit().unwrap();
+        assert!(indexer.get_file(&other).is_ok());
+        assert_eq!(
+            indexer.get_skipped_files().unwrap(),
+            vec![SkippedFile {
+                name: "1.txt".to_string(),
+                path: file.parent().unwrap().to_str().unwrap().to_string(),
+                reason: "文件大小 2 MB 超过上限 1 MB".to_string(),
+            }]
+        );
+
+        // 重新读取内容后不再是跳过的文件
+        indexer.write_file_items(&file, Vec::new()).unwrap();
+        assert!(indexer.get_skipped_files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_file() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(file.parent().unwrap()).unwrap();
+
+        let items = vec![
+            Item {
+                content: "Hello, world!".into(),
+                ..Default::default()
+            },
+            Item {
+                content: "This is a test.".into(),
+                ..Default::default()
+            },
+        ];
+        indexer.write_file_items(&file, items).unwrap();
+
+        let file_result = indexer.get_file(&file).unwrap();
+        assert_eq!(file_result.name, "1.txt");
+        assert_eq!(file_result.path, file.parent().unwrap().to_str().unwrap());
+    }
+
+    #[test]
+    fn test_get_sub_directories_and_files() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(file.parent().unwrap()).unwrap();
+
+        let items = vec![
+            Item {
+                content: "Hello, world!".into(),
+                ..Default::default()
+            },
+            Item {
+                content: "This is a test.".into(),
+                ..Default::default()
+            },
+        ];
+        indexer.write_file_items(&file, items).unwrap();
+
+        let sub_dir_path = Path::new(TEST_DATA_DIR)
+            .join("office")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(&sub_dir_path).unwrap();
+
+        let (dir_result, file_result) = indexer
+            .get_sub_directories_and_files(file.parent().unwrap())
+            .unwrap();
+        assert_eq!(dir_result.len(), 1);
+        assert_eq!(file_result.len(), 1);
+    }
+
+    #[test]
+    fn test_search_directory() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
+        indexer.write_directory(&dir).unwrap();
+
+        let result = indexer
+            .search_directory(
+                "indexer",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "indexer");
+
+        let result = indexer
+            .search_directory(
+                "indexer",
+                1,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    fn test_search_file() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let items = vec![
+            Item {
+                content: "Hello, world!".into(),
+                ..Default::default()
+            },
+            Item {
+                content: "This is a test.".into(),
+                ..Default::default()
+            },
+        ];
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(file.parent().unwrap()).unwrap();
+        indexer.write_file_items(&file, items).unwrap();
+
+        let result = indexer
+            .search_file(
+                "1.t",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "1.txt");
+        assert_eq!(result[0].path, file.parent().unwrap().to_str().unwrap());
+
+        let result = indexer
+            .search_file(
+                "1.t",
+                1,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    fn test_search_item() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let items = vec![
+            Item {
+                content: "Hello, world!".into(),
+                position: ItemPosition {
+                    line: Some(1),
+                    ..Default::default()
+                },
+            },
+            Item {
+                content: "This is a test.".into(),
+                position: ItemPosition {
+                    page: Some(3),
+                    paragraph: Some(2),
+                    ..Default::default()
+                },
+            },
+        ];
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_directory(file.parent().unwrap()).unwrap();
+        indexer.write_file_items(&file, items).unwrap();
+
+        let result = indexer
+            .search_item(
+                "world",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].content, "Hello, world!");
+        assert_eq!(result[0].file, "1.txt");
+        assert_eq!(result[0].path, file.parent().unwrap().to_str().unwrap());
+        assert_eq!(result[0].position.line, Some(1));
+
+        let stored = indexer.get_file_items(&file).unwrap();
+        assert_eq!(stored[1].position.page, Some(3));
+        assert_eq!(stored[1].position.paragraph, Some(2));
+    }
+
+    #[test]
+    fn test_build_snippet() {
+        let (snippet, highlights) =
+            build_snippet("Hello, World! hello", &["hello".to_string()], false);
+        assert_eq!(snippet, "Hello, World! hello");
+        assert_eq!(
+            highlights,
+            vec![
+                Highlight { start: 0, end: 5 },
+                Highlight { start: 14, end: 19 }
+            ]
+        );
+
+        let content = format!("{}鸭子索引{}", "前".repeat(100), "后".repeat(100));
+        let (snippet, highlights) = build_snippet(&content, &["鸭子".to_string()], false);
+        assert!(snippet.starts_with(SNIPPET_ELLIPSIS));
+        assert!(snippet.ends_with(SNIPPET_ELLIPSIS));
+        assert_eq!(snippet.chars().count(), SNIPPET_MAX_CHARS + 2);
+        let start = SNIPPET_CONTEXT_CHARS + 1;
+        assert_eq!(
+            highlights,
+            vec![Highlight {
+                start,
+                end: start + 2
+            }]
+        );
+        let chars: Vec<char> = snippet.chars().collect();
+        assert_eq!(chars[start..start + 2].iter().collect::<String>(), "鸭子");
+
+        // 命中在末尾时片段向前取满
+        let content = format!("{}end", "x".repeat(200));
+        let (snippet, highlights) = build_snippet(&content, &["end".to_string()], false);
+        assert!(snippet.ends_with("end"));
+        assert_eq!(snippet.chars().count(), SNIPPET_MAX_CHARS + 1);
+        assert_eq!(
+            highlights,
+            vec![Highlight {
+                start: SNIPPET_MAX_CHARS - 2,
+                end: SNIPPET_MAX_CHARS + 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_search_page() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let items = (0..3)
+            .map(|i| Item {
+                content: format!("page {i}"),
+                ..Default::default()
+            })
+            .collect();
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_file_items(&file, items).unwrap();
+
+        let page = indexer
+            .search_item(
+                "page",
+                0,
+                2,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.total, 3);
+        assert_eq!((page.offset, page.limit), (0, 2));
+        assert!(page.has_more);
+
+        let page = indexer
+            .search_item(
+                "page",
+                2,
+                2,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.total, 3);
+        assert!(!page.has_more);
+
+        let page = indexer
+            .search_file(
+                "1.txt",
+                5,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.total, 1);
+        assert!(!page.has_more);
+    }
+
+    #[test]
+    fn test_path_prefix_wildcards() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        // 名称中的 % 和 _ 按原样匹配，不能把相似名称的兄弟目录也算进去
+        let root = env.temp_dir.path().join("a_b%");
+        let sibling = env.temp_dir.path().join("axbyz");
+        for dir in [&root, &sibling] {
+            fs::create_dir_all(dir.join("sub")).unwrap();
+            let file = dir.join("sub").join("plan.txt");
+            fs::write(&file, "").unwrap();
+            indexer.write_file_items(&file, Vec::new()).unwrap();
+        }
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![
+            root_str.clone(),
+            sibling.to_str().unwrap().to_string(),
+        ])
+        .unwrap();
+
+        let (dirs, files) = indexer.get_paths_under(&root).unwrap();
+        assert_eq!(dirs, vec![root.join("sub")]);
+        assert_eq!(files, vec![root.join("sub").join("plan.txt")]);
+
+        let filter = scope_to_root(SearchFilter::default(), Some(root_str)).unwrap();
+        let result = indexer
+            .search_file(
+                "plan",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &filter,
+            )
+            .unwrap();
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].path, root.join("sub").to_str().unwrap());
+
+        // 按路径范围过滤，区分大小写，并且使用 path 的索引
+        let mut params = SqlParams::new();
+        let sql = filter.to_sql("path", "modified_time", &mut params).unwrap();
+        let conn = get_conn().unwrap();
+        let count = || -> i64 {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM directories WHERE {sql}"),
+                rusqlite::params_from_iter(&params),
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let before = count();
+        let upper = env.temp_dir.path().join("A_B%").join("sub");
+        conn.execute(
+            "INSERT INTO directories (name, path, modified_time) VALUES ('sub', ?1, '')",
+            params![upper.to_str().unwrap()],
+        )
+        .unwrap();
+        assert_eq!(count(), before);
+        let plan: Vec<String> = conn
+            .prepare(&format!(
+                "EXPLAIN QUERY PLAN SELECT id FROM directories WHERE {sql}"
+            ))
+            .unwrap()
+            .query_map(rusqlite::params_from_iter(&params), |row| row.get(3))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(plan.iter().all(|detail| !detail.starts_with("SCAN")));
+        drop(conn);
 
-        debug!("查找子目录和文件: {}", directory.display());
-        let (sub_dirs, files) = self.get_sub_directories_and_files(directory)?;
+        indexer.delete_directory(&root).unwrap();
+        let (dirs, files) = indexer.get_paths_under(&sibling).unwrap();
+        assert_eq!(dirs, vec![sibling.join("sub")]);
+        assert_eq!(files, vec![sibling.join("sub").join("plan.txt")]);
+    }
 
-        for file in files {
-            info!("删除文件: {}", file.name);
-            self.delete_file(&Path::new(&file.path).join(&file.name))?;
+    #[test]
+    fn test_scope_to_root() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let root = env.temp_dir.path().join("root");
+        let other = env.temp_dir.path().join("other");
+        for dir in [&root, &other] {
+            fs::create_dir(dir).unwrap();
+            let file = dir.join("plan.txt");
+            fs::write(&file, "").unwrap();
+            indexer.write_file_items(&file, Vec::new()).unwrap();
         }
+        let root_str = root.to_str().unwrap().to_string();
+        let other_str = other.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone(), other_str.clone()]).unwrap();
+
+        let filter = scope_to_root(SearchFilter::default(), Some(root_str.clone())).unwrap();
+        let result = indexer
+            .search_file(
+                "plan",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &filter,
+            )
+            .unwrap();
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].path, root_str);
+
+        // 不在索引目录中
+        assert!(scope_to_root(
+            SearchFilter::default(),
+            Some(env.temp_dir.path().to_str().unwrap().to_string())
+        )
+        .is_err());
+
+        // 过滤条件中更深的目录保留，不相交时报错
+        let sub = root.join("sub").to_str().unwrap().to_string();
+        let filter = SearchFilter {
+            root: Some(sub.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            scope_to_root(filter, Some(root_str.clone())).unwrap().root,
+            Some(sub)
+        );
+        let filter = SearchFilter {
+            root: Some(other_str),
+            ..Default::default()
+        };
+        assert!(scope_to_root(filter, Some(root_str.clone())).is_err());
+        let filter = SearchFilter {
+            root: Some(env.temp_dir.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            scope_to_root(filter, Some(root_str.clone())).unwrap().root,
+            Some(root_str)
+        );
+    }
 
-        for sub_dir in sub_dirs {
-            info!("删除子目录: {}", sub_dir.path);
-            self.delete_directory(Path::new(&sub_dir.path))?;
+    #[test]
+    fn test_search_case_sensitive() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("Ärger");
+        fs::create_dir(&dir).unwrap();
+        for (name, content) in [
+            ("Ärger.txt", "ÄRGER im Büro"),
+            ("ärger.md", "kein ärger, ab"),
+        ] {
+            let file = dir.join(name);
+            fs::write(&file, content).unwrap();
+            indexer
+                .write_file_items(
+                    &file,
+                    vec![Item {
+                        content: content.to_string(),
+                        ..Default::default()
+                    }],
+                )
+                .unwrap();
         }
+        let options = |case_sensitive| MatchOptions {
+            fuzzy: false,
+            case_sensitive,
+        };
+        let sort = SearchSort::default();
+        let filter = SearchFilter::default();
+
+        // 非 ASCII 字母也不区分大小写
+        let directory_total = |query, case_sensitive| {
+            indexer
+                .search_directory(query, 0, 10, options(case_sensitive), sort, &filter)
+                .unwrap()
+                .total
+        };
+        assert_eq!(directory_total("ärger", false), 1);
+        assert_eq!(directory_total("ärger", true), 0);
+        assert_eq!(directory_total("Ärger", true), 1);
+
+        let file_names = |query, case_sensitive| {
+            let mut names: Vec<String> = indexer
+                .search_file(query, 0, 10, options(case_sensitive), sort, &filter)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|f| f.name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(file_names("ÄRGER", false), vec!["Ärger.txt", "ärger.md"]);
+        assert_eq!(file_names("ärger", true), vec!["ärger.md"]);
+
+        let item_contents = |query, case_sensitive| {
+            let mut contents: Vec<String> = indexer
+                .search_item(query, 0, 10, case_sensitive, sort, &filter)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|i| i.content)
+                .collect();
+            contents.sort();
+            contents
+        };
+        // 能用 FTS 的查询
+        assert_eq!(item_contents("ärger", false).len(), 2);
+        assert_eq!(item_contents("ÄRGER", true), vec!["ÄRGER im Büro"]);
+        // 太短只能扫描的查询
+        assert_eq!(item_contents("Ü", false), vec!["ÄRGER im Büro"]);
+        assert!(item_contents("Ü", true).is_empty());
+        assert_eq!(item_contents("AB", false), vec!["kein ärger, ab"]);
+        assert!(item_contents("AB", true).is_empty());
+
+        let item = indexer
+            .search_item("ärger", 0, 10, true, sort, &filter)
+            .unwrap()
+            .items
+            .remove(0);
+        assert_eq!(item.highlights, vec![Highlight { start: 5, end: 10 }]);
+    }
 
-        info!("删除目录记录: {}", directory.display());
-        let dir_path = path_to_str(directory)?;
-        let conn = get_conn()?;
-        conn.execute("DELETE FROM directories WHERE path = ?1", params![dir_path])?;
+    #[test]
+    fn test_search_filter() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let sub_dir = env.temp_dir.path().join("sub");
+        fs::create_dir(&sub_dir).unwrap();
+        let files = [
+            (env.temp_dir.path().join("report.txt"), "report", 10),
+            (
+                env.temp_dir.path().join("report.PDF"),
+                "report with a much longer body",
+                1,
+            ),
+            (sub_dir.join("report.md"), "report", 1),
+        ];
+        for (file, content, days_ago) in &files {
+            fs::write(file, content).unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(days_ago * 24 * 3600);
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            indexer
+                .write_file_items(
+                    file,
+                    vec![Item {
+                        content: content.to_string(),
+                        ..Default::default()
+                    }],
+                )
+                .unwrap();
+        }
+        indexer.write_directory(&sub_dir).unwrap();
+
+        let file_names = |filter: &SearchFilter| {
+            let mut names: Vec<String> = indexer
+                .search_file(
+                    "report",
+                    0,
+                    10,
+                    MatchOptions::default(),
+                    SearchSort::default(),
+                    filter,
+                )
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|f| f.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let filter = SearchFilter {
+            extensions: vec![".pdf".into(), "MD".into()],
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.PDF", "report.md"]);
+        // 目录没有扩展名
+        assert!(indexer
+            .search_directory(
+                "sub",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &filter
+            )
+            .unwrap()
+            .items
+            .is_empty());
+
+        let five_days_ago = Local::now() - chrono::Duration::days(5);
+        let filter = SearchFilter {
+            modified_after: Some(five_days_ago.format("%Y-%m-%d").to_string()),
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.PDF", "report.md"]);
+        let filter = SearchFilter {
+            modified_before: Some(five_days_ago.to_rfc3339()),
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.txt"]);
+
+        let filter = SearchFilter {
+            min_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.PDF"]);
+        let filter = SearchFilter {
+            max_size: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.md", "report.txt"]);
+
+        // 创建时间在索引时记录，查询时不读取文件
+        let filter = SearchFilter {
+            created_before: Some(five_days_ago.to_rfc3339()),
+            ..Default::default()
+        };
+        assert!(file_names(&filter).is_empty());
+        if fs::metadata(&sub_dir).unwrap().created().is_ok() {
+            let filter = SearchFilter {
+                created_after: Some(five_days_ago.format("%Y-%m-%d").to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                file_names(&filter),
+                vec!["report.PDF", "report.md", "report.txt"]
+            );
+        }
 
-        Ok(())
-    }
+        let filter = SearchFilter {
+            root: Some(sub_dir.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(file_names(&filter), vec!["report.md"]);
+        let result = indexer
+            .search_item("report", 0, 10, false, SearchSort::default(), &filter)
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file, "report.md");
+        let result = indexer
+            .search_directory(
+                "sub",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &filter,
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
 
-    pub fn get_index_status(&self) -> Result<IndexStatusStat> {
-        let conn = get_conn()?;
-        let total_directories: i64 =
-            conn.query_one("SELECT COUNT(*) FROM directories", [], |row| row.get(0))?;
-        let total_files: i64 =
-            conn.query_one("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
-        let indexed_files: i64 =
-            conn.query_one("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
-        Ok(IndexStatusStat {
-            directories: total_directories as usize,
-            files: total_files as usize,
-            items: indexed_files as usize,
-        })
+        let filter = SearchFilter {
+            modified_after: Some("yesterday".into()),
+            ..Default::default()
+        };
+        assert!(indexer
+            .search_file(
+                "report",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &filter
+            )
+            .is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test::test_mod::TestEnv;
+    #[test]
+    fn test_search_all() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("budget_archive");
+        fs::create_dir(&dir).unwrap();
+        let file = dir.join("budget");
+        fs::write(&file, "").unwrap();
+        indexer
+            .write_file_items(
+                &file,
+                vec![Item {
+                    content: "the budget for next year".into(),
+                    ..Default::default()
+                }],
+            )
+            .unwrap();
+        let other = env.temp_dir.path().join("notes.txt");
+        fs::write(&other, "").unwrap();
+        indexer
+            .write_file_items(
+                &other,
+                vec![Item {
+                    content: "budget meeting".into(),
+                    ..Default::default()
+                }],
+            )
+            .unwrap();
 
-    const TEST_DATA_DIR: &str = "../test_data/indexer";
+        let result = indexer
+            .search_all(
+                "budget",
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(result.directory_total, 1);
+        assert_eq!(result.file_total, 1);
+        assert_eq!(result.item_total, 2);
+        assert_eq!(result.hits.len(), 4);
+        // 文件名完全相同排第一，其次是以查询开头的目录，最后是内容
+        assert!(matches!(&result.hits[0], SearchHit::File(f) if f.name == "budget"));
+        assert!(matches!(&result.hits[1], SearchHit::Directory(d) if d.name == "budget_archive"));
+        assert!(matches!(&result.hits[2], SearchHit::Item(_)));
+        assert!(matches!(&result.hits[3], SearchHit::Item(_)));
+
+        let json = serde_json::to_value(&result.hits[0]).unwrap();
+        assert_eq!(json["type"], "file");
+        assert_eq!(json["name"], "budget");
+    }
 
     #[test]
-    fn test_get_index() {
-        let _env = TestEnv::new();
-        let _ = Indexer::new().unwrap();
+    fn test_search_sort() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let b_dir = env.temp_dir.path().join("b_dir");
+        fs::create_dir(&b_dir).unwrap();
+        let files = [
+            (env.temp_dir.path().join("b_note.txt"), 3),
+            (env.temp_dir.path().join("c_note.txt"), 1),
+            (b_dir.join("a_note.txt"), 2),
+        ];
+        for (file, days_ago) in &files {
+            fs::write(file, "note").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(days_ago * 24 * 3600);
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            indexer.write_file_items(file, Vec::new()).unwrap();
+        }
+
+        let file_names = |by: SortBy, order: Option<SortOrder>| {
+            indexer
+                .search_file(
+                    "note",
+                    0,
+                    10,
+                    MatchOptions::default(),
+                    SearchSort { by, order },
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|f| f.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            file_names(SortBy::Name, None),
+            vec!["a_note.txt", "b_note.txt", "c_note.txt"]
+        );
+        assert_eq!(
+            file_names(SortBy::Name, Some(SortOrder::Desc)),
+            vec!["c_note.txt", "b_note.txt", "a_note.txt"]
+        );
+        assert_eq!(
+            file_names(SortBy::Modified, None),
+            vec!["c_note.txt", "a_note.txt", "b_note.txt"]
+        );
+        assert_eq!(
+            file_names(SortBy::Modified, Some(SortOrder::Asc)),
+            vec!["b_note.txt", "a_note.txt", "c_note.txt"]
+        );
+        // temp_dir 是 b_dir 的前缀，排在前面
+        assert_eq!(
+            file_names(SortBy::Path, None),
+            vec!["b_note.txt", "c_note.txt", "a_note.txt"]
+        );
+
+        let result = indexer
+            .search_directory(
+                "b_dir",
+                0,
+                10,
+                FUZZY,
+                SearchSort {
+                    by: SortBy::Name,
+                    order: None,
+                },
+                &SearchFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].name, "b_dir");
     }
 
     #[test]
-    fn test_write_directory() {
-        let _env = TestEnv::new();
+    fn test_search_item_sort() {
+        let env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
-        let path = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
-        indexer.write_directory(&path).unwrap();
+        let old_file = env.temp_dir.path().join("old.txt");
+        let new_file = env.temp_dir.path().join("new.txt");
+        for (file, days_ago) in [(&old_file, 10), (&new_file, 1)] {
+            fs::write(file, "").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(days_ago * 24 * 3600);
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        indexer
+            .write_file_items(
+                &old_file,
+                vec![Item {
+                    content: "report report report".into(),
+                    ..Default::default()
+                }],
+            )
+            .unwrap();
+        indexer
+            .write_file_items(
+                &new_file,
+                vec![Item {
+                    content: "a long paragraph that mentions the report only once".into(),
+                    ..Default::default()
+                }],
+            )
+            .unwrap();
+
+        for query in ["report", "re"] {
+            let result = indexer
+                .search_item(
+                    query,
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items;
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].file, "old.txt");
+            assert!(result[0].score > result[1].score);
+
+            let result = indexer
+                .search_item(
+                    query,
+                    0,
+                    10,
+                    false,
+                    SearchSort {
+                        by: SortBy::Modified,
+                        order: None,
+                    },
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items;
+            assert_eq!(result[0].file, "new.txt");
+
+            let result = indexer
+                .search_item(
+                    query,
+                    0,
+                    10,
+                    false,
+                    SearchSort {
+                        by: SortBy::Relevance,
+                        order: Some(SortOrder::Asc),
+                    },
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items;
+            assert_eq!(result[0].file, "new.txt");
+            assert!(result[0].score < result[1].score);
+        }
+    }
+
+    fn term(t: &str) -> Box<QueryNode> {
+        Box::new(QueryNode::Term(t.to_string()))
     }
 
     #[test]
-    fn test_get_directory() {
-        let _env = TestEnv::new();
-        let indexer = Indexer::new().unwrap();
-        let path = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
-        indexer.write_directory(&path).unwrap();
+    fn test_parse_query() {
+        assert_eq!(parse_query("report").unwrap(), *term("report"));
+        assert_eq!(
+            parse_query("a b OR c").unwrap(),
+            QueryNode::Or(Box::new(QueryNode::And(term("a"), term("b"))), term("c"))
+        );
+        assert_eq!(
+            parse_query("a AND (b OR c) NOT d").unwrap(),
+            QueryNode::And(
+                Box::new(QueryNode::And(
+                    term("a"),
+                    Box::new(QueryNode::Or(term("b"), term("c")))
+                )),
+                Box::new(QueryNode::Not(term("d")))
+            )
+        );
+        // 小写的 and/or/not 是普通的词
+        assert_eq!(
+            parse_query("rock and roll").unwrap(),
+            QueryNode::And(
+                Box::new(QueryNode::And(term("rock"), term("and"))),
+                term("roll")
+            )
+        );
+        assert_eq!(
+            parse_query(r#"report "NOT (final)" OR "a ""b"""#).unwrap(),
+            QueryNode::Or(
+                Box::new(QueryNode::And(
+                    term("report"),
+                    Box::new(QueryNode::Phrase("NOT (final)".to_string()))
+                )),
+                Box::new(QueryNode::Phrase(r#"a "b""#.to_string()))
+            )
+        );
+        // 缺少结尾引号时到末尾为止，空短语忽略
+        assert_eq!(
+            parse_query(r#"a "" "b c"#).unwrap(),
+            QueryNode::And(term("a"), Box::new(QueryNode::Phrase("b c".to_string())))
+        );
+        assert!(parse_query("(a OR b").is_err());
+        assert!(parse_query("a OR").is_err());
+        assert!(parse_query("a)").is_err());
+        assert!(parse_query("AND").is_err());
+    }
 
-        let dir = indexer.get_directory(&path).unwrap();
-        assert_eq!(dir.name, "indexer");
-        assert_eq!(dir.path, path.canonicalize().unwrap().to_str().unwrap());
+    #[test]
+    fn test_query_to_fts() {
+        assert_eq!(
+            parse_query("report OR 报告单 NOT draft").unwrap().to_fts(),
+            Some(r#"("report" OR ("报告单" NOT "draft"))"#.to_string())
+        );
+        assert_eq!(
+            parse_query("NOT draft report").unwrap().to_fts(),
+            Some(r#"("report" NOT "draft")"#.to_string())
+        );
+        assert_eq!(parse_query("NOT draft").unwrap().to_fts(), None);
+        assert_eq!(parse_query("report ab").unwrap().to_fts(), None);
     }
 
     #[test]
-    fn test_write_file_items() {
+    fn test_search_boolean() {
         let _env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
-
-        let file = Path::new(TEST_DATA_DIR)
-            .join("1.txt")
-            .canonicalize()
-            .unwrap();
-        indexer.write_directory(file.parent().unwrap()).unwrap();
-
         let items = vec![
             Item {
-                content: "Hello, world!".into(),
+                content: "quarterly report final".into(),
+                ..Default::default()
             },
             Item {
-                content: "This is a test.".into(),
+                content: "quarterly report draft".into(),
+                ..Default::default()
+            },
+            Item {
+                content: "meeting notes".into(),
+                ..Default::default()
             },
         ];
-        indexer.write_file_items(&file, items).unwrap();
-    }
-
-    #[test]
-    fn test_get_file() {
-        let _env = TestEnv::new();
-        let indexer = Indexer::new().unwrap();
-
         let file = Path::new(TEST_DATA_DIR)
             .join("1.txt")
             .canonicalize()
             .unwrap();
-        indexer.write_directory(file.parent().unwrap()).unwrap();
-
-        let items = vec![
-            Item {
-                content: "Hello, world!".into(),
-            },
-            Item {
-                content: "This is a test.".into(),
-            },
-        ];
         indexer.write_file_items(&file, items).unwrap();
 
-        let file_result = indexer.get_file(&file).unwrap();
-        assert_eq!(file_result.name, "1.txt");
-        assert_eq!(file_result.path, file.parent().unwrap().to_str().unwrap());
+        let search = |query: &str| -> Vec<String> {
+            indexer
+                .search_item(
+                    query,
+                    0,
+                    10,
+                    false,
+                    SearchSort {
+                        by: SortBy::Modified,
+                        order: None,
+                    },
+                    &SearchFilter::default(),
+                )
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|r| r.content)
+                .collect()
+        };
+        assert_eq!(search("report NOT draft"), vec!["quarterly report final"]);
+        assert_eq!(search("final OR notes").len(), 2);
+        assert_eq!(search("notes final"), Vec::<String>::new());
+        assert_eq!(search("(draft OR final) report").len(), 2);
+        // 短语要求连续出现，不带引号的词各自匹配
+        assert_eq!(search(r#""report final""#), vec!["quarterly report final"]);
+        assert_eq!(search("final report"), vec!["quarterly report final"]);
+        assert!(search(r#""final report""#).is_empty());
+        assert_eq!(
+            search(r#""ly rep" NOT "t d""#),
+            vec!["quarterly report final"]
+        );
+        // 单独的 NOT 和短词走 LIKE
+        assert_eq!(search("NOT report"), vec!["meeting notes"]);
+        assert_eq!(search("qu NOT dr"), vec!["quarterly report final"]);
+
+        let result = indexer
+            .search_item(
+                "report OR final",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result[0].content, "quarterly report final");
+        assert_eq!(
+            result[0].highlights,
+            vec![
+                Highlight { start: 10, end: 16 },
+                Highlight { start: 17, end: 22 }
+            ]
+        );
+
+        assert_eq!(
+            indexer
+                .search_file(
+                    "1.txt OR 2.txt",
+                    0,
+                    10,
+                    MatchOptions::default(),
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert!(indexer
+            .search_file(
+                "1 NOT txt",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
     }
 
     #[test]
-    fn test_get_sub_directories_and_files() {
-        let _env = TestEnv::new();
+    fn test_fuzzy_distance() {
+        assert_eq!(fuzzy_distance("document.docx", "document"), 0);
+        assert_eq!(fuzzy_distance("document.docx", "docment"), 1);
+        assert_eq!(fuzzy_distance("Document.docx", "dokumant"), 2);
+        assert_eq!(fuzzy_distance("报告2024.pdf", "报吿2024"), 1);
+        assert_eq!(fuzzy_distance("abc", "xyz"), 3);
+        assert_eq!(fuzzy_distance("abc", ""), 0);
+    }
+
+    #[test]
+    fn test_search_fuzzy() {
+        let env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("Documents");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["document.docx", "docs.txt", "image.png"] {
+            let file = dir.join(name);
+            fs::write(&file, "").unwrap();
+            indexer.write_file_items(&file, Vec::new()).unwrap();
+        }
 
-        let file = Path::new(TEST_DATA_DIR)
-            .join("1.txt")
-            .canonicalize()
-            .unwrap();
-        indexer.write_directory(file.parent().unwrap()).unwrap();
+        assert!(indexer
+            .search_file(
+                "docment",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
+        let result = indexer
+            .search_file(
+                "docment",
+                0,
+                10,
+                FUZZY,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "document.docx");
+        // 短词不允许编辑
+        let result = indexer
+            .search_file(
+                "doc",
+                0,
+                10,
+                FUZZY,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 2);
+        assert!(indexer
+            .search_file(
+                "dox",
+                0,
+                10,
+                FUZZY,
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
+        // 距离近的排在前面
+        let result = indexer
+            .search_file(
+                "docs",
+                0,
+                10,
+                FUZZY,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].name, "docs.txt");
+        assert_eq!(result[1].name, "document.docx");
+
+        let result = indexer
+            .search_directory(
+                "Documnets",
+                0,
+                10,
+                FUZZY,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].name, "Documents");
+    }
 
+    #[test]
+    fn test_search_item_words() {
+        let _env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
         let items = vec![
+            // 只是子串命中：“和服”不是这里的词
             Item {
-                content: "Hello, world!".into(),
+                content: "我们一起讨论了和服装有关的问题".into(),
+                ..Default::default()
             },
             Item {
-                content: "This is a test.".into(),
+                content: "她穿着一件漂亮的和服".into(),
+                ..Default::default()
             },
         ];
-        indexer.write_file_items(&file, items).unwrap();
-
-        let sub_dir_path = Path::new(TEST_DATA_DIR)
-            .join("office")
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
             .canonicalize()
             .unwrap();
-        indexer.write_directory(&sub_dir_path).unwrap();
+        indexer.write_file_items(&file, items).unwrap();
 
-        let (dir_result, file_result) = indexer
-            .get_sub_directories_and_files(file.parent().unwrap())
+        let result = indexer
+            .search_item(
+                "和服",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].content, "她穿着一件漂亮的和服");
+        assert!(result[0].score > result[1].score);
+
+        // 删除后分词索引同步删除
+        indexer.delete_file(&file).unwrap();
+        let count: i64 = get_conn()
+            .unwrap()
+            .query_one(
+                "SELECT COUNT(*) FROM items_words WHERE items_words MATCH '讨论'",
+                [],
+                |row| row.get(0),
+            )
             .unwrap();
-        assert_eq!(dir_result.len(), 1);
-        assert_eq!(file_result.len(), 1);
+        assert_eq!(count, 0);
     }
 
     #[test]
-    fn test_search_directory() {
+    fn test_sanitize_query() {
+        assert_eq!(sanitize_query("  report\n ").unwrap(), "report");
+        assert_eq!(sanitize_query("a\tb").unwrap(), "a b");
+        assert!(sanitize_query(" \r\n").is_err());
+        assert_eq!(
+            sanitize_query(&"x".repeat(1000)).unwrap().len(),
+            MAX_QUERY_CHARS
+        );
+        assert_eq!(escape_like(r"100%_a\b"), r"100\%\_a\\b");
+        // NFD 的查询统一成 NFC
+        assert_eq!(sanitize_query("cafe\u{301}").unwrap(), "caf\u{e9}");
+        assert_eq!(fold_str("CAFE\u{301}"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_search_nfd_content() {
         let _env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
-        let dir = Path::new(TEST_DATA_DIR).canonicalize().unwrap();
-        indexer.write_directory(&dir).unwrap();
-
-        let result = indexer.search_directory("indexer", 0, 10).unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].name, "indexer");
+        let items = vec![Item {
+            content: "menu du cafe\u{301}".into(),
+            ..Default::default()
+        }];
+        let file = Path::new(TEST_DATA_DIR)
+            .join("1.txt")
+            .canonicalize()
+            .unwrap();
+        indexer.write_file_items(&file, items).unwrap();
 
-        let result = indexer.search_directory("indexer", 1, 10).unwrap();
-        assert_eq!(result.len(), 0);
+        assert_eq!(
+            indexer.get_file_items(&file).unwrap()[0].content,
+            "menu du caf\u{e9}"
+        );
+        for query in ["caf\u{e9}", "cafe\u{301}"] {
+            assert_eq!(
+                indexer
+                    .search_item(
+                        query,
+                        0,
+                        10,
+                        false,
+                        SearchSort::default(),
+                        &SearchFilter::default()
+                    )
+                    .unwrap()
+                    .items
+                    .len(),
+                1
+            );
+        }
     }
 
     #[test]
-    fn test_search_file() {
+    fn test_search_special_chars() {
         let _env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
         let items = vec![
             Item {
-                content: "Hello, world!".into(),
+                content: "it's 100% done".into(),
+                ..Default::default()
             },
             Item {
-                content: "This is a test.".into(),
+                content: "1000 done".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
             .join("1.txt")
             .canonicalize()
             .unwrap();
-        indexer.write_directory(file.parent().unwrap()).unwrap();
         indexer.write_file_items(&file, items).unwrap();
 
-        let result = indexer.search_file("1.t", 0, 10).unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].name, "1.txt");
-        assert_eq!(result[0].path, file.parent().unwrap().to_str().unwrap());
-
-        let result = indexer.search_file("1.t", 1, 10).unwrap();
-        assert_eq!(result.len(), 0);
+        assert_eq!(
+            indexer
+                .search_item(
+                    "it's",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert_eq!(
+            indexer
+                .search_item(
+                    "0%",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert_eq!(
+            indexer
+                .search_item(
+                    "%",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert!(indexer
+            .search_item(
+                "1_0",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
+        assert!(indexer
+            .search_file(
+                "'; DROP TABLE files; --",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
+        assert!(indexer
+            .search_file(
+                "_",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
+        assert_eq!(
+            indexer
+                .search_file(
+                    "1.txt",
+                    0,
+                    10,
+                    MatchOptions::default(),
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert!(indexer
+            .search_directory(
+                "%",
+                0,
+                10,
+                MatchOptions::default(),
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
     }
 
     #[test]
-    fn test_search_item() {
+    fn test_search_item_fts() {
         let _env = TestEnv::new();
         let indexer = Indexer::new().unwrap();
         let items = vec![
             Item {
-                content: "Hello, world!".into(),
+                content: "鸭子索引支持全文搜索".into(),
+                ..Default::default()
             },
             Item {
-                content: "This is a test.".into(),
+                content: r#"He said "hello" to me"#.into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
             .join("1.txt")
             .canonicalize()
             .unwrap();
-        indexer.write_directory(file.parent().unwrap()).unwrap();
         indexer.write_file_items(&file, items).unwrap();
 
-        let result = indexer.search_item("world", 0, 10).unwrap();
+        let result = indexer
+            .search_item(
+                "全文搜索",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default(),
+            )
+            .unwrap()
+            .items;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].content, "Hello, world!");
-        assert_eq!(result[0].file, "1.txt");
-        assert_eq!(result[0].path, file.parent().unwrap().to_str().unwrap());
+        assert_eq!(result[0].content, "鸭子索引支持全文搜索");
+        // 短语中的 "" 是引号本身
+        assert_eq!(
+            indexer
+                .search_item(
+                    r#""said ""hello""""#,
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        // 短查询退回 LIKE
+        assert_eq!(
+            indexer
+                .search_item(
+                    "鸭子",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+        assert_eq!(
+            indexer
+                .search_item(
+                    "HELLO",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default()
+                )
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+
+        indexer.delete_file(&file).unwrap();
+        assert!(indexer
+            .search_item(
+                "全文搜索",
+                0,
+                10,
+                false,
+                SearchSort::default(),
+                &SearchFilter::default()
+            )
+            .unwrap()
+            .items
+            .is_empty());
     }
 
     #[test]
@@ -554,9 +3836,11 @@ mod tests {
         let items = vec![
             Item {
                 content: "Hello, world!".into(),
+                ..Default::default()
             },
             Item {
                 content: "This is a test.".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
@@ -582,9 +3866,11 @@ mod tests {
         let items = vec![
             Item {
                 content: "Hello, world!".into(),
+                ..Default::default()
             },
             Item {
                 content: "This is a test.".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
@@ -606,9 +3892,11 @@ mod tests {
         let items = vec![
             Item {
                 content: "Hello, world!".into(),
+                ..Default::default()
             },
             Item {
                 content: "This is a test.".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
@@ -635,6 +3923,72 @@ mod tests {
         assert_eq!(file_result.len(), 0);
     }
 
+    #[test]
+    fn test_directory_parent_id() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        // 配置了同步目录时才记录变更
+        Config::set_sync_dir(&Some(env.temp_dir.path().to_str().unwrap().to_string())).unwrap();
+        let root = env.temp_dir.path().join("a%b");
+        let deep = root.join("x_y").join("z");
+        fs::create_dir_all(&deep).unwrap();
+        fs::create_dir_all(env.temp_dir.path().join("aXb").join("other")).unwrap();
+        fs::write(deep.join("1.txt"), "deep").unwrap();
+
+        // 子目录先于上级目录写入，写入上级目录时接上
+        indexer
+            .write_file_items(&deep.join("1.txt"), vec![])
+            .unwrap();
+        indexer
+            .write_directory(&env.temp_dir.path().join("aXb").join("other"))
+            .unwrap();
+        indexer.write_directory(&root).unwrap();
+        indexer.write_directory(&root.join("x_y")).unwrap();
+        let names = |dir: &Path| -> Vec<String> {
+            let (dirs, _) = indexer.get_sub_directories_and_files(dir).unwrap();
+            dirs.into_iter().map(|d| d.name).collect()
+        };
+        assert_eq!(names(&root), vec!["x_y"]);
+        assert_eq!(names(&root.join("x_y")), vec!["z"]);
+        // 路径中的 % 和 _ 不是通配符
+        assert!(names(&env.temp_dir.path().join("aXb")).is_empty());
+
+        // 移动后按新的路径列出，删除时包括各级子目录
+        let moved = env.temp_dir.path().join("moved");
+        fs::rename(&root, &moved).unwrap();
+        assert!(indexer.move_directory(&root, &moved).unwrap());
+        assert_eq!(names(&moved), vec!["x_y"]);
+        let seq: i64 = get_conn()
+            .unwrap()
+            .query_one("SELECT MAX(seq) FROM changelog", [], |row| row.get(0))
+            .unwrap();
+        indexer.delete_directory(&moved).unwrap();
+        // 先记录文件，再从下往上记录目录
+        let changes: Vec<(String, String)> = get_conn()
+            .unwrap()
+            .prepare("SELECT op, path FROM changelog WHERE seq > ?1 ORDER BY seq")
+            .unwrap()
+            .query_map([seq], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let expected = [
+            (
+                ChangeOp::DeleteFile,
+                moved.join("x_y").join("z").join("1.txt"),
+            ),
+            (ChangeOp::DeleteDirectory, moved.join("x_y").join("z")),
+            (ChangeOp::DeleteDirectory, moved.join("x_y")),
+            (ChangeOp::DeleteDirectory, moved.clone()),
+        ]
+        .map(|(op, path)| (op.to_string(), path.to_str().unwrap().to_string()));
+        assert_eq!(changes, expected);
+        let (dirs, files) = indexer.get_paths_under(&moved).unwrap();
+        assert!(dirs.is_empty());
+        assert!(files.is_empty());
+        assert_eq!(indexer.get_index_status().unwrap().directories, 1);
+    }
+
     #[test]
     fn test_delete_directory_not_exists() {
         let _env = TestEnv::new();
@@ -642,9 +3996,11 @@ mod tests {
         let items = vec![
             Item {
                 content: "Hello, world!".into(),
+                ..Default::default()
             },
             Item {
                 content: "This is a test.".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
@@ -674,9 +4030,11 @@ mod tests {
         let items = vec![
             Item {
                 content: "Hello, world!".into(),
+                ..Default::default()
             },
             Item {
                 content: "This is a test.".into(),
+                ..Default::default()
             },
         ];
         let file = Path::new(TEST_DATA_DIR)
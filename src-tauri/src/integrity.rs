@@ -0,0 +1,191 @@
+use anyhow::Result;
+use log::{info, warn};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::indexer::{release_contents, Indexer};
+use crate::sqlite::get_conn;
+use crate::utils::normalize_path;
+use crate::volume;
+
+// 报告中最多列出的不存在的目录
+const MAX_SAMPLES: usize = 100;
+
+const ORPHAN_ITEMS: &str = "FROM items WHERE file_id NOT IN (SELECT id FROM files)";
+const ORPHAN_FILES: &str = "FROM files WHERE directory_id NOT IN (SELECT id FROM directories)";
+
+/// 索引的一致性检查结果，数量都是修复前的
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    /// 所属文件已经不存在的内容
+    pub orphan_items: usize,
+    /// 所在目录已经不存在的文件
+    pub orphan_files: usize,
+    /// 磁盘上已经不存在的目录数，子目录不单独计算，离线的索引目录不检查
+    pub missing_directories: usize,
+    /// 不存在的目录，最多列出 MAX_SAMPLES 个
+    pub missing_samples: Vec<String>,
+    /// 全文索引和内容不一致
+    pub fts_out_of_sync: bool,
+    pub repaired: bool,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.orphan_items == 0
+            && self.orphan_files == 0
+            && self.missing_directories == 0
+            && !self.fts_out_of_sync
+    }
+}
+
+fn count(conn: &Connection, from: &str) -> Result<usize> {
+    let count: i64 = conn.query_row(&format!("SELECT COUNT(*) {from}"), [], |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+/// 全文索引是否和 items 一致。items_fts 用 FTS5 自带的检查，rank 为 1 时同时和内容表比较；
+/// items_words 不保存原文，只比较行号
+fn is_fts_out_of_sync(conn: &mut Connection) -> Result<bool> {
+    // 检查失败时 FTS5 内部的读语句可能没有结束，放在事务中，回滚后才能正常写入
+    let tx = conn.transaction()?;
+    let checked =
+        tx.execute_batch("INSERT INTO items_fts (items_fts, rank) VALUES ('integrity-check', 1)");
+    tx.rollback()?;
+    if let Err(e) = checked {
+        warn!("items_fts 检查不通过: {e:?}");
+        return Ok(true);
+    }
+    let missing = count(
+        conn,
+        "FROM items WHERE id NOT IN (SELECT rowid FROM items_words)",
+    )?;
+    let extra = count(
+        conn,
+        "FROM items_words WHERE rowid NOT IN (SELECT id FROM items)",
+    )?;
+    if missing + extra > 0 {
+        warn!("items_words 和内容不一致, 缺少 {missing} 行, 多出 {extra} 行");
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// 磁盘上已经不存在的目录，按路径排序后跳过已经列出的目录的子目录。
+/// 和磁盘上的大小写不一致的路径是同一个目录重复的行，也按不存在处理
+fn find_missing_directories(conn: &Connection) -> Result<Vec<PathBuf>> {
+    let mut stmt = conn.prepare("SELECT path FROM directories ORDER BY path")?;
+    let paths = stmt
+        .query_map([], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut missing: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if missing.last().is_some_and(|last| path.starts_with(last)) {
+            continue;
+        }
+        if normalize_path(&path) == path && (path.exists() || volume::is_offline(&path)?) {
+            continue;
+        }
+        missing.push(path);
+    }
+    Ok(missing)
+}
+
+/// 检查索引中的孤立数据、已经不存在的目录和全文索引，repair 为 true 时逐类修复
+pub fn verify_index(repair: bool) -> Result<IntegrityReport> {
+    let mut conn = get_conn()?;
+    let missing = find_missing_directories(&conn)?;
+    let mut report = IntegrityReport {
+        orphan_items: count(&conn, ORPHAN_ITEMS)?,
+        orphan_files: count(&conn, ORPHAN_FILES)?,
+        missing_directories: missing.len(),
+        missing_samples: missing
+            .iter()
+            .take(MAX_SAMPLES)
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+        fts_out_of_sync: is_fts_out_of_sync(&mut conn)?,
+        repaired: false,
+    };
+    info!("索引检查结果: {report:?}");
+    if !repair || report.is_ok() {
+        return Ok(report);
+    }
+
+    let indexer = Indexer::new()?;
+    for path in &missing {
+        info!("删除不存在的目录: {}", path.display());
+        indexer.delete_directory(Path::new(path))?;
+    }
+    let tx = conn.transaction()?;
+    release_contents(
+        &tx,
+        &format!("WITH released(id) AS (SELECT id {ORPHAN_FILES})"),
+        &[],
+    )?;
+    tx.execute(&format!("DELETE {ORPHAN_FILES}"), [])?;
+    tx.execute(&format!("DELETE {ORPHAN_ITEMS}"), [])?;
+    if report.fts_out_of_sync {
+        // 内容表没有问题，按 items 重新生成两个全文索引
+        tx.execute_batch(
+            "INSERT INTO items_fts (items_fts) VALUES ('rebuild');
+            INSERT INTO items_words (items_words) VALUES ('delete-all');
+            INSERT INTO items_words (rowid, content) SELECT id, jieba_segment(item_text(content)) FROM items;",
+        )?;
+    }
+    tx.commit()?;
+    report.repaired = true;
+    info!("索引已修复");
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_verify_index() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("docs");
+        let gone = dir.join("gone");
+        fs::create_dir_all(&gone).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        let items = vec![Item {
+            content: "hello world".into(),
+            ..Default::default()
+        }];
+        indexer.write_file_items(&dir.join("a.txt"), items).unwrap();
+        indexer.write_directory(&gone).unwrap();
+        assert!(verify_index(false).unwrap().is_ok());
+
+        fs::remove_dir(&gone).unwrap();
+        let conn = get_conn().unwrap();
+        conn.execute_batch(
+            "INSERT INTO files (directory_id, name, modified_time, name_folded) VALUES (99999, 'lost.txt', '', 'lost.txt');
+            INSERT INTO items (file_id, content) VALUES (99999, 'orphan');
+            INSERT INTO items_fts (rowid, content) VALUES (99999, 'ghost');",
+        )
+        .unwrap();
+        let report = verify_index(false).unwrap();
+        assert_eq!(report.orphan_items, 1);
+        assert_eq!(report.orphan_files, 1);
+        assert_eq!(report.missing_directories, 1);
+        assert_eq!(
+            report.missing_samples,
+            vec![gone.to_str().unwrap().to_string()]
+        );
+        assert!(report.fts_out_of_sync);
+        assert!(!report.repaired);
+
+        assert!(verify_index(true).unwrap().repaired);
+        let report = verify_index(false).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(indexer.get_index_status().unwrap().items, 1);
+    }
+}
@@ -0,0 +1,505 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// 系统变化日志的当前位置，下次启动时从这里读取之后的变化。
+/// 平台不支持或者读取不到（如 Windows 上没有管理员权限）时返回 None
+pub fn current_cursor(root: &Path) -> Result<Option<String>> {
+    platform::current_cursor(root)
+}
+
+/// 从系统变化日志读取 cursor 之后 root 下变化的文件和目录。日志不可用、
+/// 已经被截断或者系统要求重新扫描时返回 None，调用方改为检查目录
+pub fn changes_since(root: &Path, cursor: &str) -> Result<Option<Vec<PathBuf>>> {
+    let Some(paths) = platform::changes_since(root, cursor)? else {
+        return Ok(None);
+    };
+    Ok(Some(map_to_root(root, &real_path(root)?, paths)))
+}
+
+/// 日志中是真实路径，大小写或者符号链接可能与设置的索引目录不同，
+/// 换成索引目录下的路径，去掉索引目录以外的
+fn map_to_root(root: &Path, real_root: &Path, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = paths
+        .into_iter()
+        .filter_map(|p| {
+            p.strip_prefix(real_root)
+                .or_else(|_| p.strip_prefix(root))
+                .ok()
+                .map(|relative| root.join(relative))
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn real_path(path: &Path) -> Result<PathBuf> {
+    let path = std::fs::canonicalize(path)?;
+    // Windows 上去掉 \\?\ 前缀，与日志中的路径一致
+    Ok(match path.to_str().and_then(|p| p.strip_prefix(r"\\?\")) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    })
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use log::{debug, info};
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::mem::size_of;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_HANDLE_EOF, ERROR_JOURNAL_ENTRY_DELETED, GENERIC_READ,
+        HANDLE, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FileIdType, GetFinalPathNameByHandleW, GetVolumePathNameW, OpenFileById,
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_DESCRIPTOR, FILE_ID_DESCRIPTOR_0, FILE_NAME_NORMALIZED,
+        FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0,
+        USN_JOURNAL_DATA_V0, USN_RECORD_V2,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    // 一次读取的日志大小
+    const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    fn to_wide(s: &std::ffi::OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+
+    /// 打开路径所在的卷，读取 USN 日志需要管理员权限，打不开时返回 None
+    fn open_volume(root: &Path) -> Result<Option<Handle>> {
+        let root_wide = to_wide(root.as_os_str());
+        let mut volume = vec![0u16; 261];
+        if unsafe {
+            GetVolumePathNameW(root_wide.as_ptr(), volume.as_mut_ptr(), volume.len() as u32)
+        } == 0
+        {
+            return Ok(None);
+        }
+        let len = volume.iter().position(|&c| c == 0).unwrap_or(volume.len());
+        let volume = OsString::from_wide(&volume[..len]);
+        let volume = volume.to_string_lossy();
+        // 只支持盘符形式的卷，如 C:\ 对应 \\.\C:
+        let Some(letter) = volume
+            .strip_suffix('\\')
+            .filter(|v| v.len() == 2 && v.ends_with(':'))
+        else {
+            return Ok(None);
+        };
+        let device = to_wide(format!(r"\\.\{letter}").as_ref());
+        let handle = unsafe {
+            CreateFileW(
+                device.as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                null(),
+                OPEN_EXISTING,
+                0,
+                null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            debug!("打开卷失败，不使用 USN 日志: {letter}, {}", unsafe {
+                GetLastError()
+            });
+            return Ok(None);
+        }
+        Ok(Some(Handle(handle)))
+    }
+
+    fn query_journal(volume: &Handle) -> Option<USN_JOURNAL_DATA_V0> {
+        let mut data: USN_JOURNAL_DATA_V0 = unsafe { std::mem::zeroed() };
+        let mut returned = 0u32;
+        let ok = unsafe {
+            DeviceIoControl(
+                volume.0,
+                FSCTL_QUERY_USN_JOURNAL,
+                null(),
+                0,
+                &mut data as *mut _ as *mut _,
+                size_of::<USN_JOURNAL_DATA_V0>() as u32,
+                &mut returned,
+                null_mut(),
+            )
+        };
+        (ok != 0).then_some(data)
+    }
+
+    /// 格式为 日志 ID:USN
+    fn parse_cursor(cursor: &str) -> Option<(u64, i64)> {
+        let (id, usn) = cursor.split_once(':')?;
+        Some((id.parse().ok()?, usn.parse().ok()?))
+    }
+
+    pub fn current_cursor(root: &Path) -> Result<Option<String>> {
+        let Some(volume) = open_volume(root)? else {
+            return Ok(None);
+        };
+        Ok(query_journal(&volume).map(|data| format!("{}:{}", data.UsnJournalID, data.NextUsn)))
+    }
+
+    /// 按文件 ID 查目录现在的完整路径，目录已经删除时返回 None
+    fn resolve_dir(volume: &Handle, id: u64) -> Option<PathBuf> {
+        let descriptor = FILE_ID_DESCRIPTOR {
+            dwSize: size_of::<FILE_ID_DESCRIPTOR>() as u32,
+            Type: FileIdType,
+            Anonymous: FILE_ID_DESCRIPTOR_0 { FileId: id as i64 },
+        };
+        let handle = unsafe {
+            OpenFileById(
+                volume.0,
+                &descriptor,
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                null(),
+                FILE_FLAG_BACKUP_SEMANTICS,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let handle = Handle(handle);
+        let mut buffer = vec![0u16; 1024];
+        let len = unsafe {
+            GetFinalPathNameByHandleW(
+                handle.0,
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                FILE_NAME_NORMALIZED,
+            )
+        } as usize;
+        if len == 0 || len >= buffer.len() {
+            return None;
+        }
+        let path = OsString::from_wide(&buffer[..len])
+            .to_string_lossy()
+            .into_owned();
+        Some(PathBuf::from(path.strip_prefix(r"\\?\").unwrap_or(&path)))
+    }
+
+    pub fn changes_since(root: &Path, cursor: &str) -> Result<Option<Vec<PathBuf>>> {
+        let Some((journal_id, start_usn)) = parse_cursor(cursor) else {
+            return Ok(None);
+        };
+        let Some(volume) = open_volume(root)? else {
+            return Ok(None);
+        };
+        let Some(journal) = query_journal(&volume) else {
+            return Ok(None);
+        };
+        // 日志重建过或者需要的部分已经被覆盖
+        if journal.UsnJournalID != journal_id || start_usn < journal.FirstUsn {
+            info!("USN 日志不连续，改为检查目录: {}", root.display());
+            return Ok(None);
+        }
+
+        let mut paths = Vec::new();
+        let mut parents: HashMap<u64, Option<PathBuf>> = HashMap::new();
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        let mut next_usn = start_usn;
+        while next_usn < journal.NextUsn {
+            let request = READ_USN_JOURNAL_DATA_V0 {
+                StartUsn: next_usn,
+                ReasonMask: u32::MAX,
+                ReturnOnlyOnClose: 0,
+                Timeout: 0,
+                BytesToWaitFor: 0,
+                UsnJournalID: journal_id,
+            };
+            let mut returned = 0u32;
+            let ok = unsafe {
+                DeviceIoControl(
+                    volume.0,
+                    FSCTL_READ_USN_JOURNAL,
+                    &request as *const _ as *const _,
+                    size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+                    buffer.as_mut_ptr() as *mut _,
+                    buffer.len() as u32,
+                    &mut returned,
+                    null_mut(),
+                )
+            };
+            if ok == 0 {
+                match unsafe { GetLastError() } {
+                    ERROR_HANDLE_EOF => break,
+                    ERROR_JOURNAL_ENTRY_DELETED => return Ok(None),
+                    e => return Err(anyhow!("读取 USN 日志失败: {e}")),
+                }
+            }
+            let returned = returned as usize;
+            if returned <= size_of::<i64>() {
+                break;
+            }
+            next_usn = i64::from_ne_bytes(buffer[..8].try_into()?);
+            let mut offset = size_of::<i64>();
+            while offset + size_of::<USN_RECORD_V2>() <= returned {
+                let record = unsafe {
+                    std::ptr::read_unaligned(buffer.as_ptr().add(offset) as *const USN_RECORD_V2)
+                };
+                let length = record.RecordLength as usize;
+                if length == 0 || offset + length > returned {
+                    break;
+                }
+                if record.MajorVersion == 2 {
+                    let name_start = offset + record.FileNameOffset as usize;
+                    let name_end = name_start + record.FileNameLength as usize;
+                    if name_end <= offset + length {
+                        let name: Vec<u16> = buffer[name_start..name_end]
+                            .chunks_exact(2)
+                            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                            .collect();
+                        let parent = parents
+                            .entry(record.ParentFileReferenceNumber)
+                            .or_insert_with(|| {
+                                resolve_dir(&volume, record.ParentFileReferenceNumber)
+                            });
+                        // 上级目录也删除了，删除上级目录的记录会处理
+                        if let Some(parent) = parent {
+                            paths.push(parent.join(OsString::from_wide(&name)));
+                        }
+                    }
+                }
+                offset += length;
+            }
+        }
+        Ok(Some(paths))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::Result;
+    use fsevent_sys as fs;
+    use fsevent_sys::core_foundation as cf;
+    use log::info;
+    use std::ffi::{c_void, CStr, CString};
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    // 等待历史事件读取完的最长时间
+    const REPLAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRunLoopRunInMode(
+            mode: cf::CFStringRef,
+            seconds: cf::CFTimeInterval,
+            return_after_source_handled: cf::Boolean,
+        ) -> i32;
+        fn CFUUIDCreateString(allocator: cf::CFAllocatorRef, uuid: cf::CFRef) -> cf::CFStringRef;
+    }
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        fn FSEventsCopyUUIDForDevice(dev: libc::dev_t) -> cf::CFRef;
+    }
+
+    /// 卷的 FSEvents 数据库标识，数据库重建后会变化
+    fn device_uuid(root: &Path) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+        let dev = std::fs::metadata(root).ok()?.dev() as libc::dev_t;
+        unsafe {
+            let uuid = FSEventsCopyUUIDForDevice(dev);
+            if uuid.is_null() {
+                return None;
+            }
+            let string = CFUUIDCreateString(cf::kCFAllocatorDefault, uuid);
+            cf::CFRelease(uuid);
+            if string.is_null() {
+                return None;
+            }
+            let mut buffer = [0 as c_char; 64];
+            let ok = cf::CFStringGetCString(
+                string,
+                buffer.as_mut_ptr(),
+                buffer.len() as cf::CFIndex,
+                cf::kCFStringEncodingUTF8,
+            );
+            cf::CFRelease(string);
+            ok.then(|| {
+                CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        }
+    }
+
+    /// 格式为 设备 UUID:事件 ID
+    fn parse_cursor(cursor: &str) -> Option<(&str, u64)> {
+        let (uuid, id) = cursor.rsplit_once(':')?;
+        Some((uuid, id.parse().ok()?))
+    }
+
+    pub fn current_cursor(root: &Path) -> Result<Option<String>> {
+        let Some(uuid) = device_uuid(root) else {
+            return Ok(None);
+        };
+        let id = unsafe { fs::FSEventsGetCurrentEventId() };
+        Ok(Some(format!("{uuid}:{id}")))
+    }
+
+    #[derive(Default)]
+    struct Replay {
+        paths: Vec<PathBuf>,
+        rescan: bool,
+        done: bool,
+    }
+
+    extern "C" fn callback(
+        _stream: fs::FSEventStreamRef,
+        info: *mut c_void,
+        count: usize,
+        paths: *mut c_void,
+        flags: *const fs::FSEventStreamEventFlags,
+        _ids: *const fs::FSEventStreamEventId,
+    ) {
+        let replay = unsafe { &mut *(info as *mut Replay) };
+        let paths = paths as *const *const c_char;
+        for i in 0..count {
+            let flag = unsafe { *flags.add(i) };
+            if flag & fs::kFSEventStreamEventFlagHistoryDone != 0 {
+                replay.done = true;
+                unsafe { cf::CFRunLoopStop(cf::CFRunLoopGetCurrent()) };
+                continue;
+            }
+            if flag
+                & (fs::kFSEventStreamEventFlagMustScanSubDirs
+                    | fs::kFSEventStreamEventFlagUserDropped
+                    | fs::kFSEventStreamEventFlagKernelDropped
+                    | fs::kFSEventStreamEventFlagEventIdsWrapped
+                    | fs::kFSEventStreamEventFlagRootChanged)
+                != 0
+            {
+                replay.rescan = true;
+            }
+            let path = unsafe { CStr::from_ptr(*paths.add(i)) };
+            replay
+                .paths
+                .push(PathBuf::from(path.to_string_lossy().into_owned()));
+        }
+    }
+
+    pub fn changes_since(root: &Path, cursor: &str) -> Result<Option<Vec<PathBuf>>> {
+        let Some((uuid, since)) = parse_cursor(cursor) else {
+            return Ok(None);
+        };
+        // 事件数据库重建过，之前的事件 ID 没有意义
+        if device_uuid(root).as_deref() != Some(uuid)
+            || since > unsafe { fs::FSEventsGetCurrentEventId() }
+        {
+            info!("FSEvents 数据库已变化，改为检查目录: {}", root.display());
+            return Ok(None);
+        }
+
+        let mut replay = Replay::default();
+        let root_c = CString::new(root.as_os_str().as_bytes())?;
+        unsafe {
+            let path = cf::CFStringCreateWithCString(
+                cf::kCFAllocatorDefault,
+                root_c.as_ptr(),
+                cf::kCFStringEncodingUTF8,
+            );
+            let paths =
+                cf::CFArrayCreateMutable(cf::kCFAllocatorDefault, 1, &cf::kCFTypeArrayCallBacks);
+            cf::CFArrayAppendValue(paths, path);
+            cf::CFRelease(path);
+            let context = fs::FSEventStreamContext {
+                version: 0,
+                info: &mut replay as *mut Replay as *mut c_void,
+                retain: None,
+                release: None,
+                copy_description: None,
+            };
+            let stream = fs::FSEventStreamCreate(
+                cf::kCFAllocatorDefault,
+                callback,
+                &context,
+                paths,
+                since,
+                0.0,
+                fs::kFSEventStreamCreateFlagFileEvents | fs::kFSEventStreamCreateFlagNoDefer,
+            );
+            cf::CFRelease(paths);
+            if stream.is_null() {
+                return Ok(None);
+            }
+            fs::FSEventStreamScheduleWithRunLoop(
+                stream,
+                cf::CFRunLoopGetCurrent(),
+                cf::kCFRunLoopDefaultMode,
+            );
+            if fs::FSEventStreamStart(stream) != 0 {
+                let started = Instant::now();
+                while !replay.done && started.elapsed() < REPLAY_TIMEOUT {
+                    CFRunLoopRunInMode(cf::kCFRunLoopDefaultMode, 1.0, 0);
+                }
+                fs::FSEventStreamStop(stream);
+            }
+            fs::FSEventStreamInvalidate(stream);
+            fs::FSEventStreamRelease(stream);
+        }
+        if !replay.done || replay.rescan {
+            info!("FSEvents 历史不完整，改为检查目录: {}", root.display());
+            return Ok(None);
+        }
+        Ok(Some(replay.paths))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    use anyhow::Result;
+    use std::path::{Path, PathBuf};
+
+    pub fn current_cursor(_root: &Path) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    pub fn changes_since(_root: &Path, _cursor: &str) -> Result<Option<Vec<PathBuf>>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_to_root() {
+        let paths = vec![
+            PathBuf::from("/private/var/root/b.txt"),
+            PathBuf::from("/var/root/a.txt"),
+            PathBuf::from("/private/var/root/b.txt"),
+            PathBuf::from("/private/var/other/c.txt"),
+        ];
+        assert_eq!(
+            map_to_root(
+                Path::new("/var/root"),
+                Path::new("/private/var/root"),
+                paths
+            ),
+            vec![
+                PathBuf::from("/var/root/a.txt"),
+                PathBuf::from("/var/root/b.txt")
+            ]
+        );
+    }
+}
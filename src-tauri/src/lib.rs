@@ -1,36 +1,110 @@
-use ::log::info;
-use anyhow::Result;
+use ::log::{error, info, warn};
+use anyhow::{Context, Result};
 use serde::Serialize;
+use std::collections::HashMap;
 use std::future::Future;
 use std::path::Path;
 use std::thread;
+use std::time::Duration;
 use tauri::{async_runtime, RunEvent};
 use thiserror::Error;
 
-use crate::config::{Config, ExtensionConfigTree};
+use crate::backup::BackupInfo;
+use crate::compress::CompressStatus;
+use crate::config::{
+    BackupPolicy, BatteryPolicy, Chunking, Config, ExtensionConfigTree, ExternalReader, LowImpact,
+    MaxFileSize, WatchMode,
+};
+use crate::embedding::{EmbeddingModelInfo, EmbeddingModelStatus, EmbeddingProgress};
+use crate::encryption::EncryptionStatus;
+use crate::events::set_app_handle;
+use crate::history::SearchHistoryEntry;
+use crate::indexer::scope_to_root;
 use crate::indexer::IndexStatusStat;
 use crate::indexer::Indexer;
+use crate::indexer::MatchOptions;
+use crate::indexer::SearchAllResult;
+use crate::indexer::SearchFilter;
+use crate::indexer::SearchPage;
 use crate::indexer::SearchResultDirectory;
 use crate::indexer::SearchResultFile;
 use crate::indexer::SearchResultItem;
+use crate::indexer::SearchSort;
+use crate::indexer::SkippedFile;
+use crate::integrity::IntegrityReport;
 use crate::log::init_logger;
+use crate::maintenance::{MaintenanceResult, MaintenanceStatus};
 use crate::monitor::add_watched_path;
 use crate::monitor::del_watched_path;
 use crate::monitor::get_monitor;
-use crate::sqlite::{check_or_init_db, close_pool, init_pool};
-use crate::worker::{TaskStatusStat, Worker};
+use crate::monitor::RootWatch;
+use crate::plugin::PluginList;
+use crate::power::PowerStatus;
+use crate::progress::RootProgress;
+use crate::reconcile::ReconcileStatus;
+use crate::roots::RootFileCounts;
+use crate::safe_mode::SafeModeStatus;
+use crate::saved_search::SavedSearch;
+use crate::similar::SimilarFile;
+use crate::sqlite::{check_or_init_db, close_pool, init_pool, is_pool_ready};
+use crate::storage::IndexStorage;
+use crate::sync::{SyncResult, SyncRoot};
+use crate::tessdata::{install_bundled_languages, OcrLanguage};
+use crate::transfer::ExportManifest;
+use crate::utils::{normalize_path, path_to_str};
+use crate::worker::{FailedTask, TaskInfo, TaskStatus, TaskStatusStat, Worker};
 
+mod backup;
+mod charset;
+mod chunk;
+mod compress;
 mod config;
 mod dirs;
+mod download;
+mod embedding;
+mod encryption;
+mod events;
+mod history;
+mod ignore;
+mod image_meta;
+mod index_events;
 mod indexer;
+mod integrity;
+mod journal;
 mod log;
+mod mail;
+mod maintenance;
+mod markdown;
 mod monitor;
+mod password;
+mod plugin;
+mod power;
+mod progress;
 mod reader;
+mod reconcile;
+mod roots;
+mod safe_mode;
+mod saved_search;
+mod search_stream;
+mod share;
+mod similar;
 mod sqlite;
+mod storage;
+mod subtitle;
+mod sync;
+mod tessdata;
 mod test;
+mod throttle;
+mod tokenizer;
+mod transfer;
 mod utils;
+mod volume;
+mod wasm_plugin;
 mod worker;
 
+// 启动后这段时间内的崩溃计入启动崩溃
+const STARTUP_WINDOW: Duration = Duration::from_secs(600);
+
 #[derive(Debug, Error)]
 pub enum TauriError {
     #[error(transparent)]
@@ -61,10 +135,14 @@ where
 }
 
 #[tauri::command]
-async fn add_index_path(path: String) -> TauriResult<()> {
+async fn add_index_path(path: String, recursive: Option<bool>) -> TauriResult<()> {
     tauri_spawn(async move {
         // TODO 检查是否重复、覆盖
+        // 按磁盘上的大小写保存，和监听事件、扫描目录得到的路径一致
+        let path = path_to_str(&normalize_path(Path::new(&path)))?.to_string();
         let new_path = Path::new(&path);
+        // 监听前保存，只索引第一层的目录不监听子目录
+        Config::set_root_recursive(&path, recursive.unwrap_or(true))?;
         add_watched_path(new_path)?;
 
         let worker = Worker::new()?;
@@ -80,12 +158,49 @@ async fn add_index_path(path: String) -> TauriResult<()> {
     .await
 }
 
+#[tauri::command]
+async fn add_network_share(
+    path: String,
+    username: Option<String>,
+    password: Option<String>,
+) -> TauriResult<bool> {
+    tauri_spawn(async move {
+        share::add_share(&path, username, password)?;
+        let share = share::get_share(&path)?.context("Network share not saved")?;
+        let online = share::connect(&share)?;
+
+        let new_path = Path::new(&path);
+        if online {
+            add_watched_path(new_path)?;
+            let worker = Worker::new()?;
+            info!("开始索引共享目录: {}", new_path.display());
+            worker.submit_index_all_files(new_path)?;
+        } else {
+            info!("共享目录离线，稍后重试: {}", new_path.display());
+        }
+
+        let mut paths = Config::get_index_dir_paths()?;
+        if !paths.contains(&path) {
+            paths.push(path.clone());
+            Config::set_index_dir_paths(paths)?;
+        }
+
+        Ok(online)
+    })
+    .await
+}
+
 #[tauri::command]
 async fn del_index_path(path: String) -> TauriResult<()> {
     tauri_spawn(async move {
         let old_path = Path::new(&path);
         del_watched_path(old_path)?;
+        share::remove_share(&path)?;
+        charset::set_root_encoding(&path, None)?;
+        Config::set_root_recursive(&path, true)?;
 
+        // 刚添加就删除的目录可能还有很多没处理的任务
+        Worker::cancel_tasks(old_path)?;
         let worker = Worker::new()?;
         info!("开始删除目录: {}", old_path.display());
         worker.submit_delete_all_files(old_path)?;
@@ -93,6 +208,10 @@ async fn del_index_path(path: String) -> TauriResult<()> {
         let mut paths = Config::get_index_dir_paths()?;
         paths.retain(|p| p != &path);
         Config::set_index_dir_paths(paths)?;
+        Config::set_reconciled_at(&path, None)?;
+        Config::set_journal_cursor(&path, None)?;
+        // 删除后不再保留暂停监听的状态，重新添加时正常监听
+        monitor::resume_watching(&worker, Some(&path))?;
 
         Ok(())
     })
@@ -104,10 +223,22 @@ async fn search_directory(
     query: String,
     offset: usize,
     limit: usize,
-) -> TauriResult<Vec<SearchResultDirectory>> {
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+) -> TauriResult<SearchPage<SearchResultDirectory>> {
+    throttle::record_user_activity();
     tauri_spawn(async move {
         let indexer = Indexer::new()?;
-        indexer.search_directory(&query, offset, limit)
+        indexer.search_directory(
+            &query,
+            offset,
+            limit,
+            options.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            &scope_to_root(filter.unwrap_or_default(), root)?,
+        )
     })
     .await
 }
@@ -117,10 +248,22 @@ async fn search_file(
     query: String,
     offset: usize,
     limit: usize,
-) -> TauriResult<Vec<SearchResultFile>> {
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+) -> TauriResult<SearchPage<SearchResultFile>> {
+    throttle::record_user_activity();
     tauri_spawn(async move {
         let indexer = Indexer::new()?;
-        indexer.search_file(&query, offset, limit)
+        indexer.search_file(
+            &query,
+            offset,
+            limit,
+            options.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            &scope_to_root(filter.unwrap_or_default(), root)?,
+        )
     })
     .await
 }
@@ -130,10 +273,195 @@ async fn search_item(
     query: String,
     offset: usize,
     limit: usize,
-) -> TauriResult<Vec<SearchResultItem>> {
+    case_sensitive: Option<bool>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+) -> TauriResult<SearchPage<SearchResultItem>> {
+    throttle::record_user_activity();
     tauri_spawn(async move {
         let indexer = Indexer::new()?;
-        indexer.search_item(&query, offset, limit)
+        indexer.search_item(
+            &query,
+            offset,
+            limit,
+            case_sensitive.unwrap_or(false),
+            sort.unwrap_or_default(),
+            &scope_to_root(filter.unwrap_or_default(), root)?,
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+async fn search_similar_text(
+    query: String,
+    offset: usize,
+    limit: usize,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+) -> TauriResult<SearchPage<SearchResultItem>> {
+    throttle::record_user_activity();
+    tauri_spawn(async move {
+        embedding::search_similar_text(
+            &query,
+            offset,
+            limit,
+            &scope_to_root(filter.unwrap_or_default(), root)?,
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+async fn find_similar_files(path: String, limit: usize) -> TauriResult<Vec<SimilarFile>> {
+    tauri_spawn(async move { similar::find_similar(Path::new(&path), limit) }).await
+}
+
+#[tauri::command]
+async fn start_search(
+    query: String,
+    limit: usize,
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+    record_history: Option<bool>,
+) -> TauriResult<u64> {
+    throttle::record_user_activity();
+    tauri_spawn(async move {
+        let options = options.unwrap_or_default();
+        let sort = sort.unwrap_or_default();
+        let filter = scope_to_root(filter.unwrap_or_default(), root)?;
+        // 索引变化后自动刷新结果时不记录历史
+        if record_history.unwrap_or(true) {
+            history::add(&query, options, sort, &filter)?;
+        }
+        search_stream::start(query, limit, options, sort, filter)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn cancel_search() -> TauriResult<()> {
+    tauri_spawn(async move {
+        search_stream::cancel();
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn search_all(
+    query: String,
+    limit: usize,
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+    root: Option<String>,
+) -> TauriResult<SearchAllResult> {
+    throttle::record_user_activity();
+    tauri_spawn(async move {
+        let options = options.unwrap_or_default();
+        let sort = sort.unwrap_or_default();
+        let filter = scope_to_root(filter.unwrap_or_default(), root)?;
+        let result = Indexer::new()?.search_all(&query, limit, options, sort, &filter)?;
+        history::add(&query, options, sort, &filter)?;
+        Ok(result)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_search_history(limit: Option<usize>) -> TauriResult<Vec<SearchHistoryEntry>> {
+    tauri_spawn(async move { history::list(limit.unwrap_or(50)) }).await
+}
+
+#[tauri::command]
+async fn rerun_search_history(id: i64, limit: usize) -> TauriResult<SearchAllResult> {
+    tauri_spawn(async move {
+        let entry = history::get(id)?;
+        let result = Indexer::new()?.search_all(
+            &entry.query,
+            limit,
+            entry.options,
+            entry.sort,
+            &entry.filter,
+        )?;
+        history::add(&entry.query, entry.options, entry.sort, &entry.filter)?;
+        Ok(result)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn clear_search_history() -> TauriResult<()> {
+    tauri_spawn(async move { history::clear() }).await
+}
+
+#[tauri::command]
+async fn get_saved_searches() -> TauriResult<Vec<SavedSearch>> {
+    tauri_spawn(async move { saved_search::list() }).await
+}
+
+#[tauri::command]
+async fn create_saved_search(
+    name: String,
+    query: String,
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+) -> TauriResult<SavedSearch> {
+    tauri_spawn(async move {
+        saved_search::create(
+            &name,
+            &query,
+            options.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            &filter.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+async fn update_saved_search(
+    id: i64,
+    name: String,
+    query: String,
+    options: Option<MatchOptions>,
+    sort: Option<SearchSort>,
+    filter: Option<SearchFilter>,
+) -> TauriResult<SavedSearch> {
+    tauri_spawn(async move {
+        saved_search::update(
+            id,
+            &name,
+            &query,
+            options.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            &filter.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_saved_search(id: i64) -> TauriResult<()> {
+    tauri_spawn(async move { saved_search::delete(id) }).await
+}
+
+#[tauri::command]
+async fn run_saved_search(id: i64, limit: usize) -> TauriResult<SearchAllResult> {
+    tauri_spawn(async move {
+        let saved = saved_search::get(id)?;
+        Indexer::new()?.search_all(
+            &saved.query,
+            limit,
+            saved.options,
+            saved.sort,
+            &saved.filter,
+        )
     })
     .await
 }
@@ -166,6 +494,282 @@ async fn set_extension_enabled(extension: String, enabled: bool) -> TauriResult<
     .await
 }
 
+#[tauri::command]
+async fn get_text_encodings() -> TauriResult<HashMap<String, String>> {
+    tauri_spawn(async move { Config::get_text_encodings() }).await
+}
+
+#[tauri::command]
+async fn set_text_encoding(path: String, encoding: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move {
+        charset::set_root_encoding(&path, encoding.as_deref())?;
+
+        // 已索引的文本文件按新的编码重新读取
+        let worker = Worker::new()?;
+        for extension in ["txt", "md", "markdown", "html", "htm", "xhtml"] {
+            info!("处理文本编码变化: {path}, {encoding:?}, {extension}");
+            worker
+                .submit_index_all_files_with_force_extension(Path::new(&path), Some(extension))?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_root_watches() -> TauriResult<Vec<RootWatch>> {
+    tauri_spawn(async move { monitor::get_root_watches() }).await
+}
+
+#[tauri::command]
+async fn set_watch_mode(path: String, mode: WatchMode) -> TauriResult<()> {
+    tauri_spawn(async move { monitor::set_watch_mode(&path, mode) }).await
+}
+
+#[tauri::command]
+async fn get_reconcile_status() -> TauriResult<Vec<ReconcileStatus>> {
+    tauri_spawn(async move { Ok(reconcile::get_status()) }).await
+}
+
+#[tauri::command]
+async fn pause_watching(path: Option<String>) -> TauriResult<Vec<String>> {
+    tauri_spawn(async move { monitor::pause_watching(path.as_deref()) }).await
+}
+
+#[tauri::command]
+async fn resume_watching(path: Option<String>) -> TauriResult<Vec<String>> {
+    tauri_spawn(async move { monitor::resume_watching(&Worker::new()?, path.as_deref()) }).await
+}
+
+#[tauri::command]
+async fn set_root_recursive(path: String, recursive: bool) -> TauriResult<usize> {
+    tauri_spawn(async move {
+        let removed = Worker::new()?.set_root_recursive(Path::new(&path), recursive)?;
+        monitor::rewatch_path(Path::new(&path))?;
+        Ok(removed)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_poll_interval_secs() -> TauriResult<u64> {
+    tauri_spawn(async move { Config::get_poll_interval_secs() }).await
+}
+
+#[tauri::command]
+async fn set_poll_interval_secs(interval_secs: u64) -> TauriResult<()> {
+    // 轮询监听器在启动时创建，重启后生效
+    tauri_spawn(async move { Config::set_poll_interval_secs(interval_secs) }).await
+}
+
+#[tauri::command]
+async fn get_max_file_size() -> TauriResult<MaxFileSize> {
+    tauri_spawn(async move { Config::get_max_file_size() }).await
+}
+
+#[tauri::command]
+async fn set_max_file_size(max_file_size: MaxFileSize) -> TauriResult<()> {
+    tauri_spawn(async move {
+        Config::set_max_file_size(&max_file_size)?;
+
+        // 之前跳过的文件按新的上限重新处理，已读取的文件修改后才按新的上限处理
+        let worker = Worker::new()?;
+        for file in Indexer::new()?.get_skipped_files()? {
+            worker.submit_index_all_files(&Path::new(&file.path).join(&file.name))?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_skipped_files() -> TauriResult<Vec<SkippedFile>> {
+    tauri_spawn(async move { Indexer::new()?.get_skipped_files() }).await
+}
+
+#[tauri::command]
+async fn get_document_passwords() -> TauriResult<Vec<String>> {
+    tauri_spawn(async move { Config::get_document_passwords() }).await
+}
+
+#[tauri::command]
+async fn set_document_password(path: String, password: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move {
+        password::set_document_password(&path, password)?;
+
+        // 该路径下因为密码跳过的文件用新的密码重新读取
+        let worker = Worker::new()?;
+        for file in Indexer::new()?.get_skipped_files()? {
+            let file_path = Path::new(&file.path).join(&file.name);
+            if file_path.starts_with(&path) {
+                worker.submit_index_all_files(&file_path)?;
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_chunking() -> TauriResult<Chunking> {
+    tauri_spawn(async move { Config::get_chunking() }).await
+}
+
+#[tauri::command]
+async fn set_chunking(chunking: Chunking) -> TauriResult<()> {
+    // 已索引的文件修改后才按新的设置拆分
+    tauri_spawn(async move { chunk::set_chunking(&chunking) }).await
+}
+
+#[tauri::command]
+async fn get_low_impact() -> TauriResult<LowImpact> {
+    tauri_spawn(async move { Config::get_low_impact() }).await
+}
+
+#[tauri::command]
+async fn set_low_impact(low_impact: LowImpact) -> TauriResult<()> {
+    tauri_spawn(async move { Config::set_low_impact(&low_impact) }).await
+}
+
+#[tauri::command]
+async fn get_slow_lane_threshold_mb() -> TauriResult<u64> {
+    tauri_spawn(async move { Config::get_slow_lane_threshold_mb() }).await
+}
+
+#[tauri::command]
+async fn set_slow_lane_threshold_mb(threshold_mb: u64) -> TauriResult<()> {
+    // 新提交的任务按新的阈值分配
+    tauri_spawn(async move { Config::set_slow_lane_threshold_mb(threshold_mb) }).await
+}
+
+#[tauri::command]
+async fn get_io_rate_limit_mb() -> TauriResult<u64> {
+    tauri_spawn(async move { Config::get_io_rate_limit_mb() }).await
+}
+
+#[tauri::command]
+async fn set_io_rate_limit_mb(limit_mb: u64) -> TauriResult<()> {
+    tauri_spawn(async move { Config::set_io_rate_limit_mb(limit_mb) }).await
+}
+
+#[tauri::command]
+async fn get_task_timeout_secs() -> TauriResult<u64> {
+    tauri_spawn(async move { Config::get_task_timeout_secs() }).await
+}
+
+#[tauri::command]
+async fn set_task_timeout_secs(timeout_secs: u64) -> TauriResult<()> {
+    tauri_spawn(async move { Config::set_task_timeout_secs(timeout_secs) }).await
+}
+
+#[tauri::command]
+async fn get_ignore_patterns(root: String) -> TauriResult<Vec<String>> {
+    tauri_spawn(async move { Config::get_ignore_patterns(&root) }).await
+}
+
+#[tauri::command]
+async fn set_ignore_patterns(root: String, patterns: Vec<String>) -> TauriResult<usize> {
+    tauri_spawn(async move { Worker::new()?.set_ignore_patterns(Path::new(&root), patterns) }).await
+}
+
+#[tauri::command]
+async fn get_ocr_concurrency() -> TauriResult<usize> {
+    tauri_spawn(async move { Config::get_ocr_concurrency() }).await
+}
+
+#[tauri::command]
+async fn set_ocr_concurrency(concurrency: usize) -> TauriResult<()> {
+    // OCR 线程在启动时创建，重启后生效
+    tauri_spawn(async move { Config::set_ocr_concurrency(concurrency) }).await
+}
+
+#[tauri::command]
+async fn get_battery_policy() -> TauriResult<BatteryPolicy> {
+    tauri_spawn(async move { Config::get_battery_policy() }).await
+}
+
+#[tauri::command]
+async fn set_battery_policy(policy: BatteryPolicy) -> TauriResult<()> {
+    tauri_spawn(async move { Config::set_battery_policy(&policy) }).await
+}
+
+#[tauri::command]
+async fn get_power_status() -> TauriResult<PowerStatus> {
+    tauri_spawn(async move { Ok(power::get_power_status()) }).await
+}
+
+#[tauri::command]
+async fn get_external_readers() -> TauriResult<HashMap<String, ExternalReader>> {
+    tauri_spawn(async move { Config::get_external_readers() }).await
+}
+
+#[tauri::command]
+async fn set_external_reader(extension: String, reader: Option<ExternalReader>) -> TauriResult<()> {
+    tauri_spawn(async move {
+        let extension = plugin::set_external_reader(&extension, reader)?;
+
+        // 该扩展名的文件用新的命令重新读取，删除命令后按白名单处理
+        let worker = Worker::new()?;
+        for each in Config::get_index_dir_paths()? {
+            info!("处理外部命令变化: {extension}, {each}");
+            worker
+                .submit_index_all_files_with_force_extension(Path::new(&each), Some(&extension))?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_reader_plugins() -> TauriResult<PluginList> {
+    tauri_spawn(async move { Ok(plugin::list_plugins(&dirs::get_plugin_dir())) }).await
+}
+
+#[tauri::command]
+async fn get_ocr_languages() -> TauriResult<Vec<OcrLanguage>> {
+    tauri_spawn(async move { tessdata::get_languages() }).await
+}
+
+#[tauri::command]
+async fn get_available_ocr_languages() -> TauriResult<Vec<String>> {
+    tauri_spawn(async move { tessdata::get_available_languages() }).await
+}
+
+#[tauri::command]
+async fn download_ocr_language(code: String) -> TauriResult<()> {
+    tauri_spawn(async move { tessdata::download_language(&code) }).await
+}
+
+#[tauri::command]
+async fn set_ocr_languages(codes: Vec<String>) -> TauriResult<()> {
+    tauri_spawn(async move { tessdata::set_enabled_languages(codes) }).await
+}
+
+#[tauri::command]
+async fn get_embedding_models() -> TauriResult<Vec<EmbeddingModelStatus>> {
+    tauri_spawn(async move { embedding::list_models() }).await
+}
+
+#[tauri::command]
+async fn register_embedding_model(model: EmbeddingModelInfo) -> TauriResult<()> {
+    tauri_spawn(async move { embedding::register_model(model) }).await
+}
+
+#[tauri::command]
+async fn download_embedding_model(name: String) -> TauriResult<()> {
+    tauri_spawn(async move { embedding::download_model(&name) }).await
+}
+
+#[tauri::command]
+async fn set_embedding_model(name: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move { embedding::set_active_model(name) }).await
+}
+
+#[tauri::command]
+async fn get_embedding_progress() -> TauriResult<EmbeddingProgress> {
+    tauri_spawn(async move { embedding::get_progress() }).await
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct TotalStatus {
     task_status_stat: TaskStatusStat,
@@ -188,59 +792,443 @@ async fn get_status() -> TauriResult<TotalStatus> {
     .await
 }
 
+#[tauri::command]
+async fn pause_indexing() -> TauriResult<()> {
+    tauri_spawn(async move {
+        worker::set_paused(true);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn resume_indexing() -> TauriResult<()> {
+    tauri_spawn(async move {
+        worker::set_paused(false);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn reindex_path(path: String) -> TauriResult<()> {
+    tauri_spawn(async move { Worker::new()?.reindex_path(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn rebuild_index() -> TauriResult<usize> {
+    tauri_spawn(async move { Worker::new()?.rebuild_index() }).await
+}
+
+#[tauri::command]
+async fn verify_index(repair: bool) -> TauriResult<IntegrityReport> {
+    tauri_spawn(async move { integrity::verify_index(repair) }).await
+}
+
+#[tauri::command]
+async fn cancel_tasks(path: String) -> TauriResult<usize> {
+    tauri_spawn(async move { Worker::cancel_tasks(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn get_index_progress() -> TauriResult<Vec<RootProgress>> {
+    tauri_spawn(async move { progress::get_progress() }).await
+}
+
+#[tauri::command]
+async fn get_root_progress() -> TauriResult<Vec<RootFileCounts>> {
+    tauri_spawn(async move { roots::get_root_file_counts() }).await
+}
+
+#[tauri::command]
+async fn export_index(path: String) -> TauriResult<ExportManifest> {
+    tauri_spawn(async move { transfer::export_index(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn import_index(path: String) -> TauriResult<ExportManifest> {
+    tauri_spawn(async move { transfer::import_index(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn backup_index(path: String) -> TauriResult<BackupInfo> {
+    tauri_spawn(async move { backup::backup_index(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn list_backups() -> TauriResult<Vec<BackupInfo>> {
+    tauri_spawn(async move { backup::list_backups() }).await
+}
+
+#[tauri::command]
+async fn restore_index(path: String) -> TauriResult<()> {
+    tauri_spawn(async move { backup::restore_index(Path::new(&path)) }).await
+}
+
+#[tauri::command]
+async fn get_backup_policy() -> TauriResult<BackupPolicy> {
+    tauri_spawn(async move { Config::get_backup_policy() }).await
+}
+
+#[tauri::command]
+async fn set_backup_policy(policy: BackupPolicy) -> TauriResult<()> {
+    tauri_spawn(async move { Config::set_backup_policy(&policy) }).await
+}
+
+#[tauri::command]
+async fn run_maintenance() -> TauriResult<MaintenanceResult> {
+    tauri_spawn(async move { maintenance::run_maintenance() }).await
+}
+
+#[tauri::command]
+async fn get_maintenance_status() -> TauriResult<MaintenanceStatus> {
+    tauri_spawn(async move { maintenance::get_status() }).await
+}
+
+#[tauri::command]
+async fn restart_app(app: tauri::AppHandle) {
+    app.restart();
+}
+
+#[tauri::command]
+async fn get_encryption_status() -> TauriResult<EncryptionStatus> {
+    tauri_spawn(async move { encryption::get_status() }).await
+}
+
+#[tauri::command]
+async fn unlock_index(passphrase: String) -> TauriResult<()> {
+    tauri_spawn(async move {
+        if is_pool_ready() {
+            return Ok(());
+        }
+        encryption::unlock(&passphrase)?;
+        open_index()?;
+        start_index_services();
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_index_passphrase(passphrase: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move {
+        encryption::set_passphrase(passphrase.as_deref().filter(|p| !p.is_empty()))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_compress_status() -> TauriResult<CompressStatus> {
+    tauri_spawn(async move { compress::get_status() }).await
+}
+
+#[tauri::command]
+async fn set_compress_content(enabled: bool) -> TauriResult<()> {
+    tauri_spawn(async move { compress::set_enabled(enabled) }).await
+}
+
+#[tauri::command]
+async fn get_index_storage() -> TauriResult<IndexStorage> {
+    tauri_spawn(async move { storage::get_index_storage() }).await
+}
+
+#[tauri::command]
+async fn get_failed_tasks() -> TauriResult<Vec<FailedTask>> {
+    tauri_spawn(async move { Worker::get_failed_tasks() }).await
+}
+
+#[tauri::command]
+async fn list_tasks(
+    status: Option<TaskStatus>,
+    offset: usize,
+    limit: usize,
+) -> TauriResult<SearchPage<TaskInfo>> {
+    tauri_spawn(async move { Worker::list_tasks(status, offset, limit) }).await
+}
+
+#[tauri::command]
+async fn retry_failed_tasks() -> TauriResult<usize> {
+    tauri_spawn(async move { Worker::new()?.retry_failed_tasks() }).await
+}
+
+#[tauri::command]
+async fn retry_failed(path: String) -> TauriResult<bool> {
+    tauri_spawn(async move { Worker::new()?.retry_failed(&path) }).await
+}
+
+#[tauri::command]
+async fn get_sync_dir() -> TauriResult<Option<String>> {
+    tauri_spawn(async move { Config::get_sync_dir() }).await
+}
+
+#[tauri::command]
+async fn set_sync_dir(sync_dir: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move { sync::set_sync_dir(sync_dir) }).await
+}
+
+#[tauri::command]
+async fn get_sync_roots() -> TauriResult<Vec<SyncRoot>> {
+    tauri_spawn(async move { sync::get_sync_roots() }).await
+}
+
+#[tauri::command]
+async fn set_sync_root(remote_root: String, local_root: Option<String>) -> TauriResult<()> {
+    tauri_spawn(async move { sync::set_sync_root(remote_root, local_root) }).await
+}
+
+#[tauri::command]
+async fn sync_index() -> TauriResult<SyncResult> {
+    tauri_spawn(async move { sync::sync_now() }).await
+}
+
+#[tauri::command]
+async fn get_safe_mode_status() -> TauriResult<SafeModeStatus> {
+    tauri_spawn(async move { safe_mode::get_status() }).await
+}
+
+#[tauri::command]
+async fn exit_safe_mode(skip_suspected: bool) -> TauriResult<()> {
+    tauri_spawn(async move {
+        if !safe_mode::is_enabled()? {
+            return Ok(());
+        }
+        safe_mode::exit(skip_suspected)?;
+        start_services();
+        Ok(())
+    })
+    .await
+}
+
 pub fn setup_backend() {
     init_logger();
+    encryption::apply_pending_rekey().unwrap();
+    transfer::apply_pending_import().unwrap();
+    if encryption::is_encrypted().unwrap() {
+        info!("索引已加密，输入密码后再打开");
+        return;
+    }
+    open_index().unwrap();
+}
+
+/// 打开数据库，加密的索引在输入密码后打开
+fn open_index() -> Result<()> {
     init_pool();
 
-    check_or_init_db().unwrap();
-    Worker::reset_running_tasks().unwrap();
+    check_or_init_db()?;
+    transfer::finish_import()?;
+    safe_mode::begin_session()?;
+    Worker::reset_running_tasks()?;
+    Ok(())
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    setup_backend();
+/// 安全模式下不启动后台服务
+fn start_index_services() {
+    if safe_mode::is_enabled().unwrap() {
+        info!("安全模式，不启动后台服务");
+    } else {
+        start_services();
+    }
+}
 
+/// 启动目录检查、变更监听和后台索引等服务，安全模式下不启动
+fn start_services() {
     info!("开始检查已有目录");
     thread::Builder::new()
         .name("initial-check-index-dir-paths".to_string())
         .spawn(|| {
+            for share in Config::get_network_shares().unwrap() {
+                if let Err(e) = share::connect(&share) {
+                    error!("连接共享目录失败: {}, 错误: {:?}", share.path, e);
+                }
+            }
+
             let worker = Worker::new().unwrap();
             Config::get_index_dir_paths()
                 .unwrap()
                 .iter()
                 .for_each(|path| {
                     info!("开始检查目录: {path}");
-                    worker.submit_index_all_files(Path::new(path)).unwrap();
-                    info!("目录检查完成: {path}");
+                    if let Err(e) = reconcile::reconcile_root(&worker, path) {
+                        error!("检查目录失败: {path}, 错误: {e:?}");
+                    }
                 });
         })
         .unwrap();
 
     info!("启动后台变更监听");
     get_monitor();
+    monitor::start_health_check().unwrap();
 
     info!("启动后台索引服务");
     Worker::start_process().unwrap();
 
+    info!("启动后台向量服务");
+    embedding::start_process().unwrap();
+
+    info!("启动后台同步服务");
+    sync::start_process().unwrap();
+
+    info!("启动索引目录接入检查");
+    volume::start_process().unwrap();
+
+    info!("启动内容压缩转换");
+    compress::start_process().unwrap();
+
+    info!("启动定时备份");
+    backup::start_process().unwrap();
+
+    info!("启动空闲时维护数据库");
+    maintenance::start_process().unwrap();
+
+    thread::Builder::new()
+        .name("startup-watch".to_string())
+        .spawn(|| {
+            thread::sleep(STARTUP_WINDOW);
+            if let Err(e) = safe_mode::mark_startup_finished() {
+                error!("更新启动状态失败: {e:?}");
+            }
+        })
+        .unwrap();
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    setup_backend();
+
+    info!("安装内置OCR语言包");
+    // 安装失败时仍然可以使用已经下载的语言包
+    if let Err(e) = install_bundled_languages() {
+        warn!("安装内置OCR语言包失败: {e:?}");
+    }
+
+    if is_pool_ready() {
+        start_index_services();
+    }
+
     info!("启动tauri前端服务");
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            set_app_handle(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             search_directory,
             search_file,
             search_item,
+            search_all,
+            start_search,
+            cancel_search,
+            search_similar_text,
+            find_similar_files,
+            get_search_history,
+            rerun_search_history,
+            clear_search_history,
+            get_saved_searches,
+            create_saved_search,
+            update_saved_search,
+            delete_saved_search,
+            run_saved_search,
             add_index_path,
             del_index_path,
+            add_network_share,
             get_index_dir_paths,
             get_extension_whitelist,
             set_extension_enabled,
+            get_text_encodings,
+            set_text_encoding,
+            get_root_watches,
+            set_watch_mode,
+            get_reconcile_status,
+            pause_watching,
+            resume_watching,
+            set_root_recursive,
+            get_poll_interval_secs,
+            set_poll_interval_secs,
+            get_max_file_size,
+            set_max_file_size,
+            get_skipped_files,
+            get_document_passwords,
+            set_document_password,
+            get_chunking,
+            set_chunking,
+            get_low_impact,
+            set_low_impact,
+            get_slow_lane_threshold_mb,
+            set_slow_lane_threshold_mb,
+            get_io_rate_limit_mb,
+            set_io_rate_limit_mb,
+            get_task_timeout_secs,
+            set_task_timeout_secs,
+            get_ignore_patterns,
+            set_ignore_patterns,
+            get_ocr_concurrency,
+            set_ocr_concurrency,
+            get_battery_policy,
+            set_battery_policy,
+            get_power_status,
+            get_external_readers,
+            set_external_reader,
+            get_reader_plugins,
             get_status,
+            pause_indexing,
+            resume_indexing,
+            cancel_tasks,
+            reindex_path,
+            rebuild_index,
+            verify_index,
+            get_index_progress,
+            get_root_progress,
+            get_index_storage,
+            get_compress_status,
+            set_compress_content,
+            export_index,
+            import_index,
+            backup_index,
+            list_backups,
+            restore_index,
+            get_backup_policy,
+            set_backup_policy,
+            run_maintenance,
+            get_maintenance_status,
+            restart_app,
+            get_encryption_status,
+            unlock_index,
+            set_index_passphrase,
+            get_failed_tasks,
+            retry_failed_tasks,
+            retry_failed,
+            list_tasks,
+            get_ocr_languages,
+            get_available_ocr_languages,
+            download_ocr_language,
+            set_ocr_languages,
+            get_embedding_models,
+            register_embedding_model,
+            download_embedding_model,
+            set_embedding_model,
+            get_embedding_progress,
+            get_sync_dir,
+            set_sync_dir,
+            get_sync_roots,
+            set_sync_root,
+            sync_index,
+            get_safe_mode_status,
+            exit_safe_mode,
         ])
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
         .run(|_app_handle, event| {
-            if let RunEvent::Exit = event {
+            // 加密的索引没有输入密码时数据库没有打开，后台服务也没有启动
+            if let (RunEvent::Exit, true) = (event, is_pool_ready()) {
+                // 先停止索引线程，避免关闭连接池时还在写入
+                if let Err(e) = Worker::shutdown() {
+                    error!("停止索引线程失败: {e:?}");
+                }
+                if let Err(e) = safe_mode::end_session() {
+                    error!("保存会话状态失败: {e:?}");
+                }
                 close_pool();
             }
         });
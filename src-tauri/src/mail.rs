@@ -0,0 +1,362 @@
+use base64::Engine;
+use encoding_rs::Encoding;
+
+// 建立索引的邮件头，按这个顺序输出
+pub const INDEXED_HEADERS: &[&str] = &["subject", "from", "to", "cc", "date"];
+
+// multipart 嵌套的最大层数，避免构造出来的邮件导致递归过深
+const MAX_MULTIPART_DEPTH: usize = 8;
+
+/// 解析后的一封邮件，头的名称都是小写，值已经解码
+#[derive(Debug, Default)]
+pub struct MailMessage {
+    pub headers: Vec<(String, String)>,
+    /// 所有 text/plain 正文
+    pub text: Vec<String>,
+    /// 所有 text/html 正文，没有纯文本正文时使用
+    pub html: Vec<String>,
+}
+
+impl MailMessage {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// 头和正文以第一个空行分隔
+fn split_header_body(raw: &[u8]) -> (&[u8], &[u8]) {
+    for (i, window) in raw.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return (&raw[..i], &raw[i + 2..]);
+        }
+        if window == b"\n\r" && raw.get(i + 2) == Some(&b'\n') {
+            return (&raw[..i], &raw[i + 3..]);
+        }
+    }
+    (raw, &[])
+}
+
+/// 展开折行的邮件头，并解码其中的 RFC 2047 编码
+fn parse_headers(raw: &[u8]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(raw).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    headers
+        .into_iter()
+        .map(|(name, value)| (name, decode_encoded_words(&value)))
+        .collect()
+}
+
+/// 拆分 `text/plain; charset="utf-8"` 形式的值，参数名为小写
+fn parse_header_params(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let main = parts.next().unwrap_or("").trim().to_lowercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.trim().to_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    (main, params)
+}
+
+fn find_param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// 按字符集解码，未知的字符集按 utf8 处理
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    match charset.and_then(|c| Encoding::for_label(c.trim().as_bytes())) {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let compact: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(&compact)
+        .ok()
+}
+
+/// 解码 quoted-printable。邮件头中的 Q 编码用 `_` 表示空格
+fn decode_quoted_printable(input: &[u8], header: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' => {
+                let rest = &input[i + 1..];
+                if rest.starts_with(b"\r\n") {
+                    // 软换行
+                    i += 3;
+                } else if rest.starts_with(b"\n") {
+                    i += 2;
+                } else if let Some(byte) = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    output.push(byte);
+                    i += 3;
+                } else {
+                    output.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if header => {
+                output.push(b' ');
+                i += 1;
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+/// 解码邮件头中的 `=?charset?B|Q?text?=`，相邻编码词之间的空白忽略
+fn decode_encoded_words(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    let mut last_was_encoded = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..]
+            .split_once('?')
+            .and_then(|(charset, tail)| {
+                let (encoding, tail) = tail.split_once('?')?;
+                let end = tail.find("?=")?;
+                let text = &tail.as_bytes()[..end];
+                let bytes = match encoding {
+                    "B" | "b" => decode_base64(text)?,
+                    "Q" | "q" => decode_quoted_printable(text, true),
+                    _ => return None,
+                };
+                // 编码词总长度，从 =? 开始到 ?= 结束
+                let len = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+                Some((decode_charset(&bytes, Some(charset)), len))
+            });
+        match decoded {
+            Some((text, len)) => {
+                let between = &rest[..start];
+                if !(last_was_encoded && between.trim().is_empty()) {
+                    result.push_str(between);
+                }
+                result.push_str(&text);
+                rest = &rest[start + len..];
+                last_was_encoded = true;
+            }
+            None => {
+                result.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                last_was_encoded = false;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 按 `--boundary` 拆分 multipart 正文，忽略第一个分隔行之前和结束行之后的内容
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let end = body[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(body.len(), |i| pos + i + 1);
+        let line = String::from_utf8_lossy(&body[pos..end]);
+        let line = line.trim_end();
+        if line.starts_with(&delimiter) {
+            if let Some(start) = current {
+                parts.push(&body[start..pos]);
+            }
+            if line[delimiter.len()..].starts_with("--") {
+                return parts;
+            }
+            current = Some(end);
+        }
+        pos = end;
+    }
+    if let Some(start) = current {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+fn collect_body(
+    headers: &[(String, String)],
+    body: &[u8],
+    depth: usize,
+    message: &mut MailMessage,
+) {
+    let (content_type, params) =
+        parse_header_params(find_header(headers, "content-type").unwrap_or("text/plain"));
+    let (disposition, _) =
+        parse_header_params(find_header(headers, "content-disposition").unwrap_or(""));
+    if disposition == "attachment" {
+        return;
+    }
+
+    if content_type.starts_with("multipart/") {
+        let Some(boundary) = find_param(&params, "boundary") else {
+            return;
+        };
+        if depth >= MAX_MULTIPART_DEPTH {
+            return;
+        }
+        for part in split_multipart(body, boundary) {
+            let (part_headers, part_body) = split_header_body(part);
+            collect_body(&parse_headers(part_headers), part_body, depth + 1, message);
+        }
+        return;
+    }
+    if content_type != "text/plain" && content_type != "text/html" {
+        return;
+    }
+
+    let encoding = find_header(headers, "content-transfer-encoding")
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+    let bytes = match encoding.as_str() {
+        "base64" => decode_base64(body).unwrap_or_else(|| body.to_vec()),
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    };
+    let text = decode_charset(&bytes, find_param(&params, "charset"));
+    if content_type == "text/html" {
+        message.html.push(text);
+    } else {
+        message.text.push(text);
+    }
+}
+
+/// 解析一封 RFC 822 邮件，附件不读取
+pub fn parse_message(raw: &[u8]) -> MailMessage {
+    let (header_bytes, body) = split_header_body(raw);
+    let mut message = MailMessage {
+        headers: parse_headers(header_bytes),
+        ..Default::default()
+    };
+    let headers = message.headers.clone();
+    collect_body(&headers, body, 0, &mut message);
+    message
+}
+
+/// 按 `From ` 开头的分隔行拆分 mbox 文件，并还原正文中被转义的 `>From `
+pub fn split_mbox(raw: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages: Vec<Vec<u8>> = Vec::new();
+    let mut pos = 0;
+    while pos < raw.len() {
+        let end = raw[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(raw.len(), |i| pos + i + 1);
+        let line = &raw[pos..end];
+        if line.starts_with(b"From ") {
+            messages.push(Vec::new());
+        } else if let Some(message) = messages.last_mut() {
+            let unescaped = line.starts_with(b">")
+                && line.iter().skip_while(|b| **b == b'>').take(5).eq(b"From ");
+            message.extend_from_slice(if unescaped { &line[1..] } else { line });
+        }
+        pos = end;
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_encoded_words() {
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?5rWL6K+V?= =?utf-8?Q?_report?="),
+            "测试 report"
+        );
+        assert_eq!(
+            decode_encoded_words("Re: =?gb2312?B?xOO6ww==?= ok"),
+            "Re: 你好 ok"
+        );
+        assert_eq!(decode_encoded_words("plain =? text"), "plain =? text");
+    }
+
+    #[test]
+    fn test_parse_multipart_message() {
+        let raw = b"From: =?UTF-8?B?5byg5LiJ?= <zhang@example.com>\r\n\
+Subject: Quarterly\r\n report\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+=E4=BD=A0=E5=A5=BD, soft=\r\n\
+break\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+PHA+5L2g5aW9PC9wPg==\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename=a.txt\r\n\
+\r\n\
+attached\r\n\
+--outer--\r\n";
+        let message = parse_message(raw);
+        assert_eq!(message.header("from"), Some("张三 <zhang@example.com>"));
+        assert_eq!(message.header("subject"), Some("Quarterly report"));
+        assert_eq!(message.text, vec!["你好, softbreak\r\n"]);
+        assert_eq!(message.html, vec!["<p>你好</p>"]);
+    }
+
+    #[test]
+    fn test_split_mbox() {
+        let raw = b"From alice Mon Jan  1 00:00:00 2024\nSubject: one\n\n>From the start\n\nFrom bob Tue Jan  2 00:00:00 2024\nSubject: two\n\nbody\n";
+        let messages = split_mbox(raw);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            String::from_utf8_lossy(&messages[0]),
+            "Subject: one\n\nFrom the start\n\n"
+        );
+        assert_eq!(parse_message(&messages[1]).header("subject"), Some("two"));
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use log::{error, info};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::sqlite::{get_conn, lock_whole_db};
+use crate::storage::db_bytes;
+use crate::throttle;
+use crate::worker::Worker;
+
+// 检查是否需要维护的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+// 两次定时维护之间至少间隔的时间
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(7 * 24 * 3600);
+// 需要合并 b-tree 的全文索引表
+const FTS_TABLES: &[&str] = &["items_fts", "items_words"];
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// 维护结束时清除 RUNNING，维护中途出错或崩溃也不会一直显示正在维护
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 一次维护前后的数据库大小和用时
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceResult {
+    pub db_bytes_before: u64,
+    pub db_bytes_after: u64,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceStatus {
+    pub running: bool,
+    /// 上次维护完成的时间，从没有维护过时为空
+    pub maintained_at: Option<String>,
+}
+
+/// 合并 WAL、合并全文索引、更新统计信息并整理数据库文件。
+/// VACUUM 期间其他连接的写入会等待，定时维护只在空闲时进行
+pub fn run_maintenance() -> Result<MaintenanceResult> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        bail!("正在维护索引");
+    }
+    let _running = RunningGuard;
+    // 正在备份、导出或修改密码时等它们完成
+    let _whole_db = lock_whole_db();
+    maintain()
+}
+
+fn maintain() -> Result<MaintenanceResult> {
+    let started = Instant::now();
+    let db_bytes_before = db_bytes();
+    info!("开始维护索引, 数据库大小: {db_bytes_before}");
+    let conn = get_conn()?;
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    // 写入时产生的多个 b-tree 合并成一个，查询时少读几层
+    for table in FTS_TABLES {
+        conn.execute(
+            &format!("INSERT INTO {table} ({table}) VALUES ('optimize')"),
+            [],
+        )?;
+    }
+    conn.execute_batch(
+        r"ANALYZE;
+        VACUUM;
+        PRAGMA wal_checkpoint(TRUNCATE);",
+    )?;
+    Config::set_maintained_at(Local::now().timestamp())?;
+    let result = MaintenanceResult {
+        db_bytes_before,
+        db_bytes_after: db_bytes(),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    info!("维护索引完成: {result:?}");
+    Ok(result)
+}
+
+pub fn get_status() -> Result<MaintenanceStatus> {
+    Ok(MaintenanceStatus {
+        running: RUNNING.load(Ordering::SeqCst),
+        maintained_at: Config::get_maintained_at()?
+            .and_then(|time| DateTime::from_timestamp(time, 0))
+            .map(|time| time.with_timezone(&Local).to_rfc3339()),
+    })
+}
+
+/// 距离上次维护超过间隔，并且没有要处理的任务、最近也没有搜索时维护
+fn is_due(now: i64) -> Result<bool> {
+    let due = Config::get_maintained_at()?
+        .is_none_or(|last| now - last >= MAINTENANCE_INTERVAL.as_secs() as i64);
+    Ok(due && !throttle::user_active() && Worker::is_idle()?)
+}
+
+pub fn start_process() -> Result<()> {
+    thread::Builder::new()
+        .name("index-maintenance".to_string())
+        .spawn(|| loop {
+            thread::sleep(CHECK_INTERVAL);
+            match is_due(Local::now().timestamp()) {
+                Ok(true) => {
+                    if let Err(e) = run_maintenance() {
+                        error!("维护索引失败: {e:?}");
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => error!("检查是否需要维护索引失败: {e:?}"),
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_running_reset_after_panic() {
+        let result = std::panic::catch_unwind(|| {
+            RUNNING.store(true, Ordering::SeqCst);
+            let _running = RunningGuard;
+            panic!("维护时崩溃");
+        });
+        assert!(result.is_err());
+        assert!(!RUNNING.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_run_maintenance() {
+        let env = TestEnv::new();
+        let file = env.temp_dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let items = vec![Item {
+            content: "维护的内容".into(),
+            ..Default::default()
+        }];
+        Indexer::new()
+            .unwrap()
+            .write_file_items(&file, items)
+            .unwrap();
+
+        let now = Local::now().timestamp();
+        assert!(get_status().unwrap().maintained_at.is_none());
+        assert!(is_due(now).unwrap());
+
+        let result = run_maintenance().unwrap();
+        assert!(result.db_bytes_after > 0);
+        let status = get_status().unwrap();
+        assert!(!status.running);
+        assert!(status.maintained_at.is_some());
+        assert!(!is_due(now).unwrap());
+        assert!(is_due(now + MAINTENANCE_INTERVAL.as_secs() as i64).unwrap());
+
+        // 维护后全文索引照常使用
+        let count: i64 = get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM items_fts WHERE items_fts MATCH '维护的'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // 有等待处理的任务时不维护
+        Worker::new()
+            .unwrap()
+            .submit_index_all_files(&file)
+            .unwrap();
+        assert!(!is_due(now + MAINTENANCE_INTERVAL.as_secs() as i64).unwrap());
+    }
+}
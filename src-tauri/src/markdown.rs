@@ -0,0 +1,258 @@
+use serde_yaml::Value as YamlValue;
+
+use crate::reader::{Item, ItemPosition};
+
+/// 正在收集的一段内容：起始行号和各行
+struct Block {
+    line: usize,
+    lines: Vec<String>,
+}
+
+fn heading_level(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    // 去掉结尾可选的 #
+    let text = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, text))
+}
+
+fn fence_marker(line: &str) -> Option<&str> {
+    ["```", "~~~"]
+        .into_iter()
+        .find(|marker| line.starts_with(marker))
+}
+
+fn is_setext_underline(line: &str) -> bool {
+    !line.is_empty() && (line.chars().all(|c| c == '=') || line.chars().all(|c| c == '-'))
+}
+
+/// 列表项的标记，如 `- `、`* `、`1. `，返回去掉标记后的内容
+fn strip_list_marker(line: &str) -> Option<&str> {
+    if let Some(rest) = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+    {
+        return Some(rest);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        return line[digits..]
+            .strip_prefix(". ")
+            .or_else(|| line[digits..].strip_prefix(") "));
+    }
+    None
+}
+
+fn yaml_text(value: &YamlValue) -> Option<String> {
+    match value {
+        YamlValue::String(s) => Some(s.clone()),
+        YamlValue::Number(n) => Some(n.to_string()),
+        YamlValue::Bool(b) => Some(b.to_string()),
+        YamlValue::Sequence(values) => {
+            let texts: Vec<String> = values.iter().filter_map(yaml_text).collect();
+            (!texts.is_empty()).then(|| texts.join(", "))
+        }
+        _ => None,
+    }
+}
+
+/// 开头 `---` 之间的 YAML，如 title、tags，索引为 `tags: rust, tauri` 形式。嵌套的值不索引
+fn frontmatter_items(yaml: &str) -> Vec<Item> {
+    let Ok(YamlValue::Mapping(mapping)) = serde_yaml::from_str::<YamlValue>(yaml) else {
+        return Vec::new();
+    };
+    mapping
+        .iter()
+        .filter_map(|(key, value)| {
+            let key = key.as_str()?;
+            let value = yaml_text(value)?;
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!value.is_empty()).then(|| Item {
+                content: format!("{key}: {value}"),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// 按标题、段落、列表项和代码块拆分 Markdown，每条记录起始行号和所在的标题
+pub fn markdown_items(text: &str) -> Vec<Item> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut items = Vec::new();
+    let mut start = 0;
+
+    if lines.first().map(|line| line.trim_end()) == Some("---") {
+        if let Some(end) = lines[1..]
+            .iter()
+            .position(|line| matches!(line.trim_end(), "---" | "..."))
+        {
+            items.extend(frontmatter_items(&lines[1..end + 1].join("\n")));
+            start = end + 2;
+        }
+    }
+
+    let mut heading: Option<String> = None;
+    let mut block: Option<Block> = None;
+    let mut fence: Option<&str> = None;
+
+    let flush = |block: &mut Option<Block>, heading: &Option<String>, items: &mut Vec<Item>| {
+        if let Some(block) = block.take() {
+            let content = block.lines.join("\n");
+            if !content.trim().is_empty() {
+                items.push(Item {
+                    content,
+                    position: ItemPosition {
+                        line: Some(block.line as i64),
+                        heading: heading.clone(),
+                        ..Default::default()
+                    },
+                });
+            }
+        }
+    };
+
+    for (i, raw) in lines.iter().enumerate().skip(start) {
+        let line_num = i + 1;
+        let line = raw.trim();
+
+        // 代码块原样作为一条，不识别其中的标题
+        if let Some(marker) = fence {
+            if line.starts_with(marker) {
+                fence = None;
+                flush(&mut block, &heading, &mut items);
+            } else if let Some(block) = block.as_mut() {
+                block.lines.push(raw.to_string());
+            }
+            continue;
+        }
+        if let Some(marker) = fence_marker(line) {
+            flush(&mut block, &heading, &mut items);
+            fence = Some(marker);
+            block = Some(Block {
+                line: line_num + 1,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+
+        if line.is_empty() {
+            flush(&mut block, &heading, &mut items);
+            continue;
+        }
+        if let Some((_, text)) = heading_level(line) {
+            flush(&mut block, &heading, &mut items);
+            heading = (!text.is_empty()).then(|| text.to_string());
+            block = Some(Block {
+                line: line_num,
+                lines: vec![text.to_string()],
+            });
+            flush(&mut block, &heading, &mut items);
+            continue;
+        }
+        // 段落下一行的 === 或 --- 表示该段落是标题
+        if is_setext_underline(line) {
+            if let Some(mut current) = block.take() {
+                let text = current.lines.join(" ");
+                heading = Some(text.clone());
+                current.lines = vec![text];
+                block = Some(current);
+            }
+            flush(&mut block, &heading, &mut items);
+            continue;
+        }
+
+        let line = line.trim_start_matches('>').trim();
+        if let Some(rest) = strip_list_marker(line) {
+            flush(&mut block, &heading, &mut items);
+            block = Some(Block {
+                line: line_num,
+                lines: vec![rest.to_string()],
+            });
+        } else if let Some(block) = block.as_mut() {
+            block.lines.push(line.to_string());
+        } else {
+            block = Some(Block {
+                line: line_num,
+                lines: vec![line.to_string()],
+            });
+        }
+    }
+    flush(&mut block, &heading, &mut items);
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|item| item.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_markdown_items() {
+        let text = r#"---
+title: 使用说明
+tags: [rust, tauri]
+nested:
+  key: value
+---
+开头的说明
+
+# 安装 #
+
+下载安装包，
+然后运行。
+
+- 第一项
+- 第二项
+> 引用的内容
+
+```sh
+# 不是标题
+cargo build
+```
+
+配置
+---
+
+1. 打开设置
+"#;
+        let items = markdown_items(text);
+        assert_eq!(
+            contents(&items),
+            vec![
+                "title: 使用说明",
+                "tags: rust, tauri",
+                "开头的说明",
+                "安装",
+                "下载安装包，\n然后运行。",
+                "第一项",
+                "第二项\n引用的内容",
+                "# 不是标题\ncargo build",
+                "配置",
+                "打开设置",
+            ]
+        );
+        assert_eq!(items[0].position, ItemPosition::default());
+        assert_eq!(items[2].position.heading, None);
+        assert_eq!(items[2].position.line, Some(7));
+        assert_eq!(items[4].position.heading.as_deref(), Some("安装"));
+        assert_eq!(items[4].position.line, Some(11));
+        assert_eq!(items[7].position.line, Some(19));
+        assert_eq!(items[7].position.heading.as_deref(), Some("安装"));
+        assert_eq!(items[9].position.heading.as_deref(), Some("配置"));
+    }
+
+    #[test]
+    fn test_markdown_items_without_frontmatter() {
+        // 没有结束的 --- 时按普通内容处理
+        let items = markdown_items("---\n正文\n#不是标题");
+        assert_eq!(contents(&items), vec!["正文\n#不是标题"]);
+        assert_eq!(items[0].position.line, Some(2));
+    }
+}
@@ -1,94 +1,604 @@
 use anyhow::Result;
-use log::{debug, error, info};
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use log::{debug, error, info, warn};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::OnceCell;
-use std::sync::Mutex;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
 use std::thread;
-use std::{path::Path, sync::mpsc};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::config::Config;
+use crate::config::{Config, WatchMode};
+use crate::reconcile;
+use crate::roots::nearest_root;
+use crate::share;
+use crate::volume;
 use crate::Worker;
 
+// 重命名的两个事件之间最长间隔，等不到新路径时说明移出了监听的目录
+const RENAME_WAIT: Duration = Duration::from_millis(500);
+
+/// 需要重新索引的变更
+#[derive(Debug, PartialEq)]
+enum Change {
+    Path(PathBuf),
+    Move(PathBuf, PathBuf),
+}
+
+/// 把重命名的旧路径和新路径两个事件合并成一次移动。
+/// inotify 的两个事件带相同的 tracker，之后还会补发一个 Both 事件；Windows 的两个事件没有 tracker，但总是紧挨着
+#[derive(Default)]
+struct RenamePairer {
+    pending: Option<(Option<usize>, PathBuf, Instant)>,
+}
+
+impl RenamePairer {
+    fn on_event(&mut self, event: &Event, now: Instant) -> Vec<Change> {
+        let mut changes = self.expire(now);
+        let tracker = event.tracker();
+        match (&event.kind, event.paths.as_slice()) {
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), [from]) => {
+                debug!("文件被重命名，旧路径: {}", from.display());
+                changes.extend(self.flush());
+                self.pending = Some((tracker, from.clone(), now));
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [to]) => {
+                debug!("文件被重命名，新路径: {}", to.display());
+                match self.pending.take() {
+                    Some((pending_tracker, from, _)) if pending_tracker == tracker => {
+                        changes.push(Change::Move(from, to.clone()));
+                    }
+                    pending => {
+                        // 从监听的目录外移进来
+                        self.pending = pending;
+                        changes.extend(self.flush());
+                        changes.push(Change::Path(to.clone()));
+                    }
+                }
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+                // 带 tracker 的已经按 From 和 To 事件处理过
+                if tracker.is_none() {
+                    changes.push(Change::Move(from.clone(), to.clone()));
+                }
+            }
+            (EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_), paths) => {
+                changes.extend(self.flush());
+                for path in paths {
+                    debug!("文件被变更: {:?}, {}", event.kind, path.display());
+                    changes.push(Change::Path(path.clone()));
+                }
+            }
+            (EventKind::Access(_), _) => {
+                // 访问事件不需要重新索引
+            }
+            (EventKind::Other, paths) => {
+                debug!("其他文件系统事件: {paths:?}");
+            }
+            _ => {
+                debug!("未知的事件类型: {event:?}");
+            }
+        }
+        changes
+    }
+
+    /// 等不到新路径的旧路径按删除处理
+    fn expire(&mut self, now: Instant) -> Vec<Change> {
+        match &self.pending {
+            Some((_, _, at)) if now.duration_since(*at) >= RENAME_WAIT => {
+                self.flush().into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn flush(&mut self) -> Option<Change> {
+        self.pending.take().map(|(_, from, _)| Change::Path(from))
+    }
+}
+
 pub struct Monitor {
     watcher: RecommendedWatcher,
+    poll_watcher: PollWatcher,
+}
+
+impl Monitor {
+    fn watcher_for(&mut self, path: &Path) -> Result<&mut dyn Watcher> {
+        if use_polling(path)? {
+            info!("轮询目录变化: {}", path.display());
+            Ok(&mut self.poll_watcher)
+        } else {
+            Ok(&mut self.watcher)
+        }
+    }
+}
+
+/// 索引目录的监听方式，polling 为实际是否轮询
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RootWatch {
+    pub root: String,
+    pub mode: WatchMode,
+    pub polling: bool,
+    /// 是否包含子目录
+    pub recursive: bool,
+    /// 所在的磁盘离线，暂停监听
+    pub offline: bool,
+    /// 手动暂停了监听
+    pub paused: bool,
+}
+
+/// 按设置决定是否轮询，自动时网络文件系统上的目录轮询，本地磁盘使用系统通知
+fn use_polling(path: &Path) -> Result<bool> {
+    let roots = Config::get_index_dir_paths()?;
+    let root = nearest_root(&roots, path)
+        .map(String::as_str)
+        .or(path.to_str())
+        .unwrap_or_default();
+    match Config::get_watch_modes()?.get(root) {
+        Some(WatchMode::Poll) => Ok(true),
+        Some(WatchMode::Native) => Ok(false),
+        Some(WatchMode::Auto) | None => share::is_network_path(path),
+    }
+}
+
+pub fn get_root_watches() -> Result<Vec<RootWatch>> {
+    let modes = Config::get_watch_modes()?;
+    let shallow = Config::get_shallow_roots()?;
+    Config::get_index_dir_paths()?
+        .into_iter()
+        .map(|root| {
+            Ok(RootWatch {
+                mode: modes.get(&root).copied().unwrap_or(WatchMode::Auto),
+                polling: use_polling(Path::new(&root))?,
+                recursive: !shallow.contains(&root),
+                offline: volume::is_offline(Path::new(&root))?,
+                paused: is_watch_paused(&root),
+                root,
+            })
+        })
+        .collect()
+}
+
+/// 修改索引目录的监听方式，马上换用对应的监听器
+pub fn set_watch_mode(root: &str, mode: WatchMode) -> Result<()> {
+    info!("设置监听方式: {root}, {mode:?}");
+    let mut modes = Config::get_watch_modes()?;
+    if mode == WatchMode::Auto {
+        modes.remove(root);
+    } else {
+        modes.insert(root.to_string(), mode);
+    }
+    Config::set_watch_modes(&modes)?;
+    rewatch_path(Path::new(root))
+}
+
+/// 监听方式或者是否包含子目录变化后，按新的设置重新监听索引目录
+pub fn rewatch_path(path: &Path) -> Result<()> {
+    if path.to_str().is_some_and(is_watch_paused) {
+        return Ok(());
+    }
+    let mut monitor = get_monitor()
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to acquire monitor lock: {}", e))?;
+    // 只在其中一个监听器上，另一个取消监听会失败
+    let _ = monitor.watcher.unwatch(path);
+    let _ = monitor.poll_watcher.unwatch(path);
+    monitor
+        .watcher_for(path)?
+        .watch(path, recursive_mode(path)?)?;
+    Ok(())
+}
+
+/// 只索引第一层的索引目录不监听子目录
+fn recursive_mode(root: &Path) -> Result<RecursiveMode> {
+    let shallow = Config::get_shallow_roots()?;
+    if shallow.iter().any(|r| Path::new(r) == root) {
+        Ok(RecursiveMode::NonRecursive)
+    } else {
+        Ok(RecursiveMode::Recursive)
+    }
+}
+
+// 手动暂停监听的索引目录，如大量构建期间避免事件涌入队列，搜索不受影响。重启后恢复
+static PAUSED_ROOTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn lock_paused_roots() -> std::sync::MutexGuard<'static, BTreeSet<String>> {
+    PAUSED_ROOTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn is_watch_paused(root: &str) -> bool {
+    lock_paused_roots().contains(root)
+}
+
+/// 暂停监听索引目录，root 为 None 时暂停全部，返回这次暂停的目录
+pub fn pause_watching(root: Option<&str>) -> Result<Vec<String>> {
+    let roots = Config::get_index_dir_paths()?;
+    let mut paused = Vec::new();
+    for root in roots.iter().filter(|r| root.is_none_or(|root| root == *r)) {
+        if is_watch_paused(root) {
+            continue;
+        }
+        if let Err(e) = del_watched_path(Path::new(root)) {
+            // 离线的目录本来就没有监听
+            debug!("取消监听失败: {root}, {e:?}");
+        }
+        info!("暂停监听: {root}");
+        lock_paused_roots().insert(root.clone());
+        paused.push(root.clone());
+    }
+    Ok(paused)
+}
+
+/// 恢复监听索引目录，并扫描暂停期间的变化，root 为 None 时恢复全部，返回这次恢复的目录
+pub fn resume_watching(worker: &Worker, root: Option<&str>) -> Result<Vec<String>> {
+    let resumed: Vec<String> = {
+        let mut paused = lock_paused_roots();
+        let resumed = paused
+            .iter()
+            .filter(|r| root.is_none_or(|root| root == *r))
+            .cloned()
+            .collect();
+        paused.retain(|r| root.is_some_and(|root| root != r));
+        resumed
+    };
+    for root in &resumed {
+        info!("恢复监听: {root}");
+        let path = Path::new(root);
+        // 已经删除的目录不再监听，离线的目录重新接入后自动监听
+        if !Config::get_index_dir_paths()?.contains(root) || volume::is_offline(path)? {
+            continue;
+        }
+        add_watched_path(path)?;
+        worker.submit_index_all_files(path)?;
+    }
+    Ok(resumed)
 }
 
 static MONITOR: OnceCell<Mutex<Monitor>> = OnceCell::new();
 
+// 每次创建监听器加一，事件线程退出时据此判断是被替换了还是意外退出
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+// 监听器报告了错误或者事件线程意外退出，健康检查时重新创建
+static WATCHER_FAILED: AtomicBool = AtomicBool::new(false);
+// 健康检查的间隔
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// 两次检查之间经过的时间比间隔多出这么多时，认为系统睡眠后唤醒，监听器可能已经失效
+const RESUME_GAP: Duration = Duration::from_secs(60);
+
+/// 创建监听器和处理事件的线程，监听在线的索引目录，返回监听失败的目录
+fn create_monitor() -> Result<(Monitor, Vec<(PathBuf, anyhow::Error)>)> {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let (tx, rx) = mpsc::channel();
+    let watcher = notify::recommended_watcher(tx.clone())?;
+    // 网络目录收不到可靠的文件系统通知，只能轮询
+    let poll_interval = Duration::from_secs(Config::get_poll_interval_secs()?);
+    let poll_watcher = PollWatcher::new(
+        tx,
+        notify::Config::default().with_poll_interval(poll_interval),
+    )?;
+    let mut monitor = Monitor {
+        watcher,
+        poll_watcher,
+    };
+
+    let mut failed = Vec::new();
+    for path in Config::get_index_dir_paths()? {
+        if is_watch_paused(&path) {
+            continue;
+        }
+        let path = Path::new(&path);
+        let result = monitor
+            .watcher_for(path)
+            .and_then(|w| Ok(w.watch(path, recursive_mode(path)?)?));
+        if let Err(e) = result {
+            if volume::is_offline(path)? {
+                // 离线的共享目录、移动硬盘等重新接入后再监听
+                error!("索引目录离线，暂不监听: {}, 错误: {:?}", path.display(), e);
+            } else {
+                failed.push((path.to_path_buf(), e));
+            }
+        }
+    }
+
+    thread::Builder::new()
+        .name("file-monitor".into())
+        .spawn(move || {
+            let worker = Worker::new().unwrap();
+            let mut renames = RenamePairer::default();
+            loop {
+                let changes = match rx.recv_timeout(RENAME_WAIT) {
+                    Ok(Ok(event)) => renames.on_event(&event, Instant::now()),
+                    Ok(Err(e)) => {
+                        error!("监听错误: {e:?}");
+                        WATCHER_FAILED.store(true, Ordering::SeqCst);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => renames.expire(Instant::now()),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                for change in changes {
+                    let result = match &change {
+                        Change::Path(path) => worker.submit_index_all_files(path),
+                        Change::Move(from, to) => worker.submit_move(from, to),
+                    };
+                    if let Err(e) = result {
+                        error!("提交索引任务失败: {change:?}, 错误: {e:?}");
+                    }
+                }
+            }
+            // 监听器被替换时旧的通道断开，不是替换时说明监听器意外停止
+            if GENERATION.load(Ordering::SeqCst) == generation {
+                error!("监听事件线程意外退出");
+                WATCHER_FAILED.store(true, Ordering::SeqCst);
+            }
+        })?;
+
+    Ok((monitor, failed))
+}
+
 pub fn get_monitor() -> &'static Mutex<Monitor> {
     MONITOR.get_or_init(|| {
         info!("初始化 WATCHER");
-        let (tx, rx) = mpsc::channel();
-        let mut watcher = notify::recommended_watcher(tx).unwrap();
+        let (monitor, failed) = create_monitor().unwrap();
+        if let Some((path, e)) = failed.first() {
+            panic!("监听目录失败: {}, 错误: {:?}", path.display(), e);
+        }
+        Mutex::new(monitor)
+    })
+}
 
-        Config::get_index_dir_paths()
-            .unwrap()
-            .iter()
-            .for_each(|path| {
-                watcher
-                    .watch(Path::new(path), RecursiveMode::Recursive)
-                    .unwrap();
-            });
-
-        thread::Builder::new()
-            .name("file-monitor".into())
-            .spawn(move || {
-                let worker = Worker::new().unwrap();
-                for res in rx {
-                    match res {
-                        Ok(event) => {
-                            match event.kind {
-                                notify::EventKind::Create(_)
-                                | notify::EventKind::Modify(_)
-                                | notify::EventKind::Remove(_) => {
-                                    for path in &event.paths {
-                                        debug!("文件被变更: {:?}, {}", event.kind, path.display());
-                                        if let Err(e) = worker.submit_index_all_files(path) {
-                                            error!(
-                                                "提交索引任务失败: {}, 错误: {:?}",
-                                                path.display(),
-                                                e
-                                            );
-                                        }
-                                    }
-                                }
-                                notify::EventKind::Access(_) => {
-                                    // 访问事件不需要重新索引
-                                }
-                                notify::EventKind::Other => {
-                                    debug!("其他文件系统事件: {:?}", event.paths);
-                                }
-                                _ => {
-                                    debug!("未知的事件类型: {event:?}");
-                                }
-                            }
-                        }
-                        Err(e) => error!("监听错误: {e:?}"),
+/// 重新创建监听器、监听所有在线的索引目录，并扫描索引目录补上失效期间漏掉的变化
+pub fn restart_monitor(worker: &Worker) -> Result<()> {
+    info!("重新创建 WATCHER");
+    let (monitor, failed) = create_monitor()?;
+    for (path, e) in failed {
+        error!("监听目录失败: {}, 错误: {:?}", path.display(), e);
+    }
+    // 替换后旧的监听器被释放，旧的事件线程随之退出
+    *get_monitor()
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to acquire monitor lock: {}", e))? = monitor;
+    for root in Config::get_index_dir_paths()? {
+        worker.submit_index_all_files(Path::new(&root))?;
+    }
+    Ok(())
+}
+
+/// 两次检查之间的时间明显超过检查间隔，说明期间系统睡眠过
+fn resumed_from_sleep(last: SystemTime, now: SystemTime) -> bool {
+    now.duration_since(last)
+        .is_ok_and(|elapsed| elapsed >= HEALTH_CHECK_INTERVAL + RESUME_GAP)
+}
+
+/// 定时检查监听器，出错、事件线程退出或者系统睡眠唤醒后重新创建
+pub fn start_health_check() -> Result<()> {
+    thread::Builder::new()
+        .name("monitor-health".to_string())
+        .spawn(|| {
+            let worker = Worker::new().unwrap();
+            let mut last = SystemTime::now();
+            loop {
+                thread::sleep(HEALTH_CHECK_INTERVAL);
+                let now = SystemTime::now();
+                let resumed = resumed_from_sleep(last, now);
+                let checked = last;
+                last = now;
+                let failed = WATCHER_FAILED.swap(false, Ordering::SeqCst);
+                if !(resumed || failed) {
+                    // 上次检查之前的变化都已经提交了任务，下次启动时不用再检查
+                    if let Err(e) = reconcile::advance(checked) {
+                        error!("更新检查时间失败: {e:?}");
                     }
+                    continue;
                 }
-            })
-            .unwrap();
-
-        Mutex::new(Monitor { watcher })
-    })
+                warn!("监听器可能已经失效，重新创建。睡眠唤醒: {resumed}, 监听出错: {failed}");
+                if let Err(e) = restart_monitor(&worker) {
+                    error!("重新创建监听器失败: {e:?}");
+                    WATCHER_FAILED.store(true, Ordering::SeqCst);
+                }
+            }
+        })?;
+    Ok(())
 }
 
 pub fn add_watched_path(new_path: &Path) -> Result<()> {
     info!("设置新的监听路径: {}", new_path.display());
+    if new_path.to_str().is_some_and(is_watch_paused) {
+        info!("已暂停监听，恢复后再监听: {}", new_path.display());
+        return Ok(());
+    }
     let mut monitor = get_monitor()
         .lock()
         .map_err(|e| anyhow::anyhow!("Failed to acquire monitor lock: {}", e))?;
 
-    monitor.watcher.watch(new_path, RecursiveMode::Recursive)?;
+    monitor
+        .watcher_for(new_path)?
+        .watch(new_path, recursive_mode(new_path)?)?;
     Ok(())
 }
 
 pub fn del_watched_path(old_path: &Path) -> Result<()> {
     info!("删除旧的监听路径: {}", old_path.display());
+    if old_path.to_str().is_some_and(is_watch_paused) {
+        return Ok(());
+    }
     let mut monitor = get_monitor()
         .lock()
         .map_err(|e| anyhow::anyhow!("Failed to acquire monitor lock: {}", e))?;
 
-    monitor.watcher.unwatch(old_path)?;
+    monitor.watcher_for(old_path)?.unwatch(old_path)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use notify::event::CreateKind;
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_resumed_from_sleep() {
+        let last = SystemTime::now();
+        assert!(!resumed_from_sleep(last, last + HEALTH_CHECK_INTERVAL));
+        assert!(!resumed_from_sleep(
+            last,
+            last + HEALTH_CHECK_INTERVAL + RESUME_GAP / 2
+        ));
+        assert!(resumed_from_sleep(last, last + Duration::from_secs(3600)));
+        // 时间被往回调
+        assert!(!resumed_from_sleep(last + HEALTH_CHECK_INTERVAL, last));
+    }
+
+    #[test]
+    fn test_restart_monitor() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root]).unwrap();
+        get_monitor();
+        let generation = GENERATION.load(Ordering::SeqCst);
+
+        // 重新监听后扫描索引目录，补上失效期间的变化
+        let worker = Worker::new().unwrap();
+        restart_monitor(&worker).unwrap();
+        assert_eq!(GENERATION.load(Ordering::SeqCst), generation + 1);
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+        // 旧的事件线程退出时不算意外停止
+        thread::sleep(RENAME_WAIT * 2);
+        assert!(!WATCHER_FAILED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_pause_watching() {
+        let env = TestEnv::new();
+        let a = env.temp_dir.path().join("a");
+        let b = env.temp_dir.path().join("b");
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let a = a.to_str().unwrap().to_string();
+        let b = b.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![a.clone(), b.clone()]).unwrap();
+        get_monitor();
+
+        assert_eq!(pause_watching(Some(&a)).unwrap(), vec![a.clone()]);
+        assert_eq!(pause_watching(None).unwrap(), vec![b.clone()]);
+        let watches = get_root_watches().unwrap();
+        assert!(watches.iter().all(|w| w.paused));
+        // 暂停期间修改监听方式和重新接入都不会恢复监听
+        set_watch_mode(&a, WatchMode::Poll).unwrap();
+        add_watched_path(Path::new(&a)).unwrap();
+        assert!(is_watch_paused(&a));
+
+        // 恢复后扫描暂停期间的变化
+        let worker = Worker::new().unwrap();
+        assert_eq!(resume_watching(&worker, Some(&a)).unwrap(), vec![a.clone()]);
+        assert!(!is_watch_paused(&a));
+        assert!(is_watch_paused(&b));
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+        assert_eq!(resume_watching(&worker, None).unwrap(), vec![b]);
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 2);
+        assert!(resume_watching(&worker, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_use_polling() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root.clone()]).unwrap();
+        let file = env.temp_dir.path().join("a.txt");
+
+        // 本地磁盘默认使用系统通知
+        assert!(!use_polling(&file).unwrap());
+        let modes = HashMap::from([(root.clone(), WatchMode::Poll)]);
+        Config::set_watch_modes(&modes).unwrap();
+        assert!(use_polling(&file).unwrap());
+        assert_eq!(
+            get_root_watches().unwrap(),
+            vec![RootWatch {
+                root: root.clone(),
+                mode: WatchMode::Poll,
+                polling: true,
+                recursive: true,
+                offline: false,
+                paused: false,
+            }]
+        );
+
+        // 设置中的共享目录自动轮询，也可以强制使用系统通知
+        Config::set_watch_modes(&HashMap::new()).unwrap();
+        share::add_share(&root, None, None).unwrap();
+        assert!(use_polling(&file).unwrap());
+        let modes = HashMap::from([(root, WatchMode::Native)]);
+        Config::set_watch_modes(&modes).unwrap();
+        assert!(!use_polling(&file).unwrap());
+    }
+
+    fn rename_event(mode: RenameMode, paths: &[&str], tracker: Option<usize>) -> Event {
+        let mut event = Event::new(EventKind::Modify(ModifyKind::Name(mode)));
+        for path in paths {
+            event = event.add_path(PathBuf::from(path));
+        }
+        match tracker {
+            Some(tracker) => event.set_tracker(tracker),
+            None => event,
+        }
+    }
+
+    #[test]
+    fn test_rename_pairer() {
+        let mut renames = RenamePairer::default();
+        let now = Instant::now();
+        let moved = Change::Move(PathBuf::from("/a"), PathBuf::from("/b"));
+
+        // inotify：From 和 To 带相同的 tracker，补发的 Both 忽略
+        let from = rename_event(RenameMode::From, &["/a"], Some(1));
+        assert!(renames.on_event(&from, now).is_empty());
+        let to = rename_event(RenameMode::To, &["/b"], Some(1));
+        assert_eq!(renames.on_event(&to, now), vec![moved]);
+        let both = rename_event(RenameMode::Both, &["/a", "/b"], Some(1));
+        assert!(renames.on_event(&both, now).is_empty());
+
+        // Windows：没有 tracker，紧挨着的两个事件
+        let from = rename_event(RenameMode::From, &["/a"], None);
+        assert!(renames.on_event(&from, now).is_empty());
+        let to = rename_event(RenameMode::To, &["/b"], None);
+        assert_eq!(
+            renames.on_event(&to, now),
+            vec![Change::Move(PathBuf::from("/a"), PathBuf::from("/b"))]
+        );
+
+        // 移出监听的目录，等不到新路径时按删除处理
+        let from = rename_event(RenameMode::From, &["/a"], Some(2));
+        assert!(renames.on_event(&from, now).is_empty());
+        assert!(renames.expire(now).is_empty());
+        assert_eq!(
+            renames.expire(now + RENAME_WAIT),
+            vec![Change::Path(PathBuf::from("/a"))]
+        );
+
+        // 从外面移进来，以及中间夹着其他事件
+        let to = rename_event(RenameMode::To, &["/b"], Some(3));
+        assert_eq!(
+            renames.on_event(&to, now),
+            vec![Change::Path(PathBuf::from("/b"))]
+        );
+        let from = rename_event(RenameMode::From, &["/a"], None);
+        renames.on_event(&from, now);
+        let create = Event::new(EventKind::Create(CreateKind::File)).add_path("/c".into());
+        assert_eq!(
+            renames.on_event(&create, now),
+            vec![
+                Change::Path(PathBuf::from("/a")),
+                Change::Path(PathBuf::from("/c"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use keyring::Entry;
+use std::path::Path;
+use thiserror::Error;
+
+use crate::config::Config;
+
+// 与共享目录的凭据分开保存，账号为文件或目录的路径
+const KEYRING_SERVICE: &str = "DuckIndex-documents";
+
+/// 有密码保护的文档读取失败的原因，这类文件只索引文件名并记录原因，不再重试
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProtectedError {
+    #[error("文档有密码保护")]
+    NoPassword,
+    #[error("文档密码错误")]
+    WrongPassword,
+}
+
+fn password_entry(path: &str) -> Result<Entry> {
+    Ok(Entry::new(KEYRING_SERVICE, path)?)
+}
+
+/// 设置文件或目录下所有文档的密码，password 为 None 时删除
+pub fn set_document_password(path: &str, password: Option<String>) -> Result<()> {
+    let mut paths = Config::get_document_passwords()?;
+    paths.retain(|p| p != path);
+    match password {
+        Some(password) => {
+            password_entry(path)?.set_password(&password)?;
+            paths.push(path.to_string());
+        }
+        None => match password_entry(path)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        },
+    }
+    paths.sort();
+    Config::set_document_passwords(&paths)
+}
+
+/// 设置了密码的路径中离文件最近的一个
+pub fn password_path(file_path: &Path) -> Result<Option<String>> {
+    Ok(Config::get_document_passwords()?
+        .into_iter()
+        .filter(|path| file_path.starts_with(path))
+        .max_by_key(|path| Path::new(path).components().count()))
+}
+
+/// 读取文件时使用的密码，没有设置时返回 None
+pub fn find_password(file_path: &Path) -> Result<Option<String>> {
+    let Some(path) = password_path(file_path)? else {
+        return Ok(None);
+    };
+    match password_entry(&path)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_password_path() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path().join("docs");
+        let file = dir.join("sub").join("a.pdf");
+        let dir = dir.to_str().unwrap().to_string();
+        let sub = Path::new(&dir).join("sub").to_str().unwrap().to_string();
+
+        Config::set_document_passwords(&vec![dir.clone(), sub.clone()]).unwrap();
+        assert_eq!(password_path(&file).unwrap(), Some(sub.clone()));
+        assert_eq!(
+            password_path(&Path::new(&dir).join("b.pdf")).unwrap(),
+            Some(dir.clone())
+        );
+        // 只按完整的路径部分匹配
+        assert_eq!(
+            password_path(Path::new(&format!("{dir}2/c.pdf"))).unwrap(),
+            None
+        );
+
+        set_document_password(&sub, None).unwrap();
+        assert_eq!(Config::get_document_passwords().unwrap(), vec![dir]);
+    }
+}
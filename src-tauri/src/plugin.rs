@@ -0,0 +1,532 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::charset::decode_text;
+use crate::config::{Config, ExternalReader};
+use crate::reader::{Item, ItemPosition, Reader};
+use crate::wasm_plugin::WasmPlugin;
+use crate::worker::is_current_task_cancelled;
+
+/// 插件接口的版本，接口不兼容地修改时加一，版本不同的插件不加载
+pub const PLUGIN_API_VERSION: u32 = 1;
+// 插件目录下的清单文件名
+const MANIFEST_FILE: &str = "plugin.toml";
+// 连续失败达到该次数后，本次运行不再调用这个插件
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+// 插件输出的上限，超过时认为插件出错
+const MAX_OUTPUT_SIZE: u64 = 64 * 1024 * 1024;
+// 出错时日志中保留的 stderr 长度
+const MAX_STDERR_SIZE: u64 = 4 * 1024;
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+/// 插件清单 `<插件目录>/<插件>/plugin.toml`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub api_version: u32,
+    pub name: String,
+    /// 支持的扩展名，不带点
+    pub extensions: Vec<String>,
+    /// 可执行文件和参数，参数中的 `{file}` 替换为要读取的文件。
+    /// 可执行文件在插件目录中存在时使用插件目录中的，否则从 PATH 中查找
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// 插件目录中的 WASM 模块，在沙箱中运行，与 command 二选一
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm: Option<String>,
+    /// 读取一个文件的超时时间，超时后结束插件进程
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// 插件输出的每一行，如 `{"content": "第一段", "page": 1, "paragraph": 1}`，
+/// 位置字段与 ItemPosition 相同，都可以省略
+#[derive(Deserialize)]
+struct PluginItem {
+    content: String,
+    #[serde(flatten)]
+    position: ItemPosition,
+}
+
+/// 通过子进程或 WASM 沙箱调用的外部 reader，插件崩溃、卡住或输出错误都不影响索引进程
+pub struct PluginReader {
+    pub manifest: PluginManifest,
+    dir: PathBuf,
+    wasm: Option<WasmPlugin>,
+    failures: AtomicU32,
+}
+
+impl PluginReader {
+    fn load(dir: &Path) -> Result<Self> {
+        let manifest: PluginManifest =
+            toml::from_str(&fs::read_to_string(dir.join(MANIFEST_FILE))?)?;
+        if manifest.api_version != PLUGIN_API_VERSION {
+            bail!(
+                "插件接口版本不兼容: {}，当前版本: {PLUGIN_API_VERSION}",
+                manifest.api_version
+            );
+        }
+        let wasm = match (&manifest.wasm, manifest.command.is_empty()) {
+            (Some(_), false) => bail!("插件不能同时设置 command 和 wasm"),
+            (None, true) => bail!("插件没有设置 command 或 wasm"),
+            (Some(module), true) => Some(WasmPlugin::new(&dir.join(module))?),
+            (None, false) => None,
+        };
+        let extensions = manifest
+            .extensions
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect();
+        Ok(PluginReader {
+            manifest: PluginManifest {
+                extensions,
+                ..manifest
+            },
+            dir: dir.to_path_buf(),
+            wasm,
+            failures: AtomicU32::new(0),
+        })
+    }
+
+    fn run(&self, file_path: &Path) -> Result<Vec<Item>> {
+        if let Some(wasm) = &self.wasm {
+            return self.run_wasm(wasm, file_path);
+        }
+        let name = &self.manifest.name;
+        let output = run_command(
+            name,
+            &self.manifest.command,
+            Some(&self.dir),
+            file_path,
+            self.manifest.timeout_secs,
+        )?;
+        parse_plugin_output(&output).with_context(|| format!("插件输出格式错误: {name}"))
+    }
+
+    fn run_wasm(&self, wasm: &WasmPlugin, file_path: &Path) -> Result<Vec<Item>> {
+        let name = &self.manifest.name;
+        let data = fs::read(file_path)?;
+        let output = wasm
+            .run(&data, Duration::from_secs(self.manifest.timeout_secs))
+            .map_err(|e| anyhow!("插件运行失败: {name}, {e}"))?;
+        if output.len() as u64 > MAX_OUTPUT_SIZE {
+            bail!("插件输出超过 {} MB: {name}", MAX_OUTPUT_SIZE / 1024 / 1024);
+        }
+        parse_plugin_output(&output).with_context(|| format!("插件输出格式错误: {name}"))
+    }
+}
+
+impl Reader for PluginReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let name = &self.manifest.name;
+        if self.failures.load(Ordering::Relaxed) >= MAX_CONSECUTIVE_FAILURES {
+            bail!("插件连续失败，已停用: {name}");
+        }
+        match self.run(file_path) {
+            Ok(items) => {
+                self.failures.store(0, Ordering::Relaxed);
+                Ok(items)
+            }
+            Err(e) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if failures == MAX_CONSECUTIVE_FAILURES {
+                    warn!("插件连续失败 {failures} 次，本次运行不再使用: {name}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        self.manifest
+            .extensions
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// 启动外部命令读取文件，返回标准输出。参数中的 `{file}` 替换为要读取的文件。
+/// 指定 dir 时在该目录中运行，可执行文件在 dir 中存在时使用 dir 中的，否则从 PATH 中查找
+fn run_command(
+    name: &str,
+    command: &[String],
+    dir: Option<&Path>,
+    file_path: &Path,
+    timeout_secs: u64,
+) -> Result<Vec<u8>> {
+    let (program, args) = command
+        .split_first()
+        .with_context(|| format!("没有设置命令: {name}"))?;
+    let program = match dir.map(|dir| dir.join(program)) {
+        Some(local) if local.is_file() => local,
+        _ => PathBuf::from(program),
+    };
+    let file = file_path
+        .to_str()
+        .with_context(|| format!("Invalid file path: {file_path:?}"))?;
+
+    let mut command = Command::new(&program);
+    command
+        .args(args.iter().map(|arg| arg.replace("{file}", file)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("启动命令失败: {name}, {}", program.display()))?;
+
+    // 在单独的线程读取输出，避免管道写满时进程阻塞。超出上限的部分读出后丢弃
+    let mut stdout = child.stdout.take().context("没有命令的输出")?;
+    let mut stderr = child.stderr.take().context("没有命令的错误输出")?;
+    let stdout_reader = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        (&mut stdout)
+            .take(MAX_OUTPUT_SIZE + 1)
+            .read_to_end(&mut output)?;
+        io::copy(&mut stdout, &mut io::sink())?;
+        Ok(output)
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = (&mut stderr).take(MAX_STDERR_SIZE).read_to_end(&mut output);
+        let _ = io::copy(&mut stderr, &mut io::sink());
+        String::from_utf8_lossy(&output).trim().to_string()
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            bail!("命令超时: {name}, {timeout_secs} 秒");
+        }
+        if is_current_task_cancelled() {
+            child.kill()?;
+            child.wait()?;
+            bail!("命令已取消: {name}");
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let output = stdout_reader
+        .join()
+        .map_err(|_| anyhow!("读取命令输出失败: {name}"))??;
+    let stderr = stderr_reader.join().unwrap_or_default();
+    if !status.success() {
+        bail!("命令运行失败: {name}, {status}, {stderr}");
+    }
+    if output.len() as u64 > MAX_OUTPUT_SIZE {
+        bail!("命令输出超过 {} MB: {name}", MAX_OUTPUT_SIZE / 1024 / 1024);
+    }
+    Ok(output)
+}
+
+/// 每行一个 JSON 对象，空行跳过
+fn parse_plugin_output(output: &[u8]) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for (i, line) in String::from_utf8_lossy(output).lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item: PluginItem =
+            serde_json::from_str(line).with_context(|| format!("第 {} 行", i + 1))?;
+        if !item.content.trim().is_empty() {
+            items.push(Item {
+                content: item.content,
+                position: item.position,
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// 用设置中的外部命令读取文件，标准输出按行索引，空行跳过
+pub fn read_with_external(reader: &ExternalReader, file_path: &Path) -> Result<Vec<Item>> {
+    let mut command = reader.command.clone();
+    if !command.iter().skip(1).any(|arg| arg.contains("{file}")) {
+        command.push("{file}".to_string());
+    }
+    let name = command.first().cloned().unwrap_or_default();
+    let output = run_command(&name, &command, None, file_path, reader.timeout_secs)?;
+    let mut items = Vec::new();
+    for (i, line) in decode_text(&output, None).lines().enumerate() {
+        if !line.trim().is_empty() {
+            items.push(Item {
+                content: line.to_string(),
+                position: ItemPosition {
+                    line: Some(i as i64 + 1),
+                    ..Default::default()
+                },
+            });
+        }
+    }
+    Ok(items)
+}
+
+/// 设置扩展名使用的外部命令，reader 为空时删除，返回整理后的扩展名
+pub fn set_external_reader(extension: &str, reader: Option<ExternalReader>) -> Result<String> {
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        bail!("扩展名不能为空");
+    }
+    let mut readers = Config::get_external_readers()?;
+    match reader {
+        Some(reader) => {
+            let command: Vec<String> = reader
+                .command
+                .iter()
+                .map(|arg| arg.trim().to_string())
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            if command.is_empty() {
+                bail!("命令不能为空");
+            }
+            if reader.timeout_secs == 0 {
+                bail!("超时时间不能为 0");
+            }
+            readers.insert(extension.clone(), ExternalReader { command, ..reader });
+        }
+        None => {
+            readers.remove(&extension);
+        }
+    }
+    Config::set_external_readers(&readers)?;
+    Ok(extension)
+}
+
+/// 加载插件目录下的所有插件，有问题的插件记录日志后跳过
+pub fn load_plugins(plugin_dir: &Path) -> Vec<PluginReader> {
+    let mut plugins = Vec::new();
+    let Ok(entries) = fs::read_dir(plugin_dir) else {
+        return plugins;
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(MANIFEST_FILE).is_file())
+        .collect();
+    dirs.sort();
+    for dir in dirs {
+        match PluginReader::load(&dir) {
+            Ok(plugin) => {
+                info!(
+                    "加载插件: {}, 扩展名: {:?}",
+                    plugin.manifest.name, plugin.manifest.extensions
+                );
+                plugins.push(plugin);
+            }
+            Err(e) => warn!("加载插件失败: {}, 错误: {e}", dir.display()),
+        }
+    }
+    plugins
+}
+
+/// 设置页面显示的插件目录和已安装的插件
+#[derive(Debug, Serialize)]
+pub struct PluginList {
+    pub dir: String,
+    pub plugins: Vec<PluginManifest>,
+}
+
+pub fn list_plugins(plugin_dir: &Path) -> PluginList {
+    PluginList {
+        dir: plugin_dir.to_string_lossy().to_string(),
+        plugins: load_plugins(plugin_dir)
+            .into_iter()
+            .map(|plugin| plugin.manifest)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    fn write_plugin(dir: &Path, manifest: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+    }
+
+    #[test]
+    fn test_load_plugins() {
+        let env = TestEnv::new();
+        let plugin_dir = env.temp_dir.path().join("plugins");
+        write_plugin(
+            &plugin_dir.join("caj"),
+            r#"api_version = 1
+name = "CAJ"
+extensions = [".CAJ", "nh"]
+command = ["caj2text", "{file}"]
+"#,
+        );
+        write_plugin(
+            &plugin_dir.join("future"),
+            r#"api_version = 2
+name = "Future"
+extensions = ["future"]
+command = ["future"]
+"#,
+        );
+        write_plugin(&plugin_dir.join("broken"), "name = ");
+        write_plugin(
+            &plugin_dir.join("both"),
+            r#"api_version = 1
+name = "Both"
+extensions = ["both"]
+command = ["both"]
+wasm = "both.wasm"
+"#,
+        );
+
+        let plugins = load_plugins(&plugin_dir);
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].supports(), vec!["caj", "nh"]);
+        assert_eq!(plugins[0].manifest.timeout_secs, 60);
+        assert!(load_plugins(&env.temp_dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_plugin_output() {
+        let items = parse_plugin_output(
+            "{\"content\": \"第一段\", \"page\": 2, \"paragraph\": 1}\n\n{\"content\": \" \"}\n{\"content\": \"无位置\"}\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].position,
+            ItemPosition {
+                page: Some(2),
+                paragraph: Some(1),
+                ..Default::default()
+            }
+        );
+        assert_eq!(items[1].position, ItemPosition::default());
+        assert!(parse_plugin_output(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_set_external_reader() {
+        let _env = TestEnv::new();
+        let pandoc = ExternalReader {
+            command: ["pandoc", " --to ", "plain", ""].map(String::from).to_vec(),
+            timeout_secs: 60,
+        };
+        assert_eq!(set_external_reader(".RST", Some(pandoc)).unwrap(), "rst");
+        assert_eq!(
+            Config::get_external_readers().unwrap()["rst"].command,
+            vec!["pandoc", "--to", "plain"]
+        );
+        let empty = ExternalReader {
+            command: vec![" ".to_string()],
+            timeout_secs: 60,
+        };
+        assert!(set_external_reader("rst", Some(empty)).is_err());
+
+        set_external_reader("rst", None).unwrap();
+        assert!(Config::get_external_readers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wasm_plugin_reader() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path().join("echo");
+        write_plugin(
+            &dir,
+            r#"api_version = 1
+name = "Echo"
+extensions = ["echo"]
+wasm = "echo.wat"
+"#,
+        );
+        // 把文件内容原样输出，文件中写的就是插件的输出
+        fs::write(
+            dir.join("echo.wat"),
+            r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32) (i32.const 0))
+  (func (export "read") (param $ptr i32) (param $len i32) (result i64)
+    (i64.extend_i32_u (local.get $len))))"#,
+        )
+        .unwrap();
+        let plugin = PluginReader::load(&dir).unwrap();
+
+        let file = env.temp_dir.path().join("a.echo");
+        fs::write(&file, "{\"content\": \"沙箱中读取的内容\", \"page\": 3}\n").unwrap();
+        let items = plugin.read(&file).unwrap();
+        assert_eq!(items[0].content, "沙箱中读取的内容");
+        assert_eq!(items[0].position.page, Some(3));
+
+        fs::write(&file, "not json").unwrap();
+        assert!(plugin.read(&file).is_err());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_plugin_reader() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path().join("echo");
+        write_plugin(
+            &dir,
+            r#"api_version = 1
+name = "Echo"
+extensions = ["echo"]
+command = ["sh", "echo.sh", "{file}"]
+timeout_secs = 1
+"#,
+        );
+        fs::write(
+            dir.join("echo.sh"),
+            r#"case "$1" in
+*slow*) sleep 5 ;;
+*fail*) echo "boom" >&2; exit 3 ;;
+*) printf '{"content": "%s", "line": 1}\n' "$(cat "$1")" ;;
+esac
+"#,
+        )
+        .unwrap();
+        let plugin = PluginReader::load(&dir).unwrap();
+
+        let file = env.temp_dir.path().join("a.echo");
+        fs::write(&file, "插件读取的内容").unwrap();
+        let items = plugin.read(&file).unwrap();
+        assert_eq!(items[0].content, "插件读取的内容");
+        assert_eq!(items[0].position.line, Some(1));
+
+        let error = plugin
+            .read(&env.temp_dir.path().join("fail.echo"))
+            .unwrap_err();
+        assert!(error.to_string().contains("boom"));
+        let error = plugin
+            .read(&env.temp_dir.path().join("slow.echo"))
+            .unwrap_err();
+        assert!(error.to_string().contains("超时"));
+
+        // 连续失败后停用，不再启动插件
+        for _ in 2..MAX_CONSECUTIVE_FAILURES {
+            assert!(plugin.read(&env.temp_dir.path().join("fail.echo")).is_err());
+        }
+        assert!(plugin
+            .read(&file)
+            .unwrap_err()
+            .to_string()
+            .contains("已停用"));
+    }
+}
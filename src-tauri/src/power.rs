@@ -0,0 +1,133 @@
+use anyhow::Result;
+use log::{debug, info};
+use serde::Serialize;
+use starship_battery::units::ratio;
+use starship_battery::{Manager, State};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::config::{BatteryAction, BatteryPolicy, Config};
+use crate::worker::Worker;
+
+// 读取电池状态的间隔，每个任务都读取开销太大
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 电源状态，没有电池时 on_battery 为 false，percent 为 None
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct PowerStatus {
+    pub on_battery: bool,
+    pub percent: Option<f32>,
+}
+
+/// 使用电池供电时推迟 OCR，接通电源后重新处理
+#[derive(Debug, Error)]
+#[error("使用电池供电，OCR 推迟到接通电源后进行")]
+pub struct OcrDeferred;
+
+static STATUS: Mutex<Option<(Instant, PowerStatus)>> = Mutex::new(None);
+// 上次检查时是否处于省电状态，用来发现接通电源
+static SAVING: AtomicBool = AtomicBool::new(false);
+
+fn read_power_status() -> Result<PowerStatus> {
+    let mut on_battery = false;
+    let mut charges = Vec::new();
+    for battery in Manager::new()?.batteries()? {
+        let battery = battery?;
+        on_battery |= battery.state() == State::Discharging;
+        charges.push(battery.state_of_charge().get::<ratio::percent>());
+    }
+    let percent = (!charges.is_empty()).then(|| charges.iter().sum::<f32>() / charges.len() as f32);
+    Ok(PowerStatus {
+        on_battery,
+        percent,
+    })
+}
+
+/// 当前的电源状态，读取失败时按接通电源处理
+pub fn get_power_status() -> PowerStatus {
+    let mut status = match STATUS.lock() {
+        Ok(status) => status,
+        Err(e) => e.into_inner(),
+    };
+    if let Some((checked, power)) = *status {
+        if checked.elapsed() < CHECK_INTERVAL {
+            return power;
+        }
+    }
+    let power = read_power_status().unwrap_or_else(|e| {
+        debug!("读取电池状态失败: {e}");
+        PowerStatus {
+            on_battery: false,
+            percent: None,
+        }
+    });
+    *status = Some((Instant::now(), power));
+    power
+}
+
+fn action_for(policy: &BatteryPolicy, power: &PowerStatus) -> Option<BatteryAction> {
+    if !policy.enabled || !power.on_battery {
+        return None;
+    }
+    match power.percent {
+        Some(p) if p >= policy.threshold_percent as f32 => None,
+        _ => Some(policy.action),
+    }
+}
+
+/// 当前需要执行的省电措施，接通电源时把推迟的 OCR 任务重新排队
+pub fn battery_action() -> Result<Option<BatteryAction>> {
+    let action = action_for(&Config::get_battery_policy()?, &get_power_status());
+    let was_saving = SAVING.swap(action.is_some(), Ordering::SeqCst);
+    if action.is_some() && !was_saving {
+        info!("电量低，索引进入省电模式: {action:?}");
+    } else if action.is_none() && was_saving {
+        let count = Worker::resume_deferred_tasks()?;
+        info!("已接通电源，恢复 {count} 个推迟的 OCR 任务");
+    }
+    Ok(action)
+}
+
+/// 识别图片前调用，省电模式下返回 OcrDeferred
+pub fn check_ocr() -> Result<()> {
+    if battery_action()?.is_some() {
+        return Err(OcrDeferred.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_for() {
+        let policy = BatteryPolicy {
+            enabled: true,
+            threshold_percent: 50,
+            action: BatteryAction::Pause,
+        };
+        let power = |on_battery, percent| PowerStatus {
+            on_battery,
+            percent,
+        };
+        assert_eq!(action_for(&policy, &power(false, Some(20.0))), None);
+        assert_eq!(action_for(&policy, &power(true, Some(80.0))), None);
+        assert_eq!(
+            action_for(&policy, &power(true, Some(20.0))),
+            Some(BatteryAction::Pause)
+        );
+        // 读不到电量时按电量低处理
+        assert_eq!(
+            action_for(&policy, &power(true, None)),
+            Some(BatteryAction::Pause)
+        );
+        let disabled = BatteryPolicy {
+            enabled: false,
+            ..policy
+        };
+        assert_eq!(action_for(&disabled, &power(true, Some(20.0))), None);
+    }
+}
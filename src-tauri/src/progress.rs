@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::events::emit;
+use crate::roots::nearest_root;
+use crate::worker::Worker;
+
+pub const INDEX_PROGRESS_EVENT: &str = "index-progress";
+
+// 推送进度的最小间隔，文件很多时避免事件过多
+const EMIT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 一个索引目录本轮索引的进度，队列处理完后重新计算
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RootProgress {
+    pub root: String,
+    pub files_completed: usize,
+    pub bytes_read: u64,
+    pub items_written: usize,
+    /// 还没处理的文件和目录
+    pub pending: usize,
+    pub files_per_sec: f64,
+    pub bytes_per_sec: f64,
+    /// 按目前的速度估算的剩余时间，还没有处理完任何文件时为 None
+    pub eta_secs: Option<u64>,
+}
+
+struct RootCounter {
+    started: Instant,
+    files: usize,
+    bytes: u64,
+    items: usize,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    roots: HashMap<String, RootCounter>,
+    last_emit: Option<Instant>,
+}
+
+static PROGRESS: OnceCell<Mutex<ProgressState>> = OnceCell::new();
+
+fn get_progress_state() -> &'static Mutex<ProgressState> {
+    PROGRESS.get_or_init(|| Mutex::new(ProgressState::default()))
+}
+
+/// 各索引目录下还没处理完的任务数
+fn pending_tasks(roots: &[String]) -> Result<HashMap<String, usize>> {
+    let mut pending = HashMap::new();
+    for path in Worker::get_unfinished_task_paths()? {
+        if let Some(root) = nearest_root(roots, Path::new(&path)) {
+            *pending.entry(root.clone()).or_insert(0) += 1;
+        }
+    }
+    Ok(pending)
+}
+
+fn snapshot(state: &ProgressState) -> Result<Vec<RootProgress>> {
+    let roots: Vec<String> = state.roots.keys().cloned().collect();
+    let pending = pending_tasks(&roots)?;
+    let mut result: Vec<RootProgress> = state
+        .roots
+        .iter()
+        .map(|(root, counter)| {
+            let pending = pending.get(root).copied().unwrap_or(0);
+            let elapsed = counter.started.elapsed().as_secs_f64().max(0.001);
+            let files_per_sec = counter.files as f64 / elapsed;
+            RootProgress {
+                root: root.clone(),
+                files_completed: counter.files,
+                bytes_read: counter.bytes,
+                items_written: counter.items,
+                pending,
+                files_per_sec,
+                bytes_per_sec: counter.bytes as f64 / elapsed,
+                eta_secs: (files_per_sec > 0.0).then(|| (pending as f64 / files_per_sec) as u64),
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| a.root.cmp(&b.root));
+    Ok(result)
+}
+
+/// 推送后已经处理完的索引目录清零，下一轮重新计算速度
+fn emit_snapshot(state: &mut ProgressState) -> Result<()> {
+    state.last_emit = Some(Instant::now());
+    let progress = snapshot(state)?;
+    state
+        .roots
+        .retain(|root, _| progress.iter().any(|p| &p.root == root && p.pending > 0));
+    emit(INDEX_PROGRESS_EVENT, progress);
+    Ok(())
+}
+
+fn lock_state() -> Result<std::sync::MutexGuard<'static, ProgressState>> {
+    get_progress_state()
+        .lock()
+        .map_err(|e| anyhow!("获取进度锁失败: {}", e))
+}
+
+/// 记录一个处理完的文件，距离上次推送超过间隔时推送各索引目录的进度
+pub fn record_file(path: &Path, items: usize) -> Result<()> {
+    let roots = Config::get_index_dir_paths()?;
+    let Some(root) = nearest_root(&roots, path).cloned() else {
+        return Ok(());
+    };
+    let bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    let mut state = lock_state()?;
+    let counter = state.roots.entry(root).or_insert_with(|| RootCounter {
+        started: Instant::now(),
+        files: 0,
+        bytes: 0,
+        items: 0,
+    });
+    counter.files += 1;
+    counter.bytes += bytes;
+    counter.items += items;
+
+    if state
+        .last_emit
+        .is_none_or(|last| last.elapsed() >= EMIT_INTERVAL)
+    {
+        emit_snapshot(&mut state)?;
+    }
+    Ok(())
+}
+
+/// 队列处理完时推送最后一次进度，让前端看到 100%
+pub fn flush() -> Result<()> {
+    let mut state = lock_state()?;
+    if state.roots.is_empty() {
+        return Ok(());
+    }
+    emit_snapshot(&mut state)
+}
+
+/// 当前的进度，前端打开时用来显示初始状态
+pub fn get_progress() -> Result<Vec<RootProgress>> {
+    snapshot(&*lock_state()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_record_file() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "12345").unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone()]).unwrap();
+
+        // 索引目录以外的文件不计入
+        record_file(&env.temp_dir.path().join("other.txt"), 1).unwrap();
+        assert!(get_progress().unwrap().is_empty());
+
+        let worker = Worker::new().unwrap();
+        worker.submit_index_all_files(&root).unwrap();
+        record_file(&root.join("a.txt"), 3).unwrap();
+        let progress = get_progress().unwrap();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].root, root_str);
+        assert_eq!(progress[0].files_completed, 1);
+        assert_eq!(progress[0].bytes_read, 5);
+        assert_eq!(progress[0].items_written, 3);
+        // 目录还没有扫描，只有一个目录任务
+        assert_eq!(progress[0].pending, 1);
+        assert!(progress[0].eta_secs.is_some());
+
+        // 队列处理完后清零
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+        flush().unwrap();
+        assert!(get_progress().unwrap().is_empty());
+    }
+}
@@ -1,42 +1,127 @@
-use anyhow::{Context, Result};
-use log::debug;
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use lopdf::xobject::PdfImage;
 use lopdf::Document as pdfDocument;
-use quick_xml::events::Event as quickXmlEvent;
+use lopdf::{Dictionary, Object, Stream};
+use onenote_parser::contents::{OutlineElement, OutlineItem};
+use onenote_parser::Parser as OneNoteParser;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event as quickXmlEvent};
 use quick_xml::Reader as quickXmlReader;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::{Path, PathBuf};
+use std::io::{self, BufReader, Read};
+use std::path::Path;
 use std::sync::Arc;
-use std::{env, fs, vec};
+use std::{fs, vec};
 use tempfile::TempDir;
 use tesseract::Tesseract;
+use thiserror::Error;
+use typed_path::TypedPath;
+use zip::read::ZipFile;
 use zip::ZipArchive;
 
+use crate::charset::{decode_text, root_encoding};
 use crate::config::Config;
-
-#[derive(Debug)]
+use crate::dirs::{get_plugin_dir, get_tessdata_dir};
+use crate::image_meta::image_properties;
+use crate::mail::{parse_message, split_mbox, INDEXED_HEADERS};
+use crate::markdown::markdown_items;
+use crate::password::{find_password, ProtectedError};
+use crate::plugin::{load_plugins, read_with_external};
+use crate::power::{check_ocr, OcrDeferred};
+use crate::subtitle::{mkv_subtitles, mp4_subtitles, parse_srt, sidecar_subtitles};
+use crate::tessdata::get_tesseract_languages;
+
+#[derive(Debug, Default)]
 pub struct Item {
     pub content: String,
+    pub position: ItemPosition,
+}
+
+/// 内容在文件中的位置，都从 1 开始，只填写对应格式有意义的字段：
+/// 文档属性和图片的拍摄信息没有位置。
+/// PDF 为页码，pptx 为幻灯片序号和段落，docx 为段落，xlsx 为工作表序号、行号和单元格引用，文本和图片为行号，
+/// mbox 为邮件序号和段落，视频字幕为开始时间，Markdown 为行号和所在的标题
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ItemPosition {
+    pub page: Option<i64>,
+    pub paragraph: Option<i64>,
+    pub sheet_row: Option<i64>,
+    pub line: Option<i64>,
+    /// 压缩包内文件的虚拟路径，如 `backup.zip!/report.docx`
+    pub entry: Option<String>,
+    /// 视频字幕的开始时间，单位为毫秒
+    pub time_ms: Option<i64>,
+    /// xlsx 的单元格引用，如 `Sheet1!B3`
+    pub cell: Option<String>,
+    /// Markdown 中内容所在的最近一级标题
+    pub heading: Option<String>,
 }
 
-pub trait Reader {
+pub trait Reader: Send + Sync {
     fn read(&self, file_path: &Path) -> Result<Vec<Item>>;
     fn supports(&self) -> Vec<&str>;
 }
 
 pub struct CompositeReader {
     reader_map: HashMap<String, Arc<dyn Reader>>,
+    // 插件的扩展名不在白名单中，安装插件即启用
+    plugin_extensions: HashSet<String>,
 }
 
 impl CompositeReader {
     pub fn new() -> Result<Self> {
+        let mut reader_map = Self::document_readers();
+        // 插件只能增加新的格式，不能替换内置的 reader
+        let mut plugin_extensions = HashSet::new();
+        for plugin in load_plugins(&get_plugin_dir()) {
+            let plugin: Arc<dyn Reader> = Arc::new(plugin);
+            for ext in plugin.supports() {
+                if reader_map.contains_key(ext) {
+                    warn!("插件的扩展名已经有内置的 reader，忽略: {ext}");
+                } else {
+                    reader_map.insert(ext.to_string(), plugin.clone());
+                    plugin_extensions.insert(ext.to_string());
+                }
+            }
+        }
+        // 压缩包内的文件只用普通的读取器读取，不再展开嵌套的压缩包
+        let archive_reader: Arc<dyn Reader> = Arc::new(ArchiveReader {
+            readers: reader_map.clone(),
+        });
+        for ext in archive_reader.supports() {
+            reader_map.insert(ext.to_string(), archive_reader.clone());
+        }
+        Ok(CompositeReader {
+            reader_map,
+            plugin_extensions,
+        })
+    }
+
+    fn document_readers() -> HashMap<String, Arc<dyn Reader>> {
         let readers: Vec<Arc<dyn Reader>> = vec![
             Arc::new(TxtReader),
+            Arc::new(MarkdownReader),
             Arc::new(DocxReader),
             Arc::new(PdfReader),
             Arc::new(PptxReader),
             Arc::new(XlsxReader),
+            Arc::new(OdtReader),
+            Arc::new(OdsReader),
+            Arc::new(OdpReader),
+            Arc::new(OneNoteReader),
+            Arc::new(HtmlReader),
+            Arc::new(JsonReader),
+            Arc::new(YamlReader),
+            Arc::new(TomlReader),
+            Arc::new(XmlReader),
+            Arc::new(CodeReader),
+            Arc::new(EmailReader),
+            Arc::new(VideoReader),
             Arc::new(OcrReader),
         ];
         let mut reader_map: HashMap<String, Arc<dyn Reader>> = HashMap::new();
@@ -45,7 +130,7 @@ impl CompositeReader {
                 reader_map.insert(ext.to_string(), reader.clone());
             }
         }
-        Ok(CompositeReader { reader_map })
+        reader_map
     }
 
     fn is_hidden(&self, path: &Path) -> Result<bool> {
@@ -71,25 +156,11 @@ impl CompositeReader {
     }
 
     pub fn get_supported_extensions(&self) -> Result<HashSet<String>> {
-        let ext_whitelist = Config::get_extension_whitelist()?;
-
-        fn collect_enabled_extensions(
-            nodes: &[crate::config::ExtensionConfigTree],
-            result: &mut HashSet<String>,
-        ) {
-            for node in nodes {
-                if node.is_extension && node.enabled == Some(true) {
-                    result.insert(node.label.to_string());
-                }
-                if let Some(children) = &node.children {
-                    collect_enabled_extensions(children, result);
-                }
-            }
-        }
-
-        let mut enabled_extensions = HashSet::new();
-        collect_enabled_extensions(&ext_whitelist, &mut enabled_extensions);
-        Ok(enabled_extensions)
+        let mut extensions = enabled_extensions()?;
+        extensions.extend(self.plugin_extensions.iter().cloned());
+        // 设置了外部命令的扩展名不需要再在白名单中启用
+        extensions.extend(Config::get_external_readers()?.into_keys());
+        Ok(extensions)
     }
 
     pub fn supports(&self, file: &Path) -> Result<bool> {
@@ -108,12 +179,37 @@ impl CompositeReader {
         Ok(false)
     }
 
+    /// 超过大小上限的文件不交给 reader 读取，避免一次分配几个 GB 的内存，返回跳过的原因
+    pub fn skip_reason(&self, file_path: &Path) -> Result<Option<String>> {
+        let ext = file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let Some(limit) = Config::get_max_file_size()?.limit_bytes(ext) else {
+            return Ok(None);
+        };
+        let size = fs::metadata(file_path)?.len();
+        if size <= limit {
+            return Ok(None);
+        }
+        const MB: u64 = 1024 * 1024;
+        Ok(Some(format!(
+            "文件大小 {} MB 超过上限 {} MB",
+            size.div_ceil(MB),
+            limit / MB
+        )))
+    }
+
     pub fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
         if let Some(ext) = file_path.extension() {
             let ext_str = ext
                 .to_str()
                 .with_context(|| format!("Invalid extension in file: {file_path:?}"))?
                 .to_lowercase();
+            // 外部命令优先于内置的 reader，可以用来替换效果不好的内置格式
+            if let Some(external) = Config::get_external_readers()?.get(&ext_str) {
+                return read_with_external(external, file_path);
+            }
             if let Some(reader) = self.reader_map.get(&ext_str) {
                 return reader.read(file_path);
             } else {
@@ -126,67 +222,317 @@ impl CompositeReader {
     }
 }
 
+/// 白名单中启用的扩展名
+fn enabled_extensions() -> Result<HashSet<String>> {
+    let ext_whitelist = Config::get_extension_whitelist()?;
+
+    fn collect_enabled_extensions(
+        nodes: &[crate::config::ExtensionConfigTree],
+        result: &mut HashSet<String>,
+    ) {
+        for node in nodes {
+            if node.is_extension && node.enabled == Some(true) {
+                result.insert(node.label.to_string());
+            }
+            if let Some(children) = &node.children {
+                collect_enabled_extensions(children, result);
+            }
+        }
+    }
+
+    let mut enabled_extensions = HashSet::new();
+    collect_enabled_extensions(&ext_whitelist, &mut enabled_extensions);
+    Ok(enabled_extensions)
+}
+
+/// 段落结束时把其中的文字作为一条内容，空白段落跳过
+fn push_paragraph(items: &mut Vec<Item>, txt: &str, page: Option<i64>, paragraph: i64) {
+    if txt.trim().is_empty() {
+        return;
+    }
+    items.push(Item {
+        content: txt.trim().to_string(),
+        position: ItemPosition {
+            page,
+            paragraph: Some(paragraph),
+            ..Default::default()
+        },
+    });
+}
+
+/// 文档属性各自作为一条内容，如 `author: 张三`，放在正文之前
+fn push_properties(items: &mut Vec<Item>, properties: Vec<(&str, String)>) {
+    for (name, value) in properties {
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !value.is_empty() {
+            items.push(Item {
+                content: format!("{name}: {value}"),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// 压缩包中 `<dir><prefix><序号>.xml` 形式的文件，按序号排序
+fn numbered_parts(archive: &ZipArchive<File>, dir: &str, prefix: &str) -> Vec<(i64, String)> {
+    let mut parts: Vec<(i64, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix(dir)?
+                .strip_prefix(prefix)?
+                .strip_suffix(".xml")?
+                .parse::<i64>()
+                .ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    parts.sort();
+    parts
+}
+
 struct TxtReader;
 impl Reader for TxtReader {
     fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        // TODO 需要处理非utf8编码的文本
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
+        // GBK、Big5 等编码的文本先转换为 UTF-8，索引目录可以指定编码
+        let data = fs::read(file_path)?;
+        let text = decode_text(&data, root_encoding(file_path)?);
         let mut items = vec![];
 
-        for line in reader.lines() {
-            let line = line?;
-            items.push(Item { content: line });
+        for (i, line) in text.lines().enumerate() {
+            items.push(Item {
+                content: line.to_string(),
+                position: ItemPosition {
+                    line: Some(i as i64 + 1),
+                    ..Default::default()
+                },
+            });
         }
         Ok(items)
     }
 
     fn supports(&self) -> Vec<&str> {
-        vec!["txt", "md", "markdown"]
+        vec!["txt"]
     }
 }
 
-struct DocxReader;
-impl Reader for DocxReader {
+/// 与 TxtReader 一样处理编码，按标题和段落拆分，记录所在的标题
+struct MarkdownReader;
+impl Reader for MarkdownReader {
     fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        let temp_dir = TempDir::new()?;
-        let file = File::open(file_path)?;
-        let mut archive = ZipArchive::new(file)?;
-        archive.extract(&temp_dir)?;
+        let data = fs::read(file_path)?;
+        Ok(markdown_items(&decode_text(
+            &data,
+            root_encoding(file_path)?,
+        )))
+    }
 
-        // 提取 document.xml
-        let document_path = temp_dir.path().join("word/document.xml");
-        let reader = BufReader::new(File::open(document_path)?);
-        let mut xml_reader = quickXmlReader::from_reader(reader);
+    fn supports(&self) -> Vec<&str> {
+        vec!["md", "markdown"]
+    }
+}
 
-        let mut txt = String::new();
-        let mut buf = Vec::new();
-        let mut items = vec![];
+/// 流式读取压缩包中的 XML 文件，不解压到临时目录。文件不存在时为空
+fn zip_xml<'a>(
+    archive: &'a mut ZipArchive<File>,
+    name: &str,
+) -> Result<Option<quickXmlReader<BufReader<ZipFile<'a, File>>>>> {
+    if archive.index_for_name(name).is_none() {
+        return Ok(None);
+    }
+    Ok(Some(quickXmlReader::from_reader(BufReader::new(
+        archive.by_name(name)?,
+    ))))
+}
 
-        loop {
-            match xml_reader.read_event_into(&mut buf)? {
-                quickXmlEvent::Start(e) if e.name().as_ref() == b"w:p" => {
-                    if !txt.trim().is_empty() {
-                        items.push(Item {
-                            content: txt.trim().to_string(),
-                        });
-                        txt.clear();
+/// 关系文件中的目标路径转换为压缩包中的文件名，相对路径以 base_dir 为起点，也可能是以 / 开头的绝对路径
+fn zip_part_name(base_dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => base_dir.split('/').filter(|p| !p.is_empty()).collect(),
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// 读取 XML 文件中指定元素的文字，元素名到属性名。文件不存在时为空
+fn xml_element_texts(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    elements: &[(&[u8], &'static str)],
+) -> Result<HashMap<&'static str, String>> {
+    let mut texts: HashMap<&'static str, String> = HashMap::new();
+    let Some(mut xml_reader) = zip_xml(archive, name)? else {
+        return Ok(texts);
+    };
+    let mut buf = Vec::new();
+    let mut current = None;
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) => {
+                current = elements
+                    .iter()
+                    .find(|(element, _)| *element == e.name().as_ref())
+                    .map(|(_, name)| *name);
+            }
+            quickXmlEvent::Text(e) => {
+                if let Some(name) = current {
+                    texts.entry(name).or_default().push_str(&e.decode()?);
+                }
+            }
+            quickXmlEvent::GeneralRef(e) => {
+                if let Some(name) = current {
+                    if let Some(c) = e.resolve_char_ref()? {
+                        texts.entry(name).or_default().push(c);
+                    } else if let Some(s) = resolve_predefined_entity(&e.decode()?) {
+                        texts.entry(name).or_default().push_str(s);
                     }
                 }
-                quickXmlEvent::Text(e) => {
-                    txt.push_str(&e.decode()?);
+            }
+            quickXmlEvent::End(_) => current = None,
+            quickXmlEvent::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(texts)
+}
+
+/// Office 文档的属性，在 docProps/core.xml 和 docProps/app.xml 中
+fn office_properties(archive: &mut ZipArchive<File>) -> Result<Vec<(&'static str, String)>> {
+    let mut texts = xml_element_texts(
+        archive,
+        "docProps/core.xml",
+        &[
+            (b"dc:title", "title"),
+            (b"dc:creator", "author"),
+            (b"dc:subject", "subject"),
+            (b"cp:keywords", "keywords"),
+        ],
+    )?;
+    texts.extend(xml_element_texts(
+        archive,
+        "docProps/app.xml",
+        &[(b"Manager", "manager"), (b"Company", "company")],
+    )?);
+    Ok([
+        "title", "author", "subject", "keywords", "manager", "company",
+    ]
+    .into_iter()
+    .filter_map(|name| texts.remove(name).map(|value| (name, value)))
+    .collect())
+}
+
+/// Word 文档部件中的段落，返回 (段落序号, 文字)，空段落也计数
+fn read_word_paragraphs(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<(i64, String)>> {
+    let mut paragraphs = vec![];
+    let Some(mut xml_reader) = zip_xml(archive, name)? else {
+        return Ok(paragraphs);
+    };
+
+    let mut txt = String::new();
+    let mut buf = Vec::new();
+    // 当前文字所在的段落，空段落也计数
+    let mut paragraph = 0;
+
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) if e.name().as_ref() == b"w:p" => {
+                if !txt.trim().is_empty() {
+                    paragraphs.push((paragraph, txt.trim().to_string()));
+                }
+                txt.clear();
+                paragraph += 1;
+            }
+            quickXmlEvent::Text(e) => {
+                txt.push_str(&e.decode()?);
+            }
+            quickXmlEvent::Eof => {
+                if !txt.trim().is_empty() {
+                    paragraphs.push((paragraph, txt.trim().to_string()));
+                }
+                break;
+            } // 文件结束
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(paragraphs)
+}
+
+// 加密的 Office 文档是 OLE 复合文件，不是 zip
+const OLE_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// 打开 docx、pptx、xlsx，加密的文档用设置的密码解密到临时文件后再读取
+fn open_office_archive(file_path: &Path) -> Result<ZipArchive<File>> {
+    let mut file = File::open(file_path)?;
+    let mut magic = [0u8; 8];
+    let encrypted = file.read_exact(&mut magic).is_ok() && magic == OLE_MAGIC;
+    if !encrypted {
+        return Ok(ZipArchive::new(File::open(file_path)?)?);
+    }
+    let password = find_password(file_path)?.ok_or(ProtectedError::NoPassword)?;
+    let data = office_crypto::decrypt_from_file(file_path, &password).map_err(|e| {
+        debug!("Office 文档解密失败: {file_path:?}: {e}");
+        ProtectedError::WrongPassword
+    })?;
+    // 临时文件没有名字，关闭后由系统删除
+    let mut decrypted = tempfile::tempfile()?;
+    io::Write::write_all(&mut decrypted, &data)?;
+    ZipArchive::new(decrypted).map_err(|_| ProtectedError::WrongPassword.into())
+}
+
+struct DocxReader;
+impl Reader for DocxReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let mut archive = open_office_archive(file_path)?;
+
+        let mut items = vec![];
+        push_properties(&mut items, office_properties(&mut archive)?);
+
+        // 正文 document.xml，表格中的文字也在段落中
+        for (paragraph, txt) in read_word_paragraphs(&mut archive, "word/document.xml")? {
+            push_paragraph(&mut items, &txt, None, paragraph);
+        }
+        // 正文中的图片，页眉页脚中的一般是标志，不识别
+        push_office_images(
+            &mut archive,
+            "word/document.xml",
+            None,
+            &mut HashSet::new(),
+            &mut items,
+        )?;
+
+        // 页眉、页脚、脚注、尾注和批注不属于正文，不记录段落序号。
+        // 首页、奇偶页的页眉页脚内容经常相同，只保留一次
+        let mut parts: Vec<String> = numbered_parts(&archive, "word/", "header")
+            .into_iter()
+            .chain(numbered_parts(&archive, "word/", "footer"))
+            .map(|(_, name)| name)
+            .collect();
+        parts.extend(
+            ["footnotes.xml", "endnotes.xml", "comments.xml"]
+                .iter()
+                .map(|name| format!("word/{name}")),
+        );
+        let mut seen = HashSet::new();
+        for part in parts {
+            for (_, txt) in read_word_paragraphs(&mut archive, &part)? {
+                if seen.insert(txt.clone()) {
+                    items.push(Item {
+                        content: txt,
+                        ..Default::default()
+                    });
                 }
-                quickXmlEvent::Eof => {
-                    if !txt.trim().is_empty() {
-                        items.push(Item {
-                            content: txt.trim().to_string(),
-                        });
-                    }
-                    break;
-                } // 文件结束
-                _ => (),
             }
-            buf.clear();
         }
 
         Ok(items)
@@ -200,51 +546,49 @@ impl Reader for DocxReader {
 struct PptxReader;
 impl Reader for PptxReader {
     fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        // TODO 这个好像没有自动删除临时目录
-        let temp_dir = TempDir::new()?;
-        let file = File::open(file_path)?;
-        let mut archive = ZipArchive::new(file)?;
-        archive.extract(&temp_dir)?;
+        let mut archive = open_office_archive(file_path)?;
 
-        let document_path = temp_dir.path().join("ppt/slides/");
         let mut items = vec![];
-
-        for entry in fs::read_dir(Path::new(&document_path))? {
-            let entry = entry?;
-            let file_name = entry.file_name();
-            let file_name = file_name.to_string_lossy();
-
-            if file_name.starts_with("slide") && file_name.ends_with(".xml") {
-                let reader = BufReader::new(File::open(entry.path())?);
-                let mut xml_reader = quickXmlReader::from_reader(reader);
-                let mut txt = String::new();
-                let mut buf = Vec::new();
-                loop {
-                    match xml_reader.read_event_into(&mut buf)? {
-                        quickXmlEvent::Start(e) if e.name().as_ref() == b"a:p" => {
-                            if !txt.trim().is_empty() {
-                                items.push(Item {
-                                    content: txt.trim().to_string(),
-                                });
-                                txt.clear();
-                            }
-                        }
-                        quickXmlEvent::Text(e) => {
-                            txt.push_str(&e.decode()?);
-                        }
-                        quickXmlEvent::Eof => {
-                            if !txt.trim().is_empty() {
-                                items.push(Item {
-                                    content: txt.trim().to_string(),
-                                });
-                            }
-                            break;
-                        } // 文件结束
-                        _ => (),
+        push_properties(&mut items, office_properties(&mut archive)?);
+
+        // 每页都有的图片（如母版中的标志）只识别第一次
+        let mut seen_images = HashSet::new();
+        for (slide, name) in numbered_parts(&archive, "ppt/slides/", "slide") {
+            let Some(mut xml_reader) = zip_xml(&mut archive, &name)? else {
+                continue;
+            };
+            let mut txt = String::new();
+            let mut buf = Vec::new();
+            let mut paragraph = 0;
+            loop {
+                match xml_reader.read_event_into(&mut buf)? {
+                    quickXmlEvent::Start(e) if e.name().as_ref() == b"a:p" => {
+                        push_paragraph(&mut items, &txt, Some(slide), paragraph);
+                        txt.clear();
+                        paragraph += 1;
+                    }
+                    quickXmlEvent::Text(e) => {
+                        txt.push_str(&e.decode()?);
                     }
-                    buf.clear();
+                    quickXmlEvent::Eof => {
+                        push_paragraph(&mut items, &txt, Some(slide), paragraph);
+                        break;
+                    } // 文件结束
+                    _ => (),
                 }
+                buf.clear();
+            }
+            drop(xml_reader);
+            if let Some(notes_name) = pptx_notes_slide(&mut archive, &name)? {
+                read_pptx_notes(&mut archive, &notes_name, slide, &mut items)?;
             }
+            push_office_images(
+                &mut archive,
+                &name,
+                Some(slide),
+                &mut seen_images,
+                &mut items,
+            )?;
         }
         Ok(items)
     }
@@ -254,219 +598,2122 @@ impl Reader for PptxReader {
     }
 }
 
-struct XlsxReader;
-impl Reader for XlsxReader {
-    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        let temp_dir = TempDir::new()?;
-        let file = File::open(file_path)?;
-        let mut archive = ZipArchive::new(file)?;
-        archive.extract(&temp_dir)?;
+/// 部件在 `_rels/<部件>.rels` 中关联的其他部件，返回 (关系类型, 部件名)，
+/// 关系类型为 Type 的最后一段，如 `notesSlide`、`image`。外部链接不是部件，跳过
+fn part_relationships(
+    archive: &mut ZipArchive<File>,
+    part_name: &str,
+) -> Result<Vec<(String, String)>> {
+    let (dir, name) = part_name.rsplit_once('/').unwrap_or(("", part_name));
+    let Some(mut xml_reader) = zip_xml(archive, &format!("{dir}/_rels/{name}.rels"))? else {
+        return Ok(Vec::new());
+    };
+    let mut relationships = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) | quickXmlEvent::Empty(e)
+                if e.name().as_ref() == b"Relationship" =>
+            {
+                let external = e
+                    .try_get_attribute("TargetMode")?
+                    .is_some_and(|mode| mode.value.as_ref() == b"External");
+                let rel_type = e
+                    .try_get_attribute("Type")?
+                    .map(|t| t.unescape_value().map(|v| v.into_owned()))
+                    .transpose()?
+                    .unwrap_or_default();
+                if let Some(target) = e.try_get_attribute("Target")?.filter(|_| !external) {
+                    let rel_type = rel_type.rsplit('/').next().unwrap_or_default().to_string();
+                    relationships.push((rel_type, zip_part_name(dir, &target.unescape_value()?)));
+                }
+            }
+            quickXmlEvent::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(relationships)
+}
 
-        let document_path = temp_dir.path().join("xl/sharedStrings.xml");
-        let mut items = vec![];
+/// 幻灯片对应的备注页，在 slides/_rels/slideN.xml.rels 中关联，编号不一定与幻灯片相同
+fn pptx_notes_slide(archive: &mut ZipArchive<File>, slide_name: &str) -> Result<Option<String>> {
+    Ok(part_relationships(archive, slide_name)?
+        .into_iter()
+        .find(|(rel_type, _)| rel_type == "notesSlide")
+        .map(|(_, name)| name))
+}
 
-        // TODO 也有数据存在 sheet?.xml 中，需要读取
-        let reader =
-            BufReader::new(File::open(document_path).context("xl/sharedStrings.xml 不存在")?);
-        let mut xml_reader = quickXmlReader::from_reader(reader);
-        let mut buf = Vec::new();
-        let mut current_text = String::new();
-        let mut in_si = false;
-        let mut in_text = false;
+// 小于该大小的图片通常是图标或装饰，不识别
+const MIN_OCR_IMAGE_SIZE: u64 = 4 * 1024;
+
+/// 部件中引用的需要识别的图片，按引用顺序，已经识别过的跳过。
+/// 只识别白名单中启用了的图片格式，emf、wmf 等矢量图不识别
+fn ocr_image_parts(
+    archive: &mut ZipArchive<File>,
+    part_name: &str,
+    seen: &mut HashSet<String>,
+) -> Result<Vec<String>> {
+    let candidates: Vec<(String, String)> = part_relationships(archive, part_name)?
+        .into_iter()
+        .filter(|(rel_type, _)| rel_type == "image")
+        .filter_map(|(_, name)| {
+            let extension = name.rsplit_once('.')?.1.to_lowercase();
+            OcrReader
+                .supports()
+                .contains(&extension.as_str())
+                .then_some((name, extension))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let enabled = enabled_extensions()?;
+    let mut images = Vec::new();
+    for (name, extension) in candidates {
+        if !enabled.contains(&extension) {
+            continue;
+        }
+        let Ok(file) = archive.by_name(&name) else {
+            continue;
+        };
+        if file.size() >= MIN_OCR_IMAGE_SIZE && seen.insert(name.clone()) {
+            images.push(name);
+        }
+    }
+    Ok(images)
+}
 
-        loop {
-            match xml_reader.read_event_into(&mut buf)? {
-                quickXmlEvent::Start(e) => match e.name().as_ref() {
-                    b"si" => {
-                        in_si = true;
-                        current_text.clear();
-                    }
-                    b"t" if in_si => {
-                        in_text = true;
-                    }
-                    _ => {}
-                },
-                quickXmlEvent::Text(e) if in_text => {
-                    current_text.push_str(&e.decode()?);
+/// 识别 Office 文档部件中引用的图片，每张图片一条，截图中的文字经常只有这一份
+fn push_office_images(
+    archive: &mut ZipArchive<File>,
+    part_name: &str,
+    page: Option<i64>,
+    seen: &mut HashSet<String>,
+    items: &mut Vec<Item>,
+) -> Result<()> {
+    for name in ocr_image_parts(archive, part_name, seen)? {
+        let mut data = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut data)?;
+        match ocr_text(&data) {
+            Ok(text) => {
+                let content = text
+                    .lines()
+                    .map(remove_whitespace_for_chinese_chars)
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if !content.is_empty() {
+                    items.push(Item {
+                        content,
+                        position: ItemPosition {
+                            page,
+                            ..Default::default()
+                        },
+                    });
                 }
-                quickXmlEvent::End(e) => match e.name().as_ref() {
-                    b"si" => {
-                        if in_si && !current_text.trim().is_empty() {
-                            items.push(Item {
-                                content: current_text.trim().to_string(),
-                            });
-                        }
-                        in_si = false;
-                        current_text.clear();
-                    }
-                    b"t" => {
-                        in_text = false;
-                    }
-                    _ => {}
-                },
-                quickXmlEvent::Eof => break,
-                _ => {}
             }
-            buf.clear();
+            // 省电时推迟整个文件，接通电源后重新读取。普通线程中转给 OCR 线程重新读取
+            Err(e) if e.is::<OcrDeferred>() || e.is::<OcrRequired>() => return Err(e),
+            Err(e) => debug!("图片 OCR 失败: {part_name}, {name}: {e}"),
         }
-
-        Ok(items)
     }
+    Ok(())
+}
 
-    fn supports(&self) -> Vec<&str> {
-        vec!["xlsx"]
+/// 演讲者备注，只读取正文占位符，不读取备注页中的幻灯片编号和页眉页脚。
+/// 每段作为一条内容，如 `notes: 这里要讲慢一点`，段落序号从备注的第一段开始
+fn read_pptx_notes(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    slide: i64,
+    items: &mut Vec<Item>,
+) -> Result<()> {
+    let Some(mut xml_reader) = zip_xml(archive, name)? else {
+        return Ok(());
+    };
+    let mut buf = Vec::new();
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut is_body = false;
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) => match e.name().as_ref() {
+                b"p:sp" => {
+                    paragraphs.clear();
+                    is_body = false;
+                }
+                b"p:ph" => {
+                    is_body = e
+                        .try_get_attribute("type")?
+                        .is_some_and(|t| *t.value == *b"body")
+                }
+                b"a:p" => paragraphs.push(String::new()),
+                _ => {}
+            },
+            // 空段落也计入段落序号
+            quickXmlEvent::Empty(e) => match e.name().as_ref() {
+                b"p:ph" => {
+                    is_body = e
+                        .try_get_attribute("type")?
+                        .is_some_and(|t| *t.value == *b"body")
+                }
+                b"a:p" => paragraphs.push(String::new()),
+                _ => {}
+            },
+            quickXmlEvent::Text(e) => {
+                if let Some(paragraph) = paragraphs.last_mut() {
+                    paragraph.push_str(&e.decode()?);
+                }
+            }
+            quickXmlEvent::End(e) if e.name().as_ref() == b"p:sp" && is_body => {
+                for (i, paragraph) in paragraphs.iter().enumerate() {
+                    if !paragraph.trim().is_empty() {
+                        push_paragraph(
+                            items,
+                            &format!("notes: {}", paragraph.trim()),
+                            Some(slide),
+                            i as i64 + 1,
+                        );
+                    }
+                }
+            }
+            quickXmlEvent::Eof => break,
+            _ => {}
+        }
+        buf.clear();
     }
+    Ok(())
 }
 
-struct PdfReader;
-impl Reader for PdfReader {
-    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        let mut items = vec![];
-        let doc = pdfDocument::load(file_path)?;
-        let mut text = String::new();
+/// 按工作簿中的顺序列出工作表的名称和文件名。缺少 workbook.xml 时按文件序号排列
+fn xlsx_sheets(archive: &mut ZipArchive<File>) -> Result<Vec<(String, String)>> {
+    let workbook = "xl/workbook.xml";
+    let rels = "xl/_rels/workbook.xml.rels";
+    if archive.index_for_name(workbook).is_none() || archive.index_for_name(rels).is_none() {
+        return Ok(numbered_parts(archive, "xl/worksheets/", "sheet")
+            .into_iter()
+            .map(|(number, name)| (format!("Sheet{number}"), name))
+            .collect());
+    }
 
-        for page_num in 1..=doc.get_pages().len() {
-            let page_num_u32: u32 = page_num.try_into()?;
-            match doc.extract_text(&[page_num_u32]) {
-                Ok(page_text) => {
-                    text.push_str(page_text.trim_end_matches("\n"));
-                }
-                Err(_) => {
-                    continue;
+    let attribute = |e: &quick_xml::events::BytesStart, name: &str| -> Result<Option<String>> {
+        Ok(e.try_get_attribute(name)?
+            .map(|a| a.unescape_value().map(|v| v.into_owned()))
+            .transpose()?)
+    };
+
+    let mut targets = HashMap::new();
+    let Some(mut xml_reader) = zip_xml(archive, rels)? else {
+        return Ok(Vec::new());
+    };
+    let mut buf = Vec::new();
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) | quickXmlEvent::Empty(e)
+                if e.name().as_ref() == b"Relationship" =>
+            {
+                if let (Some(id), Some(target)) = (attribute(&e, "Id")?, attribute(&e, "Target")?) {
+                    targets.insert(id, zip_part_name("xl", &target));
                 }
             }
+            quickXmlEvent::Eof => break,
+            _ => {}
         }
-        let lines = text.lines().collect::<Vec<_>>();
-        let mut result = String::new();
+        buf.clear();
+    }
 
-        for (i, line) in lines.iter().enumerate() {
-            result.push_str(line);
-            if i < lines.len() - 1 && line.chars().last().is_some_and(|c| c.is_ascii_alphabetic()) {
-                result.push(' ');
+    drop(xml_reader);
+
+    let mut sheets = Vec::new();
+    let Some(mut xml_reader) = zip_xml(archive, workbook)? else {
+        return Ok(sheets);
+    };
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) | quickXmlEvent::Empty(e) if e.name().as_ref() == b"sheet" => {
+                let name = attribute(&e, "name")?.unwrap_or_default();
+                if let Some(part) = attribute(&e, "r:id")?.and_then(|id| targets.remove(&id)) {
+                    sheets.push((name, part));
+                }
             }
+            quickXmlEvent::Eof => break,
+            _ => {}
         }
-
-        items.push(Item { content: result });
-        Ok(items)
+        buf.clear();
     }
+    Ok(sheets)
+}
 
-    fn supports(&self) -> Vec<&str> {
-        vec!["pdf"]
+/// 共享字符串表，单元格中按序号引用。注音（rPh）不读取
+fn xlsx_shared_strings(archive: &mut ZipArchive<File>) -> Result<Vec<String>> {
+    let mut strings = Vec::new();
+    // 只有数字或内联字符串的工作簿没有 sharedStrings.xml
+    let Some(mut xml_reader) = zip_xml(archive, "xl/sharedStrings.xml")? else {
+        return Ok(strings);
+    };
+    let mut buf = Vec::new();
+    let mut current_text = String::new();
+    let mut in_text = false;
+    let mut in_phonetic = false;
+
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) => match e.name().as_ref() {
+                b"si" => current_text.clear(),
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            quickXmlEvent::Text(e) if in_text => {
+                current_text.push_str(&e.decode()?);
+            }
+            quickXmlEvent::GeneralRef(e) if in_text => {
+                if let Some(c) = e.resolve_char_ref()? {
+                    current_text.push(c);
+                } else if let Some(s) = resolve_predefined_entity(&e.decode()?) {
+                    current_text.push_str(s);
+                }
+            }
+            quickXmlEvent::Empty(e) if e.name().as_ref() == b"si" => strings.push(String::new()),
+            quickXmlEvent::End(e) => match e.name().as_ref() {
+                b"si" => strings.push(current_text.trim().to_string()),
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            quickXmlEvent::Eof => break,
+            _ => {}
+        }
+        buf.clear();
     }
+    Ok(strings)
 }
 
-struct OcrReader;
-impl Reader for OcrReader {
-    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
-        // 获取可执行文件的目录
-        // TODO https://github.com/antimatter15/tesseract-rs/issues/39
-        let exe_dir = env::current_exe()?
-            .parent()
-            .context("无法获取可执行文件目录")?
-            .to_path_buf();
-
-        let mut tessdata_path = exe_dir.join("tessdata");
+/// 列序号转换为列名，1 为 A，27 为 AA
+fn xlsx_column_name(mut column: u32) -> String {
+    let mut name = Vec::new();
+    while column > 0 {
+        column -= 1;
+        name.push(b'A' + (column % 26) as u8);
+        column /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
 
-        // 检查 tessdata 目录是否存在
-        if !tessdata_path.exists() {
-            tessdata_path = PathBuf::from("./tessdata");
+/// `Sheet1!B3` 形式的单元格引用，工作表名称包含空格或符号时加上单引号
+fn xlsx_cell_reference(sheet: &str, cell: &str) -> String {
+    if sheet.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        format!("{sheet}!{cell}")
+    } else {
+        format!("'{}'!{cell}", sheet.replace('\'', "''"))
+    }
+}
 
-            if !tessdata_path.exists() {
-                return Err(anyhow::anyhow!(
-                    "tessdata 目录不存在: {}. 请确保 tessdata 目录在可执行文件同级目录中。",
-                    tessdata_path.display()
-                ));
+/// 读取工作表中的字符串和数字单元格。公式单元格的计算结果不读取，
+/// 同一工作表中重复的内容只保留第一次出现的单元格
+fn read_xlsx_sheet(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    shared_strings: &[String],
+    (sheet, sheet_name): (i64, &str),
+    items: &mut Vec<Item>,
+) -> Result<()> {
+    let Some(mut xml_reader) = zip_xml(archive, name)? else {
+        return Ok(());
+    };
+    let mut buf = Vec::new();
+    let mut seen = HashSet::new();
+    let mut row = 0;
+    let mut column = 0;
+    let mut cell = String::new();
+    let mut cell_type = String::new();
+    let mut has_formula = false;
+    let mut in_value = false;
+    let mut in_inline = false;
+    let mut in_phonetic = false;
+    let mut value = String::new();
+
+    loop {
+        match xml_reader.read_event_into(&mut buf)? {
+            quickXmlEvent::Start(e) => match e.name().as_ref() {
+                b"row" => {
+                    // 缺少 r 属性时行号依次递增
+                    row = e
+                        .try_get_attribute("r")?
+                        .and_then(|r| String::from_utf8_lossy(&r.value).parse().ok())
+                        .unwrap_or(row + 1);
+                    column = 0;
+                }
+                b"c" => {
+                    column += 1;
+                    cell = match e.try_get_attribute("r")? {
+                        Some(r) => String::from_utf8_lossy(&r.value).into_owned(),
+                        None => format!("{}{row}", xlsx_column_name(column)),
+                    };
+                    cell_type = e
+                        .try_get_attribute("t")?
+                        .map(|t| String::from_utf8_lossy(&t.value).into_owned())
+                        .unwrap_or_default();
+                    has_formula = false;
+                    value.clear();
+                }
+                b"f" => has_formula = true,
+                b"v" => in_value = true,
+                b"is" => in_inline = true,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            quickXmlEvent::Empty(e) if e.name().as_ref() == b"f" => has_formula = true,
+            quickXmlEvent::Text(e) if in_value || (in_inline && !in_phonetic) => {
+                value.push_str(&e.decode()?);
             }
+            quickXmlEvent::GeneralRef(e) if in_value || (in_inline && !in_phonetic) => {
+                if let Some(c) = e.resolve_char_ref()? {
+                    value.push(c);
+                } else if let Some(s) = resolve_predefined_entity(&e.decode()?) {
+                    value.push_str(s);
+                }
+            }
+            quickXmlEvent::End(e) => match e.name().as_ref() {
+                b"v" => in_value = false,
+                b"is" => in_inline = false,
+                b"rPh" => in_phonetic = false,
+                b"c" => {
+                    let text = match cell_type.as_str() {
+                        "s" => value
+                            .trim()
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|index| shared_strings.get(index))
+                            .cloned()
+                            .unwrap_or_default(),
+                        "inlineStr" => value.trim().to_string(),
+                        "" | "n" if !has_formula => value.trim().to_string(),
+                        _ => String::new(),
+                    };
+                    if !text.is_empty() && seen.insert(text.clone()) {
+                        items.push(Item {
+                            content: text,
+                            position: ItemPosition {
+                                page: Some(sheet),
+                                sheet_row: Some(row),
+                                cell: Some(xlsx_cell_reference(sheet_name, &cell)),
+                                ..Default::default()
+                            },
+                        });
+                    }
+                }
+                _ => {}
+            },
+            quickXmlEvent::Eof => break,
+            _ => {}
         }
+        buf.clear();
+    }
+    Ok(())
+}
 
-        let tess = Tesseract::new(
-            Some(
-                tessdata_path
-                    .to_str()
-                    .context("tessdata 路径包含无效字符")?,
-            ),
-            Some("eng+chi_sim"),
-        )?;
-
-        // 使用内存读取避免中文路径问题
-        let image_data = std::fs::read(file_path)?;
+struct XlsxReader;
+impl Reader for XlsxReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let mut archive = open_office_archive(file_path)?;
 
-        let text = tess.set_image_from_mem(&image_data)?.get_text()?;
+        let mut items = vec![];
+        push_properties(&mut items, office_properties(&mut archive)?);
+        let shared_strings = xlsx_shared_strings(&mut archive)?;
+
+        for (i, (name, part)) in xlsx_sheets(&mut archive)?.into_iter().enumerate() {
+            let sheet = i as i64 + 1;
+            // 工作表名称也作为一条内容，和文档属性一样没有位置
+            items.push(Item {
+                content: format!("sheet: {name}"),
+                position: ItemPosition::default(),
+            });
+            read_xlsx_sheet(
+                &mut archive,
+                &part,
+                &shared_strings,
+                (sheet, &name),
+                &mut items,
+            )?;
+        }
 
-        let items = text
-            .split("\n")
-            .filter(|line| !line.trim().is_empty())
-            .map(|line| self.remove_whitespace_for_chinese_chars(line))
-            .map(|line| Item {
-                content: line.to_string(),
-            })
-            .collect();
         Ok(items)
     }
 
     fn supports(&self) -> Vec<&str> {
-        vec!["jpg", "jpeg", "png", "tif", "tiff", "gif", "webp"]
+        vec!["xlsx"]
     }
 }
 
-impl OcrReader {
-    fn remove_whitespace_for_chinese_chars(&self, s: &str) -> String {
-        let mut result = String::new();
-        let mut chars = s.trim().chars().peekable();
+/// 合并成一行，英文单词之间补上空格
+fn join_pdf_lines(text: &str) -> String {
+    let lines = text.trim_end_matches("\n").lines().collect::<Vec<_>>();
+    let mut result = String::new();
 
-        while let Some(current_char) = chars.next() {
-            result.push(current_char);
+    for (i, line) in lines.iter().enumerate() {
+        result.push_str(line);
+        if i < lines.len() - 1 && line.chars().last().is_some_and(|c| c.is_ascii_alphabetic()) {
+            result.push(' ');
+        }
+    }
+    result
+}
 
-            if self.is_chinese(current_char) {
-                while let Some(c) = chars.peek() {
-                    if c.is_whitespace() {
-                        chars.next();
-                    } else {
-                        break;
-                    }
-                }
+// 可以由 lopdf 解开的通用编码，解开后是原始的像素或者其他图片编码
+const PDF_GENERIC_FILTERS: &[&str] = &["FlateDecode", "LZWDecode", "ASCII85Decode"];
+
+/// 页面中的图片，解开编码后转换成 Tesseract 能读取的格式。扫描件通常每页就是一张图片，
+/// JPEG、JPEG 2000 直接识别，Flate 等编码的像素转换成 PNM，CCITT 传真编码包装成 TIFF。
+/// JBIG2 等无法解码的图片跳过并记录日志
+fn pdf_page_scans(doc: &pdfDocument, page_id: lopdf::ObjectId) -> Vec<Vec<u8>> {
+    let Ok(images) = doc.get_page_images(page_id) else {
+        return Vec::new();
+    };
+    images
+        .iter()
+        .filter_map(|image| match pdf_scan_image(image) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                info!("跳过 PDF 中的图片 {:?}: {e}", image.id);
+                None
             }
+        })
+        .collect()
+}
+
+fn pdf_scan_image(image: &PdfImage) -> Result<Vec<u8>> {
+    let filters = image.filters.as_deref().unwrap_or_default();
+    let generic = filters
+        .iter()
+        .take_while(|filter| PDF_GENERIC_FILTERS.contains(&filter.as_str()))
+        .count();
+    let data = if generic == 0 {
+        image.content.to_vec()
+    } else {
+        let mut dict = Dictionary::new();
+        dict.set(
+            "Filter",
+            filters[..generic]
+                .iter()
+                .map(|filter| Object::Name(filter.as_bytes().to_vec()))
+                .collect::<Vec<_>>(),
+        );
+        // 只有一个通用编码时才有预测器等参数
+        if let Some(parms) = pdf_decode_parms(image, 0).filter(|_| generic == 1) {
+            dict.set("DecodeParms", parms.clone());
         }
-        result
+        Stream::new(dict, image.content.to_vec()).decompressed_content()?
+    };
+    match &filters[generic..] {
+        [] => pnm_image(image, &data),
+        [filter] if filter == "DCTDecode" || filter == "JPXDecode" => Ok(data),
+        [filter] if filter == "CCITTFaxDecode" => {
+            ccitt_tiff_image(image, pdf_decode_parms(image, generic), &data)
+        }
+        rest => Err(anyhow!("不支持的图片编码: {}", rest.join(", "))),
     }
+}
 
-    fn is_chinese(&self, c: char) -> bool {
-        ('\u{4e00}'..='\u{9fa5}').contains(&c)
+/// 第 index 个编码的参数，DecodeParms 是数组时和 Filter 一一对应
+fn pdf_decode_parms<'a>(image: &PdfImage<'a>, index: usize) -> Option<&'a Dictionary> {
+    match image.origin_dict.get(b"DecodeParms").ok()? {
+        Object::Dictionary(parms) => Some(parms),
+        Object::Array(parms) => parms.get(index)?.as_dict().ok(),
+        _ => None,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const TEST_DATA_DIR: &str = "../test_data/reader";
+/// Decode 为 [1 0] 时像素值反转
+fn is_pdf_image_inverted(image: &PdfImage) -> bool {
+    image
+        .origin_dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| decode.first())
+        .and_then(|first| first.as_float().ok())
+        .is_some_and(|first| first > 0.5)
+}
 
-    #[test]
-    fn test_composite_reader() {
-        let reader = CompositeReader::new().unwrap();
-        let items = reader
-            .read(&Path::new(TEST_DATA_DIR).join("test.txt"))
-            .unwrap();
-        assert_eq!(items.len(), 4);
+/// 原始像素转换成 PNM。灰度和 RGB 按数据长度判断分量数，调色板和 CMYK 不转换
+fn pnm_image(image: &PdfImage, data: &[u8]) -> Result<Vec<u8>> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    if width == 0 || height == 0 {
+        return Err(anyhow!("图片大小为 0"));
     }
-
-    #[test]
-    fn test_composite_unknown_extension() {
-        let reader = CompositeReader::new().unwrap();
-        let result = reader
-            .read(&Path::new(TEST_DATA_DIR).join("test.xyz"))
-            .unwrap();
-        assert_eq!(result.len(), 0);
+    if matches!(image.color_space.as_deref(), Some("Indexed" | "DeviceCMYK")) {
+        return Err(anyhow!("不支持的颜色空间: {:?}", image.color_space));
     }
-
-    #[test]
-    fn test_txt_reader() {
-        let reader = TxtReader;
-        assert_eq!(reader.supports(), vec!["txt", "md", "markdown"]);
-        let items = reader
-            .read(&Path::new(TEST_DATA_DIR).join("test.txt"))
-            .unwrap();
-        assert_eq!(items.len(), 4);
+    let inverted = is_pdf_image_inverted(image);
+    match image.bits_per_component {
+        // 黑白图片和遮罩每行按字节对齐，PDF 中 0 为黑色，PBM 中 1 为黑色
+        Some(1) => {
+            let size = width.div_ceil(8) * height;
+            let pixels = data.get(..size).context("图片数据不完整")?;
+            let mut pnm = format!("P4\n{width} {height}\n").into_bytes();
+            pnm.extend(
+                pixels
+                    .iter()
+                    .map(|byte| if inverted { *byte } else { !byte }),
+            );
+            Ok(pnm)
+        }
+        Some(8) => {
+            let (magic, components) = match data.len() / (width * height) {
+                1 => ("P5", 1),
+                3 => ("P6", 3),
+                components => return Err(anyhow!("不支持 {components} 个分量的图片")),
+            };
+            let pixels = &data[..width * height * components];
+            let mut pnm = format!("{magic}\n{width} {height}\n255\n").into_bytes();
+            if inverted {
+                pnm.extend(pixels.iter().map(|value| 255 - value));
+            } else {
+                pnm.extend_from_slice(pixels);
+            }
+            Ok(pnm)
+        }
+        bits => Err(anyhow!("不支持每个分量 {bits:?} 位的图片")),
+    }
+}
+
+/// CCITT 传真编码的数据加上 TIFF 头，由 Tesseract 使用的 Leptonica 解码。
+/// K < 0 为 Group 4，否则为 Group 3，K > 0 时混合二维编码
+fn ccitt_tiff_image(image: &PdfImage, parms: Option<&Dictionary>, data: &[u8]) -> Result<Vec<u8>> {
+    let parm = |key: &[u8]| parms.and_then(|parms| parms.get(key).ok());
+    let k = parm(b"K").and_then(|k| k.as_i64().ok()).unwrap_or(0);
+    let width = parm(b"Columns")
+        .and_then(|columns| columns.as_i64().ok())
+        .unwrap_or(image.width);
+    let height = parm(b"Rows")
+        .and_then(|rows| rows.as_i64().ok())
+        .filter(|rows| *rows > 0)
+        .unwrap_or(image.height);
+    let byte_align = parm(b"EncodedByteAlign")
+        .and_then(|align| align.as_bool().ok())
+        .unwrap_or(false);
+    let compression: u32 = if k < 0 { 4 } else { 3 };
+    // T4Options: 第 0 位为二维编码，第 2 位为每行按字节对齐
+    let t4_options = u32::from(k > 0) | if byte_align { 4 } else { 0 };
+    // 解码后的 0 为白色，Decode 为 [1 0] 时反转
+    let photometric = u32::from(is_pdf_image_inverted(image));
+    // (标签, 类型, 值)，类型 3 为 SHORT，4 为 LONG，标签按升序排列
+    let mut entries: Vec<(u16, u16, u32)> = vec![
+        (256, 4, width as u32),
+        (257, 4, height as u32),
+        (258, 3, 1),
+        (259, 3, compression),
+        (262, 3, photometric),
+        (273, 4, 0),
+        (277, 3, 1),
+        (278, 4, height as u32),
+        (279, 4, data.len() as u32),
+    ];
+    if compression == 3 {
+        entries.push((292, 4, t4_options));
+    }
+    let data_offset = 8 + 2 + entries.len() * 12 + 4;
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend((entries.len() as u16).to_le_bytes());
+    for (tag, kind, value) in entries {
+        let value = if tag == 273 {
+            data_offset as u32
+        } else {
+            value
+        };
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(kind.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        if kind == 3 {
+            tiff.extend((value as u16).to_le_bytes());
+            tiff.extend([0, 0]);
+        } else {
+            tiff.extend(value.to_le_bytes());
+        }
+    }
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend_from_slice(data);
+    Ok(tiff)
+}
+
+/// 文档信息字典中的属性，缺少的再从 XMP 元数据中补充
+fn pdf_properties(doc: &pdfDocument) -> Vec<(&'static str, String)> {
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .and_then(|info| match info.as_reference() {
+            Ok(id) => doc.get_dictionary(id),
+            Err(_) => info.as_dict(),
+        })
+        .ok();
+    let xmp = pdf_xmp_properties(doc);
+    [
+        ("title", b"Title".as_slice()),
+        ("author", b"Author"),
+        ("subject", b"Subject"),
+        ("keywords", b"Keywords"),
+    ]
+    .into_iter()
+    .filter_map(|(name, key)| {
+        info.and_then(|info| info.get(key).ok())
+            .and_then(|value| lopdf::decode_text_string(value).ok())
+            .filter(|value| !value.trim().is_empty())
+            .or_else(|| xmp.get(name).cloned())
+            .map(|value| (name, value))
+    })
+    .collect()
+}
+
+fn pdf_xmp_properties(doc: &pdfDocument) -> HashMap<&'static str, String> {
+    let xml = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Metadata"))
+        .and_then(|metadata| metadata.as_reference())
+        .and_then(|id| doc.get_object(id))
+        .and_then(|metadata| metadata.as_stream())
+        .map(|stream| {
+            stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone())
+        });
+    match xml {
+        Ok(xml) => parse_xmp(&xml),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// 解析 XMP 中的 Dublin Core 属性，多个值用逗号连接。标题和描述按语言有多个版本，只取第一个
+fn parse_xmp(xml: &[u8]) -> HashMap<&'static str, String> {
+    let mut properties: HashMap<&'static str, String> = HashMap::new();
+    let mut xml_reader = quickXmlReader::from_reader(xml);
+    let mut buf = Vec::new();
+    let mut current = None;
+    let mut in_li = false;
+    let mut skip_li = false;
+    // pdf:Keywords 直接包含文字，dc:subject 中已有关键词时忽略
+    let mut direct = false;
+    loop {
+        match xml_reader.read_event_into(&mut buf) {
+            Ok(quickXmlEvent::Start(e)) => match e.name().as_ref() {
+                b"dc:title" => current = Some("title"),
+                b"dc:creator" => current = Some("author"),
+                b"dc:description" => current = Some("subject"),
+                b"dc:subject" => current = Some("keywords"),
+                b"pdf:Keywords" => {
+                    current = Some("keywords");
+                    direct = !properties.contains_key("keywords");
+                }
+                b"rdf:li" => {
+                    in_li = true;
+                    skip_li = matches!(current, Some(name @ ("title" | "subject")) if properties.contains_key(name));
+                    if let Some(value) = current.and_then(|name| properties.get_mut(name)) {
+                        if !skip_li && !value.is_empty() {
+                            value.push_str(", ");
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(quickXmlEvent::Text(e)) => {
+                if let (Some(name), Ok(text)) = (current, e.decode()) {
+                    if (in_li && !skip_li) || direct {
+                        properties.entry(name).or_default().push_str(&text);
+                    }
+                }
+            }
+            Ok(quickXmlEvent::GeneralRef(e)) => {
+                let text = match e.resolve_char_ref() {
+                    Ok(Some(c)) => Some(c.to_string()),
+                    _ => e
+                        .decode()
+                        .ok()
+                        .and_then(|name| resolve_predefined_entity(&name))
+                        .map(str::to_string),
+                };
+                if let (Some(name), Some(text)) = (current, text) {
+                    if (in_li && !skip_li) || direct {
+                        properties.entry(name).or_default().push_str(&text);
+                    }
+                }
+            }
+            Ok(quickXmlEvent::End(e)) => match e.name().as_ref() {
+                b"dc:title" | b"dc:creator" | b"dc:description" | b"dc:subject"
+                | b"pdf:Keywords" => {
+                    current = None;
+                    direct = false;
+                }
+                b"rdf:li" => {
+                    in_li = false;
+                    skip_li = false;
+                }
+                _ => {}
+            },
+            Ok(quickXmlEvent::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    properties.retain(|_, value| !value.trim().is_empty());
+    properties
+}
+
+fn decrypt_pdf(doc: &mut pdfDocument, password: Option<String>) -> Result<()> {
+    let password = password.ok_or(ProtectedError::NoPassword)?;
+    doc.decrypt(&password)
+        .map_err(|_| ProtectedError::WrongPassword)?;
+    Ok(())
+}
+
+struct PdfReader;
+impl Reader for PdfReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let mut items = vec![];
+        let mut doc = pdfDocument::load(file_path)?;
+        // 只有所有者密码的文档加载时已经用空密码解密，这里剩下的需要用户密码
+        if doc.is_encrypted() {
+            decrypt_pdf(&mut doc, find_password(file_path)?)?;
+        }
+        push_properties(&mut items, pdf_properties(&doc));
+
+        // 每页一条，记录页码
+        for (page_num, page_id) in doc.get_pages() {
+            let mut result = match doc.extract_text(&[page_num]) {
+                Ok(page_text) => join_pdf_lines(&page_text),
+                Err(_) => String::new(),
+            };
+            if result.trim().is_empty() {
+                // 没有文字的页面可能是扫描件，识别其中的图片
+                let mut texts = Vec::new();
+                for image in pdf_page_scans(&doc, page_id) {
+                    match ocr_text(&image) {
+                        Ok(text) => texts.push(text),
+                        Err(e) if e.is::<OcrDeferred>() || e.is::<OcrRequired>() => return Err(e),
+                        Err(e) => debug!("PDF 第 {page_num} 页 OCR 失败: {file_path:?}: {e}"),
+                    }
+                }
+                result = texts
+                    .iter()
+                    .flat_map(|text| text.lines())
+                    .map(remove_whitespace_for_chinese_chars)
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+            if result.trim().is_empty() {
+                continue;
+            }
+
+            items.push(Item {
+                content: result,
+                position: ItemPosition {
+                    page: Some(page_num as i64),
+                    ..Default::default()
+                },
+            });
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["pdf"]
+    }
+}
+
+/// OpenDocument 文件的正文都在压缩包的 content.xml 中
+fn read_odf_content(file_path: &Path) -> Result<String> {
+    let mut archive = ZipArchive::new(File::open(file_path)?)?;
+    let mut content = String::new();
+    archive
+        .by_name("content.xml")
+        .context("content.xml 不存在")?
+        .read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn is_odf_paragraph(name: &[u8]) -> bool {
+    name == b"text:p" || name == b"text:h"
+}
+
+/// 把段落中的文字、实体和空白元素追加到 txt
+fn push_odf_text(txt: &mut String, event: &quickXmlEvent) -> Result<()> {
+    match event {
+        quickXmlEvent::Text(e) => txt.push_str(&e.decode()?),
+        quickXmlEvent::GeneralRef(e) => {
+            if let Some(c) = e.resolve_char_ref()? {
+                txt.push(c);
+            } else if let Some(s) = resolve_predefined_entity(&e.decode()?) {
+                txt.push_str(s);
+            }
+        }
+        quickXmlEvent::Empty(e) => match e.name().as_ref() {
+            // 连续空格，text:c 为个数
+            b"text:s" => {
+                let count = e
+                    .try_get_attribute("text:c")?
+                    .and_then(|c| String::from_utf8_lossy(&c.value).parse().ok())
+                    .unwrap_or(1);
+                txt.push_str(&" ".repeat(count));
+            }
+            b"text:tab" | b"text:line-break" => txt.push(' '),
+            _ => {}
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 按段落读取 odt/odp 的正文。page_element 为每一页的元素名，页内的段落重新计数
+fn read_odf_paragraphs(content: &str, page_element: Option<&[u8]>) -> Result<Vec<Item>> {
+    let mut xml_reader = quickXmlReader::from_str(content);
+    let mut items = vec![];
+    let mut txt = String::new();
+    let mut page = None;
+    let mut paragraph = 0;
+    // 段落中可能嵌套段落（如脚注），只在最外层的段落结束时输出
+    let mut depth = 0;
+
+    loop {
+        let event = xml_reader.read_event()?;
+        match &event {
+            quickXmlEvent::Start(e) if Some(e.name().as_ref()) == page_element => {
+                page = Some(page.unwrap_or(0) + 1);
+                paragraph = 0;
+            }
+            quickXmlEvent::Start(e) if is_odf_paragraph(e.name().as_ref()) => {
+                if depth == 0 {
+                    paragraph += 1;
+                    txt.clear();
+                }
+                depth += 1;
+            }
+            quickXmlEvent::End(e) if is_odf_paragraph(e.name().as_ref()) => {
+                depth -= 1;
+                if depth == 0 {
+                    push_paragraph(&mut items, &txt, page, paragraph);
+                }
+            }
+            // 空段落也计数
+            quickXmlEvent::Empty(e) if depth == 0 && is_odf_paragraph(e.name().as_ref()) => {
+                paragraph += 1;
+            }
+            quickXmlEvent::Eof => break,
+            _ if depth > 0 => push_odf_text(&mut txt, &event)?,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+struct OdtReader;
+impl Reader for OdtReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        read_odf_paragraphs(&read_odf_content(file_path)?, None)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["odt"]
+    }
+}
+
+struct OdpReader;
+impl Reader for OdpReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        read_odf_paragraphs(&read_odf_content(file_path)?, Some(b"draw:page"))
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["odp"]
+    }
+}
+
+/// 与 XlsxReader 一致，只读取文本类型的单元格
+struct OdsReader;
+impl Reader for OdsReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let content = read_odf_content(file_path)?;
+        let mut xml_reader = quickXmlReader::from_str(&content);
+        let mut items = vec![];
+        let mut txt = String::new();
+        let mut sheet = 0;
+        let mut row = 0;
+        // 当前行重复的次数，空行常常合并成一个元素
+        let mut row_repeated = 1;
+        let mut in_cell = false;
+
+        fn rows_repeated(e: &quick_xml::events::BytesStart) -> Result<i64> {
+            Ok(e.try_get_attribute("table:number-rows-repeated")?
+                .and_then(|n| String::from_utf8_lossy(&n.value).parse().ok())
+                .unwrap_or(1))
+        }
+
+        loop {
+            let event = xml_reader.read_event()?;
+            match &event {
+                quickXmlEvent::Start(e) => match e.name().as_ref() {
+                    b"table:table" => {
+                        sheet += 1;
+                        row = 0;
+                    }
+                    b"table:table-row" => {
+                        row += 1;
+                        row_repeated = rows_repeated(e)?;
+                    }
+                    b"table:table-cell" => {
+                        in_cell = e
+                            .try_get_attribute("office:value-type")?
+                            .is_some_and(|t| t.value.as_ref() == b"string");
+                        txt.clear();
+                    }
+                    // 单元格中的多个段落用空格分隔
+                    name if in_cell && is_odf_paragraph(name) && !txt.is_empty() => {
+                        txt.push(' ');
+                    }
+                    _ => {}
+                },
+                quickXmlEvent::End(e) => match e.name().as_ref() {
+                    b"table:table-row" => row += row_repeated - 1,
+                    b"table:table-cell" if in_cell => {
+                        if !txt.trim().is_empty() {
+                            items.push(Item {
+                                content: txt.trim().to_string(),
+                                position: ItemPosition {
+                                    page: Some(sheet),
+                                    sheet_row: Some(row),
+                                    ..Default::default()
+                                },
+                            });
+                        }
+                        in_cell = false;
+                    }
+                    _ => {}
+                },
+                quickXmlEvent::Empty(e) if e.name().as_ref() == b"table:table-row" => {
+                    row += rows_repeated(e)?;
+                }
+                quickXmlEvent::Eof => break,
+                _ if in_cell => push_odf_text(&mut txt, &event)?,
+                _ => {}
+            }
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["ods"]
+    }
+}
+
+/// OneNote 分区文件（.one），每页的标题和每个大纲元素各一条，记录页序号和元素序号。
+/// 表格中每个单元格的大纲元素单独一条
+struct OneNoteReader;
+impl Reader for OneNoteReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let path = file_path
+            .to_str()
+            .with_context(|| format!("Invalid file path: {file_path:?}"))?;
+        let section = OneNoteParser::new().parse_section(TypedPath::derive(path))?;
+        let mut items = vec![];
+        let pages = section
+            .page_series()
+            .iter()
+            .flat_map(|series| series.pages());
+        for (i, page) in pages.enumerate() {
+            let page_num = i as i64 + 1;
+            if let Some(title) = page.title_text().filter(|title| !title.trim().is_empty()) {
+                items.push(Item {
+                    content: title.trim().to_string(),
+                    position: ItemPosition {
+                        page: Some(page_num),
+                        ..Default::default()
+                    },
+                });
+            }
+            let mut texts = vec![];
+            for outline in page
+                .contents()
+                .iter()
+                .filter_map(|content| content.outline())
+            {
+                push_onenote_outline(outline.items(), &mut texts);
+            }
+            for (j, text) in texts.into_iter().enumerate() {
+                items.push(Item {
+                    content: text,
+                    position: ItemPosition {
+                        page: Some(page_num),
+                        paragraph: Some(j as i64 + 1),
+                        ..Default::default()
+                    },
+                });
+            }
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["one"]
+    }
+}
+
+fn push_onenote_outline(outline_items: &[OutlineItem], texts: &mut Vec<String>) {
+    for outline_item in outline_items {
+        match outline_item {
+            OutlineItem::Group(group) => push_onenote_outline(group.outlines(), texts),
+            OutlineItem::Element(element) => push_onenote_element(element, texts),
+        }
+    }
+}
+
+fn push_onenote_element(element: &OutlineElement, texts: &mut Vec<String>) {
+    // OneNote 用垂直制表符表示段内换行
+    let text = element
+        .contents()
+        .iter()
+        .filter_map(|content| content.rich_text())
+        .map(|rich_text| rich_text.text().replace('\u{b}', "\n").trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !text.is_empty() {
+        texts.push(text);
+    }
+    for table in element
+        .contents()
+        .iter()
+        .filter_map(|content| content.table())
+    {
+        for row in table.contents() {
+            for cell in row.contents() {
+                for cell_element in cell.contents() {
+                    push_onenote_element(cell_element, texts);
+                }
+            }
+        }
+    }
+    push_onenote_outline(element.children(), texts);
+}
+
+// 内容不是正文的元素，连同内容一起跳过
+const HTML_SKIPPED_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "svg"];
+
+// 块级元素，开始和结束都会分隔文字块
+const HTML_BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "dt",
+    "dd",
+    "tr",
+    "td",
+    "th",
+    "table",
+    "ul",
+    "ol",
+    "dl",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "aside",
+    "main",
+    "blockquote",
+    "pre",
+    "figcaption",
+    "caption",
+    "form",
+    "hr",
+    "body",
+];
+
+/// 解码 HTML 实体，只处理数字实体和常见的命名实体，其他原样保留
+fn decode_html_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    "copy" => Some('©'),
+                    "reg" => Some('®'),
+                    "hellip" => Some('…'),
+                    "mdash" => Some('—'),
+                    "ndash" => Some('–'),
+                    "lsquo" => Some('‘'),
+                    "rsquo" => Some('’'),
+                    "ldquo" => Some('“'),
+                    "rdquo" => Some('”'),
+                    "middot" => Some('·'),
+                    _ => None,
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 标签结束的 '>' 位置，跳过属性值中的 '>'
+fn find_tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 把一段文字的连续空白合并成一个空格后追加到文字块，行内标签两侧的文字直接相连
+fn push_html_text(block: &mut String, text: &str) {
+    for c in decode_html_entities(text).chars() {
+        if !c.is_whitespace() {
+            block.push(c);
+        } else if !block.is_empty() && !block.ends_with(' ') {
+            block.push(' ');
+        }
+    }
+}
+
+/// 按标题和段落等块级元素拆分正文，<title> 作为第一条
+fn parse_html(html: &str) -> Vec<Item> {
+    let lower = html.to_ascii_lowercase();
+    let mut title = String::new();
+    let mut in_title = false;
+    let mut blocks: Vec<String> = Vec::new();
+    let mut block = String::new();
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let Some(lt) = html[pos..].find('<').map(|i| pos + i) else {
+            push_html_text(&mut block, &html[pos..]);
+            break;
+        };
+        if in_title {
+            push_html_text(&mut title, &html[pos..lt]);
+        } else {
+            push_html_text(&mut block, &html[pos..lt]);
+        }
+
+        if lower[lt..].starts_with("<!--") {
+            pos = lower[lt..].find("-->").map_or(html.len(), |i| lt + i + 3);
+            continue;
+        }
+        let Some(gt) = find_tag_end(html, lt) else {
+            break;
+        };
+        pos = gt + 1;
+        let tag = &lower[lt + 1..gt];
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+
+        if HTML_SKIPPED_ELEMENTS.contains(&name.as_str()) && !closing && !tag.ends_with('/') {
+            let end_tag = format!("</{name}");
+            pos = match lower[pos..].find(&end_tag) {
+                Some(i) => find_tag_end(html, pos + i).map_or(html.len(), |gt| gt + 1),
+                None => html.len(),
+            };
+        } else if name == "title" {
+            in_title = !closing;
+        } else if name == "br" {
+            push_html_text(&mut block, " ");
+        } else if HTML_BLOCK_ELEMENTS.contains(&name.as_str()) {
+            let text = std::mem::take(&mut block);
+            if !text.trim().is_empty() {
+                blocks.push(text.trim().to_string());
+            }
+        }
+    }
+    if !block.trim().is_empty() {
+        blocks.push(block.trim().to_string());
+    }
+
+    let mut items = vec![];
+    if !title.trim().is_empty() {
+        items.push(Item {
+            content: title.trim().to_string(),
+            ..Default::default()
+        });
+    }
+    items.extend(blocks.into_iter().enumerate().map(|(i, content)| Item {
+        content,
+        position: ItemPosition {
+            paragraph: Some(i as i64 + 1),
+            ..Default::default()
+        },
+    }));
+    items
+}
+
+struct HtmlReader;
+impl Reader for HtmlReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        // TODO 与 TxtReader 一样检测编码，还没有读取 <meta charset>
+        let html = fs::read(file_path)?;
+        Ok(parse_html(&decode_text(&html, root_encoding(file_path)?)))
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["html", "htm", "xhtml"]
+    }
+}
+
+/// 把结构化数据展开成 `路径: 值` 形式的条目，如 `server.port: 8080`、`hosts[0]: a`
+fn flatten_value(path: &str, value: &JsonValue, items: &mut Vec<Item>) {
+    match value {
+        JsonValue::Object(map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                flatten_value(&path, value, items);
+            }
+        }
+        JsonValue::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten_value(&format!("{path}[{i}]"), value, items);
+            }
+        }
+        scalar => {
+            let value = match scalar {
+                JsonValue::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let content = if path.is_empty() {
+                value
+            } else {
+                format!("{path}: {value}")
+            };
+            items.push(Item {
+                content,
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// YAML 的键可以不是字符串，统一转成字符串
+fn yaml_to_json(value: serde_yaml::Value) -> JsonValue {
+    match value {
+        serde_yaml::Value::Null => JsonValue::Null,
+        serde_yaml::Value::Bool(b) => JsonValue::Bool(b),
+        // .nan 和 .inf 不能用 JSON 数字表示，按字符串保存
+        serde_yaml::Value::Number(n) => serde_json::to_value(&n)
+            .ok()
+            .filter(|v| !v.is_null())
+            .unwrap_or_else(|| JsonValue::String(n.to_string())),
+        serde_yaml::Value::String(s) => JsonValue::String(s),
+        serde_yaml::Value::Sequence(values) => {
+            JsonValue::Array(values.into_iter().map(yaml_to_json).collect())
+        }
+        serde_yaml::Value::Mapping(map) => JsonValue::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = match yaml_to_json(key) {
+                        JsonValue::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (key, yaml_to_json(value))
+                })
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+/// TOML 的日期时间按原文保存
+fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(s) => JsonValue::String(s),
+        toml::Value::Integer(i) => JsonValue::from(i),
+        toml::Value::Float(f) => JsonValue::from(f),
+        toml::Value::Boolean(b) => JsonValue::Bool(b),
+        toml::Value::Datetime(d) => JsonValue::String(d.to_string()),
+        toml::Value::Array(values) => {
+            JsonValue::Array(values.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(table) => JsonValue::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+struct JsonReader;
+impl Reader for JsonReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let value: JsonValue = serde_json::from_reader(BufReader::new(File::open(file_path)?))?;
+        let mut items = vec![];
+        flatten_value("", &value, &mut items);
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["json"]
+    }
+}
+
+struct YamlReader;
+impl Reader for YamlReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let content = fs::read_to_string(file_path)?;
+        let mut items = vec![];
+        // 一个文件中可以有多个以 --- 分隔的文档
+        for document in serde_yaml::Deserializer::from_str(&content) {
+            let value = serde_yaml::Value::deserialize(document)?;
+            flatten_value("", &yaml_to_json(value), &mut items);
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["yaml", "yml"]
+    }
+}
+
+struct TomlReader;
+impl Reader for TomlReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let table: toml::Table = toml::from_str(&fs::read_to_string(file_path)?)?;
+        let mut items = vec![];
+        flatten_value("", &toml_to_json(toml::Value::Table(table)), &mut items);
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["toml"]
+    }
+}
+
+/// 元素的属性各一条，返回元素的路径。命名空间声明不是内容，不索引
+fn push_xml_attributes(
+    parent: Option<&str>,
+    e: &BytesStart,
+    position: &ItemPosition,
+    items: &mut Vec<Item>,
+) -> Result<String> {
+    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+    let path = match parent {
+        Some(parent) => format!("{parent}/{name}"),
+        None => name,
+    };
+    for attr in e.attributes().flatten() {
+        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+        if key == "xmlns" || key.starts_with("xmlns:") {
+            continue;
+        }
+        let value = attr.unescape_value()?;
+        if !value.trim().is_empty() {
+            items.push(Item {
+                content: format!("{path}/@{key}: {}", value.trim()),
+                position: position.clone(),
+            });
+        }
+    }
+    Ok(path)
+}
+
+/// 任意 XML 文件，每个元素直接包含的文本和每个属性各一条，记录元素路径和起始行号，
+/// 如 `project/dependencies/dependency/artifactId: junit`、`project/dependencies/dependency/@scope: test`
+struct XmlReader;
+impl Reader for XmlReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let data = fs::read(file_path)?;
+        let mut xml_reader = quickXmlReader::from_reader(data.as_slice());
+        let mut items = vec![];
+        // 每层元素的路径、起始行号和直接包含的文本
+        let mut stack: Vec<(String, i64, String)> = vec![];
+        let mut buf = Vec::new();
+        let (mut line, mut counted) = (1, 0);
+        loop {
+            let start = xml_reader.buffer_position() as usize;
+            line += data[counted..start].iter().filter(|&&b| b == b'\n').count() as i64;
+            counted = start;
+            let position = ItemPosition {
+                line: Some(line),
+                ..Default::default()
+            };
+            match xml_reader.read_event_into(&mut buf)? {
+                quickXmlEvent::Start(e) => {
+                    let parent = stack.last().map(|(path, _, _)| path.as_str());
+                    let path = push_xml_attributes(parent, &e, &position, &mut items)?;
+                    stack.push((path, line, String::new()));
+                }
+                quickXmlEvent::Empty(e) => {
+                    let parent = stack.last().map(|(path, _, _)| path.as_str());
+                    push_xml_attributes(parent, &e, &position, &mut items)?;
+                }
+                quickXmlEvent::End(_) => {
+                    if let Some((path, start_line, text)) = stack.pop() {
+                        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                        if !text.is_empty() {
+                            items.push(Item {
+                                content: format!("{path}: {text}"),
+                                position: ItemPosition {
+                                    line: Some(start_line),
+                                    ..Default::default()
+                                },
+                            });
+                        }
+                    }
+                }
+                quickXmlEvent::Text(e) => {
+                    if let Some((_, _, text)) = stack.last_mut() {
+                        text.push_str(&e.decode()?);
+                    }
+                }
+                quickXmlEvent::CData(e) => {
+                    if let Some((_, _, text)) = stack.last_mut() {
+                        text.push_str(&String::from_utf8_lossy(&e));
+                    }
+                }
+                quickXmlEvent::GeneralRef(e) => {
+                    if let Some((_, _, text)) = stack.last_mut() {
+                        if let Some(c) = e.resolve_char_ref()? {
+                            text.push(c);
+                        } else if let Some(s) = resolve_predefined_entity(&e.decode()?) {
+                            text.push_str(s);
+                        }
+                    }
+                }
+                quickXmlEvent::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["xml"]
+    }
+}
+
+// 按语言分组的源代码扩展名，与默认扩展名白名单中“代码”分类一致，默认不启用
+const CODE_EXTENSIONS: &[(&str, &[&str])] = &[
+    ("Rust", &["rs"]),
+    ("Python", &["py"]),
+    ("JavaScript", &["js", "jsx", "mjs", "cjs"]),
+    ("TypeScript", &["ts", "tsx"]),
+    ("Java", &["java"]),
+    ("Kotlin", &["kt", "kts"]),
+    ("Go", &["go"]),
+    ("C/C++", &["c", "h", "cpp", "cc", "cxx", "hpp"]),
+    ("C#", &["cs"]),
+    ("Ruby", &["rb"]),
+    ("PHP", &["php"]),
+    ("Swift", &["swift"]),
+    ("Shell", &["sh", "bash", "ps1"]),
+    ("SQL", &["sql"]),
+    ("Lua", &["lua"]),
+    ("Vue", &["vue"]),
+    ("CSS", &["css", "scss", "less"]),
+];
+
+// 超过这个长度的行通常是压缩后的代码，没有搜索价值
+const CODE_MAX_LINE_CHARS: usize = 500;
+
+/// 源代码按行索引，跳过空行和压缩过的长行，行号与原文件一致
+struct CodeReader;
+impl Reader for CodeReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        // 代码中偶尔有非 utf8 的字符，不因此放弃整个文件
+        let content = fs::read(file_path)?;
+        let content = String::from_utf8_lossy(&content);
+        let items = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && line.chars().count() <= CODE_MAX_LINE_CHARS)
+            .map(|(i, line)| Item {
+                content: line.to_string(),
+                position: ItemPosition {
+                    line: Some(i as i64 + 1),
+                    ..Default::default()
+                },
+            })
+            .collect();
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        CODE_EXTENSIONS
+            .iter()
+            .flat_map(|(_, extensions)| extensions.iter().copied())
+            .collect()
+    }
+}
+
+/// 邮件头各自作为一条内容，如 `from: 张三 <zhang@example.com>`，正文按空行分段。
+/// 没有纯文本正文时使用去掉标签后的 HTML 正文
+fn mail_items(raw: &[u8], page: Option<i64>, items: &mut Vec<Item>) {
+    let message = parse_message(raw);
+    for name in INDEXED_HEADERS {
+        if let Some(value) = message.header(name).filter(|v| !v.is_empty()) {
+            items.push(Item {
+                content: format!("{name}: {value}"),
+                position: ItemPosition {
+                    page,
+                    ..Default::default()
+                },
+            });
+        }
+    }
+
+    let paragraphs: Vec<String> = if message.text.is_empty() {
+        message
+            .html
+            .iter()
+            .flat_map(|html| parse_html(html))
+            .map(|item| item.content)
+            .collect()
+    } else {
+        message
+            .text
+            .iter()
+            .flat_map(|text| text.split("\n\n").collect::<Vec<_>>())
+            .flat_map(|text| text.split("\r\n\r\n").collect::<Vec<_>>())
+            .map(|paragraph| {
+                let mut content = String::new();
+                for line in paragraph.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    // 折行的英文单词之间补上空格，中文直接相连
+                    if content.chars().last().is_some_and(|c| c.is_ascii()) {
+                        content.push(' ');
+                    }
+                    content.push_str(line);
+                }
+                content
+            })
+            .filter(|content| !content.is_empty())
+            .collect()
+    };
+    items.extend(paragraphs.into_iter().enumerate().map(|(i, content)| Item {
+        content,
+        position: ItemPosition {
+            page,
+            paragraph: Some(i as i64 + 1),
+            ..Default::default()
+        },
+    }));
+}
+
+struct EmailReader;
+impl Reader for EmailReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let raw = fs::read(file_path)?;
+        let mut items = vec![];
+        let is_mbox = file_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mbox"));
+        if is_mbox {
+            for (i, message) in split_mbox(&raw).iter().enumerate() {
+                mail_items(message, Some(i as i64 + 1), &mut items);
+            }
+        } else {
+            mail_items(&raw, None, &mut items);
+        }
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["eml", "mbox"]
+    }
+}
+
+// 压缩包内解压后超过这个大小的文件只列出名称，不读取内容
+const ARCHIVE_MAX_ENTRY_SIZE: u64 = 20 * 1024 * 1024;
+// 最多列出的文件数，避免超大的压缩包产生过多内容
+const ARCHIVE_MAX_ENTRIES: usize = 10000;
+
+enum ArchiveKind {
+    Zip,
+    SevenZip,
+    Tar,
+    TarGz,
+}
+
+/// 依次处理压缩包内的每个文件，跳过目录。回调返回 false 时停止
+fn for_each_archive_entry(
+    file_path: &Path,
+    kind: ArchiveKind,
+    each: &mut dyn FnMut(&str, u64, &mut dyn Read) -> Result<bool>,
+) -> Result<()> {
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = ZipArchive::new(File::open(file_path)?)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                if entry.is_dir() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                let size = entry.size();
+                if !each(&name, size, &mut entry)? {
+                    break;
+                }
+            }
+        }
+        ArchiveKind::SevenZip => {
+            let mut archive =
+                sevenz_rust2::ArchiveReader::open(file_path, sevenz_rust2::Password::empty())?;
+            let mut error = None;
+            archive.for_each_entries(|entry, reader| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                match each(entry.name(), entry.size(), reader) {
+                    Ok(true) => {
+                        // 同一个数据块中的文件依次解压，没读完的部分要跳过
+                        io::copy(reader, &mut io::sink())?;
+                        Ok(true)
+                    }
+                    Ok(false) => Ok(false),
+                    Err(e) => {
+                        error = Some(e);
+                        Ok(false)
+                    }
+                }
+            })?;
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let file = BufReader::new(File::open(file_path)?);
+            let reader: Box<dyn Read> = match kind {
+                ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().into_owned();
+                let size = entry.size();
+                if !each(&name, size, &mut entry)? {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 列出压缩包内的文件名，并用对应的读取器读取白名单中启用的文件。
+/// 内容都带上 `backup.zip!/report.docx` 形式的虚拟路径
+struct ArchiveReader {
+    readers: HashMap<String, Arc<dyn Reader>>,
+}
+
+impl ArchiveReader {
+    fn archive_kind(file_path: &Path) -> Result<ArchiveKind> {
+        let name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".zip") {
+            Ok(ArchiveKind::Zip)
+        } else if name.ends_with(".7z") {
+            Ok(ArchiveKind::SevenZip)
+        } else if name.ends_with(".tar") {
+            Ok(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(ArchiveKind::TarGz)
+        } else {
+            Err(anyhow::anyhow!("Unsupported archive: {file_path:?}"))
+        }
+    }
+
+    /// 解压到临时文件后用对应的读取器读取
+    fn read_entry(
+        &self,
+        reader: &dyn Reader,
+        ext: &str,
+        content: &mut dyn Read,
+        temp_dir: &TempDir,
+    ) -> Result<Vec<Item>> {
+        let temp_file = temp_dir.path().join(format!("entry.{ext}"));
+        let mut data = Vec::new();
+        // 文件头中的大小不可信，实际读取时也限制大小
+        content
+            .take(ARCHIVE_MAX_ENTRY_SIZE + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > ARCHIVE_MAX_ENTRY_SIZE {
+            return Ok(Vec::new());
+        }
+        fs::write(&temp_file, data)?;
+        let items = reader.read(&temp_file);
+        fs::remove_file(&temp_file)?;
+        items
+    }
+}
+
+impl Reader for ArchiveReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let kind = Self::archive_kind(file_path)?;
+        let archive_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let enabled = enabled_extensions()?;
+        let temp_dir = TempDir::new()?;
+        let mut items = Vec::new();
+        let mut count = 0;
+        for_each_archive_entry(file_path, kind, &mut |name, size, content| {
+            count += 1;
+            if count > ARCHIVE_MAX_ENTRIES {
+                debug!("压缩包内文件过多，只列出前 {ARCHIVE_MAX_ENTRIES} 个: {file_path:?}");
+                return Ok(false);
+            }
+            let entry = Some(format!("{archive_name}!/{name}"));
+            items.push(Item {
+                content: name.to_string(),
+                position: ItemPosition {
+                    entry: entry.clone(),
+                    ..Default::default()
+                },
+            });
+
+            let ext = Path::new(name)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let Some(reader) = self.readers.get(&ext) else {
+                return Ok(true);
+            };
+            if !enabled.contains(&ext) || size > ARCHIVE_MAX_ENTRY_SIZE {
+                return Ok(true);
+            }
+            match self.read_entry(reader.as_ref(), &ext, content, &temp_dir) {
+                Ok(entry_items) => {
+                    items.extend(entry_items.into_iter().map(|mut item| {
+                        item.position.entry = entry.clone();
+                        item
+                    }));
+                }
+                Err(e) => debug!("读取压缩包内文件失败: {file_path:?} {name}: {e}"),
+            }
+            Ok(true)
+        })?;
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["zip", "7z", "tar", "tgz", "gz"]
+    }
+}
+
+/// 加载好语言数据的 Tesseract 实例，同一线程识别多张图片时复用
+struct OcrEngine {
+    tessdata: String,
+    languages: String,
+    tess: Tesseract,
+}
+
+thread_local! {
+    // 初始化 Tesseract 需要加载语言数据，比识别一张小图片还慢
+    static OCR_ENGINE: RefCell<Option<OcrEngine>> = const { RefCell::new(None) };
+    // 当前线程能否识别图片，普通索引线程读取时不识别，需要 OCR 的文件转给 OCR 线程
+    static OCR_ALLOWED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// 普通索引线程读取的文件中有需要识别的图片，转给 OCR 线程重新读取
+#[derive(Debug, Error)]
+#[error("文件中有需要 OCR 的图片，转给 OCR 线程处理")]
+pub struct OcrRequired;
+
+/// 设置当前线程读取文件时能否识别图片
+pub fn set_ocr_allowed(allowed: bool) {
+    OCR_ALLOWED.with(|ocr_allowed| ocr_allowed.set(allowed));
+}
+
+/// 识别图片中的文字
+fn ocr_text(image_data: &[u8]) -> Result<String> {
+    if !OCR_ALLOWED.with(Cell::get) {
+        return Err(OcrRequired.into());
+    }
+    check_ocr()?;
+    let tessdata = get_tessdata_dir()
+        .to_str()
+        .context("tessdata 路径包含无效字符")?
+        .to_string();
+    let languages = get_tesseract_languages()?;
+    // 语言设置变了时重新初始化。识别出错时实例已经被消耗，下次重新初始化
+    let engine = OCR_ENGINE
+        .with(|engine| engine.borrow_mut().take())
+        .filter(|engine| engine.tessdata == tessdata && engine.languages == languages);
+    let tess = match engine {
+        Some(engine) => engine.tess,
+        None => {
+            debug!("初始化 Tesseract: {languages}");
+            Tesseract::new(Some(&tessdata), Some(&languages))?
+        }
+    };
+    let mut tess = tess.set_image_from_mem(image_data)?;
+    let text = tess.get_text()?;
+    OCR_ENGINE.with(|engine| {
+        *engine.borrow_mut() = Some(OcrEngine {
+            tessdata,
+            languages,
+            tess,
+        })
+    });
+    Ok(text)
+}
+
+fn remove_whitespace_for_chinese_chars(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.trim().chars().peekable();
+
+    while let Some(current_char) = chars.next() {
+        result.push(current_char);
+
+        if is_chinese(current_char) {
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+    result
+}
+
+fn is_chinese(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fa5}').contains(&c)
+}
+
+/// 视频中内嵌的文本字幕，每条字幕一项并记录开始时间。
+/// 没有内嵌字幕时读取同名的 SRT 字幕文件
+struct VideoReader;
+impl Reader for VideoReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        let is_mp4 = file_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"));
+        let embedded = if is_mp4 {
+            mp4_subtitles(file_path)
+        } else {
+            mkv_subtitles(file_path)
+        };
+        let mut cues = embedded.unwrap_or_else(|e| {
+            debug!("读取内嵌字幕失败: {file_path:?}: {e}");
+            Vec::new()
+        });
+        if cues.is_empty() {
+            for subtitle in sidecar_subtitles(file_path)? {
+                cues.extend(parse_srt(&fs::read(subtitle)?));
+            }
+        }
+        Ok(cues
+            .into_iter()
+            .map(|cue| Item {
+                content: cue.text,
+                position: ItemPosition {
+                    time_ms: Some(cue.start_ms),
+                    ..Default::default()
+                },
+            })
+            .collect())
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        vec!["mkv", "mp4"]
+    }
+}
+
+/// 只能通过 OCR 读取的图片格式，由单独的 OCR 线程处理
+pub const OCR_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "gif", "webp"];
+
+struct OcrReader;
+impl Reader for OcrReader {
+    fn read(&self, file_path: &Path) -> Result<Vec<Item>> {
+        // 使用内存读取避免中文路径问题
+        let image_data = std::fs::read(file_path)?;
+
+        let text = ocr_text(&image_data)?;
+
+        let mut items = vec![];
+        push_properties(&mut items, image_properties(&image_data));
+        items.extend(
+            text.split("\n")
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| Item {
+                    content: remove_whitespace_for_chinese_chars(line),
+                    position: ItemPosition {
+                        line: Some(i as i64 + 1),
+                        ..Default::default()
+                    },
+                }),
+        );
+        Ok(items)
+    }
+
+    fn supports(&self) -> Vec<&str> {
+        OCR_EXTENSIONS.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::charset::set_root_encoding;
+    use crate::config::{ExternalReader, MaxFileSize};
+    use crate::test::test_mod::TestEnv;
+
+    const TEST_DATA_DIR: &str = "../test_data/reader";
+
+    #[test]
+    fn test_composite_reader() {
+        let _env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.txt"))
+            .unwrap();
+        assert_eq!(items.len(), 4);
+    }
+
+    #[test]
+    fn test_composite_skip_reason() {
+        let env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let path = env.temp_dir.path().join("big.txt");
+        fs::write(&path, vec![b'a'; 1024 * 1024 + 1]).unwrap();
+        assert_eq!(reader.skip_reason(&path).unwrap(), None);
+
+        Config::set_max_file_size(&MaxFileSize {
+            default_mb: 100,
+            extensions: HashMap::from([("txt".to_string(), 1)]),
+        })
+        .unwrap();
+        assert_eq!(
+            reader.skip_reason(&path).unwrap().as_deref(),
+            Some("文件大小 2 MB 超过上限 1 MB")
+        );
+
+        // 0 表示不限制
+        Config::set_max_file_size(&MaxFileSize {
+            default_mb: 0,
+            extensions: HashMap::new(),
+        })
+        .unwrap();
+        assert_eq!(reader.skip_reason(&path).unwrap(), None);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_composite_external_reader() {
+        let env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let path = env.temp_dir.path().join("notes.xyz");
+        fs::write(&path, "第一行\n\n第三行\n").unwrap();
+        assert!(!reader.supports(&path).unwrap());
+
+        Config::set_external_readers(&HashMap::from([(
+            "xyz".to_string(),
+            ExternalReader {
+                command: vec!["cat".to_string()],
+                timeout_secs: 5,
+            },
+        )]))
+        .unwrap();
+        assert!(reader.supports(&path).unwrap());
+        let items = reader.read(&path).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].content, "第三行");
+        assert_eq!(items[1].position.line, Some(3));
+    }
+
+    #[test]
+    fn test_composite_unknown_extension() {
+        let _env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let result = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.xyz"))
+            .unwrap();
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    fn test_txt_reader() {
+        let _env = TestEnv::new();
+        let reader = TxtReader;
+        assert_eq!(reader.supports(), vec!["txt"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.txt"))
+            .unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[1].content, "This is a test.");
+        assert_eq!(items[1].position.line, Some(2));
+    }
+
+    #[test]
+    fn test_txt_reader_encoding() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("gbk.txt");
+        let text = "第一行是中文内容，用于检测编码。\r\n第二行\r\n";
+        fs::write(&path, encoding_rs::GBK.encode(text).0).unwrap();
+        assert_eq!(
+            contents(&TxtReader.read(&path).unwrap()),
+            vec!["第一行是中文内容，用于检测编码。", "第二行"]
+        );
+
+        // 索引目录指定的编码优先于自动检测
+        fs::write(&path, encoding_rs::BIG5.encode("中文").0).unwrap();
+        set_root_encoding(env.temp_dir.path().to_str().unwrap(), Some("big5")).unwrap();
+        assert_eq!(contents(&TxtReader.read(&path).unwrap()), vec!["中文"]);
     }
 
     #[test]
@@ -477,7 +2724,102 @@ mod tests {
             .read(&Path::new(TEST_DATA_DIR).join("office/test.docx"))
             .unwrap();
         // println!("Items: {:?}", items);
-        assert_eq!(items.len(), 10);
+        assert_eq!(items.len(), 11);
+        assert_eq!(items[0].content, "author: dongchao");
+        // 空段落也计入段落序号
+        assert_eq!(items[5].content, "再来一行");
+        assert_eq!(
+            items[5].position,
+            ItemPosition {
+                paragraph: Some(6),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_office_properties() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("properties.docx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, content) in [
+            (
+                "docProps/core.xml",
+                r#"<cp:coreProperties xmlns:cp="cp" xmlns:dc="dc"><dc:title>季度报告</dc:title><dc:subject></dc:subject><dc:creator>张三</dc:creator><cp:keywords>财务 &amp; 预算</cp:keywords><cp:lastModifiedBy>李四</cp:lastModifiedBy></cp:coreProperties>"#,
+            ),
+            (
+                "docProps/app.xml",
+                "<Properties><Company>DuckIndex</Company><Manager></Manager></Properties>",
+            ),
+        ] {
+            zip.start_file(name, options).unwrap();
+            io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut items = vec![];
+        push_properties(&mut items, office_properties(&mut archive).unwrap());
+        assert_eq!(
+            contents(&items),
+            vec![
+                "title: 季度报告",
+                "author: 张三",
+                "keywords: 财务 & 预算",
+                "company: DuckIndex",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zip_part_name() {
+        assert_eq!(
+            zip_part_name("ppt/slides", "../notesSlides/notesSlide1.xml"),
+            "ppt/notesSlides/notesSlide1.xml"
+        );
+        assert_eq!(
+            zip_part_name("xl", "worksheets/sheet1.xml"),
+            "xl/worksheets/sheet1.xml"
+        );
+        assert_eq!(
+            zip_part_name("xl", "/xl/worksheets/sheet2.xml"),
+            "xl/worksheets/sheet2.xml"
+        );
+    }
+
+    #[test]
+    fn test_docx_reader_other_parts() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("parts.docx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let paragraphs = |texts: &[&str]| {
+            texts
+                .iter()
+                .map(|t| format!("<w:p><w:r><w:t>{t}</w:t></w:r></w:p>"))
+                .collect::<String>()
+        };
+        for (name, texts) in [
+            ("word/document.xml", vec!["正文"]),
+            ("word/header1.xml", vec!["公司机密"]),
+            ("word/header2.xml", vec!["公司机密"]),
+            ("word/footer1.xml", vec!["第 1 页"]),
+            ("word/footnotes.xml", vec!["", "脚注内容"]),
+            ("word/comments.xml", vec!["请核对数字"]),
+        ] {
+            zip.start_file(name, options).unwrap();
+            io::Write::write_all(&mut zip, paragraphs(&texts).as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let items = DocxReader.read(&path).unwrap();
+        assert_eq!(
+            contents(&items),
+            vec!["正文", "公司机密", "第 1 页", "脚注内容", "请核对数字"]
+        );
+        assert_eq!(items[0].position.paragraph, Some(1));
+        assert_eq!(items[1].position, ItemPosition::default());
     }
 
     #[test]
@@ -489,6 +2831,116 @@ mod tests {
             .unwrap();
         // println!("Items: {:?}", items);
         assert_eq!(items.len(), 5);
+        assert_eq!(items[2].content, "内容标题");
+        assert_eq!(
+            items[2].position,
+            ItemPosition {
+                page: Some(2),
+                paragraph: Some(1),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_pptx_reader_notes() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("notes.pptx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, content) in [
+            (
+                "ppt/slides/slide1.xml",
+                "<p:sld><p:sp><a:p><a:r><a:t>封面</a:t></a:r></a:p></p:sp></p:sld>",
+            ),
+            (
+                "ppt/slides/slide2.xml",
+                "<p:sld><p:sp><a:p><a:r><a:t>季度总结</a:t></a:r></a:p></p:sp></p:sld>",
+            ),
+            (
+                "ppt/slides/_rels/slide2.xml.rels",
+                r#"<Relationships><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide" Target="../notesSlides/notesSlide1.xml"/></Relationships>"#,
+            ),
+            (
+                "ppt/notesSlides/notesSlide1.xml",
+                r#"<p:notes>
+                <p:sp><p:nvSpPr><p:nvPr><p:ph type="sldImg"/></p:nvPr></p:nvSpPr></p:sp>
+                <p:sp><p:nvSpPr><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>先讲收入</a:t></a:r></a:p><a:p/><a:p><a:r><a:t>再讲成本</a:t></a:r></a:p></p:txBody></p:sp>
+                <p:sp><p:nvSpPr><p:nvPr><p:ph type="sldNum" idx="5"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:fld type="slidenum"><a:t>2</a:t></a:fld></a:p></p:txBody></p:sp>
+                </p:notes>"#,
+            ),
+        ] {
+            zip.start_file(name, options).unwrap();
+            io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let items = PptxReader.read(&path).unwrap();
+        assert_eq!(
+            contents(&items),
+            vec!["封面", "季度总结", "notes: 先讲收入", "notes: 再讲成本"]
+        );
+        assert_eq!(
+            items[3].position,
+            ItemPosition {
+                page: Some(2),
+                paragraph: Some(3),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_ocr_image_parts() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("images.pptx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let image = vec![0u8; MIN_OCR_IMAGE_SIZE as usize];
+        for (name, content) in [
+            (
+                "ppt/slides/_rels/slide1.xml.rels",
+                r#"<Relationships>
+                <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="../media/image1.png"/>
+                <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="../media/icon.png"/>
+                <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="../media/image2.emf"/>
+                <Relationship Id="rId4" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com/a.png" TargetMode="External"/>
+                <Relationship Id="rId5" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide" Target="../notesSlides/notesSlide1.xml"/>
+                </Relationships>"#.as_bytes(),
+            ),
+            ("ppt/media/image1.png", image.as_slice()),
+            ("ppt/media/icon.png", b"tiny".as_slice()),
+            ("ppt/media/image2.emf", image.as_slice()),
+        ] {
+            zip.start_file(name, options).unwrap();
+            io::Write::write_all(&mut zip, content).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(
+            pptx_notes_slide(&mut archive, "ppt/slides/slide1.xml").unwrap(),
+            Some("ppt/notesSlides/notesSlide1.xml".to_string())
+        );
+        let mut seen = HashSet::new();
+        assert_eq!(
+            ocr_image_parts(&mut archive, "ppt/slides/slide1.xml", &mut seen).unwrap(),
+            vec!["ppt/media/image1.png"]
+        );
+        // 已经识别过的图片不再识别
+        assert!(
+            ocr_image_parts(&mut archive, "ppt/slides/slide1.xml", &mut seen)
+                .unwrap()
+                .is_empty()
+        );
+
+        // 图片格式没有启用时不识别
+        Config::set_extension_enabled("png", false).unwrap();
+        assert!(
+            ocr_image_parts(&mut archive, "ppt/slides/slide1.xml", &mut HashSet::new())
+                .unwrap()
+                .is_empty()
+        );
     }
 
     #[test]
@@ -499,7 +2951,253 @@ mod tests {
             .read(&Path::new(TEST_DATA_DIR).join("test.pdf"))
             .unwrap();
         // println!("Items: {:?}", items);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].content, "author: dongchao");
+        assert_eq!(items[1].position.page, Some(1));
+    }
+
+    #[test]
+    fn test_pdf_reader_protected() {
+        let env = TestEnv::new();
+        let mut doc = pdfDocument::load(Path::new(TEST_DATA_DIR).join("test.pdf")).unwrap();
+        let state = lopdf::EncryptionState::try_from(lopdf::EncryptionVersion::V2 {
+            document: &doc,
+            owner_password: "owner",
+            user_password: "secret",
+            key_length: 128,
+            permissions: lopdf::Permissions::all(),
+        })
+        .unwrap();
+        doc.encrypt(&state).unwrap();
+        let path = env.temp_dir.path().join("protected.pdf");
+        doc.save(&path).unwrap();
+
+        let err = PdfReader.read(&path).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtectedError>(),
+            Some(&ProtectedError::NoPassword)
+        );
+
+        // 密码保存在系统钥匙串中，这里直接用密码解密
+        let mut doc = pdfDocument::load(&path).unwrap();
+        let err = decrypt_pdf(&mut doc, Some("wrong".to_string())).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtectedError>(),
+            Some(&ProtectedError::WrongPassword)
+        );
+        decrypt_pdf(&mut doc, Some("secret".to_string())).unwrap();
+        assert_eq!(
+            pdf_properties(&doc),
+            vec![("author", "dongchao".to_string())]
+        );
+        assert!(!doc.extract_text(&[1]).unwrap().trim().is_empty());
+    }
+
+    #[test]
+    fn test_office_reader_protected() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("protected.docx");
+        let mut data = OLE_MAGIC.to_vec();
+        data.extend([0u8; 504]);
+        fs::write(&path, data).unwrap();
+
+        let err = DocxReader.read(&path).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtectedError>(),
+            Some(&ProtectedError::NoPassword)
+        );
+    }
+
+    #[test]
+    fn test_pdf_properties() {
+        use lopdf::{dictionary, Object, Stream};
+
+        let env = TestEnv::new();
+        let mut doc = pdfDocument::with_version("1.5");
+        let pages_id = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Kids" => Vec::<Object>::new(),
+            "Count" => 0,
+        });
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:pdf="http://ns.adobe.com/pdf/1.3/">
+<dc:title><rdf:Alt><rdf:li xml:lang="x-default">XMP 标题</rdf:li></rdf:Alt></dc:title>
+<dc:description><rdf:Alt><rdf:li xml:lang="x-default">财务 &amp; 预算</rdf:li><rdf:li xml:lang="en">Finance</rdf:li></rdf:Alt></dc:description>
+<dc:subject><rdf:Bag><rdf:li>季度</rdf:li><rdf:li>报告</rdf:li></rdf:Bag></dc:subject>
+<pdf:Keywords>ignored</pdf:Keywords>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let metadata_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            xmp.as_bytes().to_vec(),
+        ));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Metadata" => metadata_id,
+        });
+        let info_id = doc.add_object(dictionary! {
+            "Title" => lopdf::text_string("季度报告"),
+            "Author" => lopdf::text_string("Alice"),
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+        let file = env.temp_dir.path().join("scan0001.pdf");
+        doc.save(&file).unwrap();
+
+        let items = PdfReader.read(&file).unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "title: 季度报告",
+                "author: Alice",
+                "subject: 财务 & 预算",
+                "keywords: 季度, 报告",
+            ]
+        );
+        assert_eq!(items[0].position, ItemPosition::default());
+    }
+
+    /// 只有一页的 PDF，页面中有给定的图片
+    fn pdf_with_images(images: Vec<lopdf::Stream>) -> (pdfDocument, lopdf::ObjectId) {
+        use lopdf::{dictionary, Object};
+
+        let mut doc = pdfDocument::with_version("1.5");
+        let mut xobjects = lopdf::Dictionary::new();
+        for (i, image) in images.into_iter().enumerate() {
+            xobjects.set(format!("Im{i}"), doc.add_object(image));
+        }
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        (doc, page_id)
+    }
+
+    fn pdf_image(
+        width: i64,
+        height: i64,
+        bits: i64,
+        filters: &[&str],
+        content: Vec<u8>,
+    ) -> lopdf::Stream {
+        use lopdf::{dictionary, Object};
+
+        lopdf::Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => bits,
+                "Filter" => filters
+                    .iter()
+                    .map(|filter| Object::Name(filter.as_bytes().to_vec()))
+                    .collect::<Vec<_>>(),
+            },
+            content,
+        )
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut encoder, data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_pdf_page_scans() {
+        let (doc, page_id) = pdf_with_images(vec![
+            pdf_image(1, 1, 8, &["DCTDecode"], b"jpeg data".to_vec()),
+            // 多个编码时先解开 Flate 再交给 Tesseract
+            pdf_image(1, 1, 8, &["FlateDecode", "DCTDecode"], zlib(b"jpeg data")),
+            // 扫描仪常用的 Flate 编码的灰度和黑白像素
+            pdf_image(2, 2, 8, &["FlateDecode"], zlib(&[0, 64, 128, 255])),
+            pdf_image(2, 2, 1, &["FlateDecode"], zlib(&[0b0100_0000, 0b1000_0000])),
+            pdf_image(8, 1, 1, &["CCITTFaxDecode"], b"fax".to_vec()),
+            // 无法解码的图片跳过
+            pdf_image(1, 1, 1, &["JBIG2Decode"], b"jbig2".to_vec()),
+            pdf_image(1, 1, 8, &["FlateDecode"], b"not zlib".to_vec()),
+        ]);
+        let mut scans = pdf_page_scans(&doc, page_id);
+        scans.sort();
+        let mut expected = vec![
+            b"jpeg data".to_vec(),
+            b"jpeg data".to_vec(),
+            [b"P5\n2 2\n255\n".as_slice(), &[0, 64, 128, 255]].concat(),
+            [b"P4\n2 2\n".as_slice(), &[0b1011_1111, 0b0111_1111]].concat(),
+        ];
+        let tiff = scans
+            .iter()
+            .find(|scan| scan.starts_with(b"II*\0"))
+            .unwrap()
+            .clone();
+        assert!(tiff.ends_with(b"fax"));
+        expected.push(tiff);
+        expected.sort();
+        assert_eq!(scans, expected);
+    }
+
+    #[test]
+    fn test_pdf_flate_scan_ocr() {
+        let env = TestEnv::new();
+        // 把测试图片转换成 Flate 编码的灰度像素，和扫描仪生成的 PDF 一样
+        let bmp = fs::read(Path::new(TEST_DATA_DIR).join("pic/test.bmp")).unwrap();
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bmp[offset..offset + 4].try_into().unwrap());
+        let (offset, width, height) = (
+            u32_at(10) as usize,
+            u32_at(18) as usize,
+            u32_at(22) as usize,
+        );
+        let bytes_per_pixel = u16::from_le_bytes([bmp[28], bmp[29]]) as usize / 8;
+        let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+        let mut gray = Vec::with_capacity(width * height);
+        // BMP 的行从下往上保存，像素为 BGR
+        for row in (0..height).rev() {
+            for column in 0..width {
+                let pixel = offset + row * row_size + column * bytes_per_pixel;
+                let (b, g, r) = (
+                    bmp[pixel] as u32,
+                    bmp[pixel + 1] as u32,
+                    bmp[pixel + 2] as u32,
+                );
+                gray.push(((r * 299 + g * 587 + b * 114) / 1000) as u8);
+            }
+        }
+        let (mut doc, _) = pdf_with_images(vec![pdf_image(
+            width as i64,
+            height as i64,
+            8,
+            &["FlateDecode"],
+            zlib(&gray),
+        )]);
+        let pdf_path = env.temp_dir.path().join("scan.pdf");
+        doc.save(&pdf_path).unwrap();
+
+        let items = PdfReader.read(&pdf_path).unwrap();
         assert_eq!(items.len(), 1);
+        assert_eq!(items[0].position.page, Some(1));
+        assert!(!items[0].content.trim().is_empty());
+
+        // 普通索引线程不识别，转给 OCR 线程
+        set_ocr_allowed(false);
+        assert!(PdfReader.read(&pdf_path).unwrap_err().is::<OcrRequired>());
+        set_ocr_allowed(true);
     }
 
     #[test]
@@ -510,7 +3208,412 @@ mod tests {
         let xlsx_path = Path::new(TEST_DATA_DIR).join("office/test.xlsx");
         let items = reader.read(&xlsx_path).unwrap();
         // println!("XLSX Items: {:?}", items);
-        assert_eq!(items.len(), 7);
+        assert_eq!(
+            contents(&items),
+            vec![
+                "sheet: Sheet1",
+                "这是第一个sheet",
+                "第一行",
+                "第二行",
+                "sheet: Sheet2",
+                "这是第二个sheet",
+                "第三行",
+                "第四行",
+                "sheet: Sheet3",
+            ]
+        );
+        assert_eq!(
+            items[5].position,
+            ItemPosition {
+                page: Some(2),
+                sheet_row: Some(3),
+                cell: Some("Sheet2!A3".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_xlsx_reader_inline_strings() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("inline.xlsx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, content) in [
+            (
+                "xl/workbook.xml",
+                r#"<workbook><sheets><sheet name="销售 数据" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships><Relationship Id="rId1" Target="worksheets/sheet1.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet><sheetData>
+                <row r="2"><c r="A2" t="inlineStr"><is><t>订单号</t></is></c><c r="B2"><v>20240501</v></c></row>
+                <row><c t="inlineStr"><is><t>合计</t></is></c><c><f>SUM(B2)</f><v>20240501</v></c><c t="b"><v>1</v></c></row>
+                </sheetData></worksheet>"#,
+            ),
+        ] {
+            zip.start_file(name, options).unwrap();
+            io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        // 没有 sharedStrings.xml 也能读取，公式和布尔值不读取
+        let items = XlsxReader.read(&path).unwrap();
+        assert_eq!(
+            contents(&items),
+            vec!["sheet: 销售 数据", "订单号", "20240501", "合计"]
+        );
+        assert_eq!(items[2].position.cell.as_deref(), Some("'销售 数据'!B2"));
+        assert_eq!(items[3].position.cell.as_deref(), Some("'销售 数据'!A3"));
+        assert_eq!(items[3].position.sheet_row, Some(3));
+    }
+
+    #[test]
+    fn test_odt_reader() {
+        let reader = OdtReader;
+        assert_eq!(reader.supports(), vec!["odt"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("office/test.odt"))
+            .unwrap();
+        let contents: Vec<&str> = items.iter().map(|i| i.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "标题",
+                "这是个测试文档",
+                "Tom  & Jerry end",
+                "正文带格式的文字"
+            ]
+        );
+        // 空段落也计入段落序号
+        assert_eq!(items[2].position.paragraph, Some(4));
+    }
+
+    #[test]
+    fn test_ods_reader() {
+        let reader = OdsReader;
+        assert_eq!(reader.supports(), vec!["ods"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("office/test.ods"))
+            .unwrap();
+        let cells: Vec<(&str, Option<i64>, Option<i64>)> = items
+            .iter()
+            .map(|i| (i.content.as_str(), i.position.page, i.position.sheet_row))
+            .collect();
+        // 数字单元格不读取，重复的空行计入行号
+        assert_eq!(
+            cells,
+            vec![
+                ("第一行", Some(1), Some(1)),
+                ("第五行 第二段", Some(1), Some(5)),
+                ("第二个sheet", Some(2), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_odp_reader() {
+        let reader = OdpReader;
+        assert_eq!(reader.supports(), vec!["odp"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("office/test.odp"))
+            .unwrap();
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[4].content, "内容2");
+        assert_eq!(
+            items[4].position,
+            ItemPosition {
+                page: Some(2),
+                paragraph: Some(3),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_onenote_reader() {
+        let env = TestEnv::new();
+        let reader = OneNoteReader;
+        assert_eq!(reader.supports(), vec!["one"]);
+        // 不是 OneNote 分区文件时报错，由调用方只索引文件名
+        let path = env.temp_dir.path().join("broken.one");
+        fs::write(&path, "不是 OneNote 文件").unwrap();
+        assert!(reader.read(&path).is_err());
+    }
+
+    #[test]
+    fn test_html_reader() {
+        let _env = TestEnv::new();
+        let reader = HtmlReader;
+        assert_eq!(reader.supports(), vec!["html", "htm", "xhtml"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.html"))
+            .unwrap();
+        let contents: Vec<&str> = items.iter().map(|i| i.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "测试 & 网页",
+                "标题",
+                "这是加粗的文字， 换行后继续。",
+                "链接 中文",
+                "第一项",
+                "第二项 第二行",
+            ]
+        );
+        assert_eq!(items[0].position, ItemPosition::default());
+        assert_eq!(items[1].position.paragraph, Some(1));
+    }
+
+    fn contents(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_json_reader() {
+        let reader = JsonReader;
+        assert_eq!(reader.supports(), vec!["json"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.json"))
+            .unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "description: 测试配置",
+                "hosts[0]: a.example.com",
+                "hosts[1]: b.example.com",
+                "proxy: null",
+                "server.host: localhost",
+                "server.port: 8080",
+                "server.tls: false",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yaml_reader() {
+        let reader = YamlReader;
+        assert_eq!(reader.supports(), vec!["yaml", "yml"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.yaml"))
+            .unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "404: 未找到",
+                "hosts[0]: a.example.com",
+                "hosts[1]: b.example.com",
+                "server.host: localhost",
+                "server.port: 8080",
+                "description: 测试配置",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toml_reader() {
+        let reader = TomlReader;
+        assert_eq!(reader.supports(), vec!["toml"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.toml"))
+            .unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "description: 测试配置",
+                "released: 2024-05-01",
+                "server.host: localhost",
+                "server.port: 8080",
+                "servers[0].name: alpha",
+                "servers[1].name: beta",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xml_reader() {
+        let reader = XmlReader;
+        assert_eq!(reader.supports(), vec!["xml"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.xml"))
+            .unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "project/groupId: com.example",
+                "project/name: 测试 & 示例",
+                "project/dependencies/dependency/@scope: test",
+                "project/dependencies/dependency/artifactId: junit",
+                "project/description: <不是标签>",
+            ]
+        );
+        assert_eq!(items[0].position.line, Some(3));
+        assert_eq!(items[3].position.line, Some(7));
+    }
+
+    #[test]
+    fn test_email_reader() {
+        let reader = EmailReader;
+        assert_eq!(reader.supports(), vec!["eml", "mbox"]);
+        let items = reader
+            .read(&Path::new(TEST_DATA_DIR).join("test.eml"))
+            .unwrap();
+        assert_eq!(
+            contents(&items),
+            vec![
+                "subject: 季度报告",
+                "from: 张三 <zhang@example.com>",
+                "to: alice@example.com",
+                "date: Mon, 1 Jan 2024 09:00:00 +0800",
+                "Hello Alice, the quarterly report is ready.",
+                "附件请查收",
+            ]
+        );
+        assert_eq!(items[0].position, ItemPosition::default());
+        assert_eq!(items[5].position.paragraph, Some(2));
+
+        let env = TestEnv::new();
+        let file = env.temp_dir.path().join("inbox.mbox");
+        fs::write(
+            &file,
+            "From a Mon Jan  1 00:00:00 2024\nSubject: one\n\nfirst\n\nFrom b Tue Jan  2 00:00:00 2024\nSubject: two\nContent-Type: text/html\n\n<p>second</p>\n",
+        )
+        .unwrap();
+        let items = reader.read(&file).unwrap();
+        let messages: Vec<(&str, Option<i64>)> = items
+            .iter()
+            .map(|i| (i.content.as_str(), i.position.page))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("subject: one", Some(1)),
+                ("first", Some(1)),
+                ("subject: two", Some(2)),
+                ("second", Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archive_reader() {
+        let env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let src = env.temp_dir.path().join("src");
+        fs::create_dir_all(src.join("docs")).unwrap();
+        fs::write(src.join("docs/readme.txt"), "你好\nworld").unwrap();
+        fs::write(src.join("data.bin"), [0u8, 1, 2]).unwrap();
+
+        let zip_path = env.temp_dir.path().join("backup.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("docs/", options).unwrap();
+        zip.start_file("docs/readme.txt", options).unwrap();
+        io::Write::write_all(&mut zip, "你好\nworld".as_bytes()).unwrap();
+        zip.start_file("data.bin", options).unwrap();
+        io::Write::write_all(&mut zip, &[0u8, 1, 2]).unwrap();
+        zip.finish().unwrap();
+
+        let tgz_path = env.temp_dir.path().join("backup.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&tgz_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_dir_all(".", &src).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let seven_zip_path = env.temp_dir.path().join("backup.7z");
+        sevenz_rust2::compress_to_path(&src, &seven_zip_path).unwrap();
+
+        for path in [zip_path, tgz_path, seven_zip_path] {
+            let archive_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let mut items: Vec<(String, Option<i64>, String)> = reader
+                .read(&path)
+                .unwrap()
+                .into_iter()
+                .map(|i| (i.content, i.position.line, i.position.entry.unwrap()))
+                .collect();
+            items.sort();
+            let entry = |name: &str| format!("{archive_name}!/{name}");
+            let readme = "docs/readme.txt".to_string();
+            let data = "data.bin".to_string();
+            let mut expected = vec![
+                (data.clone(), None, entry(&data)),
+                (readme.clone(), None, entry(&readme)),
+                ("world".to_string(), Some(2), entry(&readme)),
+                ("你好".to_string(), Some(1), entry(&readme)),
+            ];
+            expected.sort();
+            assert_eq!(items, expected, "{archive_name}");
+        }
+
+        // 只支持 tar.gz，单独压缩的 gz 文件不读取
+        let gz_path = env.temp_dir.path().join("single.gz");
+        fs::write(&gz_path, [0u8]).unwrap();
+        assert!(reader.read(&gz_path).is_err());
+    }
+
+    #[test]
+    fn test_video_reader() {
+        let env = TestEnv::new();
+        let reader = VideoReader;
+        assert_eq!(reader.supports(), vec!["mkv", "mp4"]);
+        // 没有内嵌字幕时读取同名的 SRT 字幕
+        let video = env.temp_dir.path().join("lecture.mkv");
+        fs::write(&video, "not a video").unwrap();
+        fs::write(
+            env.temp_dir.path().join("lecture.srt"),
+            "1\n00:01:02,500 --> 00:01:04,000\n今天讲排序算法\n",
+        )
+        .unwrap();
+        let items = reader.read(&video).unwrap();
+        assert_eq!(contents(&items), vec!["今天讲排序算法"]);
+        assert_eq!(items[0].position.time_ms, Some(62_500));
+    }
+
+    #[test]
+    fn test_code_reader() {
+        let env = TestEnv::new();
+        let reader = CodeReader;
+        assert!(reader.supports().contains(&"rs"));
+        let file = env.temp_dir.path().join("main.rs");
+        let minified = format!("var a={};", "1+".repeat(CODE_MAX_LINE_CHARS));
+        fs::write(
+            &file,
+            format!("fn main() {{\n\n    println!(\"你好\");\n{minified}\n}}\n"),
+        )
+        .unwrap();
+        let items = reader.read(&file).unwrap();
+        let lines: Vec<(&str, Option<i64>)> = items
+            .iter()
+            .map(|i| (i.content.as_str(), i.position.line))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("fn main() {", Some(1)),
+                ("println!(\"你好\");", Some(3)),
+                ("}", Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_whitelist_covers_readers() {
+        let _env = TestEnv::new();
+        let reader = CompositeReader::new().unwrap();
+        let whitelist = serde_json::to_string(&Config::get_extension_whitelist().unwrap()).unwrap();
+        for ext in reader.reader_map.keys() {
+            assert!(
+                whitelist.contains(&format!("\"label\":\"{ext}\"")),
+                "{ext} is not in the default whitelist"
+            );
+        }
     }
 
     #[test]
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::journal;
+use crate::monitor::is_watch_paused;
+use crate::volume;
+use crate::worker::Worker;
+
+// 比较修改时间时多检查的一段时间，网络文件系统的时间精度和时钟误差都在这个范围内
+const RECONCILE_MARGIN: Duration = Duration::from_secs(60);
+// 检查多少个目录更新一次状态
+const STATUS_INTERVAL: usize = 100;
+
+/// 一个索引目录启动时的检查情况
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReconcileStatus {
+    pub root: String,
+    /// 没有记录过检查时间，提交了完整扫描
+    pub full: bool,
+    /// 从系统变化日志（USN、FSEvents）读取了变化，没有检查目录
+    pub journal: bool,
+    pub running: bool,
+    pub dirs: usize,
+    /// 提交的任务数，包括有变化的目录
+    pub changes: usize,
+    pub elapsed_ms: u64,
+}
+
+static STATUS: Mutex<BTreeMap<String, ReconcileStatus>> = Mutex::new(BTreeMap::new());
+// 上次推进检查时间时读取的变化日志位置，下次推进时保存
+static PENDING_CURSORS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+fn update_status(status: &ReconcileStatus) {
+    STATUS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(status.root.clone(), status.clone());
+}
+
+pub fn get_status() -> Vec<ReconcileStatus> {
+    STATUS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+        .cloned()
+        .collect()
+}
+
+fn to_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn current_cursor(root: &str) -> Option<String> {
+    journal::current_cursor(Path::new(root)).unwrap_or_else(|e| {
+        warn!("读取系统变化日志位置失败: {root}, {e:?}");
+        None
+    })
+}
+
+/// 日志中的路径加上所在的目录，检查目录时会提交其中删除的文件
+fn with_parents(root: &Path, paths: Vec<PathBuf>) -> BTreeSet<PathBuf> {
+    let mut result = BTreeSet::new();
+    for path in paths {
+        if let Some(parent) = path.parent().filter(|p| p.starts_with(root)) {
+            result.insert(parent.to_path_buf());
+        }
+        result.insert(path);
+    }
+    result
+}
+
+/// 检查程序没有运行期间索引目录的变化。优先从系统变化日志读取变化的路径，
+/// 读取不到时只检查有变化的目录和上次检查之后修改的文件，没有检查记录时提交完整扫描。
+/// 离线的目录重新接入后再扫描
+pub fn reconcile_root(worker: &Worker, root: &str) -> Result<ReconcileStatus> {
+    let path = Path::new(root);
+    let started = SystemTime::now();
+    let cursor = current_cursor(root);
+    let timer = Instant::now();
+    let mut status = ReconcileStatus {
+        root: root.to_string(),
+        full: false,
+        journal: false,
+        running: false,
+        dirs: 0,
+        changes: 0,
+        elapsed_ms: 0,
+    };
+    if volume::is_offline(path)? {
+        info!("索引目录离线，重新接入后再检查: {root}");
+        return Ok(status);
+    }
+    let reconciled_at = Config::get_reconciled_at()?.get(root).copied();
+    let changes = match Config::get_journal_cursors()?.get(root) {
+        Some(since) if reconciled_at.is_some() => journal::changes_since(path, since)
+            .unwrap_or_else(|e| {
+                warn!("读取系统变化日志失败，改为检查目录: {root}, {e:?}");
+                None
+            }),
+        _ => None,
+    };
+    match (reconciled_at, changes) {
+        (Some(_), Some(changes)) => {
+            info!("从系统变化日志读取到 {} 个变化: {root}", changes.len());
+            status.journal = true;
+            for path in with_parents(path, changes) {
+                status.changes += worker.reconcile_path(&path)?;
+            }
+        }
+        (Some(secs), None) => {
+            let since = UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64);
+            let since = since.checked_sub(RECONCILE_MARGIN).unwrap_or(UNIX_EPOCH);
+            info!("开始快速检查目录: {root}, 上次检查: {secs}");
+            status.running = true;
+            update_status(&status);
+            let mut pending: Vec<PathBuf> = vec![path.to_path_buf()];
+            while let Some(dir) = pending.pop() {
+                let (sub_dirs, submitted) = worker.reconcile_directory(&dir, since)?;
+                pending.extend(sub_dirs);
+                status.dirs += 1;
+                status.changes += submitted;
+                if status.dirs.is_multiple_of(STATUS_INTERVAL) {
+                    status.elapsed_ms = timer.elapsed().as_millis() as u64;
+                    update_status(&status);
+                }
+            }
+        }
+        (None, _) => {
+            info!("没有检查记录，完整扫描目录: {root}");
+            status.full = true;
+            worker.submit_index_all_files(path)?;
+        }
+    }
+    // 提交的任务保存在数据库中，中途退出下次启动时继续处理
+    Config::set_reconciled_at(root, Some(to_secs(started)))?;
+    Config::set_journal_cursor(root, cursor)?;
+    status.running = false;
+    status.elapsed_ms = timer.elapsed().as_millis() as u64;
+    info!(
+        "目录检查完成: {root}, 检查 {} 个目录, {} 处变化, 用时 {} 毫秒",
+        status.dirs, status.changes, status.elapsed_ms
+    );
+    update_status(&status);
+    Ok(status)
+}
+
+/// 监听正常时定期推进检查时间，time 之前的变化都已经提交了任务。
+/// 变化日志的位置保存上次推进时读取的，同样是已经提交过任务的。
+/// 暂停监听、离线和正在检查的目录不推进
+pub fn advance(time: SystemTime) -> Result<()> {
+    let mut pending = PENDING_CURSORS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let previous = pending.take().unwrap_or_default();
+    let mut cursors = HashMap::new();
+    let running: Vec<String> = get_status()
+        .into_iter()
+        .filter(|s| s.running)
+        .map(|s| s.root)
+        .collect();
+    for root in Config::get_index_dir_paths()? {
+        if is_watch_paused(&root)
+            || running.contains(&root)
+            || volume::is_offline(Path::new(&root))?
+        {
+            debug!("不推进检查时间: {root}");
+            continue;
+        }
+        Config::set_reconciled_at(&root, Some(to_secs(time)))?;
+        if let Some(cursor) = previous.get(&root) {
+            Config::set_journal_cursor(&root, Some(cursor.clone()))?;
+        }
+        if let Some(cursor) = current_cursor(&root) {
+            cursors.insert(root, cursor);
+        }
+    }
+    *pending = Some(cursors);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    fn process_all(worker: &Worker) {
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reconcile_root() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone()]).unwrap();
+        let worker = Worker::new().unwrap();
+
+        // 第一次没有检查记录，完整扫描
+        let status = reconcile_root(&worker, &root_str).unwrap();
+        assert!(status.full);
+        assert!(!status.running);
+        assert!(Config::get_reconciled_at().unwrap().contains_key(&root_str));
+        process_all(&worker);
+
+        // 没有变化
+        let status = reconcile_root(&worker, &root_str).unwrap();
+        assert!(!status.full);
+        assert_eq!(status.dirs, 2);
+        assert_eq!(status.changes, 0);
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 0);
+
+        // 新增的文件
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+        let status = reconcile_root(&worker, &root_str).unwrap();
+        assert_eq!(status.changes, 2);
+        assert_eq!(get_status(), vec![status]);
+
+        // 变化日志中的路径，删除的文件由所在的目录提交删除任务
+        process_all(&worker);
+        fs::remove_file(root.join("sub/a.txt")).unwrap();
+        fs::write(root.join("sub/b.txt"), "bb").unwrap();
+        let mut changes = 0;
+        for path in with_parents(&root, vec![root.join("sub/a.txt"), root.join("sub/b.txt")]) {
+            changes += worker.reconcile_path(&path).unwrap();
+        }
+        // 目录、删除的 a.txt 和修改的 b.txt
+        assert_eq!(changes, 3);
+
+        // 暂停监听的目录不推进检查时间
+        Config::set_reconciled_at(&root_str, Some(0)).unwrap();
+        crate::monitor::pause_watching(Some(&root_str)).unwrap();
+        advance(SystemTime::now()).unwrap();
+        assert_eq!(Config::get_reconciled_at().unwrap()[&root_str], 0);
+    }
+}
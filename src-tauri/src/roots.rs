@@ -0,0 +1,145 @@
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::sqlite::get_conn;
+use crate::worker::Worker;
+
+/// 一个索引目录下已索引的文件数和还没处理完的任务数
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RootFileCounts {
+    pub root: String,
+    pub indexed: usize,
+    pub pending: usize,
+    pub failed: usize,
+}
+
+/// 路径所在的索引目录，嵌套时取最近的一个
+pub fn nearest_root<'a>(roots: &'a [String], path: &Path) -> Option<&'a String> {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| Path::new(root).components().count())
+}
+
+/// 只索引第一层的索引目录下，子目录和其中的文件不索引
+pub fn is_too_deep(path: &Path) -> Result<bool> {
+    let roots = Config::get_index_dir_paths()?;
+    let Some(root) = nearest_root(&roots, path) else {
+        return Ok(false);
+    };
+    if !Config::get_shallow_roots()?.contains(root) || path == Path::new(root) {
+        return Ok(false);
+    }
+    Ok(path.parent() != Some(Path::new(root)) || path.is_dir())
+}
+
+/// 路径所在索引目录的 id，不在任何索引目录下时返回 None
+pub fn find_root_id(path: &Path) -> Result<Option<i64>> {
+    let roots = Config::get_index_dir_paths()?;
+    let Some(root) = nearest_root(&roots, path) else {
+        return Ok(None);
+    };
+    let conn = get_conn()?;
+    let id = conn
+        .prepare_cached(
+            "INSERT INTO roots (path) VALUES (?1) ON CONFLICT(path) DO UPDATE SET path = path RETURNING id",
+        )?
+        .query_row(params![root], |row| row.get(0))?;
+    Ok(Some(id))
+}
+
+/// 各索引目录的文件数和任务数，按设置中的顺序
+pub fn get_root_file_counts() -> Result<Vec<RootFileCounts>> {
+    let conn = get_conn()?;
+    let mut result = Vec::new();
+    for root in Config::get_index_dir_paths()? {
+        let root_id: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM roots WHERE path = ?1",
+                params![root],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let (indexed, pending, failed) = match root_id {
+            Some(root_id) => {
+                let indexed = conn.query_row(
+                    "SELECT COUNT(*) FROM files WHERE root_id = ?1",
+                    params![root_id],
+                    |row| row.get(0),
+                )?;
+                let (pending, failed) = Worker::count_root_tasks(root_id)?;
+                (indexed, pending, failed)
+            }
+            None => (0, 0, 0),
+        };
+        result.push(RootFileCounts {
+            root,
+            indexed,
+            pending,
+            failed,
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+    use std::fs;
+
+    #[test]
+    fn test_root_file_counts() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        let nested = root.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(nested.join("b.txt"), "b").unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        let nested_str = nested.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone(), nested_str.clone()]).unwrap();
+
+        assert_eq!(
+            find_root_id(&nested.join("b.txt")).unwrap(),
+            find_root_id(&nested).unwrap()
+        );
+        assert_ne!(
+            find_root_id(&root.join("a.txt")).unwrap(),
+            find_root_id(&nested).unwrap()
+        );
+        assert_eq!(find_root_id(env.temp_dir.path()).unwrap(), None);
+
+        let worker = Worker::new().unwrap();
+        worker.submit_index_all_files(&root).unwrap();
+        // 提交时只有一个目录任务，子目录由索引线程扫描时提交
+        let counts = get_root_file_counts().unwrap();
+        assert_eq!(
+            counts,
+            vec![
+                RootFileCounts {
+                    root: root_str.clone(),
+                    indexed: 0,
+                    pending: 1,
+                    failed: 0,
+                },
+                RootFileCounts {
+                    root: nested_str.clone(),
+                    indexed: 0,
+                    pending: 0,
+                    failed: 0,
+                },
+            ]
+        );
+
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+        let counts = get_root_file_counts().unwrap();
+        assert_eq!((counts[0].indexed, counts[0].pending), (1, 0));
+        assert_eq!((counts[1].indexed, counts[1].pending), (1, 0));
+    }
+}
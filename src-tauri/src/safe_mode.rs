@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::dirs::get_index_dir;
+use crate::indexer::Indexer;
+use crate::worker::Worker;
+
+// 启动索引阶段连续崩溃达到该次数后进入安全模式
+const SAFE_MODE_CRASH_THRESHOLD: u32 = 3;
+
+/// 保存在数据库之外，数据库本身损坏导致的崩溃也能记录下来
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct SessionState {
+    running: bool,
+    consecutive_crashes: u32,
+    suspected_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SafeModeStatus {
+    pub enabled: bool,
+    pub consecutive_crashes: u32,
+    pub suspected_paths: Vec<String>,
+}
+
+static SAFE_MODE: OnceCell<Mutex<bool>> = OnceCell::new();
+
+fn get_safe_mode() -> &'static Mutex<bool> {
+    SAFE_MODE.get_or_init(|| Mutex::new(false))
+}
+
+fn session_file() -> PathBuf {
+    get_index_dir().join("session.json")
+}
+
+fn load_state() -> SessionState {
+    let path = session_file();
+    if !path.exists() {
+        return SessionState::default();
+    }
+    match fs::read(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| Ok(serde_json::from_slice(&data)?))
+    {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("读取会话状态失败: {}, 错误: {e}", path.display());
+            SessionState::default()
+        }
+    }
+}
+
+fn save_state(state: &SessionState) -> Result<()> {
+    fs::write(session_file(), serde_json::to_vec(state)?)?;
+    Ok(())
+}
+
+/// 启动时调用，必须在重置运行中任务之前：上次没有正常退出时，
+/// 当时正在处理的任务就是崩溃的嫌疑路径。返回是否进入安全模式
+pub fn begin_session() -> Result<bool> {
+    let mut state = load_state();
+    if state.running {
+        state.consecutive_crashes += 1;
+        let running = Worker::get_running_task_paths()?;
+        if !running.is_empty() {
+            state.suspected_paths = running;
+        }
+        warn!(
+            "上次没有正常退出，连续崩溃次数: {}, 嫌疑路径: {:?}",
+            state.consecutive_crashes, state.suspected_paths
+        );
+    }
+    state.running = true;
+    save_state(&state)?;
+
+    let enabled = state.consecutive_crashes >= SAFE_MODE_CRASH_THRESHOLD;
+    if enabled {
+        warn!("进入安全模式，只提供搜索功能");
+    }
+    *get_safe_mode()
+        .lock()
+        .map_err(|e| anyhow!("Failed to acquire safe mode lock: {}", e))? = enabled;
+    Ok(enabled)
+}
+
+/// 启动阶段的索引平稳运行一段时间后调用，之后的崩溃不再计入启动崩溃
+pub fn mark_startup_finished() -> Result<()> {
+    if is_enabled()? {
+        return Ok(());
+    }
+    let mut state = load_state();
+    if state.consecutive_crashes > 0 {
+        info!("启动阶段运行正常，清除崩溃记录");
+        state.consecutive_crashes = 0;
+        state.suspected_paths.clear();
+        save_state(&state)?;
+    }
+    Ok(())
+}
+
+/// 正常退出时调用
+pub fn end_session() -> Result<()> {
+    let mut state = load_state();
+    state.running = false;
+    save_state(&state)
+}
+
+pub fn is_enabled() -> Result<bool> {
+    Ok(*get_safe_mode()
+        .lock()
+        .map_err(|e| anyhow!("Failed to acquire safe mode lock: {}", e))?)
+}
+
+pub fn get_status() -> Result<SafeModeStatus> {
+    let state = load_state();
+    Ok(SafeModeStatus {
+        enabled: is_enabled()?,
+        consecutive_crashes: state.consecutive_crashes,
+        suspected_paths: state.suspected_paths,
+    })
+}
+
+/// 退出安全模式。skip_suspected 为 true 时嫌疑文件只索引文件名，不再读取内容
+pub fn exit(skip_suspected: bool) -> Result<()> {
+    let mut state = load_state();
+    if skip_suspected {
+        let indexer = Indexer::new()?;
+        for path in &state.suspected_paths {
+            let path = Path::new(path);
+            info!("跳过嫌疑路径: {}", path.display());
+            Worker::remove_pending_tasks(path)?;
+            if path.is_file() {
+                indexer.delete_file(path)?;
+                indexer.write_file_items(path, Vec::new())?;
+            }
+        }
+    }
+    state.consecutive_crashes = 0;
+    state.suspected_paths.clear();
+    save_state(&state)?;
+    *get_safe_mode()
+        .lock()
+        .map_err(|e| anyhow!("Failed to acquire safe mode lock: {}", e))? = false;
+    info!("退出安全模式");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_safe_mode_after_crashes() {
+        let env = TestEnv::new();
+        // TestEnv 已经开始了一次会话，模拟之后每次都没有正常退出
+        for _ in 1..SAFE_MODE_CRASH_THRESHOLD {
+            assert!(!begin_session().unwrap());
+        }
+        assert!(begin_session().unwrap());
+        assert_eq!(
+            get_status().unwrap().consecutive_crashes,
+            SAFE_MODE_CRASH_THRESHOLD
+        );
+
+        let file = env.temp_dir.path().join("crash.txt");
+        fs::write(&file, "boom").unwrap();
+        let mut state = load_state();
+        state.suspected_paths = vec![file.to_str().unwrap().to_string()];
+        save_state(&state).unwrap();
+
+        exit(true).unwrap();
+        assert_eq!(
+            get_status().unwrap(),
+            SafeModeStatus {
+                enabled: false,
+                consecutive_crashes: 0,
+                suspected_paths: Vec::new(),
+            }
+        );
+        assert!(Indexer::new().unwrap().get_file(&file).is_ok());
+
+        end_session().unwrap();
+        assert!(!begin_session().unwrap());
+        assert_eq!(get_status().unwrap().consecutive_crashes, 0);
+    }
+}
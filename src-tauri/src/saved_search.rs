@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use rusqlite::{params, Params};
+use serde::{Deserialize, Serialize};
+
+use crate::indexer::{MatchOptions, SearchFilter, SearchSort};
+use crate::sqlite::get_conn;
+
+/// 命名保存的搜索条件，可以随时重新执行
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    pub query: String,
+    #[serde(flatten)]
+    pub options: MatchOptions,
+    pub sort: SearchSort,
+    pub filter: SearchFilter,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+fn check_name_and_query(name: &str, query: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(anyhow!("Saved search name is empty"));
+    }
+    if query.trim().is_empty() {
+        return Err(anyhow!("Saved search query is empty"));
+    }
+    Ok(())
+}
+
+fn query_saved_searches<P: Params>(condition: &str, params: P) -> Result<Vec<SavedSearch>> {
+    let conn = get_conn()?;
+    let mut stmt = conn.prepare(&format!(
+        r"SELECT id, name, query, fuzzy, case_sensitive, sort, filter, created_at, updated_at
+        FROM saved_searches {condition}"
+    ))?;
+    let rows = stmt.query_map(params, |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, bool>(3)?,
+            row.get::<_, bool>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+            row.get::<_, String>(7)?,
+            row.get::<_, String>(8)?,
+        ))
+    })?;
+    let mut searches = Vec::new();
+    for row in rows {
+        let (id, name, query, fuzzy, case_sensitive, sort, filter, created_at, updated_at) = row?;
+        searches.push(SavedSearch {
+            id,
+            name,
+            query,
+            options: MatchOptions {
+                fuzzy,
+                case_sensitive,
+            },
+            sort: serde_json::from_str(&sort)?,
+            filter: serde_json::from_str(&filter)?,
+            created_at,
+            updated_at,
+        });
+    }
+    Ok(searches)
+}
+
+/// 名称不能重复
+pub fn create(
+    name: &str,
+    query: &str,
+    options: MatchOptions,
+    sort: SearchSort,
+    filter: &SearchFilter,
+) -> Result<SavedSearch> {
+    check_name_and_query(name, query)?;
+    let now = Local::now().to_rfc3339();
+    let id: i64 = get_conn()?.query_row(
+        r"INSERT INTO saved_searches (name, query, fuzzy, case_sensitive, sort, filter, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7) RETURNING id",
+        params![
+            name.trim(),
+            query.trim(),
+            options.fuzzy,
+            options.case_sensitive,
+            serde_json::to_string(&sort)?,
+            serde_json::to_string(filter)?,
+            now
+        ],
+        |row| row.get(0),
+    )?;
+    get(id)
+}
+
+/// 按名称排序
+pub fn list() -> Result<Vec<SavedSearch>> {
+    query_saved_searches("ORDER BY name, id", [])
+}
+
+pub fn get(id: i64) -> Result<SavedSearch> {
+    query_saved_searches("WHERE id = ?1", params![id])?
+        .into_iter()
+        .next()
+        .with_context(|| format!("Saved search {id} not found"))
+}
+
+pub fn update(
+    id: i64,
+    name: &str,
+    query: &str,
+    options: MatchOptions,
+    sort: SearchSort,
+    filter: &SearchFilter,
+) -> Result<SavedSearch> {
+    check_name_and_query(name, query)?;
+    let updated = get_conn()?.execute(
+        r"UPDATE saved_searches SET name = ?2, query = ?3, fuzzy = ?4, case_sensitive = ?5, sort = ?6,
+        filter = ?7, updated_at = ?8
+        WHERE id = ?1",
+        params![
+            id,
+            name.trim(),
+            query.trim(),
+            options.fuzzy,
+            options.case_sensitive,
+            serde_json::to_string(&sort)?,
+            serde_json::to_string(filter)?,
+            Local::now().to_rfc3339()
+        ],
+    )?;
+    if updated == 0 {
+        return Err(anyhow!("Saved search {id} not found"));
+    }
+    get(id)
+}
+
+pub fn delete(id: i64) -> Result<()> {
+    get_conn()?.execute("DELETE FROM saved_searches WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::SortBy;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_saved_searches() {
+        let _env = TestEnv::new();
+        let filter = SearchFilter {
+            extensions: vec!["pdf".into()],
+            min_size: Some(1024),
+            ..Default::default()
+        };
+        let saved = create(
+            " 报告 ",
+            "季度 AND 报告",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &filter,
+        )
+        .unwrap();
+        assert_eq!(saved.name, "报告");
+        assert_eq!(saved.filter, filter);
+        assert!(create(
+            "报告",
+            "other",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &filter
+        )
+        .is_err());
+        assert!(create(
+            "empty",
+            " ",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &filter
+        )
+        .is_err());
+
+        create(
+            "Budget",
+            "budget",
+            MatchOptions::default(),
+            SearchSort::default(),
+            &SearchFilter::default(),
+        )
+        .unwrap();
+        let names: Vec<String> = list().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["Budget", "报告"]);
+
+        let sort = SearchSort {
+            by: SortBy::Modified,
+            order: None,
+        };
+        let options = MatchOptions {
+            fuzzy: true,
+            case_sensitive: true,
+        };
+        let updated = update(
+            saved.id,
+            "周报",
+            "周报",
+            options,
+            sort,
+            &SearchFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(updated.name, "周报");
+        assert_eq!(updated.options, options);
+        assert_eq!(updated.sort, sort);
+        assert_eq!(updated.created_at, saved.created_at);
+        assert_eq!(get(saved.id).unwrap(), updated);
+
+        delete(saved.id).unwrap();
+        assert!(get(saved.id).is_err());
+        assert!(update(
+            saved.id,
+            "x",
+            "x",
+            MatchOptions::default(),
+            sort,
+            &SearchFilter::default()
+        )
+        .is_err());
+        assert_eq!(list().unwrap().len(), 1);
+    }
+}
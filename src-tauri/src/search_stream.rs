@@ -0,0 +1,240 @@
+use anyhow::Result;
+use log::{error, info};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use crate::events::emit;
+use crate::indexer::{Indexer, MatchOptions, SearchFilter, SearchHit, SearchPage, SearchSort};
+
+pub const SEARCH_BATCH_EVENT: &str = "search-batch";
+pub const SEARCH_DONE_EVENT: &str = "search-done";
+// 每批查询的条数，越小第一批结果越快返回
+const STREAM_BATCH_SIZE: usize = 20;
+
+// 只有最新的一次搜索有效，开始新的搜索或者取消时递增，旧的搜索在下一批之前停止
+static LATEST_SEARCH_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Directory,
+    File,
+    Item,
+}
+
+/// 一批结果，同一批只包含一种类型
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchBatch {
+    pub search_id: u64,
+    pub kind: SearchKind,
+    pub hits: Vec<SearchHit>,
+    /// 该类型满足条件的总数
+    pub total: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchDone {
+    pub search_id: u64,
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+fn next_search_id() -> u64 {
+    LATEST_SEARCH_ID.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+fn is_cancelled(search_id: u64) -> bool {
+    LATEST_SEARCH_ID.load(Ordering::SeqCst) != search_id
+}
+
+/// 取消正在进行的搜索
+pub fn cancel() {
+    LATEST_SEARCH_ID.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 分批取一种类型的结果，被取消时返回 false
+fn stream_kind<T, F, W>(
+    search_id: u64,
+    kind: SearchKind,
+    limit: usize,
+    fetch: F,
+    wrap: W,
+    on_batch: &mut impl FnMut(SearchBatch),
+) -> Result<bool>
+where
+    F: Fn(usize, usize) -> Result<SearchPage<T>>,
+    W: Fn(T) -> SearchHit,
+{
+    let mut offset = 0;
+    loop {
+        if is_cancelled(search_id) {
+            return Ok(false);
+        }
+        let page = fetch(offset, STREAM_BATCH_SIZE.min(limit - offset))?;
+        offset += page.items.len();
+        let has_more = page.has_more;
+        on_batch(SearchBatch {
+            search_id,
+            kind,
+            hits: page.items.into_iter().map(&wrap).collect(),
+            total: page.total,
+        });
+        if !has_more || offset >= limit {
+            return Ok(true);
+        }
+    }
+}
+
+/// 依次搜索目录、文件和内容，每类最多 limit 条，每取到一批就回调一次。
+/// 被新的搜索或 cancel 取消时返回 false
+pub fn run_search(
+    search_id: u64,
+    query: &str,
+    limit: usize,
+    options: MatchOptions,
+    sort: SearchSort,
+    filter: &SearchFilter,
+    mut on_batch: impl FnMut(SearchBatch),
+) -> Result<bool> {
+    let indexer = Indexer::new()?;
+    Ok(stream_kind(
+        search_id,
+        SearchKind::Directory,
+        limit,
+        |offset, size| indexer.search_directory(query, offset, size, options, sort, filter),
+        SearchHit::Directory,
+        &mut on_batch,
+    )? && stream_kind(
+        search_id,
+        SearchKind::File,
+        limit,
+        |offset, size| indexer.search_file(query, offset, size, options, sort, filter),
+        SearchHit::File,
+        &mut on_batch,
+    )? && stream_kind(
+        search_id,
+        SearchKind::Item,
+        limit,
+        |offset, size| {
+            indexer.search_item(query, offset, size, options.case_sensitive, sort, filter)
+        },
+        |item| SearchHit::Item(Box::new(item)),
+        &mut on_batch,
+    )?)
+}
+
+/// 在后台线程开始搜索并立即返回搜索 id，之前的搜索会被取消。
+/// 结果通过 SEARCH_BATCH_EVENT 推送，结束时推送 SEARCH_DONE_EVENT
+pub fn start(
+    query: String,
+    limit: usize,
+    options: MatchOptions,
+    sort: SearchSort,
+    filter: SearchFilter,
+) -> Result<u64> {
+    let search_id = next_search_id();
+    thread::Builder::new()
+        .name(format!("search-{search_id}"))
+        .spawn(move || {
+            let result = run_search(search_id, &query, limit, options, sort, &filter, |batch| {
+                emit(SEARCH_BATCH_EVENT, batch)
+            });
+            let done = match result {
+                Ok(completed) => {
+                    if !completed {
+                        info!("搜索已取消: {search_id}");
+                    }
+                    SearchDone {
+                        search_id,
+                        cancelled: !completed,
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    error!("搜索失败: {search_id}, 错误: {e}");
+                    SearchDone {
+                        search_id,
+                        cancelled: false,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            emit(SEARCH_DONE_EVENT, done);
+        })?;
+    Ok(search_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+    use std::fs;
+
+    #[test]
+    fn test_run_search() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let file = env.temp_dir.path().join("stream.txt");
+        fs::write(&file, "").unwrap();
+        let items = (0..50)
+            .map(|i| Item {
+                content: format!("stream {i}"),
+                ..Default::default()
+            })
+            .collect();
+        indexer.write_file_items(&file, items).unwrap();
+
+        let search_id = next_search_id();
+        let mut batches = Vec::new();
+        let completed = run_search(
+            search_id,
+            "stream",
+            45,
+            MatchOptions::default(),
+            SearchSort::default(),
+            &SearchFilter::default(),
+            |batch| batches.push(batch),
+        )
+        .unwrap();
+        assert!(completed);
+        let summary: Vec<(SearchKind, usize, usize)> = batches
+            .iter()
+            .map(|b| (b.kind, b.hits.len(), b.total))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (SearchKind::Directory, 0, 0),
+                (SearchKind::File, 1, 1),
+                (SearchKind::Item, 20, 50),
+                (SearchKind::Item, 20, 50),
+                (SearchKind::Item, 5, 50),
+            ]
+        );
+
+        // 开始新的搜索后旧的搜索停止
+        let old_id = next_search_id();
+        let new_id = next_search_id();
+        assert!(is_cancelled(old_id));
+        assert!(!is_cancelled(new_id));
+
+        let mut count = 0;
+        let completed = run_search(
+            new_id,
+            "stream",
+            45,
+            MatchOptions::default(),
+            SearchSort::default(),
+            &SearchFilter::default(),
+            |_| {
+                count += 1;
+                cancel();
+            },
+        )
+        .unwrap();
+        assert!(!completed);
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use keyring::Entry;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::config::Config;
+
+// 系统钥匙串中保存凭据使用的服务名，账号为共享路径
+const KEYRING_SERVICE: &str = "DuckIndex";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkShare {
+    pub path: String,
+    pub username: Option<String>,
+}
+
+fn credential_entry(path: &str) -> Result<Entry> {
+    Ok(Entry::new(KEYRING_SERVICE, path)?)
+}
+
+fn get_password(path: &str) -> Result<Option<String>> {
+    match credential_entry(path)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 添加或更新共享目录，密码保存在系统钥匙串中而不是数据库里
+pub fn add_share(path: &str, username: Option<String>, password: Option<String>) -> Result<()> {
+    if let Some(password) = password {
+        credential_entry(path)?.set_password(&password)?;
+    }
+    let mut shares = Config::get_network_shares()?;
+    shares.retain(|s| s.path != path);
+    shares.push(NetworkShare {
+        path: path.to_string(),
+        username,
+    });
+    Config::set_network_shares(&shares)
+}
+
+pub fn remove_share(path: &str) -> Result<()> {
+    let mut shares = Config::get_network_shares()?;
+    if !shares.iter().any(|s| s.path == path) {
+        return Ok(());
+    }
+    match credential_entry(path)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => return Err(e.into()),
+    }
+    shares.retain(|s| s.path != path);
+    Config::set_network_shares(&shares)
+}
+
+pub fn get_share(path: &str) -> Result<Option<NetworkShare>> {
+    Ok(Config::get_network_shares()?
+        .into_iter()
+        .find(|s| s.path == path))
+}
+
+/// path 所在的共享目录
+pub fn find_share_root(path: &Path) -> Result<Option<NetworkShare>> {
+    Ok(Config::get_network_shares()?
+        .into_iter()
+        .find(|s| path.starts_with(&s.path)))
+}
+
+/// 共享目录离线时，其下的文件不能当作已删除处理
+pub fn is_offline(path: &Path) -> Result<bool> {
+    Ok(find_share_root(path)?.is_some_and(|share| !Path::new(&share.path).exists()))
+}
+
+/// path 是否在网络文件系统上：设置中的共享目录、UNC 路径，或者挂载的 SMB/NFS 等。
+/// 这些目录的文件变化通知不可靠，需要轮询
+pub fn is_network_path(path: &Path) -> Result<bool> {
+    if find_share_root(path)?.is_some() {
+        return Ok(true);
+    }
+    if path.to_str().is_some_and(|p| unc_root(p).is_some()) {
+        return Ok(true);
+    }
+    Ok(platform_is_remote(path))
+}
+
+/// 按需连接共享目录，返回目录当前是否可用
+pub fn connect(share: &NetworkShare) -> Result<bool> {
+    if Path::new(&share.path).exists() {
+        return Ok(true);
+    }
+    info!("连接共享目录: {}", share.path);
+    let password = get_password(&share.path)?;
+    if let Err(e) = platform_connect(share, password.as_deref()) {
+        warn!("共享目录不可用: {}, {e}", share.path);
+        return Ok(false);
+    }
+    Ok(Path::new(&share.path).exists())
+}
+
+/// `\\server\share\sub` 对应的连接名 `\\server\share`
+fn unc_root(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\")?;
+    let mut parts = rest.split(['\\', '/']).filter(|p| !p.is_empty());
+    let server = parts.next()?;
+    let share = parts.next()?;
+    Some(format!(r"\\{server}\{share}"))
+}
+
+#[cfg(windows)]
+fn platform_connect(share: &NetworkShare, password: Option<&str>) -> Result<()> {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::NetworkManagement::WNet::{
+        WNetAddConnection2W, CONNECT_TEMPORARY, NETRESOURCEW, RESOURCETYPE_DISK,
+    };
+
+    fn to_wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(Some(0)).collect()
+    }
+
+    let root = unc_root(&share.path).ok_or_else(|| anyhow!("Not a UNC path: {}", share.path))?;
+    let mut remote_name = to_wide(&root);
+    let resource = NETRESOURCEW {
+        dwScope: 0,
+        dwType: RESOURCETYPE_DISK,
+        dwDisplayType: 0,
+        dwUsage: 0,
+        lpLocalName: null_mut(),
+        lpRemoteName: remote_name.as_mut_ptr(),
+        lpComment: null_mut(),
+        lpProvider: null_mut(),
+    };
+    let password = password.map(to_wide);
+    let username = share.username.as_deref().map(to_wide);
+    // SAFETY: 所有字符串都以 0 结尾，并且在调用期间有效
+    let result = unsafe {
+        WNetAddConnection2W(
+            &resource,
+            password.as_ref().map_or(null(), |p| p.as_ptr()),
+            username.as_ref().map_or(null(), |u| u.as_ptr()),
+            CONNECT_TEMPORARY,
+        )
+    };
+    if result != 0 {
+        return Err(anyhow!("Failed to connect {}: error code {}", root, result));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn platform_connect(share: &NetworkShare, _password: Option<&str>) -> Result<()> {
+    // 非 Windows 系统由系统负责挂载 SMB/NFS
+    Err(anyhow!("Network share {} is not mounted", share.path))
+}
+
+#[cfg(windows)]
+fn platform_is_remote(path: &Path) -> bool {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Component;
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    // 映射的网络驱动器，如 `Z:\`
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    let root: Vec<u16> = prefix
+        .as_os_str()
+        .encode_wide()
+        .chain(OsStr::new("\\").encode_wide())
+        .chain(Some(0))
+        .collect();
+    // SAFETY: root 以 0 结尾，并且在调用期间有效
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+}
+
+#[cfg(target_os = "linux")]
+fn platform_is_remote(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    const NFS_SUPER_MAGIC: u32 = 0x6969;
+    const SMB_SUPER_MAGIC: u32 = 0x517b;
+    const CIFS_MAGIC_NUMBER: u32 = 0xff53_4d42;
+    const SMB2_MAGIC_NUMBER: u32 = 0xfe53_4d42;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: path 以 0 结尾，stat 由 statfs 填充
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    matches!(
+        stat.f_type as u32,
+        NFS_SUPER_MAGIC | SMB_SUPER_MAGIC | CIFS_MAGIC_NUMBER | SMB2_MAGIC_NUMBER
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn platform_is_remote(path: &Path) -> bool {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: path 以 0 结尾，stat 由 statfs 填充，f_fstypename 以 0 结尾
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    matches!(name.to_bytes(), b"smbfs" | b"nfs" | b"afpfs" | b"webdav")
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+fn platform_is_remote(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_unc_root() {
+        assert_eq!(
+            unc_root(r"\\nas\photos\2024\summer"),
+            Some(r"\\nas\photos".to_string())
+        );
+        assert_eq!(unc_root(r"\\nas\photos"), Some(r"\\nas\photos".to_string()));
+        assert_eq!(unc_root(r"\\nas"), None);
+        assert_eq!(unc_root(r"C:\photos"), None);
+    }
+
+    #[test]
+    fn test_add_remove_share() {
+        let env = TestEnv::new();
+        let offline = env.temp_dir.path().join("offline_share");
+        let offline = offline.to_str().unwrap();
+
+        add_share(offline, Some("alice".into()), None).unwrap();
+        assert_eq!(
+            get_share(offline).unwrap(),
+            Some(NetworkShare {
+                path: offline.into(),
+                username: Some("alice".into()),
+            })
+        );
+        assert!(is_offline(&Path::new(offline).join("a.txt")).unwrap());
+        assert!(!is_offline(env.temp_dir.path()).unwrap());
+        assert!(!connect(&get_share(offline).unwrap().unwrap()).unwrap());
+
+        remove_share(offline).unwrap();
+        assert_eq!(get_share(offline).unwrap(), None);
+        assert!(!is_offline(&Path::new(offline).join("a.txt")).unwrap());
+    }
+
+    #[test]
+    fn test_is_network_path() {
+        let env = TestEnv::new();
+        let share = env.temp_dir.path().join("share");
+        assert!(!is_network_path(&share).unwrap());
+        add_share(share.to_str().unwrap(), None, None).unwrap();
+        assert!(is_network_path(&share.join("a.txt")).unwrap());
+        assert!(is_network_path(Path::new(r"\\nas\photos\2024")).unwrap());
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use log::info;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::embedding::{blob_to_vector, vector_to_blob};
+use crate::indexer::SearchResultFile;
+use crate::reader::Item;
+use crate::sqlite::get_conn;
+use crate::tokenizer::cut_for_query;
+use crate::utils::{filename_to_str, parent_to_str};
+
+// 词哈希到固定维度，不需要维护词表
+const DOC_VECTOR_DIMS: usize = 1024;
+// 升级前已索引的文件没有向量，查找时每批补算的文件数
+const BACKFILL_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SimilarFile {
+    #[serde(flatten)]
+    pub file: SearchResultFile,
+    /// 余弦相似度，越大越相似
+    pub score: f64,
+}
+
+// 向量会持久化，不能使用每次运行结果都不同的 DefaultHasher
+fn fnv1a(word: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in word.as_bytes() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// 文件的词频向量，词频取对数。没有内容时返回 None。
+/// IDF 随索引变化，查找时再计算
+pub fn document_vector(items: &[Item]) -> Option<Vec<f32>> {
+    let mut counts: HashMap<usize, u32> = HashMap::new();
+    for item in items {
+        for word in cut_for_query(&item.content) {
+            *counts
+                .entry((fnv1a(&word) % DOC_VECTOR_DIMS as u64) as usize)
+                .or_default() += 1;
+        }
+    }
+    if counts.is_empty() {
+        return None;
+    }
+    let mut vector = vec![0f32; DOC_VECTOR_DIMS];
+    for (index, count) in counts {
+        vector[index] = 1.0 + (count as f32).ln();
+    }
+    Some(vector)
+}
+
+/// write_file_items 中与内容在同一个事务里写入
+pub fn write_document_vector(conn: &Connection, file_id: i64, items: &[Item]) -> Result<()> {
+    match document_vector(items) {
+        Some(vector) => conn.execute(
+            "INSERT OR REPLACE INTO file_vectors (file_id, vector) VALUES (?1, ?2)",
+            params![file_id, vector_to_blob(&vector)],
+        )?,
+        None => conn.execute(
+            "DELETE FROM file_vectors WHERE file_id = ?1",
+            params![file_id],
+        )?,
+    };
+    Ok(())
+}
+
+fn backfill_document_vectors() -> Result<()> {
+    let mut conn = get_conn()?;
+    loop {
+        let file_ids = {
+            let mut stmt = conn.prepare(
+                r"SELECT DISTINCT items.file_id FROM items
+                LEFT OUTER JOIN file_vectors ON items.file_id = file_vectors.file_id
+                WHERE file_vectors.file_id IS NULL
+                LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![BACKFILL_BATCH_SIZE as i64], |row| {
+                row.get::<_, i64>(0)
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if file_ids.is_empty() {
+            return Ok(());
+        }
+        info!("补算文件向量: {} 个文件", file_ids.len());
+        let tx = conn.transaction()?;
+        for file_id in file_ids {
+            let items = {
+                let mut stmt =
+                    tx.prepare("SELECT item_text(content) FROM items WHERE file_id = ?1")?;
+                let rows = stmt.query_map(params![file_id], |row| {
+                    Ok(Item {
+                        content: row.get(0)?,
+                        ..Default::default()
+                    })
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            write_document_vector(&tx, file_id, &items)?;
+        }
+        tx.commit()?;
+    }
+}
+
+/// 按 TF-IDF 余弦相似度查找与指定文件内容相似的其他文件，相同内容的文件只列出保存内容的一个
+pub fn find_similar(file: &Path, limit: usize) -> Result<Vec<SimilarFile>> {
+    backfill_document_vectors()?;
+    let conn = get_conn()?;
+    let (file_id, target): (i64, Vec<u8>) = conn
+        .query_row(
+            r"SELECT file_vectors.file_id, file_vectors.vector FROM files
+            JOIN directories ON files.directory_id = directories.id
+            JOIN file_vectors ON file_vectors.file_id = files.content_file_id
+            WHERE directories.path = ?1 AND files.name = ?2",
+            params![parent_to_str(file)?, filename_to_str(file)?],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .with_context(|| format!("File {} has no indexed content", file.display()))?;
+    let target = blob_to_vector(&target);
+
+    // 第一遍统计每一维出现在多少个文件中
+    let mut document_count = 0usize;
+    let mut document_frequency = vec![0usize; DOC_VECTOR_DIMS];
+    {
+        let mut stmt = conn.prepare("SELECT vector FROM file_vectors")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            document_count += 1;
+            for (i, v) in blob_to_vector(row.get_ref(0)?.as_blob()?)
+                .iter()
+                .enumerate()
+            {
+                if *v > 0.0 {
+                    document_frequency[i] += 1;
+                }
+            }
+        }
+    }
+    let idf: Vec<f32> = document_frequency
+        .iter()
+        .map(|df| (1.0 + document_count as f32 / (1.0 + *df as f32)).ln())
+        .collect();
+    let weight = |vector: &[f32]| -> Vec<f32> {
+        let mut weighted: Vec<f32> = vector.iter().zip(&idf).map(|(v, w)| v * w).collect();
+        let norm = weighted.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            weighted.iter_mut().for_each(|v| *v /= norm);
+        }
+        weighted
+    };
+    let target = weight(&target);
+
+    // 第二遍计算相似度
+    let mut scores = Vec::new();
+    {
+        let mut stmt =
+            conn.prepare("SELECT file_id, vector FROM file_vectors WHERE file_id != ?1")?;
+        let mut rows = stmt.query(params![file_id])?;
+        while let Some(row) = rows.next()? {
+            let vector = weight(&blob_to_vector(row.get_ref(1)?.as_blob()?));
+            let score: f32 = target.iter().zip(&vector).map(|(a, b)| a * b).sum();
+            if score > 0.0 {
+                scores.push((score, row.get::<_, i64>(0)?));
+            }
+        }
+    }
+    scores.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut stmt = conn.prepare(
+        r"SELECT files.name, directories.path, files.modified_time FROM files
+        JOIN directories ON files.directory_id = directories.id
+        WHERE files.id = ?1",
+    )?;
+    let mut result = Vec::new();
+    for (score, id) in scores.into_iter().take(limit) {
+        let file = stmt.query_row(params![id], |row| {
+            Ok(SearchResultFile {
+                name: row.get(0)?,
+                path: row.get(1)?,
+                modified_time: row.get(2)?,
+            })
+        })?;
+        result.push(SimilarFile {
+            file,
+            score: score as f64,
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::test::test_mod::TestEnv;
+    use std::fs;
+
+    fn write(indexer: &Indexer, file: &Path, contents: &[&str]) {
+        fs::write(file, "").unwrap();
+        let items = contents
+            .iter()
+            .map(|c| Item {
+                content: c.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        indexer.write_file_items(file, items).unwrap();
+    }
+
+    #[test]
+    fn test_find_similar() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path();
+        write(
+            &indexer,
+            &dir.join("q1.txt"),
+            &["第一季度销售报告", "华东地区销售额增长"],
+        );
+        write(
+            &indexer,
+            &dir.join("q2.txt"),
+            &["第二季度销售报告", "华东地区销售额下降"],
+        );
+        write(&indexer, &dir.join("trip.txt"), &["周末去爬山", "天气很好"]);
+        write(&indexer, &dir.join("empty.txt"), &[]);
+
+        let result = find_similar(&dir.join("q1.txt"), 10).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file.name, "q2.txt");
+        assert!(result[0].score > 0.5);
+
+        assert!(find_similar(&dir.join("empty.txt"), 10).is_err());
+
+        // 删除文件后向量一起删除
+        indexer.delete_file(&dir.join("q2.txt")).unwrap();
+        assert!(find_similar(&dir.join("q1.txt"), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backfill_document_vectors() {
+        let env = TestEnv::new();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path();
+        write(&indexer, &dir.join("a.txt"), &["销售报告"]);
+        write(&indexer, &dir.join("b.txt"), &["销售报告汇总"]);
+        // 模拟升级前没有向量的文件
+        get_conn()
+            .unwrap()
+            .execute("DELETE FROM file_vectors", [])
+            .unwrap();
+
+        let result = find_similar(&dir.join("a.txt"), 10).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file.name, "b.txt");
+    }
+}
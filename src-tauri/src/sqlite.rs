@@ -1,30 +1,74 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::{error, info};
 use once_cell::sync::OnceCell;
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
 
 use crate::dirs::get_index_dir;
+use crate::encryption::get_passphrase;
+use crate::indexer::register_sql_functions;
+
+// 索引目录中的数据库文件名
+pub const DB_FILE: &str = "index.db";
+
+// 索引线程、监听线程和前端的查询同时使用连接，默认的 10 个在大量扫描时会互相等待
+const POOL_MAX_SIZE: u32 = 16;
+// 每个连接缓存的预编译语句数，扫描时反复执行的查询不用每次重新编译
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+// 每个连接的页缓存，单位为 KiB，默认的 2 MiB 在百万级文件的索引上命中率很低
+const CACHE_SIZE_KIB: i64 = 16 * 1024;
+// 内存映射读取数据库文件的上限，各个连接共享操作系统的页缓存
+const MMAP_SIZE: i64 = 256 * 1024 * 1024;
+
+// 关闭连接池前等待借出的连接归还的最长时间
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
 
 // 全局静态变量
 static POOL: OnceCell<Arc<Mutex<Option<Pool<SqliteConnectionManager>>>>> = OnceCell::new();
+// VACUUM、备份、导出和修改密码都要读写整个数据库文件，同时进行时互相等待
+static WHOLE_DB_LOCK: Mutex<()> = Mutex::new(());
 
-pub fn init_pool() {
-    POOL.get_or_init(|| {
-        info!("初始化连接池...");
-        let sqlite_path = get_index_dir().join("index.db");
+/// 读写整个数据库文件的操作开始前获取，依次进行。要在获取连接之前调用，避免和连接池互相等待
+pub fn lock_whole_db() -> MutexGuard<'static, ()> {
+    WHOLE_DB_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn build_pool() -> Result<Pool<SqliteConnectionManager>> {
+    let sqlite_path = get_index_dir().join(DB_FILE);
+    let manager = SqliteConnectionManager::file(sqlite_path).with_init(|conn| {
+        // 加密的数据库必须先设置密码再执行其他语句
+        if let Some(passphrase) = get_passphrase() {
+            conn.pragma_update(None, "key", passphrase)?;
+        }
+        conn.execute_batch(r"PRAGMA busy_timeout = 2147483647;")?;
+        // WAL 模式下 NORMAL 不会损坏数据库，只是断电时可能丢失最后提交的事务，重新扫描即可补上
+        conn.execute_batch(&format!(
+            r"PRAGMA cache_size = -{CACHE_SIZE_KIB};
+            PRAGMA mmap_size = {MMAP_SIZE};
+            PRAGMA temp_store = MEMORY;
+            PRAGMA synchronous = NORMAL;"
+        ))?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
 
-        let manager = SqliteConnectionManager::file(sqlite_path).with_init(|conn| {
-            conn.execute_batch(r"PRAGMA busy_timeout = 2147483647;")?;
+        conn.busy_handler(Some(|_retries| true))?;
 
-            conn.busy_handler(Some(|_retries| true))?;
+        register_sql_functions(conn)?;
 
-            Ok(())
-        });
+        Ok(())
+    });
+    Ok(Pool::builder().max_size(POOL_MAX_SIZE).build(manager)?)
+}
+
+pub fn init_pool() {
+    POOL.get_or_init(|| {
+        info!("初始化连接池...");
         Arc::new(Mutex::new(Some(
-            Pool::new(manager).expect("Failed to create pool"),
+            build_pool().expect("Failed to create pool"),
         )))
     });
 }
@@ -32,7 +76,7 @@ pub fn init_pool() {
 pub fn get_conn() -> Result<PooledConnection<SqliteConnectionManager>> {
     Ok(POOL
         .get()
-        .expect("Pool not initialized")
+        .context("数据库还没有打开")?
         .lock()
         .map_err(|e| {
             error!("获取数据库连接失败: {e:?}");
@@ -43,10 +87,20 @@ pub fn get_conn() -> Result<PooledConnection<SqliteConnectionManager>> {
         .get()?)
 }
 
+/// 连接池是否已经打开，加密的索引输入密码前没有打开
+pub fn is_pool_ready() -> bool {
+    POOL.get()
+        .is_some_and(|pool| pool.lock().is_ok_and(|pool| pool.is_some()))
+}
+
 pub fn close_pool() {
+    if !is_pool_ready() {
+        return;
+    }
     info!("关闭连接池...");
     let conn = get_conn().expect("Failed to get connection");
-    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); vacuum;")
+    // VACUUM 在很大的索引上要几分钟，改在空闲时维护，退出时只合并 WAL
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
         .expect("Failed to execute batch");
 
     if let Some(pool_arc) = POOL.get() {
@@ -59,91 +113,303 @@ pub fn close_pool() {
     }
 }
 
-pub fn check_or_init_db() -> Result<()> {
-    if check_db_init().is_err() {
-        let conn = get_conn()?;
-        conn.execute_batch(
-            r#"PRAGMA journal_mode = WAL;
-            PRAGMA auto_vacuum = FULL;
-
-            -- config.rs
-            DROP TABLE IF EXISTS config;
-            CREATE TABLE config (
-                id INTEGER PRIMARY KEY,
-                key TEXT NOT NULL,
-                value TEXT NOT NULL,
-                unique (key)
-            );
-            INSERT INTO config (key, value) VALUES ('IndexDirPaths', '[]');
-            INSERT INTO config (key, value) VALUES ('ExtensionWhitelist', '[{"label":"文档","is_extension":false,"children":[{"label":"txt","is_extension":true,"enabled":true},{"label":"md","is_extension":true,"enabled":true},{"label":"markdown","is_extension":true,"enabled":true},{"label":"docx","is_extension":true,"enabled":true},{"label":"pptx","is_extension":true,"enabled":true},{"label":"pdf","is_extension":true,"enabled":true}]}, {"label":"数据","is_extension":false,"children":[{"label":"xlsx","is_extension":true,"enabled":false}]}, {"label":"图片","is_extension":false,"children":[{"label":"jpg","is_extension":true,"enabled":true},{"label":"jpeg","is_extension":true,"enabled":true},{"label":"png","is_extension":true,"enabled":true},{"label":"tif","is_extension":true,"enabled":true},{"label":"tiff","is_extension":true,"enabled":true},{"label":"gif","is_extension":true,"enabled":true},{"label":"webp","is_extension":true,"enabled":true}]}]');
-
-            -- indexer.rs
-            DROP TABLE IF EXISTS directories;
-            CREATE TABLE directories (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                path TEXT NOT NULL,
-                modified_time TEXT NOT NULL,
-                UNIQUE (path)
-            );
-            CREATE INDEX idx_directories_name ON directories (name);
-            DROP TABLE IF EXISTS files;
-            CREATE TABLE files (
-                id INTEGER PRIMARY KEY,
-                directory_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                modified_time TEXT NOT NULL,
-                UNIQUE (directory_id, name)
-            );
-            CREATE INDEX idx_files_name ON files (name);
-            DROP TABLE IF EXISTS items;
-            CREATE TABLE items (
-                id INTEGER PRIMARY KEY,
-                file_id INTEGER NOT NULL,
-                content TEXT NOT NULL
-            );
-            CREATE INDEX idx_items_file_id ON items (file_id);
-
-            -- worker.rs
-            DROP TABLE IF EXISTS tasks;
-            CREATE TABLE tasks (
-                id INTEGER PRIMARY KEY,
-                path_type TEXT NOT NULL,
-                path TEXT NOT NULL,
-                task_type TEXT NOT NULL,
-                status TEXT NOT NULL,
-                worker TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                UNIQUE (path_type, path)
+/// 等借出的连接都归还后关闭连接池，执行 f 后用当前的密码重新打开连接池。
+/// 期间 get_conn 等待连接池重新打开，f 中可以直接替换数据库文件而不会丢失其他连接的写入
+pub fn with_drained_pool<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let pool_arc = POOL.get().context("数据库还没有打开")?;
+    let mut pool_lock = pool_arc
+        .lock()
+        .map_err(|e| anyhow!("获取连接池失败: {e:?}"))?;
+    let pool = pool_lock.as_ref().context("数据库还没有打开")?;
+    let start = Instant::now();
+    loop {
+        let state = pool.state();
+        if state.idle_connections == state.connections {
+            break;
+        }
+        if start.elapsed() > DRAIN_TIMEOUT {
+            bail!(
+                "还有 {} 个数据库连接正在使用，请稍后再试",
+                state.connections - state.idle_connections
             );
-            CREATE INDEX idx_tasks_status ON tasks (status);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    pool.get()?
+        .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    // 关闭所有连接，最后一个连接关闭时删除 WAL 文件
+    pool_lock.take();
+    info!("数据库连接池已关闭，等待替换数据库");
+    let result = f();
+    *pool_lock = Some(build_pool()?);
+    info!("数据库连接池已重新打开");
+    result
+}
 
-            -- version
-            DROP TABLE IF EXISTS db_version;
-            CREATE TABLE db_version (
-                version TEXT
-            );
-            INSERT INTO db_version (version) VALUES ('0.1');
-            "#,
-        )?;
+const DB_VERSION: &str = "0.30";
+
+// 旧版本数据库升级到下一版本需要执行的 SQL，依次执行，升级时不丢失已有索引。
+// 每一步保存在 migrations 目录中，文件名按升级顺序编号，新的一步加在最后
+const UPGRADES: &[(&str, &str, &str)] = &[
+    ("0.1", "0.2", include_str!("../migrations/001_v0.2.sql")),
+    ("0.2", "0.3", include_str!("../migrations/002_v0.3.sql")),
+    ("0.3", "0.4", include_str!("../migrations/003_v0.4.sql")),
+    ("0.4", "0.5", include_str!("../migrations/004_v0.5.sql")),
+    ("0.5", "0.6", include_str!("../migrations/005_v0.6.sql")),
+    ("0.6", "0.7", include_str!("../migrations/006_v0.7.sql")),
+    ("0.7", "0.8", include_str!("../migrations/007_v0.8.sql")),
+    ("0.8", "0.9", include_str!("../migrations/008_v0.9.sql")),
+    ("0.9", "0.10", include_str!("../migrations/009_v0.10.sql")),
+    ("0.10", "0.11", include_str!("../migrations/010_v0.11.sql")),
+    ("0.11", "0.12", include_str!("../migrations/011_v0.12.sql")),
+    ("0.12", "0.13", include_str!("../migrations/012_v0.13.sql")),
+    ("0.13", "0.14", include_str!("../migrations/013_v0.14.sql")),
+    ("0.14", "0.15", include_str!("../migrations/014_v0.15.sql")),
+    ("0.15", "0.16", include_str!("../migrations/015_v0.16.sql")),
+    ("0.16", "0.17", include_str!("../migrations/016_v0.17.sql")),
+    ("0.17", "0.18", include_str!("../migrations/017_v0.18.sql")),
+    ("0.18", "0.19", include_str!("../migrations/018_v0.19.sql")),
+    ("0.19", "0.20", include_str!("../migrations/019_v0.20.sql")),
+    ("0.20", "0.21", include_str!("../migrations/020_v0.21.sql")),
+    ("0.21", "0.22", include_str!("../migrations/021_v0.22.sql")),
+    ("0.22", "0.23", include_str!("../migrations/022_v0.23.sql")),
+    ("0.23", "0.24", include_str!("../migrations/023_v0.24.sql")),
+    ("0.24", "0.25", include_str!("../migrations/024_v0.25.sql")),
+    ("0.25", "0.26", include_str!("../migrations/025_v0.26.sql")),
+    ("0.26", "0.27", include_str!("../migrations/026_v0.27.sql")),
+    ("0.27", "0.28", include_str!("../migrations/027_v0.28.sql")),
+    ("0.28", "0.29", include_str!("../migrations/028_v0.29.sql")),
+    ("0.29", "0.30", include_str!("../migrations/029_v0.30.sql")),
+];
+
+/// 新数据库创建表，已有的数据库按 UPGRADES 依次升级，不会删除已有的索引
+pub fn check_or_init_db() -> Result<()> {
+    let initialized: bool = get_conn()?.query_one(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'db_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    // 只有全新的数据库才初始化，读取版本出错时不能当作空数据库处理
+    if !initialized {
+        info!("初始化数据库");
+        init_db()?;
     }
-    Ok(())
+    upgrade_db(&get_db_version()?)
 }
 
-fn check_db_init() -> Result<()> {
-    let conn = get_conn()?;
-    let row = conn
-        .query_one("select version from db_version", [], |row| {
-            row.get::<_, String>(0)
-        })
-        .map_err(|e| anyhow!("Database not initialized: {}", e))?;
+/// 该版本的数据库能否由当前程序打开，是当前版本或者能逐版本升级到当前版本
+pub fn is_upgradable(version: &str) -> bool {
+    version == DB_VERSION || UPGRADES.iter().any(|(from, _, _)| *from == version)
+}
 
-    if row != "0.1" {
+fn upgrade_db(version: &str) -> Result<()> {
+    if !is_upgradable(version) {
+        bail!("数据库版本 {version} 无法升级到 {DB_VERSION}，可能是更新版本的程序创建的，数据库保持不变");
+    }
+    let mut version = version.to_string();
+    for (from, to, sql) in UPGRADES {
+        if version == *from {
+            info!("升级数据库版本: {from} -> {to}");
+            let mut conn = get_conn()?;
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.execute("UPDATE db_version SET version = ?1", params![to])?;
+            tx.commit()?;
+            version = to.to_string();
+        }
+    }
+    if version != DB_VERSION {
         return Err(anyhow!(
-            "Database version mismatch: expected 0.1, found {}",
-            row
+            "Database version mismatch: expected {}, found {}",
+            DB_VERSION,
+            version
         ));
     }
     Ok(())
 }
+
+/// 创建 0.1 版本的表，再由 upgrade_db 升级到当前版本。已经有同名的表时出错，不会覆盖
+fn init_db() -> Result<()> {
+    let mut conn = get_conn()?;
+    // 这两个设置不能在事务中修改
+    conn.execute_batch(
+        r"PRAGMA journal_mode = WAL;
+        PRAGMA auto_vacuum = FULL;",
+    )?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        r#"-- config.rs
+        CREATE TABLE config (
+            id INTEGER PRIMARY KEY,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            unique (key)
+        );
+
+        -- indexer.rs
+        CREATE TABLE directories (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            modified_time TEXT NOT NULL,
+            UNIQUE (path)
+        );
+        CREATE INDEX idx_directories_name ON directories (name);
+        CREATE TABLE files (
+            id INTEGER PRIMARY KEY,
+            directory_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            modified_time TEXT NOT NULL,
+            UNIQUE (directory_id, name)
+        );
+        CREATE INDEX idx_files_name ON files (name);
+        CREATE TABLE items (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL,
+            content TEXT NOT NULL
+        );
+        CREATE INDEX idx_items_file_id ON items (file_id);
+
+        -- worker.rs
+        CREATE TABLE tasks (
+            id INTEGER PRIMARY KEY,
+            path_type TEXT NOT NULL,
+            path TEXT NOT NULL,
+            task_type TEXT NOT NULL,
+            status TEXT NOT NULL,
+            worker TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            UNIQUE (path_type, path)
+        );
+        CREATE INDEX idx_tasks_status ON tasks (status);
+
+        -- version
+        CREATE TABLE db_version (
+            version TEXT
+        );
+        INSERT INTO db_version (version) VALUES ('0.1');
+        "#,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+// 重建索引时清空的表。配置、搜索历史、保存的搜索、索引目录编号和同步用的变更记录保留
+const INDEX_TABLES: &[&str] = &[
+    "directories",
+    "files",
+    "items",
+    "items_fts",
+    "items_words",
+    "item_vectors",
+    "file_vectors",
+    "tasks",
+    "failed_files",
+];
+
+/// 在一个事务中删除索引内容的表，再按当前的结构（包括索引和触发器）重新创建
+pub fn rebuild_index_tables() -> Result<()> {
+    let mut conn = get_conn()?;
+    let tx = conn.transaction()?;
+    let mut schema: Vec<(String, String)> = Vec::new();
+    {
+        // 全文索引的影子表随虚拟表一起删除和创建，不在列表中
+        let mut stmt = tx.prepare(
+            "SELECT type, sql FROM sqlite_master WHERE tbl_name = ?1 AND sql IS NOT NULL",
+        )?;
+        for table in INDEX_TABLES {
+            let rows = stmt.query_map(params![table], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            schema.extend(rows.collect::<rusqlite::Result<Vec<_>>>()?);
+        }
+    }
+    // 先创建所有的表，再创建依赖这些表的索引和触发器
+    schema.sort_by_key(|(kind, _)| kind != "table");
+    for table in INDEX_TABLES {
+        tx.execute_batch(&format!("DROP TABLE IF EXISTS {table}"))?;
+    }
+    for (_, sql) in &schema {
+        tx.execute_batch(sql)?;
+    }
+    tx.commit()?;
+    info!("已清空索引");
+    Ok(())
+}
+
+fn get_db_version() -> Result<String> {
+    let conn = get_conn()?;
+    conn.query_one("select version from db_version", [], |row| {
+        row.get::<_, String>(0)
+    })
+    .map_err(|e| anyhow!("Database not initialized: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    fn count_config() -> i64 {
+        get_conn()
+            .unwrap()
+            .query_one("SELECT count(*) FROM config", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_or_init_db_keeps_data() {
+        let _env = TestEnv::new();
+        get_conn()
+            .unwrap()
+            .execute("INSERT INTO config (key, value) VALUES ('Test', '1')", [])
+            .unwrap();
+        let before = count_config();
+        check_or_init_db().unwrap();
+        assert_eq!(count_config(), before);
+
+        // 更新版本的程序创建的数据库不能升级，也不能清空
+        let conn = get_conn().unwrap();
+        conn.execute("UPDATE db_version SET version = '9.9'", [])
+            .unwrap();
+        assert!(check_or_init_db().is_err());
+        assert_eq!(count_config(), before);
+
+        // 版本表丢失时不当作新数据库重新初始化
+        conn.execute_batch("DROP TABLE db_version").unwrap();
+        assert!(check_or_init_db().is_err());
+        assert_eq!(count_config(), before);
+    }
+
+    #[test]
+    fn test_upgrades_chain() {
+        // 每一步从上一步的版本开始，最后一步升级到当前版本
+        let mut version = "0.1";
+        for (from, to, sql) in UPGRADES {
+            assert_eq!(*from, version);
+            assert!(!sql.trim().is_empty());
+            version = to;
+        }
+        assert_eq!(version, DB_VERSION);
+    }
+
+    #[test]
+    fn test_connection_tuning() {
+        let _env = TestEnv::new();
+        let conn = get_conn().unwrap();
+        let cache_size: i64 = conn
+            .query_one("PRAGMA cache_size", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(cache_size, -CACHE_SIZE_KIB);
+        let temp_store: i64 = conn
+            .query_one("PRAGMA temp_store", [], |row| row.get(0))
+            .unwrap();
+        // 2 为 MEMORY
+        assert_eq!(temp_store, 2);
+
+        // 同一个连接上再次准备相同的语句时从缓存中取出
+        for _ in 0..2 {
+            conn.prepare_cached("SELECT value FROM config WHERE key = ?1")
+                .unwrap();
+        }
+    }
+}
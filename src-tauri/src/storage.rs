@@ -0,0 +1,169 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::fs;
+
+use crate::config::Config;
+use crate::dirs::get_index_dir;
+use crate::sqlite::{get_conn, DB_FILE};
+use crate::utils::subpath_range;
+
+// 文件数最多的扩展名列出多少个
+const TOP_EXTENSIONS: usize = 20;
+
+/// 一个索引目录占用的索引
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RootStorage {
+    pub root: String,
+    /// 目录数按路径统计，包括嵌套在其中的索引目录
+    pub directories: usize,
+    pub files: usize,
+    pub items: usize,
+    /// 保存的文字占用的字节数，开启压缩后是压缩后的大小
+    pub text_bytes: u64,
+}
+
+/// 一种扩展名的文件数和文件总大小，没有扩展名时为空字符串
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionCount {
+    pub extension: String,
+    pub files: usize,
+    pub size: u64,
+}
+
+/// 索引占用的空间
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexStorage {
+    /// 数据库文件的大小，包括还没合并的 WAL 文件
+    pub db_bytes: u64,
+    pub roots: Vec<RootStorage>,
+    pub top_extensions: Vec<ExtensionCount>,
+}
+
+pub fn db_bytes() -> u64 {
+    let dir = get_index_dir();
+    [DB_FILE.to_string(), format!("{DB_FILE}-wal")]
+        .iter()
+        .filter_map(|name| fs::metadata(dir.join(name)).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn root_storage(conn: &Connection, root: String) -> Result<RootStorage> {
+    let (lower, upper) = subpath_range(&root);
+    let directories: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM directories WHERE path = ?1 OR (path >= ?2 AND path < ?3)",
+        params![root, lower, upper],
+        |row| row.get(0),
+    )?;
+    let (files, items, text_bytes): (i64, i64, i64) = conn.query_row(
+        r"SELECT
+            (SELECT COUNT(*) FROM files WHERE root_id = roots.id),
+            COUNT(items.id),
+            COALESCE(SUM(length(CAST(items.content AS BLOB))), 0)
+        FROM roots
+        LEFT JOIN files ON files.root_id = roots.id
+        LEFT JOIN items ON items.file_id = files.id
+        WHERE roots.path = ?1",
+        params![root],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    Ok(RootStorage {
+        root,
+        directories: directories as usize,
+        files: files as usize,
+        items: items as usize,
+        text_bytes: text_bytes as u64,
+    })
+}
+
+/// 数据库大小、各索引目录的索引数量和文件数最多的扩展名，按设置中的顺序列出索引目录
+pub fn get_index_storage() -> Result<IndexStorage> {
+    let conn = get_conn()?;
+    let roots = Config::get_index_dir_paths()?
+        .into_iter()
+        .map(|root| root_storage(&conn, root))
+        .collect::<Result<Vec<_>>>()?;
+    let mut stmt = conn.prepare(
+        r"SELECT COALESCE(extension, ''), COUNT(*), COALESCE(SUM(size), 0) FROM files
+        GROUP BY COALESCE(extension, '') ORDER BY COUNT(*) DESC, 1 LIMIT ?1",
+    )?;
+    let top_extensions = stmt
+        .query_map(params![TOP_EXTENSIONS], |row| {
+            Ok(ExtensionCount {
+                extension: row.get(0)?,
+                files: row.get::<_, i64>(1)? as usize,
+                size: row.get::<_, i64>(2)? as u64,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(IndexStorage {
+        db_bytes: db_bytes(),
+        roots,
+        top_extensions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_get_index_storage() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "bb").unwrap();
+        fs::write(root.join("sub/c.pdf"), "ccc").unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone()]).unwrap();
+
+        let indexer = Indexer::new().unwrap();
+        indexer.write_directory(&root).unwrap();
+        let items = vec![Item {
+            content: "你好".into(),
+            ..Default::default()
+        }];
+        indexer
+            .write_file_items(&root.join("a.txt"), items)
+            .unwrap();
+        indexer
+            .write_file_items(&root.join("sub/b.txt"), vec![])
+            .unwrap();
+        indexer
+            .write_file_items(&root.join("sub/c.pdf"), vec![])
+            .unwrap();
+
+        let storage = get_index_storage().unwrap();
+        assert!(storage.db_bytes > 0);
+        assert_eq!(
+            storage.roots,
+            vec![RootStorage {
+                root: root_str,
+                directories: 2,
+                files: 3,
+                items: 1,
+                text_bytes: 6,
+            }]
+        );
+        assert_eq!(
+            storage.top_extensions,
+            vec![
+                ExtensionCount {
+                    extension: "txt".into(),
+                    files: 2,
+                    size: 3,
+                },
+                ExtensionCount {
+                    extension: "pdf".into(),
+                    files: 1,
+                    size: 3,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use anyhow::Result;
+use matroska_demuxer::{Frame, MatroskaFile, TrackType as MkvTrackType};
+use mp4::TrackType as Mp4TrackType;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// 一条字幕，start_ms 为开始时间（毫秒）
+#[derive(Debug, PartialEq)]
+pub struct Cue {
+    pub start_ms: i64,
+    pub text: String,
+}
+
+/// 去掉字幕中的样式标签，如 `<i>`、`{\an8}`，多行合并为一行，英文单词之间补上空格
+pub fn clean_subtitle_text(text: &str) -> String {
+    let text = text.replace("\\N", "\n").replace("\\n", "\n");
+    let mut plain = String::new();
+    let mut skip_until = None;
+    for c in text.chars() {
+        match (skip_until, c) {
+            (None, '<') => skip_until = Some('>'),
+            (None, '{') => skip_until = Some('}'),
+            (None, c) => plain.push(c),
+            (Some(end), c) if c == end => skip_until = None,
+            _ => {}
+        }
+    }
+
+    let mut result = String::new();
+    for line in plain.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if result.chars().last().is_some_and(|c| c.is_ascii()) {
+            result.push(' ');
+        }
+        result.push_str(line);
+    }
+    result
+}
+
+/// 解析 `00:01:02,500` 或 `00:01:02.500` 形式的时间
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let (hms, millis) = value.trim().split_once([',', '.'])?;
+    let mut seconds = 0;
+    for part in hms.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<i64>().ok()?;
+    }
+    Some(seconds * 1000 + millis.trim().parse::<i64>().ok()?)
+}
+
+/// 字幕文件通常是 UTF-8，不是时按 GB18030 解码，兼容常见的中文字幕
+fn decode_subtitle(data: &[u8]) -> String {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::GB18030.decode(data).0.into_owned(),
+    }
+}
+
+/// 解析 SRT 字幕，每条字幕为序号、`开始 --> 结束` 的时间行和若干行文字
+pub fn parse_srt(data: &[u8]) -> Vec<Cue> {
+    let text = decode_subtitle(data).replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(start_ms) = lines
+            .next()
+            .and_then(|timing| timing.split_once("-->"))
+            .and_then(|(start, _)| parse_timestamp(start))
+        else {
+            continue;
+        };
+        let text = clean_subtitle_text(&lines.collect::<Vec<_>>().join("\n"));
+        if !text.is_empty() {
+            cues.push(Cue { start_ms, text });
+        }
+    }
+    cues
+}
+
+/// 与视频同名的 SRT 字幕，如 `movie.srt`、`movie.zh.srt`
+pub fn sidecar_subtitles(video: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) else {
+        return Ok(Vec::new());
+    };
+    let stem = stem.to_string_lossy().to_lowercase();
+    let mut subtitles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let Some(name) = name.strip_suffix(".srt") else {
+            continue;
+        };
+        let matches = name == stem
+            || name
+                .strip_prefix(stem.as_str())
+                .is_some_and(|lang| lang.starts_with('.') && !lang[1..].contains('.'));
+        if matches && path.is_file() {
+            subtitles.push(path);
+        }
+    }
+    subtitles.sort();
+    Ok(subtitles)
+}
+
+/// mkv 中的文本字幕轨道，图片字幕（PGS、VobSub）无法读取，跳过。
+/// 字幕与音视频交错存放，需要读完整个文件
+pub fn mkv_subtitles(path: &Path) -> Result<Vec<Cue>> {
+    let mut mkv = MatroskaFile::open(BufReader::new(File::open(path)?))?;
+    let codecs: HashMap<u64, String> = mkv
+        .tracks()
+        .iter()
+        .filter(|track| track.track_type() == MkvTrackType::Subtitle)
+        .filter(|track| track.codec_id().starts_with("S_TEXT/"))
+        .map(|track| (track.track_number().get(), track.codec_id().to_string()))
+        .collect();
+    if codecs.is_empty() {
+        return Ok(Vec::new());
+    }
+    // 时间戳的单位为 timestamp_scale 纳秒
+    let scale = mkv.info().timestamp_scale().get() as i64;
+
+    let mut cues = Vec::new();
+    let mut frame = Frame::default();
+    while mkv.next_frame(&mut frame)? {
+        let Some(codec) = codecs.get(&frame.track) else {
+            continue;
+        };
+        let data = String::from_utf8_lossy(&frame.data);
+        let text = match codec.as_str() {
+            // ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+            "S_TEXT/ASS" | "S_TEXT/SSA" => data.splitn(9, ',').nth(8).unwrap_or("").to_string(),
+            _ => data.into_owned(),
+        };
+        let text = clean_subtitle_text(&text);
+        if !text.is_empty() {
+            cues.push(Cue {
+                start_ms: frame.timestamp as i64 * scale / 1_000_000,
+                text,
+            });
+        }
+    }
+    cues.sort_by_key(|cue| cue.start_ms);
+    Ok(cues)
+}
+
+/// mp4 中的 tx3g 字幕轨道，每个样本为两字节长度加 UTF-8 文字，后面可能跟样式
+pub fn mp4_subtitles(path: &Path) -> Result<Vec<Cue>> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut mp4 = mp4::Mp4Reader::read_header(BufReader::new(file), size)?;
+    let tracks: Vec<(u32, u32, u32)> = mp4
+        .tracks()
+        .iter()
+        .filter(|(_, track)| matches!(track.track_type(), Ok(Mp4TrackType::Subtitle)))
+        .map(|(id, track)| (*id, track.timescale(), track.sample_count()))
+        .collect();
+
+    let mut cues = Vec::new();
+    for (track_id, timescale, sample_count) in tracks {
+        for sample_id in 1..=sample_count {
+            let Some(sample) = mp4.read_sample(track_id, sample_id)? else {
+                continue;
+            };
+            let Some(len) = sample
+                .bytes
+                .get(..2)
+                .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            else {
+                continue;
+            };
+            let Some(text) = sample.bytes.get(2..2 + len) else {
+                continue;
+            };
+            let text = clean_subtitle_text(&String::from_utf8_lossy(text));
+            if !text.is_empty() {
+                cues.push(Cue {
+                    start_ms: (sample.start_time * 1000 / timescale.max(1) as u64) as i64,
+                    text,
+                });
+            }
+        }
+    }
+    cues.sort_by_key(|cue| cue.start_ms);
+    Ok(cues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_parse_srt() {
+        let srt = "\u{feff}1\r\n00:00:01,500 --> 00:00:03,000\r\n<i>Hello</i>\r\nworld\r\n\r\n2\r\n01:02:03,040 --> 01:02:05,000\r\n{\\an8}你好\r\n\r\n3\r\n00:00:09,000 --> 00:00:10,000\r\n\r\n";
+        assert_eq!(
+            parse_srt(srt.as_bytes()),
+            vec![
+                Cue {
+                    start_ms: 1500,
+                    text: "Hello world".to_string()
+                },
+                Cue {
+                    start_ms: 3_723_040,
+                    text: "你好".to_string()
+                },
+            ]
+        );
+        let (gbk, _, _) =
+            encoding_rs::GB18030.encode("1\n00:00:01,000 --> 00:00:02,000\n中文字幕\n");
+        assert_eq!(parse_srt(&gbk)[0].text, "中文字幕");
+    }
+
+    #[test]
+    fn test_sidecar_subtitles() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path();
+        for name in [
+            "Movie.mkv",
+            "movie.srt",
+            "Movie.zh.srt",
+            "movie.part2.zh.srt",
+            "other.srt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let names: Vec<String> = sidecar_subtitles(&dir.join("Movie.mkv"))
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["Movie.zh.srt", "movie.srt"]);
+    }
+
+    /// EBML 元素：ID、长度和数据，长度统一用 8 字节编码
+    fn element(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x01);
+        element.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+        element.extend_from_slice(data);
+        element
+    }
+
+    #[test]
+    fn test_mkv_subtitles() {
+        let env = TestEnv::new();
+        let header = element(
+            &[0x1A, 0x45, 0xDF, 0xA3],
+            &[
+                element(&[0x42, 0x82], b"matroska"),
+                element(&[0x42, 0x87], &[4]),
+                element(&[0x42, 0x85], &[2]),
+            ]
+            .concat(),
+        );
+        let info = element(
+            &[0x15, 0x49, 0xA9, 0x66],
+            &[
+                element(&[0x2A, 0xD7, 0xB1], &[0x0F, 0x42, 0x40]),
+                element(&[0x4D, 0x80], b"test"),
+                element(&[0x57, 0x41], b"test"),
+            ]
+            .concat(),
+        );
+        let track = |number: u8, track_type: u8, codec: &[u8]| {
+            element(
+                &[0xAE],
+                &[
+                    element(&[0xD7], &[number]),
+                    element(&[0x73, 0xC5], &[number]),
+                    element(&[0x83], &[track_type]),
+                    element(&[0x86], codec),
+                ]
+                .concat(),
+            )
+        };
+        let tracks = element(
+            &[0x16, 0x54, 0xAE, 0x6B],
+            &[
+                track(1, 0x11, b"S_TEXT/UTF8"),
+                track(2, 0x11, b"S_TEXT/ASS"),
+            ]
+            .concat(),
+        );
+        // SimpleBlock：轨道号、相对于 Cluster 的时间戳、标志和数据
+        let block = |track: u8, timestamp: i16, data: &str| {
+            let mut block = vec![0x80 | track];
+            block.extend_from_slice(&timestamp.to_be_bytes());
+            block.push(0x80);
+            block.extend_from_slice(data.as_bytes());
+            element(&[0xA3], &block)
+        };
+        let cluster = element(
+            &[0x1F, 0x43, 0xB6, 0x75],
+            &[
+                element(&[0xE7], &[0x03, 0xE8]),
+                block(1, 500, "<b>Hello</b>\nworld"),
+                block(2, 0, "0,0,Default,,0,0,0,,{\\i1}你好\\N世界"),
+            ]
+            .concat(),
+        );
+        let segment = element(&[0x18, 0x53, 0x80, 0x67], &[info, tracks, cluster].concat());
+        let path = env.temp_dir.path().join("movie.mkv");
+        fs::write(&path, [header, segment].concat()).unwrap();
+
+        assert_eq!(
+            mkv_subtitles(&path).unwrap(),
+            vec![
+                Cue {
+                    start_ms: 1000,
+                    text: "你好世界".to_string()
+                },
+                Cue {
+                    start_ms: 1500,
+                    text: "Hello world".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mp4_subtitles() {
+        let env = TestEnv::new();
+        let path = env.temp_dir.path().join("movie.mp4");
+        let config = mp4::Mp4Config {
+            major_brand: "isom".parse().unwrap(),
+            minor_version: 512,
+            compatible_brands: vec!["isom".parse().unwrap()],
+            timescale: 1000,
+        };
+        let mut writer =
+            mp4::Mp4Writer::write_start(File::create(&path).unwrap(), &config).unwrap();
+        writer
+            .add_track(&mp4::TrackConfig {
+                track_type: Mp4TrackType::Subtitle,
+                timescale: 1000,
+                language: "und".to_string(),
+                media_conf: mp4::MediaConfig::TtxtConfig(mp4::TtxtConfig {}),
+            })
+            .unwrap();
+        // 写入时按各样本的时长计算开始时间
+        for (start_time, duration, text) in [
+            (0, 2500, ""),
+            (2500, 1500, "第一句"),
+            (4000, 1000, "second line"),
+        ] {
+            let mut bytes = (text.len() as u16).to_be_bytes().to_vec();
+            bytes.extend_from_slice(text.as_bytes());
+            writer
+                .write_sample(
+                    1,
+                    &mp4::Mp4Sample {
+                        start_time,
+                        duration,
+                        rendering_offset: 0,
+                        is_sync: true,
+                        bytes: mp4::Bytes::from(bytes),
+                    },
+                )
+                .unwrap();
+        }
+        writer.write_end().unwrap();
+
+        assert_eq!(
+            mp4_subtitles(&path).unwrap(),
+            vec![
+                Cue {
+                    start_ms: 2500,
+                    text: "第一句".to_string()
+                },
+                Cue {
+                    start_ms: 4000,
+                    text: "second line".to_string()
+                },
+            ]
+        );
+    }
+}
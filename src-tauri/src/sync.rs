@@ -0,0 +1,718 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use log::{debug, error, info, warn};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::download::sha256_file;
+use crate::embedding;
+use crate::index_events;
+use crate::indexer::{ChangeOp, Indexer};
+use crate::reader::{Item, ItemPosition};
+use crate::sqlite::get_conn;
+use crate::utils::path_to_str;
+use crate::worker::Worker;
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(300);
+const CHANGELOG_EXTENSION: &str = "json";
+
+/// 同步目录中的一个变更，路径保存为相对索引根目录的路径，
+/// 这样两台机器上同一个同步盘的挂载位置可以不同
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Change {
+    seq: i64,
+    op: String,
+    root: String,
+    path: String,
+    modified_time: Option<String>,
+    items: Vec<String>,
+    /// 与 items 一一对应，旧版本导出的变更没有这一项
+    #[serde(default)]
+    positions: Vec<ItemPosition>,
+    /// 文件大小和内容哈希，两台机器上同一个文件的修改时间可能不同，按内容确认是同一版本。
+    /// 旧版本导出的变更没有这两项，只能比较修改时间
+    #[serde(default)]
+    size: Option<i64>,
+    #[serde(default)]
+    content_hash: Option<String>,
+}
+
+/// 导出到 `<同步目录>/<机器ID>/<结束序号>.json` 的一批变更
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChangelogBatch {
+    machine: String,
+    from_seq: i64,
+    to_seq: i64,
+    changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncResult {
+    pub exported: usize,
+    pub applied: usize,
+}
+
+/// 同步目录中其他机器的一个索引根目录，以及在本机上对应的索引根目录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncRoot {
+    pub remote_root: String,
+    pub local_root: Option<String>,
+}
+
+/// 本机的机器 ID，第一次使用时随机生成
+fn get_machine_id() -> Result<String> {
+    if let Some(machine_id) = Config::get_sync_machine_id()? {
+        return Ok(machine_id);
+    }
+    let machine_id = new_machine_id()?;
+    Config::set_sync_machine_id(&machine_id)?;
+    Ok(machine_id)
+}
+
+fn new_machine_id() -> Result<String> {
+    Ok(get_conn()?.query_one("SELECT lower(hex(randomblob(8)))", [], |row| row.get(0))?)
+}
+
+/// 导入其他机器的索引后换一个机器 ID，重新开始导出和应用变更，
+/// 避免和原来的机器在同步目录中写入同一个目录。导入的 changelog 已经由原来的机器导出，不再导出
+pub fn reset_sync_state() -> Result<()> {
+    get_conn()?.execute("DELETE FROM changelog", [])?;
+    Config::set_sync_machine_id(&new_machine_id()?)?;
+    Config::set_sync_exported_seq(0)?;
+    Config::set_sync_applied_seqs(&HashMap::new())?;
+    Ok(())
+}
+
+/// 路径所在的索引根目录，以及相对路径（以 `/` 分隔）
+fn split_root(path: &Path, roots: &[String]) -> Option<(String, String)> {
+    roots.iter().find_map(|root| {
+        let relative = path.strip_prefix(root).ok()?;
+        let parts: Vec<&str> = relative
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<_>>()?;
+        Some((root.clone(), parts.join("/")))
+    })
+}
+
+/// 其他机器的索引根目录在本机上对应的目录：设置中指定的索引根目录，没有指定时是路径相同的索引根目录。
+/// 目录名相同的两个目录不一定是同一个目录，不自动对应
+fn map_root(
+    remote_root: &str,
+    local_roots: &[String],
+    root_map: &HashMap<String, String>,
+) -> Option<PathBuf> {
+    let local_root = root_map
+        .get(remote_root)
+        .map(String::as_str)
+        .unwrap_or(remote_root);
+    local_roots
+        .iter()
+        .find(|root| *root == local_root)
+        .map(PathBuf::from)
+}
+
+fn join_relative(root: &Path, relative: &str) -> Result<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in relative.split('/').filter(|p| !p.is_empty()) {
+        // 不允许同步文件中的路径跳出索引根目录
+        if Path::new(part)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(anyhow!("Invalid path in changelog: {}", relative));
+        }
+        path.push(part);
+    }
+    Ok(path)
+}
+
+/// 本机磁盘上的文件和变更中的是否同一版本。有内容哈希时比较大小和哈希，否则比较修改时间
+fn same_version(indexer: &Indexer, path: &Path, change: &Change) -> Result<bool> {
+    let local_time = indexer.get_modified_time(path)?;
+    let (Some(size), Some(hash)) = (change.size, &change.content_hash) else {
+        let remote_time = change
+            .modified_time
+            .as_deref()
+            .context("IndexFile change without modified_time")?;
+        return Ok(same_modified_time(&local_time, remote_time));
+    };
+    if fs::metadata(path)?.len() as i64 != size {
+        return Ok(false);
+    }
+    let local_hash = match indexer.indexed_content_hash(path, size, &local_time)? {
+        Some(local_hash) => local_hash,
+        None => sha256_file(path)?,
+    };
+    Ok(local_hash == *hash)
+}
+
+/// 本机是否已经索引过变更中的版本
+fn is_indexed(indexer: &Indexer, path: &Path, change: &Change) -> Result<bool> {
+    if let Some(hash) = &change.content_hash {
+        return Ok(indexer
+            .indexed_file_content(path)?
+            .is_some_and(|(_, local_hash)| local_hash == *hash));
+    }
+    Ok(
+        match (indexer.get_file(path), change.modified_time.as_deref()) {
+            (Ok(file), Some(remote_time)) => same_modified_time(&file.modified_time, remote_time),
+            _ => false,
+        },
+    )
+}
+
+fn same_modified_time(a: &str, b: &str) -> bool {
+    match (
+        DateTime::parse_from_rfc3339(a),
+        DateTime::parse_from_rfc3339(b),
+    ) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn batch_file_name(to_seq: i64) -> String {
+    format!("{to_seq:020}.{CHANGELOG_EXTENSION}")
+}
+
+/// 把本机 seq 之后的变更导出到同步目录，返回导出的变更数量和导出到的序号。
+/// 序号取自导出时读取的变更，之后新写入的变更留给下次导出
+pub fn export_changes(sync_dir: &Path, since_seq: i64) -> Result<(usize, i64)> {
+    let machine = get_machine_id()?;
+    let roots = Config::get_index_dir_paths()?;
+    let rows: Vec<(i64, String, String)> = {
+        let conn = get_conn()?;
+        let mut stmt =
+            conn.prepare("SELECT seq, op, path FROM changelog WHERE seq > ?1 ORDER BY seq")?;
+        let rows = stmt
+            .query_map(params![since_seq], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        rows
+    };
+    let Some(to_seq) = rows.last().map(|(seq, _, _)| *seq) else {
+        return Ok((0, since_seq));
+    };
+
+    // 同一路径只保留最后一次变更
+    let mut seen = HashSet::new();
+    let latest: Vec<&(i64, String, String)> = rows
+        .iter()
+        .rev()
+        .filter(|(_, _, path)| seen.insert(path.clone()))
+        .collect();
+
+    let indexer = Indexer::new()?;
+    let mut changes = Vec::new();
+    for (seq, op, path) in latest.into_iter().rev() {
+        let op = ChangeOp::from_str(op)?;
+        let Some((root, relative)) = split_root(Path::new(path), &roots) else {
+            debug!("不在索引目录中，不导出: {path}");
+            continue;
+        };
+        let (modified_time, content, items, positions) = if op == ChangeOp::IndexFile {
+            let Ok(file) = indexer.get_file(Path::new(path)) else {
+                continue;
+            };
+            // 索引后文件又改过时不导出，重新索引后会再导出
+            let Some(content) = indexer.indexed_file_content(Path::new(path))? else {
+                debug!("文件已经改过，不导出: {path}");
+                continue;
+            };
+            let (items, positions) = indexer
+                .get_file_items(Path::new(path))?
+                .into_iter()
+                .map(|item| (item.content, item.position))
+                .unzip();
+            (Some(file.modified_time), Some(content), items, positions)
+        } else {
+            (None, None, Vec::new(), Vec::new())
+        };
+        let (size, content_hash) = content.unzip();
+        changes.push(Change {
+            seq: *seq,
+            op: op.to_string(),
+            root,
+            path: relative,
+            modified_time,
+            items,
+            positions,
+            size,
+            content_hash,
+        });
+    }
+
+    let exported = changes.len();
+    let batch = ChangelogBatch {
+        machine: machine.clone(),
+        from_seq: since_seq,
+        to_seq,
+        changes,
+    };
+    let machine_dir = sync_dir.join(&machine);
+    fs::create_dir_all(&machine_dir)?;
+    // 先写临时文件再改名，避免其他机器读到写了一半的文件
+    let dest = machine_dir.join(batch_file_name(to_seq));
+    let tmp = dest.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(&batch)?)?;
+    fs::rename(&tmp, &dest)?;
+    info!("导出同步变更: {exported} 条, 序号 {since_seq} -> {to_seq}");
+    Ok((exported, to_seq))
+}
+
+/// 删除已经导出到同步目录的变更
+fn prune_changelog(to_seq: i64) -> Result<()> {
+    let deleted = get_conn()?.execute("DELETE FROM changelog WHERE seq <= ?1", params![to_seq])?;
+    debug!("删除已导出的变更: {deleted} 条");
+    Ok(())
+}
+
+/// 应用一条其他机器的变更，本机文件状态与变更不一致时跳过
+fn apply_change(
+    indexer: &Indexer,
+    change: &Change,
+    local_roots: &[String],
+    root_map: &HashMap<String, String>,
+) -> Result<bool> {
+    let Some(root) = map_root(&change.root, local_roots, root_map) else {
+        debug!("本机没有对应的索引目录: {}", change.root);
+        return Ok(false);
+    };
+    let path = join_relative(&root, &change.path)?;
+    match ChangeOp::from_str(&change.op)? {
+        ChangeOp::IndexFile => {
+            // 本机已经索引过同一版本，或者本机的文件和导出时的内容不同
+            if !path.is_file()
+                || is_indexed(indexer, &path, change)?
+                || !same_version(indexer, &path, change)?
+            {
+                return Ok(false);
+            }
+            indexer.delete_file(&path)?;
+            let items = change
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, content)| Item {
+                    content: content.clone(),
+                    position: change.positions.get(i).cloned().unwrap_or_default(),
+                })
+                .collect();
+            let file_id = indexer.write_file_items(&path, items)?;
+            if let Some(hash) = &change.content_hash {
+                indexer.set_content_hash(file_id, hash)?;
+            }
+            // 已经有索引结果，本机不需要再读取一次
+            Worker::remove_pending_tasks(&path)?;
+        }
+        ChangeOp::DeleteFile => {
+            if path.exists() {
+                return Ok(false);
+            }
+            indexer.delete_file(&path)?;
+        }
+        ChangeOp::DeleteDirectory => {
+            if path.exists() {
+                return Ok(false);
+            }
+            indexer.delete_directory(&path)?;
+        }
+    }
+    debug!("应用同步变更: {} {}", change.op, path.display());
+    Ok(true)
+}
+
+/// 应用同步目录中其他机器导出的变更，返回应用的变更数量
+pub fn apply_changes(sync_dir: &Path) -> Result<usize> {
+    let machine = get_machine_id()?;
+    let local_roots = Config::get_index_dir_paths()?;
+    let root_map = Config::get_sync_root_map()?;
+    let mut applied_seqs = Config::get_sync_applied_seqs()?;
+    let indexer = Indexer::new_without_changelog()?;
+    let mut applied = 0;
+
+    for entry in fs::read_dir(sync_dir)? {
+        let machine_dir = entry?.path();
+        let Some(remote) = machine_dir.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !machine_dir.is_dir() || remote == machine {
+            continue;
+        }
+        let remote = remote.to_string();
+        let applied_seq = applied_seqs.get(&remote).copied().unwrap_or(0);
+
+        let mut batches: Vec<(i64, PathBuf)> = fs::read_dir(&machine_dir)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(CHANGELOG_EXTENSION) {
+                    return None;
+                }
+                let to_seq = path.file_stem()?.to_str()?.parse::<i64>().ok()?;
+                Some((to_seq, path))
+            })
+            .filter(|(to_seq, _)| *to_seq > applied_seq)
+            .collect();
+        batches.sort();
+
+        for (to_seq, path) in batches {
+            let batch: ChangelogBatch = match fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice(&data)?))
+            {
+                Ok(batch) => batch,
+                Err(e) => {
+                    // 可能还没有同步完整，下次再试
+                    warn!("读取同步文件失败: {}, 错误: {e}", path.display());
+                    break;
+                }
+            };
+            // 应用失败时只记录到失败之前的序号，下次同步从失败的变更开始重试
+            let mut failed = false;
+            for change in batch.changes.iter().filter(|c| c.seq > applied_seq) {
+                match apply_change(&indexer, change, &local_roots, &root_map) {
+                    Ok(true) => applied += 1,
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!(
+                            "应用同步变更失败，下次同步时重试: {}, 错误: {e:?}",
+                            change.path
+                        );
+                        failed = true;
+                        break;
+                    }
+                }
+                applied_seqs.insert(remote.clone(), change.seq);
+            }
+            if !failed {
+                applied_seqs.insert(remote.clone(), to_seq);
+            }
+            Config::set_sync_applied_seqs(&applied_seqs)?;
+            if failed {
+                break;
+            }
+        }
+    }
+
+    if applied > 0 {
+        info!("应用同步变更: {applied} 条");
+        embedding::notify_embedding();
+        index_events::flush(true);
+    }
+    Ok(applied)
+}
+
+/// 导出本机变更并应用其他机器的变更，未配置同步目录时不做任何事
+pub fn sync_now() -> Result<SyncResult> {
+    let Some(sync_dir) = Config::get_sync_dir()? else {
+        return Ok(SyncResult::default());
+    };
+    let sync_dir = Path::new(&sync_dir);
+    if !sync_dir.is_dir() {
+        return Err(anyhow!("Sync directory not found: {}", sync_dir.display()));
+    }
+    let since_seq = Config::get_sync_exported_seq()?;
+    let (exported, to_seq) = export_changes(sync_dir, since_seq)?;
+    Config::set_sync_exported_seq(to_seq)?;
+    prune_changelog(to_seq)?;
+    let applied = apply_changes(sync_dir)?;
+    Ok(SyncResult { exported, applied })
+}
+
+/// 同步目录中其他机器导出过的索引根目录和本机对应的目录
+pub fn get_sync_roots() -> Result<Vec<SyncRoot>> {
+    let Some(sync_dir) = Config::get_sync_dir()? else {
+        return Ok(Vec::new());
+    };
+    let machine = get_machine_id()?;
+    let mut remote_roots = BTreeSet::new();
+    for entry in fs::read_dir(sync_dir)? {
+        let machine_dir = entry?.path();
+        if !machine_dir.is_dir()
+            || machine_dir.file_name().and_then(|n| n.to_str()) == Some(machine.as_str())
+        {
+            continue;
+        }
+        for entry in fs::read_dir(&machine_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(CHANGELOG_EXTENSION) {
+                continue;
+            }
+            // 还没有同步完整的文件跳过
+            let Ok(batch) = fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<ChangelogBatch>(&data)?))
+            else {
+                continue;
+            };
+            remote_roots.extend(batch.changes.into_iter().map(|change| change.root));
+        }
+    }
+    let local_roots = Config::get_index_dir_paths()?;
+    let root_map = Config::get_sync_root_map()?;
+    Ok(remote_roots
+        .into_iter()
+        .map(|remote_root| SyncRoot {
+            local_root: map_root(&remote_root, &local_roots, &root_map)
+                .map(|root| root.to_string_lossy().to_string()),
+            remote_root,
+        })
+        .collect())
+}
+
+/// 指定其他机器的索引根目录在本机对应的索引根目录，为空时取消对应
+pub fn set_sync_root(remote_root: String, local_root: Option<String>) -> Result<()> {
+    let mut root_map = Config::get_sync_root_map()?;
+    match local_root {
+        Some(local_root) => {
+            if !Config::get_index_dir_paths()?.contains(&local_root) {
+                return Err(anyhow!("Path {} is not an indexed root", local_root));
+            }
+            root_map.insert(remote_root, local_root);
+        }
+        None => {
+            root_map.remove(&remote_root);
+        }
+    }
+    Config::set_sync_root_map(&root_map)
+}
+
+pub fn set_sync_dir(sync_dir: Option<String>) -> Result<()> {
+    if let Some(dir) = &sync_dir {
+        let path = Path::new(dir);
+        if !path.is_dir() {
+            return Err(anyhow!("Sync directory not found: {}", dir));
+        }
+        path_to_str(path)?;
+    }
+    Config::set_sync_dir(&sync_dir)
+}
+
+pub fn start_process() -> Result<()> {
+    thread::Builder::new()
+        .name("index-sync".to_string())
+        .spawn(|| loop {
+            if let Err(e) = sync_now() {
+                error!("同步索引失败: {e:?}");
+            }
+            thread::sleep(SYNC_INTERVAL);
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_split_and_map_root() {
+        let roots = vec![Path::new("/home/a/Photos").to_str().unwrap().to_string()];
+        assert_eq!(
+            split_root(Path::new("/home/a/Photos/2024/x.jpg"), &roots),
+            Some((roots[0].clone(), "2024/x.jpg".to_string()))
+        );
+        assert_eq!(split_root(Path::new("/home/a/Docs/x.txt"), &roots), None);
+
+        let local_roots = vec![
+            "/home/b/Dropbox/Photos".to_string(),
+            "/home/a/Docs".to_string(),
+        ];
+        let mut root_map = HashMap::new();
+        // 只有目录名相同时不对应
+        assert_eq!(
+            map_root(r"C:\Users\a\Photos", &local_roots, &root_map),
+            None
+        );
+        root_map.insert(
+            r"C:\Users\a\Photos".to_string(),
+            "/home/b/Dropbox/Photos".to_string(),
+        );
+        assert_eq!(
+            map_root(r"C:\Users\a\Photos", &local_roots, &root_map),
+            Some(PathBuf::from("/home/b/Dropbox/Photos"))
+        );
+        // 路径相同的索引根目录直接对应
+        assert_eq!(
+            map_root("/home/a/Docs", &local_roots, &root_map),
+            Some(PathBuf::from("/home/a/Docs"))
+        );
+        assert_eq!(map_root("/home/a/Music", &local_roots, &root_map), None);
+
+        assert!(join_relative(Path::new("/r"), "a/../../etc").is_err());
+    }
+
+    #[test]
+    fn test_export_and_apply_changes() {
+        let env = TestEnv::new();
+        let sync_dir = env.temp_dir.path().join("sync");
+        let root = env.temp_dir.path().join("Photos");
+        fs::create_dir_all(&sync_dir).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        let file = root.join("a.txt");
+        fs::write(&file, "hello").unwrap();
+        Config::set_index_dir_paths(vec![root.to_str().unwrap().to_string()]).unwrap();
+        set_sync_dir(Some(sync_dir.to_str().unwrap().to_string())).unwrap();
+
+        let indexer = Indexer::new().unwrap();
+        indexer
+            .write_file_items(
+                &file,
+                vec![Item {
+                    content: "ocr text".to_string(),
+                    position: ItemPosition {
+                        line: Some(2),
+                        ..Default::default()
+                    },
+                }],
+            )
+            .unwrap();
+        assert_eq!(export_changes(&sync_dir, 0).unwrap(), (1, 1));
+
+        // 模拟另一台机器：换一个机器 ID，清空本机索引
+        let remote = get_machine_id().unwrap();
+        Config::set_sync_machine_id("other").unwrap();
+        Indexer::new_without_changelog()
+            .unwrap()
+            .delete_file(&file)
+            .unwrap();
+
+        assert_eq!(apply_changes(&sync_dir).unwrap(), 1);
+        let items = indexer.get_file_items(&file).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].content, "ocr text");
+        assert_eq!(items[0].position.line, Some(2));
+        assert_eq!(Config::get_sync_applied_seqs().unwrap()[&remote], 1);
+
+        // 已应用的变更不会重复应用
+        assert_eq!(apply_changes(&sync_dir).unwrap(), 0);
+
+        // 记录导出到的序号，已经导出的变更从 changelog 中删除
+        sync_now().unwrap();
+        assert_eq!(Config::get_sync_exported_seq().unwrap(), 1);
+        let remaining: i64 = get_conn()
+            .unwrap()
+            .query_one("SELECT COUNT(*) FROM changelog", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_apply_changes_stops_at_failure() {
+        let env = TestEnv::new();
+        let sync_dir = env.temp_dir.path().join("sync");
+        let root = env.temp_dir.path().join("Photos");
+        fs::create_dir_all(sync_dir.join("remote")).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone()]).unwrap();
+        let change = |seq: i64, path: &str| Change {
+            seq,
+            op: ChangeOp::DeleteFile.to_string(),
+            root: root_str.clone(),
+            path: path.to_string(),
+            modified_time: None,
+            items: Vec::new(),
+            positions: Vec::new(),
+            size: None,
+            content_hash: None,
+        };
+        let batch = ChangelogBatch {
+            machine: "remote".to_string(),
+            from_seq: 0,
+            to_seq: 3,
+            changes: vec![
+                change(1, "a.txt"),
+                change(2, "../b.txt"),
+                change(3, "c.txt"),
+            ],
+        };
+        fs::write(
+            sync_dir.join("remote").join(batch_file_name(3)),
+            serde_json::to_vec(&batch).unwrap(),
+        )
+        .unwrap();
+
+        // 失败的变更和之后的变更都不记为已应用，下次同步时重试
+        assert_eq!(apply_changes(&sync_dir).unwrap(), 1);
+        assert_eq!(Config::get_sync_applied_seqs().unwrap()["remote"], 1);
+        assert_eq!(apply_changes(&sync_dir).unwrap(), 0);
+        assert_eq!(Config::get_sync_applied_seqs().unwrap()["remote"], 1);
+    }
+
+    #[test]
+    fn test_apply_changes_by_content() {
+        let env = TestEnv::new();
+        let sync_dir = env.temp_dir.path().join("sync");
+        let remote_root = env.temp_dir.path().join("remote");
+        let local_root = env.temp_dir.path().join("local");
+        for dir in [&sync_dir, &remote_root, &local_root] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let remote_str = remote_root.to_str().unwrap().to_string();
+        let local_str = local_root.to_str().unwrap().to_string();
+        for name in ["same.txt", "changed.txt"] {
+            fs::write(remote_root.join(name), "hello").unwrap();
+        }
+        Config::set_index_dir_paths(vec![remote_str.clone()]).unwrap();
+        set_sync_dir(Some(sync_dir.to_str().unwrap().to_string())).unwrap();
+        let indexer = Indexer::new().unwrap();
+        for name in ["same.txt", "changed.txt"] {
+            let items = vec![Item {
+                content: format!("{name} ocr"),
+                ..Default::default()
+            }];
+            indexer
+                .write_file_items(&remote_root.join(name), items)
+                .unwrap();
+        }
+        assert_eq!(export_changes(&sync_dir, 0).unwrap(), (2, 2));
+
+        // 另一台机器上的目录位置和名称不同，同步下来的文件修改时间也不同
+        Config::set_sync_machine_id("other").unwrap();
+        Config::set_index_dir_paths(vec![local_str.clone()]).unwrap();
+        fs::write(local_root.join("same.txt"), "hello").unwrap();
+        fs::write(local_root.join("changed.txt"), "world").unwrap();
+        let earlier = SystemTime::now() - Duration::from_secs(3600);
+        for name in ["same.txt", "changed.txt"] {
+            fs::File::options()
+                .write(true)
+                .open(local_root.join(name))
+                .unwrap()
+                .set_modified(earlier)
+                .unwrap();
+        }
+        assert_eq!(
+            get_sync_roots().unwrap(),
+            vec![SyncRoot {
+                remote_root: remote_str.clone(),
+                local_root: None,
+            }]
+        );
+        assert!(set_sync_root(remote_str.clone(), Some("/not/indexed".to_string())).is_err());
+        set_sync_root(remote_str.clone(), Some(local_str.clone())).unwrap();
+        assert_eq!(
+            get_sync_roots().unwrap()[0].local_root.as_deref(),
+            Some(local_str.as_str())
+        );
+
+        // 内容相同的文件使用同步的索引，内容不同的文件不使用
+        assert_eq!(apply_changes(&sync_dir).unwrap(), 1);
+        let items = indexer
+            .get_file_items(&local_root.join("same.txt"))
+            .unwrap();
+        assert_eq!(items[0].content, "same.txt ocr");
+        assert!(indexer.get_file(&local_root.join("changed.txt")).is_err());
+    }
+}
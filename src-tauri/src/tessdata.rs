@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::dirs::get_tessdata_dir;
+use crate::download::{download_file, Checksum};
+use crate::events::emit;
+
+// 与安装包内置的 tessdata 保持同一来源
+const TESSDATA_CONTENTS_API: &str = "https://api.github.com/repos/tesseract-ocr/tessdata/contents";
+const TRAINEDDATA_EXTENSION: &str = "traineddata";
+pub const DOWNLOAD_PROGRESS_EVENT: &str = "ocr-language-download-progress";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OcrLanguage {
+    pub code: String,
+    pub installed: bool,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrLanguageDownloadProgress {
+    pub code: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubContent {
+    name: String,
+    sha: String,
+    download_url: Option<String>,
+}
+
+fn check_language_code(code: &str) -> Result<()> {
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("Invalid OCR language code: {}", code));
+    }
+    Ok(())
+}
+
+fn traineddata_file_name(code: &str) -> String {
+    format!("{code}.{TRAINEDDATA_EXTENSION}")
+}
+
+/// 安装包内置的 tessdata 目录：可执行文件同级目录，开发时为当前目录
+fn bundled_tessdata_dir() -> Option<PathBuf> {
+    let exe_tessdata = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("tessdata")));
+    [exe_tessdata, Some(PathBuf::from("./tessdata"))]
+        .into_iter()
+        .flatten()
+        .find(|dir| dir.is_dir())
+}
+
+/// 把内置的语言包复制到应用数据目录，已存在的不覆盖
+pub fn install_bundled_languages() -> Result<()> {
+    let Some(bundled_dir) = bundled_tessdata_dir() else {
+        info!("未找到内置 tessdata 目录");
+        return Ok(());
+    };
+    let tessdata_dir = get_tessdata_dir();
+    for entry in fs::read_dir(&bundled_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(TRAINEDDATA_EXTENSION) {
+            continue;
+        }
+        let dest = tessdata_dir.join(path.file_name().context("Invalid traineddata file")?);
+        if !dest.exists() {
+            info!("安装内置语言包: {} -> {}", path.display(), dest.display());
+            // 先复制到临时文件再改名，复制中途退出时不会留下不完整的语言包
+            let mut tmp_name = dest.file_name().unwrap_or_default().to_os_string();
+            tmp_name.push(".tmp");
+            let tmp = dest.with_file_name(tmp_name);
+            fs::copy(&path, &tmp)?;
+            fs::rename(&tmp, &dest)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn get_installed_languages() -> Result<Vec<String>> {
+    let mut languages = Vec::new();
+    for entry in fs::read_dir(get_tessdata_dir())? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(TRAINEDDATA_EXTENSION) {
+            if let Some(code) = path.file_stem().and_then(|s| s.to_str()) {
+                languages.push(code.to_string());
+            }
+        }
+    }
+    languages.sort();
+    Ok(languages)
+}
+
+/// 已安装的语言包及其启用状态
+pub fn get_languages() -> Result<Vec<OcrLanguage>> {
+    let enabled: HashSet<String> = Config::get_ocr_languages()?.into_iter().collect();
+    Ok(get_installed_languages()?
+        .into_iter()
+        .map(|code| OcrLanguage {
+            enabled: enabled.contains(&code),
+            installed: true,
+            code,
+        })
+        .collect())
+}
+
+fn fetch_contents<T>(url: &str) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let value = ureq::get(url)
+        .header("Accept", "application/vnd.github+json")
+        .call()
+        .with_context(|| format!("Failed to request {url}"))?
+        .body_mut()
+        .read_to_string()?;
+    Ok(serde_json::from_str(&value)?)
+}
+
+/// 可下载的语言包列表
+pub fn get_available_languages() -> Result<Vec<String>> {
+    let contents: Vec<GithubContent> = fetch_contents(TESSDATA_CONTENTS_API)?;
+    let suffix = format!(".{TRAINEDDATA_EXTENSION}");
+    let mut languages: Vec<String> = contents
+        .into_iter()
+        .filter_map(|c| c.name.strip_suffix(&suffix).map(|s| s.to_string()))
+        .collect();
+    languages.sort();
+    Ok(languages)
+}
+
+/// 下载语言包到应用数据目录，下载进度通过 DOWNLOAD_PROGRESS_EVENT 推送
+pub fn download_language(code: &str) -> Result<()> {
+    check_language_code(code)?;
+    let file_name = traineddata_file_name(code);
+    let content: GithubContent = fetch_contents(&format!("{TESSDATA_CONTENTS_API}/{file_name}"))?;
+    let url = content
+        .download_url
+        .with_context(|| format!("No download url for OCR language: {code}"))?;
+
+    info!("开始下载语言包: {code}, {url}");
+    download_file(
+        &url,
+        &get_tessdata_dir().join(&file_name),
+        Some(&Checksum::GitBlobSha1(content.sha)),
+        |downloaded, total| {
+            emit(
+                DOWNLOAD_PROGRESS_EVENT,
+                OcrLanguageDownloadProgress {
+                    code: code.to_string(),
+                    downloaded,
+                    total,
+                },
+            )
+        },
+    )
+}
+
+pub fn set_enabled_languages(codes: Vec<String>) -> Result<()> {
+    let installed = get_installed_languages()?;
+    for code in &codes {
+        check_language_code(code)?;
+        if !installed.contains(code) {
+            return Err(anyhow!("OCR language '{}' is not installed", code));
+        }
+    }
+    Config::set_ocr_languages(&codes)
+}
+
+/// 传给 tesseract 的语言参数，如 `eng+chi_sim`
+pub fn get_tesseract_languages() -> Result<String> {
+    let installed = get_installed_languages()?;
+    let languages: Vec<String> = Config::get_ocr_languages()?
+        .into_iter()
+        .filter(|code| installed.contains(code))
+        .collect();
+    if languages.is_empty() {
+        return Err(anyhow!(
+            "No OCR language installed in {}",
+            get_tessdata_dir().display()
+        ));
+    }
+    Ok(languages.join("+"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_check_language_code() {
+        assert!(check_language_code("chi_sim").is_ok());
+        assert!(check_language_code("").is_err());
+        assert!(check_language_code("../eng").is_err());
+    }
+
+    #[test]
+    fn test_install_bundled_languages() {
+        let _env = TestEnv::new();
+        install_bundled_languages().unwrap();
+        assert_eq!(
+            get_installed_languages().unwrap(),
+            vec!["chi_sim".to_string(), "eng".to_string()]
+        );
+        assert_eq!(get_tesseract_languages().unwrap(), "eng+chi_sim");
+    }
+
+    #[test]
+    fn test_set_enabled_languages() {
+        let _env = TestEnv::new();
+        install_bundled_languages().unwrap();
+
+        set_enabled_languages(vec!["eng".into()]).unwrap();
+        assert_eq!(get_tesseract_languages().unwrap(), "eng");
+        let languages = get_languages().unwrap();
+        assert_eq!(
+            languages,
+            vec![
+                OcrLanguage {
+                    code: "chi_sim".into(),
+                    installed: true,
+                    enabled: false,
+                },
+                OcrLanguage {
+                    code: "eng".into(),
+                    installed: true,
+                    enabled: true,
+                },
+            ]
+        );
+
+        assert!(set_enabled_languages(vec!["fra".into()]).is_err());
+    }
+}
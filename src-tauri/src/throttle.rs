@@ -0,0 +1,243 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, LowImpact};
+use crate::power;
+
+// 最近一次搜索后这段时间内认为用户正在使用
+const USER_ACTIVE_WINDOW: Duration = Duration::from_secs(60);
+
+static LAST_ACTIVITY: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// 所有索引线程共用的读取令牌桶，每秒补充限速的字节数，最多攒下一秒的量
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+static IO_BUCKET: Mutex<Option<TokenBucket>> = Mutex::new(None);
+
+thread_local! {
+    // 当前索引线程是否已经降低了优先级
+    static LOWERED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 记录一次用户操作，自动模式下之后一段时间内使用低负载模式
+pub fn record_user_activity() {
+    if let Ok(mut last) = LAST_ACTIVITY.lock() {
+        *last = Some(Instant::now());
+    }
+}
+
+/// 最近是否有搜索等用户操作
+pub fn user_active() -> bool {
+    LAST_ACTIVITY
+        .lock()
+        .ok()
+        .and_then(|last| *last)
+        .is_some_and(|last| last.elapsed() < USER_ACTIVE_WINDOW)
+}
+
+/// 电量低时也使用低负载模式
+fn engaged(config: &LowImpact) -> Result<bool> {
+    Ok(config.enabled || (config.auto && user_active()) || power::battery_action()?.is_some())
+}
+
+/// 当前是否使用低负载模式
+pub fn is_engaged() -> Result<bool> {
+    engaged(&Config::get_low_impact()?)
+}
+
+/// 处理完一个文件后休息的时间，读取速度超过上限时按上限补足
+fn pause_for(config: &LowImpact, bytes: u64) -> Duration {
+    let sleep = Duration::from_millis(config.sleep_ms);
+    if config.max_read_mb_per_sec == 0 {
+        return sleep;
+    }
+    let limit = (config.max_read_mb_per_sec * 1024 * 1024) as f64;
+    sleep.max(Duration::from_secs_f64(bytes as f64 / limit))
+}
+
+/// 按是否使用低负载模式调整当前索引线程的优先级，每个任务开始前调用
+pub fn adjust_thread_priority() -> Result<()> {
+    let engaged = is_engaged()?;
+    if LOWERED.with(|lowered| lowered.get()) == engaged {
+        return Ok(());
+    }
+    match set_thread_background(engaged) {
+        Ok(()) => info!(
+            "索引线程{}低负载模式",
+            if engaged { "进入" } else { "退出" }
+        ),
+        Err(e) => warn!("调整索引线程优先级失败: {e}"),
+    }
+    // 失败时也记下状态，避免每个任务都重试
+    LOWERED.with(|lowered| lowered.set(engaged));
+    Ok(())
+}
+
+/// 从令牌桶中取出读取的字节数，返回需要等待的时间。
+/// 令牌可以透支，大文件读完后等待补足，多个线程同时透支时等待时间依次变长
+fn take_tokens(bucket: &mut TokenBucket, rate: f64, bytes: u64, now: Instant) -> Duration {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+    bucket.updated = now;
+    bucket.tokens -= bytes as f64;
+    if bucket.tokens >= 0.0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(-bucket.tokens / rate)
+    }
+}
+
+/// 按全局读取限速等待，读取文件内容以外的读取（如计算哈希）也要计入
+pub fn limit_io(bytes: u64) -> Result<()> {
+    let limit_mb = Config::get_io_rate_limit_mb()?;
+    if limit_mb == 0 {
+        return Ok(());
+    }
+    let rate = (limit_mb * 1024 * 1024) as f64;
+    let wait = {
+        let mut bucket = match IO_BUCKET.lock() {
+            Ok(bucket) => bucket,
+            Err(e) => e.into_inner(),
+        };
+        let now = Instant::now();
+        let bucket = bucket.get_or_insert(TokenBucket {
+            tokens: rate,
+            updated: now,
+        });
+        take_tokens(bucket, rate, bytes, now)
+    };
+    thread::sleep(wait);
+    Ok(())
+}
+
+/// 处理完一个文件后按全局读取限速等待，低负载模式下再休息一会
+pub fn after_file(path: &Path) -> Result<()> {
+    let bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    limit_io(bytes)?;
+    let config = Config::get_low_impact()?;
+    if !engaged(&config)? {
+        return Ok(());
+    }
+    thread::sleep(pause_for(&config, bytes));
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_thread_background(background: bool) -> Result<()> {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_MODE_BACKGROUND_BEGIN,
+        THREAD_MODE_BACKGROUND_END,
+    };
+    // 后台模式同时降低 CPU、磁盘和内存优先级
+    let mode = if background {
+        THREAD_MODE_BACKGROUND_BEGIN
+    } else {
+        THREAD_MODE_BACKGROUND_END
+    };
+    if unsafe { SetThreadPriority(GetCurrentThread(), mode) } == 0 {
+        bail!("{}", std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn set_thread_background(background: bool) -> Result<()> {
+    // 后台优先级同时限制磁盘读写
+    let prio = if background { libc::PRIO_DARWIN_BG } else { 0 };
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_THREAD, 0, prio) } != 0 {
+        bail!("{}", std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_background(background: bool) -> Result<()> {
+    // Linux 下 nice 值按线程生效，没有权限时不能调回原来的优先级
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    let nice = if background { 10 } else { 0 };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+        bail!("{}", std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn set_thread_background(_background: bool) -> Result<()> {
+    bail!("当前系统不支持调整线程优先级")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_low_impact() {
+        let _env = TestEnv::new();
+        let mut config = Config::get_low_impact().unwrap();
+        assert!(!config.enabled);
+
+        // 没有搜索时自动模式不生效
+        *LAST_ACTIVITY.lock().unwrap() = None;
+        assert!(!is_engaged().unwrap());
+        record_user_activity();
+        assert!(is_engaged().unwrap());
+
+        config.auto = false;
+        Config::set_low_impact(&config).unwrap();
+        assert!(!is_engaged().unwrap());
+        config.enabled = true;
+        Config::set_low_impact(&config).unwrap();
+        assert!(is_engaged().unwrap());
+
+        config.sleep_ms = 100;
+        config.max_read_mb_per_sec = 10;
+        assert_eq!(pause_for(&config, 1024), Duration::from_millis(100));
+        assert_eq!(pause_for(&config, 20 * 1024 * 1024), Duration::from_secs(2));
+        config.max_read_mb_per_sec = 0;
+        assert_eq!(
+            pause_for(&config, 20 * 1024 * 1024),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_take_tokens() {
+        let rate = 10.0 * 1024.0 * 1024.0;
+        let start = Instant::now();
+        let mut bucket = TokenBucket {
+            tokens: rate,
+            updated: start,
+        };
+        // 攒下的一秒额度内不用等待
+        assert_eq!(
+            take_tokens(&mut bucket, rate, 4 * 1024 * 1024, start),
+            Duration::ZERO
+        );
+        // 透支的部分按速率等待，其他线程接着透支时等待更久
+        assert_eq!(
+            take_tokens(&mut bucket, rate, 11 * 1024 * 1024, start),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            take_tokens(&mut bucket, rate, 10 * 1024 * 1024, start),
+            Duration::from_millis(1500)
+        );
+        // 时间过去后补充令牌，最多补满一秒的量
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            take_tokens(&mut bucket, rate, 10 * 1024 * 1024, later),
+            Duration::ZERO
+        );
+        assert_eq!(bucket.tokens, 0.0);
+    }
+}
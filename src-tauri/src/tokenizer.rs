@@ -0,0 +1,56 @@
+use jieba_rs::Jieba;
+use once_cell::sync::Lazy;
+
+// 加载词典需要几百毫秒，全局只加载一次
+static JIEBA: Lazy<Jieba> = Lazy::new(Jieba::new);
+
+fn is_word(word: &str) -> bool {
+    word.chars().any(|c| c.is_alphanumeric())
+}
+
+/// 建索引用的分词：搜索引擎模式，长词会额外切出其中的短词，提高召回
+pub fn cut_for_index(text: &str) -> Vec<String> {
+    JIEBA
+        .cut_for_search(text, true)
+        .into_iter()
+        .filter(|w| is_word(w))
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// 查询用的分词：精确模式
+pub fn cut_for_query(text: &str) -> Vec<String> {
+    JIEBA
+        .cut(text, true)
+        .into_iter()
+        .filter(|w| is_word(w))
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// 分词后用空格连接，写入 items_words 后由 unicode61 分词器按空格切开
+pub fn segment(text: &str) -> String {
+    cut_for_index(text).join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cut_for_query() {
+        assert_eq!(
+            cut_for_query("我们的季度报告, Hello!"),
+            vec!["我们", "的", "季度", "报告", "hello"]
+        );
+    }
+
+    #[test]
+    fn test_segment() {
+        let segmented = segment("中华人民共和国的季度报告");
+        let words: Vec<&str> = segmented.split(' ').collect();
+        assert!(words.contains(&"中华人民共和国"));
+        assert!(words.contains(&"共和国"));
+        assert!(words.contains(&"报告"));
+    }
+}
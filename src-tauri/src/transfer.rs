@@ -0,0 +1,259 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use log::info;
+use rusqlite::{params, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::config::Config;
+use crate::dirs::get_index_dir;
+use crate::encryption;
+use crate::sqlite::{get_conn, is_upgradable, lock_whole_db, DB_FILE};
+use crate::sync;
+
+// 导出文件的格式版本，格式不兼容时增加
+const FORMAT_VERSION: u32 = 1;
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DB_ENTRY: &str = "index.db";
+
+// 本次启动替换成了导入的数据库，打开数据库后清除上一台机器的状态
+static IMPORTED: AtomicBool = AtomicBool::new(false);
+
+/// 导出文件中的说明
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportManifest {
+    pub format: u32,
+    pub db_version: String,
+    pub exported_at: String,
+    /// 导出时的索引目录，导入后路径不存在的目录按离线处理
+    pub roots: Vec<String>,
+}
+
+/// 等待下次启动时替换的数据库
+fn pending_import_path() -> PathBuf {
+    get_index_dir().join(format!("{DB_FILE}.import"))
+}
+
+pub fn has_pending_import() -> bool {
+    pending_import_path().exists()
+}
+
+/// 导出索引到 zip 文件。数据库用 VACUUM INTO 生成一致的快照，导出期间可以继续索引。
+/// 加密的索引导出后仍然用原来的密码加密
+pub fn export_index(path: &Path) -> Result<ExportManifest> {
+    let temp_dir = tempfile::tempdir_in(get_index_dir())?;
+    let snapshot = temp_dir.path().join(DB_FILE);
+    let snapshot_str = snapshot
+        .to_str()
+        .with_context(|| format!("路径不是有效的 UTF-8: {}", snapshot.display()))?;
+    info!("生成数据库快照: {}", snapshot.display());
+    // 快照用当前的密码加密，生成和检查期间不能修改密码
+    let whole_db = lock_whole_db();
+    get_conn()?.execute("VACUUM INTO ?1", params![snapshot_str])?;
+    let db_version: String = encryption::open(&snapshot, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        .query_row("SELECT version FROM db_version", [], |row| row.get(0))?;
+    drop(whole_db);
+    let manifest = ExportManifest {
+        format: FORMAT_VERSION,
+        db_version,
+        exported_at: Local::now().to_rfc3339(),
+        roots: Config::get_index_dir_paths()?,
+    };
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    zip.start_file(MANIFEST_ENTRY, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.start_file(DB_ENTRY, options)?;
+    io::copy(&mut File::open(&snapshot)?, &mut zip)?;
+    zip.finish()?;
+    info!("导出索引完成: {}, {manifest:?}", path.display());
+    Ok(manifest)
+}
+
+/// 检查导出的文件并解压数据库，下次启动时替换当前的索引。
+/// 正在使用的数据库不能直接替换，后台线程和连接池都持有连接
+pub fn import_index(path: &Path) -> Result<ExportManifest> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let manifest: ExportManifest = {
+        let mut content = String::new();
+        zip.by_name(MANIFEST_ENTRY)
+            .map_err(|_| anyhow!("不是导出的索引文件: {}", path.display()))?
+            .read_to_string(&mut content)?;
+        serde_json::from_str(&content)?
+    };
+    if manifest.format != FORMAT_VERSION {
+        bail!("不支持的导出格式版本: {}", manifest.format);
+    }
+    if !is_upgradable(&manifest.db_version) {
+        bail!(
+            "数据库版本 {} 无法在当前程序中使用，可能是更新版本的程序导出的",
+            manifest.db_version
+        );
+    }
+
+    stage_database(&mut zip.by_name(DB_ENTRY)?, Some(&manifest.db_version))?;
+    info!("导入的索引已准备好，重启后生效: {manifest:?}");
+    Ok(manifest)
+}
+
+/// 检查数据库能否用当前的密码打开、版本能否升级，并通过 quick_check，返回数据库版本
+pub fn check_database(path: &Path) -> Result<String> {
+    let conn = encryption::open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let version: String = conn
+        .query_row("SELECT version FROM db_version", [], |row| row.get(0))
+        .context("无法读取数据库，加密的索引只能使用相同密码的数据库")?;
+    if !is_upgradable(&version) {
+        bail!("数据库版本 {version} 无法在当前程序中使用，可能是更新版本的程序创建的");
+    }
+    let result: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if result != "ok" {
+        bail!("数据库检查不通过: {result}");
+    }
+    Ok(version)
+}
+
+/// 把数据库复制到索引目录，检查通过后下次启动时替换当前的索引，不通过时删除复制的文件。
+/// expected_version 为说明中记录的版本，和数据库中的不一致时不替换
+pub fn stage_database(source: &mut impl Read, expected_version: Option<&str>) -> Result<String> {
+    if encryption::pending_rekey_path().exists() {
+        bail!("正在修改索引密码，请稍后再试");
+    }
+    let temp_path = get_index_dir().join(format!("{DB_FILE}.import.tmp"));
+    io::copy(source, &mut File::create(&temp_path)?)?;
+    let checked = check_database(&temp_path).and_then(|version| match expected_version {
+        Some(expected) if expected != version => {
+            bail!("数据库版本 {version} 和说明中的 {expected} 不一致")
+        }
+        _ => Ok(version),
+    });
+    match checked {
+        Ok(version) => {
+            fs::rename(&temp_path, pending_import_path())?;
+            Ok(version)
+        }
+        Err(e) => {
+            fs::remove_file(&temp_path)?;
+            Err(e)
+        }
+    }
+}
+
+/// 启动时在打开数据库之前调用，有等待导入的数据库时替换当前的数据库。
+/// 原来的数据库连同 WAL 文件改名为 .bak 保留
+pub fn apply_pending_import() -> Result<bool> {
+    let pending = pending_import_path();
+    if !pending.exists() {
+        return Ok(false);
+    }
+    let dir = get_index_dir();
+    for suffix in ["", "-wal", "-shm"] {
+        let current = dir.join(format!("{DB_FILE}{suffix}"));
+        let backup = dir.join(format!("{DB_FILE}.bak{suffix}"));
+        if backup.exists() {
+            fs::remove_file(&backup)?;
+        }
+        if current.exists() {
+            fs::rename(&current, &backup)?;
+        }
+    }
+    fs::rename(&pending, dir.join(DB_FILE))?;
+    IMPORTED.store(true, Ordering::SeqCst);
+    info!("已替换为导入的索引，原来的索引保存为 {DB_FILE}.bak");
+    Ok(true)
+}
+
+/// 打开数据库后调用，本次启动导入了索引时清除上一台机器的状态
+pub fn finish_import() -> Result<()> {
+    if IMPORTED.swap(false, Ordering::SeqCst) {
+        reset_machine_state()?;
+    }
+    Ok(())
+}
+
+/// 导入后清除上一台机器的检查时间和系统变化日志位置，启动检查按修改时间比较所有文件，
+/// 修改时间没变的文件不重新读取。同步使用新的机器 ID，不再沿用上一台机器的同步进度
+pub fn reset_machine_state() -> Result<()> {
+    for root in Config::get_index_dir_paths()? {
+        Config::set_reconciled_at(&root, None)?;
+        Config::set_journal_cursor(&root, None)?;
+    }
+    sync::reset_sync_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::reader::Item;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_export_and_import_index() {
+        let env = TestEnv::new();
+        let root = env.temp_dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        let root_str = root.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root_str.clone()]).unwrap();
+        Config::set_reconciled_at(&root_str, Some(1)).unwrap();
+        Config::set_sync_machine_id("exporter").unwrap();
+        Config::set_sync_exported_seq(1).unwrap();
+        Config::set_sync_applied_seqs(&HashMap::from([("other".to_string(), 3)])).unwrap();
+        let items = vec![Item {
+            content: "识别很慢的内容".into(),
+            ..Default::default()
+        }];
+        Indexer::new()
+            .unwrap()
+            .write_file_items(&root.join("a.txt"), items)
+            .unwrap();
+
+        let archive = env.temp_dir.path().join("index.zip");
+        let manifest = export_index(&archive).unwrap();
+        assert_eq!(manifest.roots, vec![root_str.clone()]);
+
+        assert!(!apply_pending_import().unwrap());
+        assert_eq!(import_index(&archive).unwrap(), manifest);
+        let conn =
+            Connection::open_with_flags(pending_import_path(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .unwrap();
+        let content: String = conn
+            .query_row("SELECT content FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(content, "识别很慢的内容");
+        drop(conn);
+
+        reset_machine_state().unwrap();
+        assert!(Config::get_reconciled_at().unwrap().is_empty());
+        let machine_id = Config::get_sync_machine_id().unwrap().unwrap();
+        assert_ne!(machine_id, "exporter");
+        assert_eq!(Config::get_sync_exported_seq().unwrap(), 0);
+        assert!(Config::get_sync_applied_seqs().unwrap().is_empty());
+
+        // 不认识的数据库版本不导入
+        let newer = env.temp_dir.path().join("newer.zip");
+        let mut zip = ZipWriter::new(File::create(&newer).unwrap());
+        zip.start_file(MANIFEST_ENTRY, SimpleFileOptions::default())
+            .unwrap();
+        let newer_manifest = ExportManifest {
+            db_version: "99.0".into(),
+            ..manifest
+        };
+        zip.write_all(&serde_json::to_vec(&newer_manifest).unwrap())
+            .unwrap();
+        zip.finish().unwrap();
+        assert!(import_index(&newer).is_err());
+        assert!(import_index(&root.join("a.txt")).is_err());
+    }
+}
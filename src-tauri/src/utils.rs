@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
 pub fn path_to_str(path: &Path) -> Result<&str> {
     path.to_str()
@@ -13,6 +14,17 @@ pub fn filename_to_str(path: &Path) -> Result<&str> {
         .with_context(|| format!("Failed to convert filename to string: {}", path.display()))
 }
 
+/// 路径下各级子路径的范围 `[lower, upper)`。子路径都以 `路径 + 分隔符` 开头，
+/// 按字符串比较都在 `路径 + 分隔符` 和 `路径 + 分隔符的下一个字符` 之间，按范围查询可以使用 path 列的索引。
+/// 驱动器根目录 `C:\` 等以分隔符结尾的路径先去掉结尾的分隔符
+pub fn subpath_range(path: &str) -> (String, String) {
+    let base = path.strip_suffix(MAIN_SEPARATOR).unwrap_or(path);
+    (
+        format!("{base}{MAIN_SEPARATOR}"),
+        format!("{base}{}", (MAIN_SEPARATOR as u8 + 1) as char),
+    )
+}
+
 pub fn parent_to_str(path: &Path) -> Result<&str> {
     path.parent()
         .with_context(|| {
@@ -29,3 +41,111 @@ pub fn parent_to_str(path: &Path) -> Result<&str> {
             )
         })
 }
+
+/// 统一路径的大小写。Windows 的文件系统不区分大小写，监听事件、读取目录和用户输入的路径大小写可能不同，
+/// 同一个目录会在索引中保存成多行，按磁盘上实际的名称重新拼接，不存在的部分保持原样
+#[cfg(windows)]
+pub fn normalize_path(path: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Component, Prefix};
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{FindClose, FindFirstFileW, WIN32_FIND_DATAW};
+
+    let mut normalized = PathBuf::new();
+    let mut exists = true;
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(letter) => {
+                    normalized.push(format!("{}:", letter.to_ascii_uppercase() as char))
+                }
+                _ => normalized.push(prefix.as_os_str()),
+            },
+            Component::Normal(name) if exists => {
+                // 通配符不是合法的文件名，不能交给 FindFirstFileW
+                let wildcard = name
+                    .encode_wide()
+                    .any(|c| c == '*' as u16 || c == '?' as u16);
+                let child = normalized.join(name);
+                let wide: Vec<u16> = child.as_os_str().encode_wide().chain(Some(0)).collect();
+                // SAFETY: wide 以 0 结尾，data 在调用期间有效，句柄用完后关闭
+                let found = (!wildcard).then(|| unsafe {
+                    let mut data: WIN32_FIND_DATAW = std::mem::zeroed();
+                    let handle = FindFirstFileW(wide.as_ptr(), &mut data);
+                    if handle == INVALID_HANDLE_VALUE {
+                        return None;
+                    }
+                    FindClose(handle);
+                    let len = data
+                        .cFileName
+                        .iter()
+                        .position(|&c| c == 0)
+                        .unwrap_or(data.cFileName.len());
+                    Some(OsString::from_wide(&data.cFileName[..len]))
+                });
+                match found.flatten() {
+                    Some(actual) => normalized.push(actual),
+                    None => {
+                        exists = false;
+                        normalized.push(name);
+                    }
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// 其他系统的文件系统一般区分大小写，大小写保持原样，只统一 macOS 上的 Unicode 形式
+#[cfg(not(windows))]
+pub fn normalize_path(path: &Path) -> PathBuf {
+    nfc_path(path)
+}
+
+/// 统一成 NFC。macOS 返回的文件名是 NFD，用户输入的查询一般是 NFC，形式不同时看起来一样的字符串也匹配不上
+pub fn to_nfc(text: &str) -> String {
+    if is_nfc(text) {
+        text.to_string()
+    } else {
+        text.nfc().collect()
+    }
+}
+
+/// macOS 的文件系统不区分 NFC 和 NFD，路径统一成 NFC 保存，用 NFC 的路径也能访问文件
+#[cfg(target_os = "macos")]
+pub fn nfc_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path) => PathBuf::from(to_nfc(path)),
+        None => path.to_path_buf(),
+    }
+}
+
+/// 其他系统按原样比较文件名，换了形式就找不到文件，路径保持原样
+#[cfg(not(target_os = "macos"))]
+pub fn nfc_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subpath_range() {
+        let dir = format!("{MAIN_SEPARATOR}data");
+        let (lower, upper) = subpath_range(&dir);
+        let child = format!("{dir}{MAIN_SEPARATOR}a{MAIN_SEPARATOR}b.txt");
+        assert!(lower <= child && child < upper);
+        // 名称相同前缀的同级目录不在范围内
+        let sibling = format!("{dir}-old{MAIN_SEPARATOR}b.txt");
+        assert!(!(lower <= sibling && sibling < upper));
+
+        // 以分隔符结尾的根目录
+        let (lower, upper) = subpath_range(std::path::MAIN_SEPARATOR_STR);
+        assert!(lower <= dir && dir < upper);
+        let (lower, upper) = subpath_range(&format!("{dir}{MAIN_SEPARATOR}"));
+        assert!(lower <= child && child < upper);
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::monitor::{add_watched_path, del_watched_path};
+use crate::roots::nearest_root;
+use crate::share;
+use crate::worker::Worker;
+
+// 检查索引目录所在的磁盘是否接入的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// 离线的索引目录，暂停监听，重新接入后恢复
+static OFFLINE_ROOTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// 路径所在的磁盘是否离线：共享目录没有连接，或者移动硬盘拔出后索引目录不存在。
+/// 离线时其下的文件不能当作已删除处理
+pub fn is_offline(path: &Path) -> Result<bool> {
+    if share::is_offline(path)? {
+        return Ok(true);
+    }
+    let roots = Config::get_index_dir_paths()?;
+    Ok(nearest_root(&roots, path).is_some_and(|root| !Path::new(root).exists()))
+}
+
+/// 检查各索引目录是否在线，返回状态变化的目录和现在是否在线
+fn check_roots() -> Result<Vec<(String, bool)>> {
+    let mut changes = Vec::new();
+    for root in Config::get_index_dir_paths()? {
+        let path = Path::new(&root);
+        if let Some(share) = share::find_share_root(path)? {
+            // 共享目录断开后自动重新连接
+            share::connect(&share)?;
+        }
+        let offline = is_offline(path)?;
+        let mut offline_roots = OFFLINE_ROOTS.lock().unwrap_or_else(PoisonError::into_inner);
+        let changed = if offline {
+            offline_roots.insert(root.clone())
+        } else {
+            offline_roots.remove(&root)
+        };
+        if changed {
+            changes.push((root, !offline));
+        }
+    }
+    Ok(changes)
+}
+
+/// 离线时暂停监听，重新接入后恢复监听，并扫描离线期间的变化
+fn apply_change(worker: &Worker, root: &str, online: bool) -> Result<()> {
+    let path = Path::new(root);
+    if online {
+        info!("索引目录重新接入，恢复监听并检查变化: {root}");
+        add_watched_path(path)?;
+        worker.submit_index_all_files(path)?;
+    } else {
+        warn!("索引目录离线，暂停监听: {root}");
+        if let Err(e) = del_watched_path(path) {
+            debug!("取消监听离线目录失败: {root}, {e:?}");
+        }
+    }
+    Ok(())
+}
+
+pub fn start_process() -> Result<()> {
+    thread::Builder::new()
+        .name("volume-watch".to_string())
+        .spawn(|| {
+            let worker = Worker::new().unwrap();
+            loop {
+                match check_roots() {
+                    Ok(changes) => {
+                        for (root, online) in changes {
+                            if let Err(e) = apply_change(&worker, &root, online) {
+                                error!("处理索引目录接入状态失败: {root}, {e:?}");
+                            }
+                        }
+                    }
+                    Err(e) => error!("检查索引目录是否在线失败: {e:?}"),
+                }
+                thread::sleep(CHECK_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+
+    #[test]
+    fn test_offline_root() {
+        let env = TestEnv::new();
+        let usb = env.temp_dir.path().join("usb");
+        fs::create_dir_all(&usb).unwrap();
+        let root = usb.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root.clone()]).unwrap();
+        assert!(!is_offline(&usb.join("a.txt")).unwrap());
+        assert!(check_roots().unwrap().is_empty());
+
+        // 拔出后索引目录不存在
+        let unplugged = env.temp_dir.path().join("unplugged");
+        fs::rename(&usb, &unplugged).unwrap();
+        assert!(is_offline(&usb.join("a.txt")).unwrap());
+        assert!(!is_offline(&unplugged).unwrap());
+        assert_eq!(check_roots().unwrap(), vec![(root.clone(), false)]);
+        assert!(check_roots().unwrap().is_empty());
+
+        fs::rename(&unplugged, &usb).unwrap();
+        assert_eq!(check_roots().unwrap(), vec![(root, true)]);
+    }
+}
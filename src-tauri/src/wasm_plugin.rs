@@ -0,0 +1,170 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+// 每个文件的线性内存上限
+const WASM_MAX_MEMORY: usize = 512 * 1024 * 1024;
+// 超时按 epoch 计算，每隔这么久加一
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+
+static ENGINE: OnceCell<Engine> = OnceCell::new();
+
+/// 所有 WASM 插件共用一个 Engine，由一个后台线程推进 epoch 实现超时
+fn get_engine() -> Result<&'static Engine> {
+    ENGINE.get_or_try_init(|| {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        let ticker = engine.clone();
+        thread::Builder::new()
+            .name("wasm-epoch".to_string())
+            .spawn(move || loop {
+                thread::sleep(EPOCH_TICK);
+                ticker.increment_epoch();
+            })?;
+        Ok(engine)
+    })
+}
+
+/// WASM 模块形式的插件，在沙箱中运行：不提供任何导入函数，不能访问文件和网络，
+/// 内存和运行时间都有上限。每个文件使用新的实例，上一个文件的状态不会留下来。
+///
+/// 模块需要导出：
+/// - `memory`：线性内存
+/// - `alloc(len: i32) -> i32`：分配 len 字节，返回地址，宿主在这里写入文件内容
+/// - `read(ptr: i32, len: i32) -> i64`：读取文件内容，返回输出的地址和长度 `(ptr << 32) | len`，
+///   输出格式与命令行插件相同，每行一个 JSON 对象
+pub struct WasmPlugin {
+    path: PathBuf,
+    // 第一次读取文件时才编译，只加载插件列表时不需要
+    module: OnceCell<Module>,
+}
+
+impl WasmPlugin {
+    pub fn new(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            bail!("WASM 模块不存在: {}", path.display());
+        }
+        Ok(WasmPlugin {
+            path: path.to_path_buf(),
+            module: OnceCell::new(),
+        })
+    }
+
+    fn module(&self) -> Result<&Module> {
+        self.module.get_or_try_init(|| {
+            info!("编译 WASM 插件: {}", self.path.display());
+            Ok(Module::from_file(get_engine()?, &self.path)?)
+        })
+    }
+
+    /// 运行插件读取 data，返回插件的输出
+    pub fn run(&self, data: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let module = self.module()?;
+        if module.imports().len() > 0 {
+            bail!("WASM 插件不能导入外部函数");
+        }
+        let len = i32::try_from(data.len()).context("文件太大，WASM 插件无法读取")?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(WASM_MAX_MEMORY)
+            .instances(1)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+        let ticks = (timeout.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64;
+        store.set_epoch_deadline(ticks);
+
+        let result = (|| -> Result<Vec<u8>> {
+            let instance = Instance::new(&mut store, module, &[])?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .context("WASM 插件没有导出 memory")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let read = instance.get_typed_func::<(i32, i32), i64>(&mut store, "read")?;
+
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as u32 as usize, data)?;
+            let packed = read.call(&mut store, (ptr, len))? as u64;
+
+            let (start, len) = ((packed >> 32) as usize, (packed & 0xFFFF_FFFF) as usize);
+            memory
+                .data(&store)
+                .get(start..start + len)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| anyhow!("WASM 插件返回的输出超出内存范围"))
+        })();
+
+        result.map_err(|e| match e.downcast_ref::<Trap>() {
+            Some(Trap::Interrupt) => anyhow!("WASM 插件超时: {} 秒", timeout.as_secs()),
+            _ => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::test_mod::TestEnv;
+    use std::fs;
+
+    // 把输入原样作为输出
+    const ECHO_WAT: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32) (i32.const 1024))
+  (func (export "read") (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len)))))"#;
+
+    fn write_module(env: &TestEnv, name: &str, wat: &str) -> WasmPlugin {
+        let path = env.temp_dir.path().join(name);
+        fs::write(&path, wat).unwrap();
+        WasmPlugin::new(&path).unwrap()
+    }
+
+    #[test]
+    fn test_wasm_plugin() {
+        let env = TestEnv::new();
+        let timeout = Duration::from_secs(1);
+        let echo = write_module(&env, "echo.wat", ECHO_WAT);
+        assert_eq!(echo.run(b"hello", timeout).unwrap(), b"hello");
+
+        let endless = write_module(
+            &env,
+            "endless.wat",
+            r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 0))
+  (func (export "read") (param i32 i32) (result i64) (loop $l (br $l)) (i64.const 0)))"#,
+        );
+        assert!(endless
+            .run(b"", timeout)
+            .unwrap_err()
+            .to_string()
+            .contains("超时"));
+
+        let import = write_module(
+            &env,
+            "import.wat",
+            r#"(module (import "env" "open" (func)) (memory (export "memory") 1))"#,
+        );
+        assert!(import.run(b"", timeout).is_err());
+
+        let greedy = write_module(
+            &env,
+            "greedy.wat",
+            r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (drop (memory.grow (i32.const 65536))) (i32.const 0))
+  (func (export "read") (param i32 i32) (result i64) (i64.const 0x10001)))"#,
+        );
+        // 超过内存上限时 memory.grow 失败，输出的地址也就超出了内存范围
+        assert!(greedy.run(b"", timeout).is_err());
+        assert!(WasmPlugin::new(&env.temp_dir.path().join("missing.wasm")).is_err());
+    }
+}
@@ -4,34 +4,267 @@ use chrono::Local;
 use log::debug;
 use log::error;
 use log::info;
+use log::warn;
 use once_cell::sync::OnceCell;
-use rusqlite::params;
-use serde::Serialize;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Mutex;
-use std::thread;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 use strum::Display;
 use strum::EnumString;
-
-use crate::indexer::Indexer;
-use crate::reader::CompositeReader;
-use crate::sqlite::get_conn;
+use thiserror::Error;
+
+use crate::chunk::chunk_items;
+use crate::config::{BatteryAction, Config};
+use crate::download::sha256_file_until;
+use crate::ignore::{self, IgnoreRules};
+use crate::index_events;
+use crate::indexer::{file_extension, ChangeOp, FileWrite, Indexer, SearchPage};
+use crate::password::ProtectedError;
+use crate::power::{self, OcrDeferred};
+use crate::progress;
+use crate::reader::{set_ocr_allowed, CompositeReader, Item, OcrRequired, OCR_EXTENSIONS};
+use crate::roots::{self, find_root_id};
+use crate::sqlite::{get_conn, rebuild_index_tables};
+use crate::throttle;
+use crate::utils::{filename_to_str, nfc_path, normalize_path, path_to_str, subpath_range};
+use crate::volume;
 
 static WORKER_LOCK: OnceCell<Mutex<()>> = OnceCell::new();
 
+// 暂停时索引线程和向量线程不再取新的任务，正在处理的任务会处理完。重启后恢复
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
 fn get_worker_lock() -> &'static Mutex<()> {
     WORKER_LOCK.get_or_init(|| Mutex::new(()))
 }
 
+pub fn set_paused(paused: bool) {
+    info!("{}索引", if paused { "暂停" } else { "继续" });
+    PAUSED.store(paused, Ordering::SeqCst);
+    notify_tasks();
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+// 新任务的通知，空闲的索引线程等待通知而不是轮询。
+// 通知时计数加一，线程取任务前记下计数，避免取任务和开始等待之间漏掉通知
+static TASK_SIGNAL: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
+// 有等待重试的任务时最长等待时间，期间可能接通电源需要恢复推迟的任务
+const IDLE_RECHECK: Duration = Duration::from_secs(60);
+
+fn task_signal_count() -> u64 {
+    *TASK_SIGNAL.0.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 唤醒空闲的索引线程
+pub fn notify_tasks() {
+    let (count, signal) = &TASK_SIGNAL;
+    *count.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+    signal.notify_all();
+}
+
+/// 等待 seen 之后的通知，timeout 为 None 时一直等待
+fn wait_for_tasks(seen: u64, timeout: Option<Duration>) {
+    let (count, signal) = &TASK_SIGNAL;
+    let count = count.lock().unwrap_or_else(PoisonError::into_inner);
+    match timeout {
+        Some(timeout) => drop(
+            signal
+                .wait_timeout_while(count, timeout, |count| *count == seen)
+                .unwrap_or_else(PoisonError::into_inner),
+        ),
+        None => drop(
+            signal
+                .wait_while(count, |count| *count == seen)
+                .unwrap_or_else(PoisonError::into_inner),
+        ),
+    }
+}
+
+// 重建索引前等待正在处理的任务结束的最长时间
+const REBUILD_WAIT: Duration = Duration::from_secs(30);
+
+// 退出时通知索引线程处理完当前任务后停止
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+// 后台索引线程，退出时等待它们停止
+static WORKER_THREADS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+// 退出时等待索引线程停止的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 已经取消、但还在处理中的任务
+static CANCELLED_TASKS: OnceCell<Mutex<HashSet<i64>>> = OnceCell::new();
+
+thread_local! {
+    // 当前线程正在处理的任务，读取文件的代码通过它检查任务是否已经取消
+    static CURRENT_TASK: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+fn get_cancelled_tasks() -> &'static Mutex<HashSet<i64>> {
+    CANCELLED_TASKS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+// 读取超时后放弃、但读取线程还没有结束的任务
+static ABANDONED_TASKS: Mutex<Option<HashSet<i64>>> = Mutex::new(None);
+
+fn set_abandoned(id: i64, abandoned: bool) {
+    let mut tasks = ABANDONED_TASKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let tasks = tasks.get_or_insert_with(HashSet::new);
+    if abandoned {
+        tasks.insert(id);
+    } else {
+        tasks.remove(&id);
+    }
+}
+
+/// 当前线程正在处理的任务是否已经取消，耗时的读取（如外部命令）可以提前结束
+pub fn is_current_task_cancelled() -> bool {
+    CURRENT_TASK.with(Cell::get).is_some_and(|id| {
+        get_cancelled_tasks()
+            .lock()
+            .is_ok_and(|cancelled| cancelled.contains(&id))
+            || ABANDONED_TASKS
+                .lock()
+                .is_ok_and(|tasks| tasks.as_ref().is_some_and(|tasks| tasks.contains(&id)))
+    })
+}
+
+/// 读取文件超过了任务超时时间
+#[derive(Debug, Error)]
+#[error("timeout: 读取超过 {0} 秒")]
+pub struct TaskTimeout(pub u64);
+
+type ReadJob = Box<dyn FnOnce() -> Result<FileRead> + Send>;
+
+/// 读取文件的线程。读取卡住时无法强行结束，超时后放弃这个线程，之后的读取使用新的线程。
+/// 同一个线程处理多个文件，线程内缓存的 OCR 引擎等可以重复使用
+struct ReadThread {
+    jobs: mpsc::Sender<(i64, ReadJob)>,
+    results: mpsc::Receiver<Result<FileRead>>,
+}
+
+impl ReadThread {
+    fn spawn(name: &str) -> Result<ReadThread> {
+        let (jobs, job_receiver) = mpsc::channel::<(i64, ReadJob)>();
+        let (result_sender, results) = mpsc::channel();
+        thread::Builder::new()
+            .name(format!("{name}-reader"))
+            .spawn(move || {
+                for (id, job) in job_receiver {
+                    if let Err(e) = throttle::adjust_thread_priority() {
+                        debug!("调整读取线程优先级失败: {e:?}");
+                    }
+                    CURRENT_TASK.with(|task| task.set(Some(id)));
+                    let result = catch_panic(job);
+                    CURRENT_TASK.with(|task| task.set(None));
+                    set_abandoned(id, false);
+                    // 已经被放弃时没有人接收结果，线程结束
+                    if result_sender.send(result).is_err() {
+                        info!("读取线程已被放弃，结束: {id}");
+                        break;
+                    }
+                }
+            })?;
+        Ok(ReadThread { jobs, results })
+    }
+}
+
+/// 取任务的结果
+#[derive(Debug, PartialEq)]
+pub enum Pickup {
+    Processed,
+    /// 没有可以处理的任务，等待新任务的通知，最长等待时间为 None 时一直等待
+    Idle(Option<Duration>),
+}
+
 pub struct Worker {
     indexer: Indexer,
-    reader: CompositeReader,
+    reader: Arc<CompositeReader>,
+    read_thread: RefCell<Option<ReadThread>>,
     name: String,
+    batch: RefCell<WriteBatch>,
+    lane: Lane,
+}
+
+/// 索引线程处理的任务种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lane {
+    /// 不需要 OCR 的普通文件
+    Normal,
+    /// 大文件线程优先处理超过大小阈值的文件，没有时也处理普通文件
+    Slow,
+    /// OCR 线程只处理需要识别文字的图片，比读取文本慢得多，不占用其他线程
+    Ocr,
+}
+
+// 一次提交的文件数和内容条数上限，小文件很多时避免每个文件提交一次
+const BATCH_MAX_FILES: usize = 200;
+const BATCH_MAX_ITEMS: usize = 20_000;
+// 第一个文件进入批次后最多等待的时间
+const BATCH_MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// 读取完还没有提交的文件，与任务的删除在同一个事务中提交，
+/// 提交前退出时任务仍然是运行状态，重启后重新处理
+#[derive(Default)]
+struct WriteBatch {
+    files: Vec<(i64, FileWrite)>,
+    items: usize,
+    started: Option<Instant>,
+}
+
+impl WriteBatch {
+    fn push(&mut self, task_id: i64, file_write: FileWrite) {
+        self.items += file_write.item_count();
+        self.started.get_or_insert_with(Instant::now);
+        self.files.push((task_id, file_write));
+    }
+
+    fn remove(&mut self, task_id: i64) {
+        if let Some(i) = self.files.iter().position(|(id, _)| *id == task_id) {
+            let (_, file_write) = self.files.remove(i);
+            self.items -= file_write.item_count();
+        }
+    }
+
+    fn contains(&self, task_id: i64) -> bool {
+        self.files.iter().any(|(id, _)| *id == task_id)
+    }
+
+    /// 补上批次中的文件的内容哈希，之后写入的相同内容的文件可以共享它的内容
+    fn set_content_hashes(&mut self, hashes: Vec<(PathBuf, String)>) {
+        for (path, hash) in hashes {
+            if let Some((_, file_write)) = self
+                .files
+                .iter_mut()
+                .find(|(_, file_write)| file_write.path() == path)
+            {
+                file_write.set_content_hash(hash);
+            }
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.files.len() >= BATCH_MAX_FILES
+            || self.items >= BATCH_MAX_ITEMS
+            || self
+                .started
+                .is_some_and(|started| started.elapsed() >= BATCH_MAX_DELAY)
+    }
 }
 
 #[derive(Debug, PartialEq, EnumString, Display)]
@@ -50,8 +283,8 @@ enum PathType {
     File,
 }
 
-#[derive(Debug, PartialEq, EnumString, Display)]
-enum TaskStatus {
+#[derive(Debug, PartialEq, Clone, Copy, EnumString, Display, Serialize, Deserialize)]
+pub enum TaskStatus {
     #[strum(to_string = "Pending")]
     Pending,
     #[strum(to_string = "Running")]
@@ -63,6 +296,42 @@ pub struct TaskStatusStat {
     pub pending: usize,
     pub running: usize,
     pub running_tasks: Vec<String>,
+    pub failed: usize,
+    pub paused: bool,
+    /// 待索引中等待大文件线程处理的文件
+    pub slow_pending: usize,
+    /// 待索引中等待 OCR 线程处理的图片
+    pub ocr_pending: usize,
+    /// 使用电池供电且电量低时的省电措施
+    pub battery: Option<BatteryAction>,
+}
+
+/// 重试后仍然失败的任务
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailedTask {
+    pub path: String,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: String,
+}
+
+/// 队列中的一个任务，用于查看排队和卡住的任务
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskInfo {
+    pub id: i64,
+    pub path_type: String,
+    pub path: String,
+    pub task_type: String,
+    pub status: String,
+    pub worker: Option<String>,
+    pub attempts: u32,
+    pub error: Option<String>,
+    /// 等待重试的任务下次处理的时间（毫秒时间戳）
+    pub retry_at: Option<i64>,
+    pub slow: bool,
+    pub ocr: bool,
+    pub created_at: String,
+    pub updated_at: String,
 }
 
 impl Worker {
@@ -76,6 +345,21 @@ impl Worker {
                 TaskStatus::Running.to_string()
             ],
         )?;
+        notify_tasks();
+        Ok(())
+    }
+
+    /// 删除该路径还没开始处理和已经失败的任务
+    pub fn remove_pending_tasks(path: &Path) -> Result<()> {
+        let conn = get_conn()?;
+        let path = path
+            .to_str()
+            .with_context(|| format!("Invalid file path: {path:?}"))?;
+        conn.execute(
+            "DELETE FROM tasks WHERE path = ?1 AND status = ?2",
+            params![path, TaskStatus::Pending.to_string()],
+        )?;
+        conn.execute("DELETE FROM failed_files WHERE path = ?1", params![path])?;
         Ok(())
     }
 
@@ -85,36 +369,86 @@ impl Worker {
         let name = thread::current().name().unwrap_or("unknown").to_string();
         Ok(Worker {
             indexer,
-            reader,
+            reader: Arc::new(reader),
+            read_thread: RefCell::new(None),
             name,
+            batch: RefCell::new(WriteBatch::default()),
+            lane: Lane::Normal,
+        })
+    }
+
+    pub fn new_slow_lane() -> Result<Worker> {
+        Ok(Worker {
+            lane: Lane::Slow,
+            ..Self::new()?
+        })
+    }
+
+    pub fn new_ocr_lane() -> Result<Worker> {
+        Ok(Worker {
+            lane: Lane::Ocr,
+            ..Self::new()?
         })
     }
 
     fn add_task(&self, path_type: &PathType, path: &Path, task_type: &TaskType) -> Result<i64> {
+        self.add_task_with_force_extension(path_type, path, task_type, None)
+    }
+
+    /// 提交任务，force_extension 用于目录任务，合并到已有任务的扩展名中
+    fn add_task_with_force_extension(
+        &self,
+        path_type: &PathType,
+        path: &Path,
+        task_type: &TaskType,
+        force_extension: Option<&str>,
+    ) -> Result<i64> {
         let conn = get_conn()?;
 
         let path = path
             .to_str()
             .with_context(|| format!("Invalid file path: {path:?}"))?
             .to_string();
+        let root_id = find_root_id(Path::new(&path))?;
+        let index_file = *path_type == PathType::File && *task_type == TaskType::Index;
+        let slow = index_file && is_slow_file(Path::new(&path))?;
+        let ocr = index_file && is_ocr_file(Path::new(&path));
         let now = Local::now().to_rfc3339();
-        let id = conn.query_one(
-            r"INSERT INTO tasks (path_type, path, task_type, status, created_at, updated_at) 
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT(path_type, path) 
-                DO UPDATE SET updated_at = ?6 RETURNING id",
+        // 失败的文件再次提交时按新任务处理
+        conn.prepare_cached("DELETE FROM failed_files WHERE path_type = ?1 AND path = ?2")?
+            .execute(params![path_type.to_string(), path])?;
+        // 扫描时每个文件提交一次，缓存预编译的语句
+        let id = conn.prepare_cached(
+            r"INSERT INTO tasks (path_type, path, task_type, status, created_at, updated_at, root_id, slow, ocr, force_extension) 
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) ON CONFLICT(path_type, path) 
+                DO UPDATE SET updated_at = ?6, root_id = ?7, slow = ?8, ocr = ?9,
+                    retry_at = CASE WHEN deferred = 1 THEN NULL ELSE retry_at END, deferred = 0,
+                    force_extension = CASE
+                        WHEN ?10 IS NULL OR instr(',' || force_extension || ',', ',' || ?10 || ',') > 0 THEN force_extension
+                        WHEN force_extension IS NULL THEN ?10
+                        ELSE force_extension || ',' || ?10
+                    END
+                RETURNING id",
+        )?
+        .query_one(
             params![
                 path_type.to_string(),
                 path,
                 task_type.to_string(),
                 TaskStatus::Pending.to_string(),
                 now,
-                now
+                now,
+                root_id,
+                slow,
+                ocr,
+                force_extension
             ],
             |row| {
                 let id = row.get::<_, i64>(0)?;
                 Ok(id)
             },
         )?;
+        notify_tasks();
         Ok(id)
     }
 
@@ -124,7 +458,11 @@ impl Worker {
 
         for entry in fs::read_dir(path)? {
             let entry = entry?;
-            let path = entry.path();
+            let path = nfc_path(&entry.path());
+            // 排除的路径按已删除处理，之前索引过的会被删除
+            if Self::is_excluded(&path)? {
+                continue;
+            }
 
             if path.is_dir() {
                 dirs.insert(path);
@@ -140,118 +478,35 @@ impl Worker {
         self.submit_index_all_files_with_force_extension(path, None)
     }
 
+    /// 提交索引任务。目录只提交一个目录任务后马上返回，由索引线程扫描目录，
+    /// 子目录再提交目录任务，逐层扫描，很大的目录也不会阻塞调用方
     pub fn submit_index_all_files_with_force_extension(
         &self,
         path: &Path,
         force_extension: Option<&str>,
     ) -> Result<()> {
+        let path = &normalize_path(path);
         info!(
             "提交索引任务: {}, force_extension: {force_extension:?}",
             path.display()
         );
         if path.exists() {
-            if path.is_dir() {
-                if let Ok(index_dir) = self.indexer.get_directory(path) {
-                    // 数据库已经有这个目录了
-                    let modified_time = self.indexer.get_modified_time(path)?;
-                    if index_dir.modified_time != modified_time {
-                        info!(
-                            "目录索引过，但目录时间发生变更。目录: {} 原时间: {} 现时间:{}",
-                            path.display(),
-                            index_dir.modified_time,
-                            modified_time
-                        );
-                        self.add_task(&PathType::Directory, path, &TaskType::Index)?;
-                        info!("目录时间已更新。目录: {}", path.display());
-                        // 目录修改了
-                        let (index_sub_dirs, index_sub_files) =
-                            self.indexer.get_sub_directories_and_files(path)?;
-                        let (current_sub_dirs, current_sub_files) =
-                            self.split_dir_contents(path)?;
-
-                        let index_sub_dirs = HashSet::from_iter(
-                            index_sub_dirs
-                                .iter()
-                                .map(|p| Path::new(&p.path).to_path_buf()),
-                        );
-                        let index_sub_files = HashSet::from_iter(
-                            index_sub_files
-                                .iter()
-                                .map(|p| Path::new(&p.path).join(&p.name).to_path_buf()),
-                        );
-
-                        for dir in index_sub_dirs.difference(&current_sub_dirs) {
-                            // 删除的目录
-                            info!("提交删除目录任务: {}", dir.display());
-                            debug!("index_sub_dirs: {index_sub_dirs:?}");
-                            debug!("current_sub_dirs: {current_sub_dirs:?}");
-                            self.add_task(&PathType::Directory, dir, &TaskType::Delete)?;
-                        }
-                        for file in index_sub_files.difference(&current_sub_files) {
-                            // 删除的文件
-                            info!("提交删除文件任务: {}", file.display());
-                            debug!("index_sub_files: {index_sub_files:?}");
-                            debug!("current_sub_files: {current_sub_files:?}");
-                            self.add_task(&PathType::File, file, &TaskType::Delete)?;
-                        }
-                    }
-                } else {
-                    // 数据库中没有这个目录
-                    info!("目录未索引，添加任务。目录: {}", path.display());
-                    self.add_task(&PathType::Directory, path, &TaskType::Index)?;
-                }
-
-                for entry in fs::read_dir(path)? {
-                    let entry = entry?;
-                    let path = entry.path();
-
-                    if path.is_file() {
-                        if let Ok(index_file) = self.indexer.get_file(&path) {
-                            let modified_time = self.indexer.get_modified_time(&path)?;
-                            if index_file.modified_time == modified_time {
-                                // 文件时间未变更
-                                debug!("文件时间未变更。文件: {}", path.display());
-                                if let Some(force_ext) = force_extension {
-                                    // 强制索引某个文件类型
-                                    if let Some(ext) = path.extension() {
-                                        if ext.to_str().unwrap_or_default().to_lowercase()
-                                            == force_ext
-                                        {
-                                            info!(
-                                                "强制索引文件类型: {}, {}",
-                                                force_ext,
-                                                path.display()
-                                            );
-                                            self.add_task(
-                                                &PathType::File,
-                                                &path,
-                                                &TaskType::Index,
-                                            )?;
-                                        }
-                                    }
-                                }
-                                // 其他无变化，不做处理
-                            } else {
-                                info!(
-                                    "文件索引过，但文件时间发生变更。文件: {} 原时间: {} 现时间:{}",
-                                    path.display(),
-                                    index_file.modified_time,
-                                    modified_time
-                                );
-                                self.add_task(&PathType::File, &path, &TaskType::Index)?;
-                            }
-                        } else {
-                            info!("文件未索引，添加任务。文件: {}", path.display());
-                            self.add_task(&PathType::File, &path, &TaskType::Index)?;
-                        }
-                    } else if path.is_dir() {
-                        self.submit_index_all_files_with_force_extension(&path, force_extension)?;
-                    }
-                }
+            if Self::is_excluded(path)? {
+                info!("排除的路径，不索引: {}", path.display());
+            } else if path.is_dir() {
+                self.add_task_with_force_extension(
+                    &PathType::Directory,
+                    path,
+                    &TaskType::Index,
+                    force_extension,
+                )?;
             } else if path.is_file() {
                 info!("添加文件索引任务。文件: {}", path.display());
                 self.add_task(&PathType::File, path, &TaskType::Index)?;
             }
+        } else if volume::is_offline(path)? {
+            // 共享目录或移动硬盘离线，文件并没有被删除
+            info!("索引目录离线，跳过: {}", path.display());
         } else {
             info!("提交删除目录或文件: {}", path.display());
             self.add_task(&PathType::File, path, &TaskType::Delete)?;
@@ -260,22 +515,538 @@ impl Worker {
         Ok(())
     }
 
+    /// 执行目录任务：目录变化时更新目录并提交删除的文件和子目录，
+    /// 提交新的和修改过的文件，子目录提交目录任务继续扫描。
+    /// force_extensions 为逗号分隔的扩展名，这些文件没有变化也重新索引
+    fn scan_directory(&self, path: &Path, force_extensions: Option<&str>) -> Result<()> {
+        self.sync_directory(path)?;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let path = nfc_path(&entry.path());
+            if Self::is_excluded(&path)? {
+                debug!("排除的路径: {}", path.display());
+                continue;
+            }
+
+            if path.is_file() {
+                self.check_file(&path, force_extensions)?;
+            } else if path.is_dir() {
+                self.add_task_with_force_extension(
+                    &PathType::Directory,
+                    &path,
+                    &TaskType::Index,
+                    force_extensions,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 快速检查一个目录，用于启动时补上程序没有运行期间的变化。目录有变化时和扫描一样
+    /// 检查其中的每个文件，没有变化时只检查修改时间晚于 since 的文件。
+    /// 子目录不提交任务，返回给调用方继续检查，同时返回提交的任务数
+    pub fn reconcile_directory(
+        &self,
+        path: &Path,
+        since: SystemTime,
+    ) -> Result<(Vec<PathBuf>, usize)> {
+        let changed = self.sync_directory(path)?;
+        let mut submitted = usize::from(changed);
+        let mut sub_dirs = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let path = nfc_path(&entry.path());
+            if Self::is_excluded(&path)? {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                sub_dirs.push(path);
+            } else if metadata.is_file()
+                && (changed || metadata.modified().is_ok_and(|m| m >= since))
+                && self.check_file(&path, None)?
+            {
+                submitted += 1;
+            }
+        }
+        Ok((sub_dirs, submitted))
+    }
+
+    /// 检查系统变化日志中记录的一个路径，返回提交的任务数。没有索引过的目录
+    /// 可能是整个移动进来的，提交目录任务扫描其中的所有内容
+    pub fn reconcile_path(&self, path: &Path) -> Result<usize> {
+        let path = &nfc_path(path);
+        if Self::is_excluded(path)? {
+            return Ok(0);
+        }
+        if path.is_file() {
+            return Ok(usize::from(self.check_file(path, None)?));
+        }
+        if path.is_dir() && self.indexer.get_directory(path).is_ok() {
+            return Ok(usize::from(self.sync_directory(path)?));
+        }
+        self.submit_index_all_files(path)?;
+        Ok(1)
+    }
+
+    /// 目录不在数据库中或者修改时间变化时，提交删除的文件和子目录并更新目录，
+    /// 返回目录是否有变化
+    fn sync_directory(&self, path: &Path) -> Result<bool> {
+        let Ok(index_dir) = self.indexer.get_directory(path) else {
+            // 数据库中没有这个目录
+            info!("目录未索引，写入目录: {}", path.display());
+            self.indexer.write_directory(path)?;
+            return Ok(true);
+        };
+        // 数据库已经有这个目录了
+        let modified_time = self.indexer.get_modified_time(path)?;
+        if index_dir.modified_time == modified_time {
+            return Ok(false);
+        }
+        info!(
+            "目录索引过，但目录时间发生变更。目录: {} 原时间: {} 现时间:{}",
+            path.display(),
+            index_dir.modified_time,
+            modified_time
+        );
+        // 目录修改了
+        let (index_sub_dirs, index_sub_files) = self.indexer.get_sub_directories_and_files(path)?;
+        let (current_sub_dirs, current_sub_files) = self.split_dir_contents(path)?;
+
+        let index_sub_dirs = HashSet::from_iter(
+            index_sub_dirs
+                .iter()
+                .map(|p| Path::new(&p.path).to_path_buf()),
+        );
+        let index_sub_files = HashSet::from_iter(
+            index_sub_files
+                .iter()
+                .map(|p| Path::new(&p.path).join(&p.name).to_path_buf()),
+        );
+
+        for dir in index_sub_dirs.difference(&current_sub_dirs) {
+            // 删除的目录
+            info!("提交删除目录任务: {}", dir.display());
+            debug!("index_sub_dirs: {index_sub_dirs:?}");
+            debug!("current_sub_dirs: {current_sub_dirs:?}");
+            self.add_task(&PathType::Directory, dir, &TaskType::Delete)?;
+        }
+        for file in index_sub_files.difference(&current_sub_files) {
+            // 删除的文件
+            info!("提交删除文件任务: {}", file.display());
+            debug!("index_sub_files: {index_sub_files:?}");
+            debug!("current_sub_files: {current_sub_files:?}");
+            self.add_task(&PathType::File, file, &TaskType::Delete)?;
+        }
+        self.indexer.write_directory(path)?;
+        info!("目录时间已更新。目录: {}", path.display());
+        Ok(true)
+    }
+
+    /// 文件没有索引过或者修改时间变化时提交索引任务，返回是否提交了任务
+    fn check_file(&self, path: &Path, force_extensions: Option<&str>) -> Result<bool> {
+        let Ok(index_file) = self.indexer.get_file(path) else {
+            info!("文件未索引，添加任务。文件: {}", path.display());
+            self.add_task(&PathType::File, path, &TaskType::Index)?;
+            return Ok(true);
+        };
+        let modified_time = self.indexer.get_modified_time(path)?;
+        if index_file.modified_time != modified_time {
+            info!(
+                "文件索引过，但文件时间发生变更。文件: {} 原时间: {} 现时间:{}",
+                path.display(),
+                index_file.modified_time,
+                modified_time
+            );
+            self.add_task(&PathType::File, path, &TaskType::Index)?;
+            return Ok(true);
+        }
+        // 文件时间未变更
+        debug!("文件时间未变更。文件: {}", path.display());
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if force_extensions
+            .is_some_and(|force| !ext.is_empty() && force.split(',').any(|f| f == ext))
+        {
+            // 强制索引某个文件类型
+            info!("强制索引文件类型: {}, {}", ext, path.display());
+            self.add_task(&PathType::File, path, &TaskType::Index)?;
+            return Ok(true);
+        }
+        // 其他无变化，不做处理
+        Ok(false)
+    }
+
+    /// 不管修改时间，删除文件或目录的索引后重新读取，用于索引过时或者读取器升级后
+    pub fn reindex_path(&self, path: &Path) -> Result<()> {
+        info!("重新索引: {}", path.display());
+        // 先取消还没处理完的任务，避免写回旧的内容
+        Self::cancel_tasks(path)?;
+        if path.is_dir() {
+            self.indexer.delete_directory(path)?;
+        } else {
+            self.indexer.delete_file(path)?;
+        }
+        self.submit_index_all_files(path)
+    }
+
+    /// 被排除规则排除，或者在只索引第一层的索引目录的子目录中
+    fn is_excluded(path: &Path) -> Result<bool> {
+        Ok(ignore::is_ignored(path)? || roots::is_too_deep(path)?)
+    }
+
+    /// 保存索引目录的排除规则，返回从索引中删除的文件和目录数
+    pub fn set_ignore_patterns(&self, root: &Path, patterns: Vec<String>) -> Result<usize> {
+        IgnoreRules::new(root, &patterns)?;
+        Config::set_ignore_patterns(path_to_str(root)?, patterns)?;
+        self.apply_exclusions(root)
+    }
+
+    /// 设置索引目录是否包含子目录，返回从索引中删除的文件和目录数
+    pub fn set_root_recursive(&self, root: &Path, recursive: bool) -> Result<usize> {
+        Config::set_root_recursive(path_to_str(root)?, recursive)?;
+        self.apply_exclusions(root)
+    }
+
+    /// 排除的范围变化后，已经索引的、现在被排除的路径提交删除任务，
+    /// 不再被排除的在重新扫描索引目录时补上，返回删除的文件和目录数
+    fn apply_exclusions(&self, root: &Path) -> Result<usize> {
+        let (mut dirs, files) = self.indexer.get_paths_under(root)?;
+        dirs.sort();
+        let mut removed_dirs: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            if removed_dirs.iter().any(|removed| dir.starts_with(removed)) {
+                continue;
+            }
+            if Self::is_excluded(&dir)? {
+                info!("排除目录，提交删除任务: {}", dir.display());
+                self.add_task(&PathType::Directory, &dir, &TaskType::Delete)?;
+                removed_dirs.push(dir);
+            }
+        }
+        let mut removed = removed_dirs.len();
+        for file in files {
+            if removed_dirs.iter().any(|removed| file.starts_with(removed)) {
+                continue;
+            }
+            if Self::is_excluded(&file)? {
+                info!("排除文件，提交删除任务: {}", file.display());
+                self.add_task(&PathType::File, &file, &TaskType::Delete)?;
+                removed += 1;
+            }
+        }
+        self.submit_index_all_files(root)?;
+        Ok(removed)
+    }
+
+    /// 文件或目录被重命名、移动。已经索引过的只更新路径，不重新读取内容
+    pub fn submit_move(&self, from: &Path, to: &Path) -> Result<()> {
+        // 只改大小写的重命名后旧路径会解析成新的名称，旧路径只统一所在目录的大小写
+        let from = &match (from.parent(), from.file_name()) {
+            (Some(parent), Some(name)) => normalize_path(parent).join(name),
+            _ => from.to_path_buf(),
+        };
+        let to = &normalize_path(to);
+        info!("提交移动: {} -> {}", from.display(), to.display());
+        // 移出索引目录，或者扩展名变了、需要换读取器时按删除和新建处理
+        let movable = find_root_id(to)?.is_some()
+            && !Self::is_excluded(to)?
+            && (to.is_dir()
+                || (to.is_file()
+                    && filename_to_str(from).map(file_extension)?
+                        == filename_to_str(to).map(file_extension)?));
+        if movable {
+            // 旧路径还没处理完的任务会按删除处理，取消后在新路径上重新提交
+            let cancelled = Self::cancel_tasks(from)?;
+            let moved = if to.is_dir() {
+                self.indexer.move_directory(from, to)?
+            } else {
+                self.indexer.move_file(from, to)?
+            };
+            if moved {
+                if cancelled > 0 {
+                    self.submit_index_all_files(to)?;
+                }
+                return Ok(());
+            }
+        }
+        self.submit_index_all_files(from)?;
+        self.submit_index_all_files(to)
+    }
+
+    /// 清空索引后重新索引所有索引目录，返回重新提交的目录数。
+    /// 期间暂停索引线程，等正在处理的任务结束后再清空，避免旧的任务写入新的表
+    pub fn rebuild_index(&self) -> Result<usize> {
+        info!("重建索引");
+        let was_paused = is_paused();
+        set_paused(true);
+        let result = (|| {
+            let roots = Config::get_index_dir_paths()?;
+            for root in &roots {
+                Self::cancel_tasks(Path::new(root))?;
+            }
+            Self::wait_running_tasks(REBUILD_WAIT)?;
+            rebuild_index_tables()?;
+            for root in &roots {
+                self.submit_index_all_files(Path::new(root))?;
+            }
+            Ok(roots.len())
+        })();
+        set_paused(was_paused);
+        result
+    }
+
+    /// 等待正在处理的任务（包括还没提交的批次）结束
+    pub fn wait_running_tasks(timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            let running: usize = get_conn()?.query_row(
+                "SELECT COUNT(*) FROM tasks WHERE status = ?1",
+                params![TaskStatus::Running.to_string()],
+                |row| row.get(0),
+            )?;
+            if running == 0 {
+                return Ok(());
+            }
+            if start.elapsed() > timeout {
+                return Err(anyhow!("还有 {running} 个任务正在处理，请稍后再试"));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
     pub fn submit_delete_all_files(&self, path: &Path) -> Result<()> {
-        self.add_task(&PathType::Directory, path, &TaskType::Delete)?;
+        self.add_task(
+            &PathType::Directory,
+            &normalize_path(path),
+            &TaskType::Delete,
+        )?;
         Ok(())
     }
 
     pub fn get_tasks_status(&self) -> Result<TaskStatusStat> {
         let conn = get_conn()?;
-        let (pending, running) = conn.query_one(
-            "SELECT COUNT(if(status = ?1, 1, NULL)), COUNT(if(status = ?2, 1, NULL)) FROM tasks",
+        let (pending, running, slow_pending, ocr_pending) = conn.query_one(
+            "SELECT COUNT(iif(status = ?1, 1, NULL)), COUNT(iif(status = ?2, 1, NULL)), COUNT(iif(status = ?1 AND slow = 1 AND ocr = 0, 1, NULL)), COUNT(iif(status = ?1 AND ocr = 1, 1, NULL)) FROM tasks",
+            params![
+                TaskStatus::Pending.to_string(),
+                TaskStatus::Running.to_string()
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let failed = conn.query_one("SELECT COUNT(*) FROM failed_files", [], |row| row.get(0))?;
+
+        Ok(TaskStatusStat {
+            pending,
+            running,
+            running_tasks: Self::get_running_task_paths()?,
+            failed,
+            paused: is_paused(),
+            slow_pending,
+            ocr_pending,
+            battery: power::battery_action()?,
+        })
+    }
+
+    /// 重试后仍然失败、已经移出任务队列的文件
+    pub fn get_failed_tasks() -> Result<Vec<FailedTask>> {
+        let conn = get_conn()?;
+        let mut stmt = conn
+            .prepare("SELECT path, error, attempts, failed_at FROM failed_files ORDER BY path")?;
+        let tasks = stmt.query_map([], |row| {
+            Ok(FailedTask {
+                path: row.get(0)?,
+                error: row.get(1)?,
+                attempts: row.get(2)?,
+                failed_at: row.get(3)?,
+            })
+        })?;
+        Ok(tasks.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// 按处理顺序分页列出任务，status 为 None 时列出所有状态的任务
+    pub fn list_tasks(
+        status: Option<TaskStatus>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchPage<TaskInfo>> {
+        let conn = get_conn()?;
+        let status = status.map(|s| s.to_string());
+        let total = conn.query_row(
+            "SELECT COUNT(*) FROM tasks WHERE ?1 IS NULL OR status = ?1",
+            params![status],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            r"SELECT id, path_type, path, task_type, status, worker, attempts, error, retry_at, slow, ocr, created_at, updated_at
+            FROM tasks
+            WHERE ?1 IS NULL OR status = ?1
+            ORDER BY id
+            LIMIT ?2 OFFSET ?3",
+        )?;
+        let tasks = stmt
+            .query_map(params![status, limit as i64, offset as i64], |row| {
+                Ok(TaskInfo {
+                    id: row.get(0)?,
+                    path_type: row.get(1)?,
+                    path: row.get(2)?,
+                    task_type: row.get(3)?,
+                    status: row.get(4)?,
+                    worker: row.get(5)?,
+                    attempts: row.get(6)?,
+                    error: row.get(7)?,
+                    retry_at: row.get(8)?,
+                    slow: row.get(9)?,
+                    ocr: row.get(10)?,
+                    created_at: row.get(11)?,
+                    updated_at: row.get(12)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(SearchPage::new(tasks, total, offset, limit))
+    }
+
+    /// 取消该路径及其下所有的任务，返回取消的任务数。正在处理的任务处理完后丢弃结果，
+    /// 外部命令和插件会被提前结束
+    pub fn cancel_tasks(path: &Path) -> Result<usize> {
+        let task_path = path_to_str(path)?;
+        let (lower, upper) = subpath_range(task_path);
+        let mut conn = get_conn()?;
+        // 避免与取任务同时进行
+        let _lock = get_worker_lock()
+            .lock()
+            .map_err(|e| anyhow!("获取worker锁失败: {}", e))?;
+        let tx = conn.transaction()?;
+        // 正在处理的任务由处理线程在完成时删除
+        let running = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM tasks WHERE status = ?1 AND (path = ?2 OR (path >= ?3 AND path < ?4))",
+            )?;
+            let ids = stmt.query_map(
+                params![TaskStatus::Running.to_string(), task_path, lower, upper],
+                |row| row.get::<_, i64>(0),
+            )?;
+            ids.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let deleted = tx.execute(
+            "DELETE FROM tasks WHERE status != ?1 AND (path = ?2 OR (path >= ?3 AND path < ?4))",
+            params![TaskStatus::Running.to_string(), task_path, lower, upper],
+        )?;
+        // 失败的文件也不再保留
+        let failed = tx.execute(
+            "DELETE FROM failed_files WHERE path = ?1 OR (path >= ?2 AND path < ?3)",
+            params![task_path, lower, upper],
+        )?;
+        tx.commit()?;
+        get_cancelled_tasks()
+            .lock()
+            .map_err(|e| anyhow!("获取取消任务锁失败: {}", e))?
+            .extend(&running);
+        let count = running.len() + deleted + failed;
+        info!("取消任务: {}, {count} 个", path.display());
+        Ok(count)
+    }
+
+    /// 失败的文件重新排队，重新计算重试次数，返回重新排队的任务数
+    pub fn retry_failed_tasks(&self) -> Result<usize> {
+        let paths: Vec<String> = {
+            let conn = get_conn()?;
+            let mut stmt = conn.prepare("SELECT path FROM failed_files")?;
+            let paths = stmt.query_map([], |row| row.get(0))?;
+            paths.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let mut count = 0;
+        for path in paths {
+            if self.retry_failed(&path)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 一个失败的文件重新排队，不是失败的文件时返回 false
+    pub fn retry_failed(&self, path: &str) -> Result<bool> {
+        let failed: Option<(String, String)> = get_conn()?
+            .query_row(
+                "SELECT path_type, task_type FROM failed_files WHERE path = ?1",
+                params![path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((path_type, task_type)) = failed else {
+            return Ok(false);
+        };
+        info!("重试失败的文件: {path}");
+        // 提交时从失败的文件中移除
+        self.add_task(
+            &PathType::from_str(&path_type)?,
+            Path::new(path),
+            &TaskType::from_str(&task_type)?,
+        )?;
+        Ok(true)
+    }
+
+    /// 接通电源后把因为省电推迟的 OCR 任务重新排队
+    pub fn resume_deferred_tasks() -> Result<usize> {
+        let conn = get_conn()?;
+        let count = conn.execute(
+            "UPDATE tasks SET error = NULL, retry_at = NULL, deferred = 0 WHERE status = ?1 AND deferred = 1",
+            params![TaskStatus::Pending.to_string()],
+        )?;
+        notify_tasks();
+        Ok(count)
+    }
+
+    /// 没有正在处理和可以处理的任务，还没到重试时间的任务不算
+    pub fn is_idle() -> Result<bool> {
+        let conn = get_conn()?;
+        let busy: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM tasks WHERE status = ?1 OR (status = ?2 AND (retry_at IS NULL OR retry_at <= ?3)))",
+            params![
+                TaskStatus::Running.to_string(),
+                TaskStatus::Pending.to_string(),
+                Local::now().timestamp_millis()
+            ],
+            |row| row.get(0),
+        )?;
+        Ok(!busy)
+    }
+
+    /// 索引目录下还没处理完的任务数和失败的文件数
+    pub fn count_root_tasks(root_id: i64) -> Result<(usize, usize)> {
+        let conn = get_conn()?;
+        let pending = conn.query_row(
+            "SELECT COUNT(*) FROM tasks WHERE root_id = ?1",
+            params![root_id],
+            |row| row.get(0),
+        )?;
+        let failed = conn.query_row(
+            "SELECT COUNT(*) FROM failed_files WHERE root_id = ?1",
+            params![root_id],
+            |row| row.get(0),
+        )?;
+        Ok((pending, failed))
+    }
+
+    /// 还没处理完的任务的路径，包括等待重试的任务
+    pub fn get_unfinished_task_paths() -> Result<Vec<String>> {
+        let conn = get_conn()?;
+        let mut stmt = conn.prepare("SELECT path FROM tasks WHERE status IN (?1, ?2)")?;
+        let paths = stmt.query_map(
             params![
                 TaskStatus::Pending.to_string(),
                 TaskStatus::Running.to_string()
             ],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+            |row| row.get::<_, String>(0),
         )?;
+        Ok(paths.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
 
+    pub fn get_running_task_paths() -> Result<Vec<String>> {
+        let conn = get_conn()?;
         let mut stmt = conn.prepare("SELECT path FROM tasks WHERE status = ?1")?;
         let paths = stmt.query_map(params![TaskStatus::Running.to_string()], |row| {
             row.get::<_, String>(0)
@@ -284,163 +1055,749 @@ impl Worker {
         for path in paths {
             running_tasks.push(path?);
         }
-
-        Ok(TaskStatusStat {
-            pending,
-            running,
-            running_tasks,
-        })
+        Ok(running_tasks)
     }
 
     pub fn start_process() -> Result<()> {
         let num_cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
         let num_threads = std::cmp::max(1, num_cpus / 4);
-        info!("启动 {num_threads} 索引线程");
+        let ocr_threads = Config::get_ocr_concurrency()?.max(1);
+        info!("启动 {num_threads} 索引线程、1 个大文件线程和 {ocr_threads} 个 OCR 线程");
         for i in 0..num_threads {
-            thread::Builder::new()
-                .name(format!("index-worker-thread-{i}"))
-                .spawn(move || {
-                    let worker = Worker::new().unwrap();
-                    loop {
-                        match worker.process_task() {
-                            Ok(_) => {}
-                            Err(e) => {
-                                error!("处理任务失败: {e}");
-                                error!("{}", e.backtrace());
-                            }
+            Self::spawn_worker(format!("index-worker-thread-{i}"), Lane::Normal);
+        }
+        Self::spawn_worker("index-slow-lane-thread".to_string(), Lane::Slow);
+        for i in 0..ocr_threads {
+            Self::spawn_worker(format!("index-ocr-thread-{i}"), Lane::Ocr);
+        }
+        Ok(())
+    }
+
+    fn spawn_worker(name: String, lane: Lane) {
+        let handle = thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                let worker = match lane {
+                    Lane::Normal => Worker::new(),
+                    Lane::Slow => Worker::new_slow_lane(),
+                    Lane::Ocr => Worker::new_ocr_lane(),
+                }
+                .unwrap();
+                while !SHUTDOWN.load(Ordering::SeqCst) {
+                    let seen = task_signal_count();
+                    match worker.process_task() {
+                        Ok(Pickup::Processed) => {}
+                        Ok(Pickup::Idle(timeout)) => wait_for_tasks(seen, timeout),
+                        Err(e) => {
+                            error!("处理任务失败: {e}");
+                            error!("{}", e.backtrace());
                         }
                     }
-                })
-                .unwrap();
+                }
+                if let Err(e) = worker.drain() {
+                    error!("停止索引线程失败: {e:#}");
+                }
+            })
+            .unwrap();
+        match WORKER_THREADS.lock() {
+            Ok(mut threads) => threads.push(handle),
+            Err(e) => e.into_inner().push(handle),
         }
-        Ok(())
     }
 
-    pub fn process_task(&self) -> Result<()> {
-        let task = {
-            let conn = get_conn()?;
-            let _lock = get_worker_lock()
-                .lock()
-                .map_err(|e| anyhow!("获取worker锁失败: {}", e))?;
+    /// 退出前调用，通知索引线程处理完当前任务、提交批次后停止，
+    /// 超时没有停止的线程正在处理的任务重新排队，下次启动时处理
+    pub fn shutdown() -> Result<()> {
+        info!("停止索引线程...");
+        SHUTDOWN.store(true, Ordering::SeqCst);
+        notify_tasks();
+        let handles = match WORKER_THREADS.lock() {
+            Ok(mut threads) => std::mem::take(&mut *threads),
+            Err(e) => std::mem::take(&mut *e.into_inner()),
+        };
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        for handle in handles {
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(50));
+            }
+            let name = handle.thread().name().unwrap_or("unknown").to_string();
+            if !handle.is_finished() {
+                warn!("索引线程 {name} 没有按时停止");
+            } else if handle.join().is_err() {
+                error!("索引线程 {name} 异常退出");
+            }
+        }
+        Self::reset_running_tasks()
+    }
+
+    /// 线程停止前提交批次，当前线程还没完成的任务重新排队
+    fn drain(&self) -> Result<()> {
+        let flushed = self.flush_batch();
+        let conn = get_conn()?;
+        let count = conn.execute(
+            "UPDATE tasks SET status = ?1, updated_at = ?2, worker = null WHERE status = ?3 AND worker = ?4",
+            params![
+                TaskStatus::Pending.to_string(),
+                Local::now().to_rfc3339(),
+                TaskStatus::Running.to_string(),
+                self.name
+            ],
+        )?;
+        info!("索引线程 {} 已停止，{count} 个任务重新排队", self.name);
+        flushed
+    }
 
+    /// 处理一个任务，没有可以处理的任务时返回需要等待的时间，由索引线程等待通知
+    pub fn process_task(&self) -> Result<Pickup> {
+        if is_paused() || power::battery_action()? == Some(BatteryAction::Pause) {
+            self.flush_batch()?;
+            // 电量变化没有通知，最多等待1s
+            debug!("索引已暂停，休息1s");
+            return Ok(Pickup::Idle(Some(Duration::from_secs(1))));
+        }
+        throttle::adjust_thread_priority()?;
+        let task = {
+            let conn = get_conn()?;
+            let _lock = get_worker_lock()
+                .lock()
+                .map_err(|e| anyhow!("获取worker锁失败: {}", e))?;
+
+            // 等待重试的任务到时间后才处理，普通线程不处理大文件，图片只由 OCR 线程处理
+            let (ocr, slow) = self.lane_params();
             conn.query_row(
                 r"UPDATE tasks
-                SET status = ?1, updated_at = ?2, worker = ?3
+                SET status = ?1, updated_at = ?2, worker = ?3, deferred = 0
                 WHERE id = (
                     SELECT id FROM tasks
-                    WHERE status = ?4
-                    ORDER BY id
+                    WHERE status = ?4 AND (retry_at IS NULL OR retry_at <= ?5) AND ocr = ?6 AND (slow = 0 OR ?7)
+                    ORDER BY slow DESC, id
                     LIMIT 1
                 )
-                RETURNING id, path_type, path, task_type",
+                RETURNING id, path_type, path, task_type, force_extension",
                 params![
                     TaskStatus::Running.to_string(),
                     Local::now().to_rfc3339(),
                     self.name,
-                    TaskStatus::Pending.to_string()
+                    TaskStatus::Pending.to_string(),
+                    Local::now().timestamp_millis(),
+                    ocr,
+                    slow
                 ],
                 |row| {
                     let id = row.get::<_, i64>(0)?;
                     let path_type = row.get::<_, String>(1)?;
                     let path = row.get::<_, String>(2)?;
                     let task_type = row.get::<_, String>(3)?;
-                    Ok((id, path_type, path, task_type))
+                    let force_extension = row.get::<_, Option<String>>(4)?;
+                    Ok((id, path_type, path, task_type, force_extension))
                 },
             )
         };
 
         match task {
-            Ok((id, path_type, path, task_type)) => {
+            Ok((id, path_type, path, task_type, force_extension)) => {
                 debug!("处理任务: {id}, {path_type}, {path}, {task_type}");
                 let path = Path::new(&path);
                 let path_type = PathType::from_str(&path_type)?;
                 let task_type = TaskType::from_str(&task_type)?;
 
-                // 重试机制：最多重试3次
-                let mut retry_count = 0;
-                let max_retries = 3;
-
-                while retry_count < max_retries {
-                    let result: Result<()> = match task_type {
-                        TaskType::Index => match path_type {
-                            PathType::Directory => {
-                                if path.is_dir() {
-                                    self.indexer.write_directory(path)?;
-                                    Ok(())
-                                } else {
-                                    Err(anyhow!("Directory not found"))
-                                }
-                            }
-                            PathType::File => {
-                                self.indexer.delete_file(path)?;
-                                if path.is_file() {
-                                    if self.reader.supports(path)? {
-                                        match self.reader.read(path) {
-                                            Ok(items) => {
-                                                self.indexer.write_file_items(path, items)?;
-                                                Ok(())
-                                            }
-                                            Err(e) => {
-                                                self.indexer.write_file_items(path, Vec::new())?;
-                                                Err(anyhow!("Read file failed: {}", e))
-                                            }
-                                        }
-                                    } else {
-                                        // 文件类型不支持索引，只写入文件名
-                                        self.indexer.write_file_items(path, Vec::new())?;
-                                        Ok(())
-                                    }
-                                } else {
-                                    Err(anyhow!("File not found"))
-                                }
-                            }
-                        },
-                        TaskType::Delete => match path_type {
-                            PathType::Directory => self.indexer.delete_directory(path),
-                            PathType::File => self.indexer.delete_file(path),
-                        },
-                    };
-
-                    match result {
-                        Ok(_) => {
-                            info!("任务处理成功: {id}, {path_type}, {}", path.display());
-                            break;
+                CURRENT_TASK.with(|task| task.set(Some(id)));
+                let result = catch_panic(|| {
+                    self.run_task(id, &path_type, path, &task_type, force_extension.as_deref())
+                });
+                CURRENT_TASK.with(|task| task.set(None));
+                let cancelled = get_cancelled_tasks()
+                    .lock()
+                    .map_err(|e| anyhow!("获取取消任务锁失败: {}", e))?
+                    .remove(&id);
+
+                match result {
+                    _ if cancelled => {
+                        info!("任务已取消: {id}, {path_type}, {}", path.display());
+                        self.batch.borrow_mut().remove(id);
+                        let conn = get_conn()?;
+                        conn.execute("delete from tasks where id = ?", params![id])?;
+                    }
+                    Ok(items) => {
+                        info!("任务处理成功: {id}, {path_type}, {}", path.display());
+                        // 批量写入的任务在提交时删除
+                        if !self.batch.borrow().contains(id) {
+                            let conn = get_conn()?;
+                            conn.execute("delete from tasks where id = ?", params![id])?;
                         }
-                        Err(e) => {
-                            retry_count += 1;
-                            error!("任务处理失败: {id}, {path_type}, {}, {e}", path.display());
-                            error!("{}", e.backtrace());
-                            if retry_count == max_retries {
-                                // 重试失败，只写入文件名
-                                error!(
-                                    "任务重试全部失败，只写入文件名: {id}, {path_type}, {}",
-                                    path.display()
-                                );
-                                self.indexer.write_file_items(path, Vec::new())?;
-                                break;
-                            }
+                        if path_type == PathType::File && task_type == TaskType::Index {
+                            progress::record_file(path, items)?;
+                            throttle::after_file(path)?;
                         }
                     }
+                    Err(e) => {
+                        error!("任务处理失败: {id}, {path_type}, {}, {e:#}", path.display());
+                        error!("{}", e.backtrace());
+                        self.fail_task(id, &e)?;
+                    }
                 }
                 debug!("处理任务完成: {}, {}, {}", id, path_type, path.display());
-                let conn = get_conn()?;
-                conn.execute("delete from tasks where id = ?", params![id])?;
+                if self.batch.borrow().is_full() || !self.has_ready_task()? {
+                    self.flush_batch()?;
+                }
+                index_events::flush(false);
             }
             Err(rusqlite::Error::QueryReturnedNoRows) => {
-                // 没有待处理的任务，休息1s
-                debug!("没有待处理的任务，休息1s");
-                thread::sleep(Duration::from_secs(1));
-                return Ok(());
+                self.flush_batch()?;
+                // 队列处理完时推送最后一次进度
+                progress::flush()?;
+                // 通知前端刷新搜索结果
+                index_events::flush(true);
+                // 没有待处理的任务，等待新任务或者下一个任务的重试时间
+                let timeout = self.next_retry_delay()?;
+                debug!("没有待处理的任务，等待: {timeout:?}");
+                return Ok(Pickup::Idle(timeout));
             }
             Err(e) => {
                 error!("获取任务失败: {e}");
                 return Err(anyhow!("获取任务失败: {}", e));
             }
         }
+        Ok(Pickup::Processed)
+    }
+
+    /// 当前线程处理的任务：是否是图片，是否处理大文件
+    fn lane_params(&self) -> (bool, bool) {
+        (self.lane == Lane::Ocr, self.lane != Lane::Normal)
+    }
+
+    /// 距离下一个等待重试的任务到时间还有多久，最多 IDLE_RECHECK，没有等待重试的任务时为 None
+    fn next_retry_delay(&self) -> Result<Option<Duration>> {
+        let conn = get_conn()?;
+        let (ocr, slow) = self.lane_params();
+        let retry_at: Option<i64> = conn.query_row(
+            "SELECT MIN(retry_at) FROM tasks WHERE status = ?1 AND ocr = ?2 AND (slow = 0 OR ?3)",
+            params![TaskStatus::Pending.to_string(), ocr, slow],
+            |row| row.get(0),
+        )?;
+        Ok(retry_at.map(|retry_at| {
+            let delay = (retry_at - Local::now().timestamp_millis()).max(0) as u64;
+            Duration::from_millis(delay).min(IDLE_RECHECK)
+        }))
+    }
+
+    /// 是否还有可以马上处理的任务，没有时不再等待，直接提交批次
+    fn has_ready_task(&self) -> Result<bool> {
+        let conn = get_conn()?;
+        let (ocr, slow) = self.lane_params();
+        Ok(conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM tasks WHERE status = ?1 AND (retry_at IS NULL OR retry_at <= ?2) AND ocr = ?3 AND (slow = 0 OR ?4))",
+            params![
+                TaskStatus::Pending.to_string(),
+                Local::now().timestamp_millis(),
+                ocr,
+                slow
+            ],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// 在一个事务中写入批次中的文件并删除对应的任务，已经取消的任务只删除任务
+    fn flush_batch(&self) -> Result<()> {
+        let batch = self.batch.take();
+        if batch.files.is_empty() {
+            return Ok(());
+        }
+        // 提交期间持有取消任务锁，提交后任务已经删除，不会再被取消
+        let result = (|| -> Result<()> {
+            let mut cancelled = get_cancelled_tasks()
+                .lock()
+                .map_err(|e| anyhow!("获取取消任务锁失败: {}", e))?;
+            let mut conn = get_conn()?;
+            let tx = conn.transaction()?;
+            let mut written = Vec::new();
+            for (id, file_write) in &batch.files {
+                if !cancelled.remove(id) {
+                    self.indexer.write_prepared_file(&tx, file_write)?;
+                    written.push(path_to_str(file_write.path())?);
+                }
+                tx.execute("delete from tasks where id = ?", params![id])?;
+            }
+            tx.commit()?;
+            // 提交成功后才推送给前端，回滚的写入不会出现在搜索结果的刷新中
+            for path in written {
+                index_events::record(ChangeOp::IndexFile, path);
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => {
+                debug!(
+                    "批量写入 {} 个文件, {} 条内容",
+                    batch.files.len(),
+                    batch.items
+                );
+                Ok(())
+            }
+            Err(e) => {
+                error!("批量写入失败: {e:#}");
+                for (id, file_write) in &batch.files {
+                    debug!("批量写入失败的文件: {}", file_write.path().display());
+                    self.fail_task(*id, &e)?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 文件放入批次，提交时写入
+    fn write_batched(
+        &self,
+        id: i64,
+        path: &Path,
+        items: Vec<Item>,
+        skip_reason: Option<&str>,
+    ) -> Result<()> {
+        let file_write = self.indexer.prepare_file(path, items, skip_reason)?;
+        self.batch.borrow_mut().push(id, file_write);
+        Ok(())
+    }
+
+    /// 处理一个任务，返回写入的内容条数
+    fn run_task(
+        &self,
+        id: i64,
+        path_type: &PathType,
+        path: &Path,
+        task_type: &TaskType,
+        force_extension: Option<&str>,
+    ) -> Result<usize> {
+        match task_type {
+            TaskType::Index => match path_type {
+                PathType::Directory => {
+                    if path.is_dir() {
+                        self.scan_directory(path, force_extension)?;
+                        Ok(0)
+                    } else if volume::is_offline(path)? {
+                        // 重新接入后会重新扫描
+                        info!("索引目录离线，跳过: {}", path.display());
+                        Ok(0)
+                    } else {
+                        Err(anyhow!("Directory not found"))
+                    }
+                }
+                PathType::File => {
+                    if !path.is_file() {
+                        if volume::is_offline(path)? {
+                            info!("索引目录离线，跳过: {}", path.display());
+                            return Ok(0);
+                        }
+                        self.indexer.delete_file(path)?;
+                        return Err(anyhow!("File not found"));
+                    }
+                    // 判断不了类型时按不支持处理，仍然索引文件名、大小和修改时间
+                    let supported = self.reader.supports(path).unwrap_or_else(|e| {
+                        info!("无法判断文件类型，只索引文件名: {}, {e}", path.display());
+                        false
+                    });
+                    if !supported {
+                        // 文件类型不支持索引，只写入文件名
+                        self.write_batched(id, path, Vec::new(), None)?;
+                        return Ok(0);
+                    }
+                    if let Some(reason) = self.reader.skip_reason(path)? {
+                        info!("跳过文件内容: {}, {reason}", path.display());
+                        self.write_batched(id, path, Vec::new(), Some(&reason))?;
+                        return Ok(0);
+                    }
+                    // 复制到其他位置的文件和已经读取的文件内容相同时共享内容，不用再读取和 OCR
+                    let plan = self.hash_plan(path)?;
+                    match self.read_file(id, path, plan) {
+                        // 读取期间任务被取消，不再写入内容
+                        Ok(_) if is_current_task_cancelled() => Ok(0),
+                        Ok(read) => {
+                            self.batch
+                                .borrow_mut()
+                                .set_content_hashes(read.batch_hashes);
+                            match read.content {
+                                FileContent::Shared(content_hash) => {
+                                    debug!("共享相同内容的文件: {}", path.display());
+                                    let file_write = self
+                                        .indexer
+                                        .prepare_file(path, Vec::new(), None)?
+                                        .sharing_content(content_hash);
+                                    self.batch.borrow_mut().push(id, file_write);
+                                    Ok(0)
+                                }
+                                FileContent::Read(items, content_hash) => {
+                                    let items = chunk_items(items, &Config::get_chunking()?);
+                                    let count = items.len();
+                                    let mut file_write =
+                                        self.indexer.prepare_file(path, items, None)?;
+                                    if let Some(content_hash) = content_hash {
+                                        file_write = file_write.with_content_hash(content_hash);
+                                    }
+                                    self.batch.borrow_mut().push(id, file_write);
+                                    Ok(count)
+                                }
+                            }
+                        }
+                        // 有密码保护的文档重试也不会成功，记录原因后在设置中列出
+                        Err(e) if e.is::<ProtectedError>() => {
+                            info!("跳过文件内容: {}, {e}", path.display());
+                            self.write_batched(id, path, Vec::new(), Some(&e.to_string()))?;
+                            Ok(0)
+                        }
+                        // 转给 OCR 线程重新读取，保留已有的索引
+                        Err(e) if e.is::<OcrRequired>() => Err(e),
+                        Err(e) => {
+                            self.index_unreadable(path, &e)?;
+                            Err(e.context("Read file failed"))
+                        }
+                    }
+                }
+            },
+            // 离线前提交的删除任务不能确认文件真的被删除了
+            TaskType::Delete if volume::is_offline(path)? => {
+                info!("索引目录离线，跳过删除: {}", path.display());
+                Ok(0)
+            }
+            TaskType::Delete => match path_type {
+                PathType::Directory => self.indexer.delete_directory(path).map(|_| 0),
+                PathType::File => self.indexer.delete_file(path).map(|_| 0),
+            },
+        }
+    }
+
+    /// 读取失败时先索引文件名，重试成功后再写入内容。之后还会重试时保留已有的索引，
+    /// 不会再重试的文件已经改过，旧的内容不再对应，只保留文件名
+    fn index_unreadable(&self, path: &Path, e: &anyhow::Error) -> Result<()> {
+        if is_transient(e) || e.is::<OcrDeferred>() {
+            if !self.indexer.has_file(path)? {
+                self.indexer.write_file_items(path, Vec::new())?;
+            }
+        } else {
+            self.indexer.delete_file(path)?;
+            self.indexer.write_file_items(path, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// 读取前决定内容哈希怎样得到。空文件不共享；索引中的大小和修改时间没变时沿用记录的哈希；
+    /// 没有大小和扩展名相同的其他文件时不可能共享，不计算哈希，之后出现大小相同的文件时再补算
+    fn hash_plan(&self, path: &Path) -> Result<HashPlan> {
+        let size = fs::metadata(path)?.len() as i64;
+        if size == 0 {
+            return Ok(HashPlan::Skip);
+        }
+        let modified_time = self.indexer.get_modified_time(path)?;
+        if let Some(hash) = self
+            .indexer
+            .indexed_content_hash(path, size, &modified_time)?
+        {
+            return Ok(HashPlan::Known(hash));
+        }
+        let extension = file_extension(filename_to_str(path)?);
+        let indexed = self
+            .indexer
+            .same_size_files(path, size, MAX_HASH_CANDIDATES)?;
+        // 同一批次中先读取的文件还没有写入，也可能内容相同
+        let batched: Vec<PathBuf> = self
+            .batch
+            .borrow()
+            .files
+            .iter()
+            .map(|(_, file_write)| file_write)
+            .filter(|file_write| {
+                file_write.path() != path && file_write.needs_content_hash(size, &extension)
+            })
+            .map(|file_write| file_write.path().to_path_buf())
+            .collect();
+        if indexed.is_empty() && batched.is_empty() {
+            return Ok(HashPlan::Skip);
+        }
+        let mut candidates: Vec<HashCandidate> = indexed
+            .into_iter()
+            .filter(|file| file.content_hash.is_none())
+            .map(|file| HashCandidate {
+                id: Some(file.id),
+                path: file.path,
+                modified_time: Some(file.modified_time),
+            })
+            .chain(batched.into_iter().map(|path| HashCandidate {
+                id: None,
+                path,
+                modified_time: None,
+            }))
+            .collect();
+        candidates.truncate(MAX_HASH_CANDIDATES);
+        Ok(HashPlan::Compute(candidates))
+    }
+
+    /// 读取文件内容，超过任务超时时间时放弃读取并返回 TaskTimeout 错误。
+    /// 哈希也在读取线程中计算，和读取一样受超时、限速和取消的限制
+    fn read_file(&self, id: i64, path: &Path, plan: HashPlan) -> Result<FileRead> {
+        let timeout_secs = Config::get_task_timeout_secs()?;
+        // 文档中的图片也只在 OCR 线程中识别，普通线程遇到时转给 OCR 线程
+        let ocr_allowed = self.lane == Lane::Ocr;
+        if timeout_secs == 0 {
+            set_ocr_allowed(ocr_allowed);
+            return read_content(&self.reader, path, plan);
+        }
+        let reader = self.reader.clone();
+        let path = path.to_path_buf();
+        self.read_with_timeout(
+            id,
+            Box::new(move || {
+                set_ocr_allowed(ocr_allowed);
+                read_content(&reader, &path, plan)
+            }),
+            Duration::from_secs(timeout_secs),
+        )
+    }
+
+    fn read_with_timeout(&self, id: i64, job: ReadJob, timeout: Duration) -> Result<FileRead> {
+        let mut read_thread = self.read_thread.borrow_mut();
+        let thread = match &mut *read_thread {
+            Some(thread) => thread,
+            none => none.insert(ReadThread::spawn(&self.name)?),
+        };
+        thread
+            .jobs
+            .send((id, job))
+            .map_err(|_| anyhow!("读取线程已经结束"))?;
+        match thread.results.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                // 卡住的线程读取完成或者检查到任务被放弃后自行结束
+                warn!("读取超时，放弃读取线程: {id}");
+                set_abandoned(id, true);
+                *read_thread = None;
+                Err(TaskTimeout(timeout.as_secs()).into())
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                *read_thread = None;
+                Err(anyhow!("读取线程异常结束"))
+            }
+        }
+    }
+
+    /// 记录任务失败的原因。暂时性的错误（如文件被其他程序占用）等待一段时间后重试，
+    /// 等待时间每次加倍，重试次数用完或者重试也不会成功的错误标记为失败
+    fn fail_task(&self, id: i64, e: &anyhow::Error) -> Result<()> {
+        let mut conn = get_conn()?;
+        if e.is::<OcrRequired>() {
+            // 不计入重试次数，由 OCR 线程重新读取
+            info!("任务 {id} 需要 OCR，转给 OCR 线程处理");
+            conn.execute(
+                "UPDATE tasks SET status = ?1, ocr = 1, updated_at = ?2, worker = null WHERE id = ?3",
+                params![
+                    TaskStatus::Pending.to_string(),
+                    Local::now().to_rfc3339(),
+                    id
+                ],
+            )?;
+            notify_tasks();
+            return Ok(());
+        }
+        if e.is::<OcrDeferred>() {
+            // 不计入重试次数，通常在接通电源时就会重新排队
+            info!("任务 {id} 需要 OCR，推迟到接通电源后处理");
+            conn.execute(
+                "UPDATE tasks SET status = ?1, error = ?2, retry_at = ?3, updated_at = ?4, worker = null, deferred = 1 WHERE id = ?5",
+                params![
+                    TaskStatus::Pending.to_string(),
+                    format!("{e:#}"),
+                    Local::now().timestamp_millis() + BATTERY_RETRY_DELAY.as_millis() as i64,
+                    Local::now().to_rfc3339(),
+                    id
+                ],
+            )?;
+            return Ok(());
+        }
+        let attempts: u32 = conn.query_row(
+            "UPDATE tasks SET attempts = attempts + 1 WHERE id = ?1 RETURNING attempts",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if !is_transient(e) || attempts >= MAX_TASK_ATTEMPTS {
+            // 不再重试的任务移到失败的文件中，不再占用任务队列
+            error!("任务 {id} 失败，不再重试");
+            let tx = conn.transaction()?;
+            tx.execute(
+                r"INSERT INTO failed_files (path_type, path, task_type, root_id, attempts, error, failed_at)
+                SELECT path_type, path, task_type, root_id, attempts, ?2, ?3 FROM tasks WHERE id = ?1
+                ON CONFLICT(path_type, path) DO UPDATE SET
+                    task_type = excluded.task_type, root_id = excluded.root_id, attempts = excluded.attempts,
+                    error = excluded.error, failed_at = excluded.failed_at",
+                params![id, format!("{e:#}"), Local::now().to_rfc3339()],
+            )?;
+            tx.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
+            tx.commit()?;
+            return Ok(());
+        }
+        let delay = retry_delay(attempts);
+        info!(
+            "任务 {id} 第 {attempts} 次失败，{} 秒后重试",
+            delay.as_secs()
+        );
+        conn.execute(
+            "UPDATE tasks SET status = ?1, error = ?2, retry_at = ?3, updated_at = ?4, worker = null WHERE id = ?5",
+            params![
+                TaskStatus::Pending.to_string(),
+                format!("{e:#}"),
+                Local::now().timestamp_millis() + delay.as_millis() as i64,
+                Local::now().to_rfc3339(),
+                id
+            ],
+        )?;
         Ok(())
     }
 }
 
+// 补算哈希的大小相同的文件数上限，大小相同的小文件很多时不逐个计算
+const MAX_HASH_CANDIDATES: usize = 4;
+
+/// 读取前决定的内容哈希的来源
+enum HashPlan {
+    /// 没有可能内容相同的文件，不计算
+    Skip,
+    /// 文件没有变化，沿用索引中记录的哈希
+    Known(String),
+    /// 计算哈希，同时补算大小相同、还没有哈希的文件
+    Compute(Vec<HashCandidate>),
+}
+
+/// 需要补算哈希的文件，id 为空时还在写入批次中
+struct HashCandidate {
+    id: Option<i64>,
+    path: PathBuf,
+    /// 索引中记录的修改时间，磁盘上的文件改过后内容已经不同，不补算
+    modified_time: Option<String>,
+}
+
+/// 读取线程的结果
+#[derive(Debug)]
+struct FileRead {
+    content: FileContent,
+    /// 补算的写入批次中的文件的哈希
+    batch_hashes: Vec<(PathBuf, String)>,
+}
+
+#[derive(Debug)]
+enum FileContent {
+    /// 和已经读取的文件内容相同，共享它的内容
+    Shared(String),
+    /// 读取出的内容和内容哈希
+    Read(Vec<Item>, Option<String>),
+}
+
+/// 计算哈希后读取文件，在读取线程中执行
+fn read_content(reader: &CompositeReader, path: &Path, plan: HashPlan) -> Result<FileRead> {
+    let indexer = Indexer::new()?;
+    let mut batch_hashes = Vec::new();
+    let content_hash = match plan {
+        HashPlan::Skip => None,
+        HashPlan::Known(hash) => Some(hash),
+        HashPlan::Compute(candidates) => {
+            for candidate in candidates {
+                let unchanged = candidate.modified_time.as_ref().is_none_or(|time| {
+                    indexer
+                        .get_modified_time(&candidate.path)
+                        .is_ok_and(|current| current == *time)
+                });
+                if !unchanged {
+                    continue;
+                }
+                // 其他文件读取失败不影响这个文件
+                let hash = match sha256_file_until(&candidate.path, is_current_task_cancelled) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        debug!("补算内容哈希失败: {}, {e}", candidate.path.display());
+                        continue;
+                    }
+                };
+                throttle::limit_io(fs::metadata(&candidate.path).map_or(0, |m| m.len()))?;
+                match candidate.id {
+                    Some(id) => indexer.set_content_hash(id, &hash)?,
+                    None => batch_hashes.push((candidate.path, hash)),
+                }
+            }
+            Some(sha256_file_until(path, is_current_task_cancelled)?)
+        }
+    };
+    if let Some(hash) = &content_hash {
+        if indexer.has_shared_content(hash, path)? {
+            return Ok(FileRead {
+                content: FileContent::Shared(hash.clone()),
+                batch_hashes,
+            });
+        }
+    }
+    // 计算哈希时读过一遍文件，读取内容前先按限速等待
+    if content_hash.is_some() {
+        throttle::limit_io(fs::metadata(path)?.len())?;
+    }
+    Ok(FileRead {
+        content: FileContent::Read(reader.read(path)?, content_hash),
+        batch_hashes,
+    })
+}
+
+/// 文件是否超过大文件阈值，阈值为 0 时不区分
+fn is_slow_file(path: &Path) -> Result<bool> {
+    let threshold_mb = Config::get_slow_lane_threshold_mb()?;
+    Ok(threshold_mb > 0 && fs::metadata(path).is_ok_and(|m| m.len() > threshold_mb * 1024 * 1024))
+}
+
+/// 只能通过 OCR 读取的图片由 OCR 线程处理
+fn is_ocr_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| OCR_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// 暂时性的错误最多尝试的次数
+const MAX_TASK_ATTEMPTS: u32 = 5;
+// 推迟的 OCR 任务没有等到接通电源时（如重启了应用）再次尝试的间隔
+const BATTERY_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+// 第一次重试前等待的时间，之后每次加倍
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+fn retry_delay(attempts: u32) -> Duration {
+    RETRY_BASE_DELAY * 2u32.pow(attempts.saturating_sub(1))
+}
+
+/// 执行任务，读取文件时的 panic 转成错误，标记任务失败后线程继续处理其他任务
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "未知错误".to_string());
+        Err(anyhow!("处理任务时崩溃: {message}"))
+    })
+}
+
+/// 文件被占用、数据库忙等错误过一会儿可能就好了，文件格式错误等重试也不会成功
+fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            // Windows 上文件被其他程序占用或锁定
+            return matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::ResourceBusy
+            ) || (cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33)));
+        }
+        if let Some(rusqlite::Error::SqliteFailure(e, _)) = cause.downcast_ref() {
+            return matches!(
+                e.code,
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+            );
+        }
+        false
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use fs_extra::dir::{copy, CopyOptions};
@@ -448,7 +1805,8 @@ mod tests {
     use std::fs::{self, rename};
 
     use super::*;
-    use crate::indexer::Indexer;
+    use crate::indexer::{Indexer, SearchFilter, SearchSort};
+    use crate::reader::ItemPosition;
     use crate::test::test_mod::TestEnv;
     use crate::worker::Worker;
 
@@ -486,12 +1844,10 @@ mod tests {
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
 
+        // 提交时只有一个目录任务，两个目录和两个文件由索引线程逐层提交
         let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 4);
-
-        for _ in 0..4 {
-            worker.process_task().unwrap();
-        }
+        assert_eq!(worker_status.pending, 1);
+        assert_eq!(process_all_tasks(&worker), 4);
 
         let worker_status = worker.get_tasks_status().unwrap();
         assert_eq!(worker_status.pending, 0);
@@ -502,6 +1858,15 @@ mod tests {
         (env, temp_test_data_worker)
     }
 
+    /// 处理完所有可以处理的任务，返回处理的任务数
+    fn process_all_tasks(worker: &Worker) -> usize {
+        let mut count = 0;
+        while worker.process_task().unwrap() == Pickup::Processed {
+            count += 1;
+        }
+        count
+    }
+
     #[test]
     fn test_index_all_files() {
         let _ = prepare_test_data_worker();
@@ -517,16 +1882,13 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 2);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 2);
         assert_eq!(indexer_status.files, 2);
 
-        for _ in 0..2 {
-            worker.process_task().unwrap();
-        }
+        // 两个目录任务和一个删除文件任务
+        assert_eq!(process_all_tasks(&worker), 3);
 
         let worker_status = worker.get_tasks_status().unwrap();
         assert_eq!(worker_status.pending, 0);
@@ -546,16 +1908,13 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 2);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 2);
         assert_eq!(indexer_status.files, 2);
 
-        for _ in 0..2 {
-            worker.process_task().unwrap();
-        }
+        // 目录任务和删除目录任务
+        assert_eq!(process_all_tasks(&worker), 2);
         let worker_status = worker.get_tasks_status().unwrap();
         assert_eq!(worker_status.pending, 0);
 
@@ -578,12 +1937,8 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 2);
-
-        for _ in 0..2 {
-            worker.process_task().unwrap();
-        }
+        // 两个目录任务和新文件
+        assert_eq!(process_all_tasks(&worker), 3);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 2);
@@ -600,12 +1955,8 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 2);
-
-        for _ in 0..2 {
-            worker.process_task().unwrap();
-        }
+        // 三个目录任务
+        assert_eq!(process_all_tasks(&worker), 3);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 3);
@@ -629,12 +1980,8 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 3);
-
-        for _ in 0..3 {
-            worker.process_task().unwrap();
-        }
+        // 三个目录任务和新文件
+        assert_eq!(process_all_tasks(&worker), 4);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 3);
@@ -651,16 +1998,43 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 1);
-
-        worker.process_task().unwrap();
+        // 两个目录任务和修改的文件
+        assert_eq!(process_all_tasks(&worker), 3);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 2);
         assert_eq!(indexer_status.files, 2);
     }
 
+    #[test]
+    fn test_reconcile_directory() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+
+        // 目录没有变化时只检查 since 之后修改的文件
+        write_all(temp_test_data_worker.join("1.txt"), "contents").unwrap();
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        let (sub_dirs, submitted) = worker
+            .reconcile_directory(&temp_test_data_worker, future)
+            .unwrap();
+        assert_eq!(sub_dirs, vec![temp_test_data_worker.join("office")]);
+        assert_eq!(submitted, 0);
+        let (_, submitted) = worker
+            .reconcile_directory(&temp_test_data_worker, SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(submitted, 1);
+
+        // 目录有变化时检查其中的所有文件，删除的文件提交删除任务
+        fs::remove_file(temp_test_data_worker.join("office/test.docx")).unwrap();
+        fs::write(temp_test_data_worker.join("office/new.txt"), "new").unwrap();
+        let (sub_dirs, submitted) = worker
+            .reconcile_directory(&temp_test_data_worker.join("office"), future)
+            .unwrap();
+        assert!(sub_dirs.is_empty());
+        assert_eq!(submitted, 2);
+        assert_eq!(process_all_tasks(&worker), 3);
+    }
+
     #[test]
     fn test_index_all_files_mod_directory() {
         let (_env, temp_test_data_worker) = prepare_test_data_worker();
@@ -675,18 +2049,214 @@ mod tests {
         worker
             .submit_index_all_files(&temp_test_data_worker)
             .unwrap();
-        let worker_status = worker.get_tasks_status().unwrap();
-        assert_eq!(worker_status.pending, 4);
-
-        for _ in 0..4 {
-            worker.process_task().unwrap();
-        }
+        // 两个目录任务、删除旧目录和新目录下的文件
+        assert_eq!(process_all_tasks(&worker), 4);
 
         let indexer_status = indexer.get_index_status().unwrap();
         assert_eq!(indexer_status.directories, 2);
         assert_eq!(indexer_status.files, 2);
     }
 
+    #[test]
+    fn test_force_extension() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let dir = env.temp_dir.path().join("force");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("sub").join("b.md"), "b").unwrap();
+        fs::write(dir.join("sub").join("c.json"), "c").unwrap();
+        worker.submit_index_all_files(&dir).unwrap();
+        process_all_tasks(&worker);
+
+        // 同一目录的强制扩展名合并到一个任务中，子目录也按这些扩展名重新索引
+        worker
+            .submit_index_all_files_with_force_extension(&dir, Some("txt"))
+            .unwrap();
+        worker
+            .submit_index_all_files_with_force_extension(&dir, Some("md"))
+            .unwrap();
+        worker
+            .submit_index_all_files_with_force_extension(&dir, Some("txt"))
+            .unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+        // 根目录、子目录、a.txt 和 b.md
+        assert_eq!(process_all_tasks(&worker), 4);
+    }
+
+    #[test]
+    fn test_reindex_path() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let file = temp_test_data_worker.join("1.txt");
+        let items = indexer.get_file_items(&file).unwrap().len();
+
+        // 修改时间没变也重新读取
+        worker.reindex_path(&file).unwrap();
+        assert!(indexer.get_file(&file).is_err());
+        assert_eq!(process_all_tasks(&worker), 1);
+        assert_eq!(indexer.get_file_items(&file).unwrap().len(), items);
+
+        worker.reindex_path(&temp_test_data_worker).unwrap();
+        assert_eq!(indexer.get_index_status().unwrap().files, 0);
+        assert_eq!(process_all_tasks(&worker), 4);
+        let status = indexer.get_index_status().unwrap();
+        assert_eq!((status.directories, status.files), (2, 2));
+    }
+
+    #[test]
+    fn test_submit_move() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = temp_test_data_worker.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root]).unwrap();
+        let file = temp_test_data_worker.join("1.txt");
+        let items = indexer.get_file_items(&file).unwrap().len();
+
+        // 重命名只更新路径，不需要重新读取
+        let renamed = temp_test_data_worker.join("renamed.txt");
+        fs::rename(&file, &renamed).unwrap();
+        worker.submit_move(&file, &renamed).unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 0);
+        assert!(indexer.get_file(&file).is_err());
+        assert_eq!(indexer.get_file_items(&renamed).unwrap().len(), items);
+
+        // 扩展名变了按删除和新建处理
+        let markdown = temp_test_data_worker.join("renamed.md");
+        fs::rename(&renamed, &markdown).unwrap();
+        worker.submit_move(&renamed, &markdown).unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 3);
+        process_all_tasks(&worker);
+        assert!(indexer.get_file(&renamed).is_err());
+        assert!(indexer.get_file(&markdown).is_ok());
+    }
+
+    #[test]
+    fn test_ignore_patterns() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = temp_test_data_worker.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root.clone()]).unwrap();
+
+        // 默认规则排除 node_modules
+        let node_modules = temp_test_data_worker.join("node_modules");
+        fs::create_dir_all(&node_modules).unwrap();
+        fs::write(node_modules.join("a.txt"), "a").unwrap();
+        worker.submit_index_all_files(&node_modules).unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 0);
+
+        // 修改规则后删除已经索引的、现在被排除的目录，不再排除的重新扫描时补上
+        let removed = worker
+            .set_ignore_patterns(&temp_test_data_worker, vec!["office/".to_string()])
+            .unwrap();
+        assert_eq!(removed, 1);
+        process_all_tasks(&worker);
+        let docx = temp_test_data_worker.join("office").join("test.docx");
+        assert!(indexer.get_file(&docx).is_err());
+        assert!(indexer.get_file(&node_modules.join("a.txt")).is_ok());
+        assert_eq!(Config::get_ignore_patterns(&root).unwrap(), vec!["office/"]);
+
+        assert!(worker
+            .set_ignore_patterns(&temp_test_data_worker, vec!["a[".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_offline_root() {
+        let (env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = temp_test_data_worker.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root]).unwrap();
+        let file = temp_test_data_worker.join("1.txt");
+        worker
+            .add_task(&PathType::File, &file, &TaskType::Index)
+            .unwrap();
+        worker
+            .add_task(
+                &PathType::Directory,
+                &temp_test_data_worker,
+                &TaskType::Delete,
+            )
+            .unwrap();
+
+        // 移动硬盘拔出后不当作文件被删除
+        fs::rename(
+            &temp_test_data_worker,
+            env.temp_dir.path().join("unplugged"),
+        )
+        .unwrap();
+        worker.submit_index_all_files(&file).unwrap();
+        assert_eq!(process_all_tasks(&worker), 2);
+        assert!(indexer.get_file(&file).is_ok());
+        assert_eq!(indexer.get_index_status().unwrap().files, 2);
+    }
+
+    #[test]
+    fn test_shallow_root() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = temp_test_data_worker.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root]).unwrap();
+        let docx = temp_test_data_worker.join("office").join("test.docx");
+
+        // 只索引第一层，子目录从索引中删除
+        let removed = worker
+            .set_root_recursive(&temp_test_data_worker, false)
+            .unwrap();
+        assert_eq!(removed, 1);
+        process_all_tasks(&worker);
+        assert!(indexer.get_file(&docx).is_err());
+        assert!(indexer
+            .get_file(&temp_test_data_worker.join("1.txt"))
+            .is_ok());
+        worker.submit_index_all_files(&docx).unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 0);
+
+        let removed = worker
+            .set_root_recursive(&temp_test_data_worker, true)
+            .unwrap();
+        assert_eq!(removed, 0);
+        process_all_tasks(&worker);
+        assert!(indexer.get_file(&docx).is_ok());
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let (_env, temp_test_data_worker) = prepare_test_data_worker();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = temp_test_data_worker.to_str().unwrap().to_string();
+        Config::set_index_dir_paths(vec![root.clone()]).unwrap();
+        worker
+            .add_task(
+                &PathType::File,
+                &temp_test_data_worker.join("old.txt"),
+                &TaskType::Index,
+            )
+            .unwrap();
+
+        assert_eq!(worker.rebuild_index().unwrap(), 1);
+        assert!(!is_paused());
+        // 清空索引和任务，配置保留，索引目录重新排队
+        let status = indexer.get_index_status().unwrap();
+        assert_eq!((status.directories, status.files, status.items), (0, 0, 0));
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+        assert_eq!(Config::get_index_dir_paths().unwrap(), vec![root]);
+
+        assert_eq!(process_all_tasks(&worker), 4);
+        let status = indexer.get_index_status().unwrap();
+        assert_eq!((status.directories, status.files), (2, 2));
+        assert!(!indexer
+            .get_file_items(&temp_test_data_worker.join("1.txt"))
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_get_tasks_status() {
         let _env = TestEnv::new();
@@ -696,7 +2266,7 @@ mod tests {
             .unwrap();
 
         let status = worker.get_tasks_status().unwrap();
-        assert_eq!(status.pending, 4);
+        assert_eq!(status.pending, 1);
         assert_eq!(status.running, 0);
         assert_eq!(status.running_tasks, Vec::<String>::new());
     }
@@ -710,13 +2280,14 @@ mod tests {
             .unwrap();
 
         let status = worker.get_tasks_status().unwrap();
-        assert_eq!(status.pending, 4);
+        assert_eq!(status.pending, 1);
         assert_eq!(status.running, 0);
         assert_eq!(status.running_tasks, Vec::<String>::new());
 
+        // 扫描目录后提交其中的文件和子目录
         worker.process_task().unwrap();
         let status = worker.get_tasks_status().unwrap();
-        assert_eq!(status.pending, 3);
+        assert_eq!(status.pending, 2);
         assert_eq!(status.running, 0);
         assert_eq!(status.running_tasks, Vec::<String>::new());
 
@@ -735,6 +2306,690 @@ mod tests {
         assert_eq!(status.running_tasks, Vec::<String>::new());
     }
 
+    #[test]
+    fn test_index_unsupported_file_names() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("names");
+        fs::create_dir_all(&dir).unwrap();
+        // 没有 reader 的扩展名、没有扩展名和隐藏文件都只索引文件名
+        for name in ["notes.xyz", "Makefile", ".env", "a.txt"] {
+            fs::write(dir.join(name), "content").unwrap();
+        }
+        worker.submit_index_all_files(&dir).unwrap();
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+
+        let status = indexer.get_index_status().unwrap();
+        assert_eq!(status.files, 4);
+        assert_eq!(status.items, 1);
+        let file = indexer.get_file(&dir.join("Makefile")).unwrap();
+        assert_eq!(file.name, "Makefile");
+    }
+
+    #[test]
+    fn test_index_protected_file() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let dir = env.temp_dir.path().join("protected");
+        fs::create_dir_all(&dir).unwrap();
+        // 加密的 Office 文档是 OLE 复合文件
+        let mut data = vec![0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+        data.extend([0u8; 504]);
+        fs::write(dir.join("secret.docx"), data).unwrap();
+        worker.submit_index_all_files(&dir).unwrap();
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+
+        let skipped = indexer.get_skipped_files().unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "secret.docx");
+        assert_eq!(skipped[0].reason, "文档有密码保护");
+    }
+
+    #[test]
+    fn test_shared_content() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let indexer = Indexer::new().unwrap();
+        let root = env.temp_dir.path().join("shared");
+        for dir in ["a", "b", "c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("a/doc.txt"), "共享的内容").unwrap();
+        fs::write(root.join("b/doc.txt"), "共享的内容").unwrap();
+        // 扩展名不同时读取方式不同，不共享
+        fs::write(root.join("b/doc.md"), "共享的内容").unwrap();
+        let index = |path: &Path| {
+            worker.submit_index_all_files(path).unwrap();
+            while worker.get_tasks_status().unwrap().pending > 0 {
+                worker.process_task().unwrap();
+            }
+        };
+        let search = || {
+            let page = indexer
+                .search_item(
+                    "共享的内容",
+                    0,
+                    10,
+                    false,
+                    SearchSort::default(),
+                    &SearchFilter::default(),
+                )
+                .unwrap();
+            let mut files: Vec<String> = page
+                .items
+                .iter()
+                .map(|item| Path::new(&item.path).join(&item.file))
+                .map(|path| {
+                    path.strip_prefix(&root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        let item_ids = || -> Vec<i64> {
+            get_conn()
+                .unwrap()
+                .prepare("SELECT id FROM items ORDER BY id")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        index(&root);
+        assert_eq!(search(), vec!["a/doc.txt", "b/doc.md", "b/doc.txt"]);
+        let ids = item_ids();
+        assert_eq!(ids.len(), 2);
+        // 没有大小和扩展名都相同的其他文件时不计算哈希
+        let hash: Option<String> = get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT content_hash FROM files WHERE name = 'doc.md'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(hash.is_none());
+
+        // 新复制的文件不重新读取，直接共享已有的内容
+        fs::write(root.join("c/doc.txt"), "共享的内容").unwrap();
+        index(&root.join("c"));
+        assert_eq!(item_ids(), ids);
+        assert_eq!(
+            indexer.get_file_items(&root.join("c/doc.txt")).unwrap()[0].content,
+            "共享的内容"
+        );
+        assert_eq!(search().len(), 4);
+
+        // 删除保存内容的文件后内容转给其他文件，最后一个文件删除后才删除内容
+        indexer.delete_file(&root.join("a/doc.txt")).unwrap();
+        indexer.delete_directory(&root.join("b")).unwrap();
+        assert_eq!(search(), vec!["c/doc.txt"]);
+        assert_eq!(item_ids().len(), 1);
+        indexer.delete_file(&root.join("c/doc.txt")).unwrap();
+        assert!(item_ids().is_empty());
+        assert!(crate::integrity::verify_index(false).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_failed_task() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let dir = env.temp_dir.path().join("failed");
+        fs::create_dir_all(&dir).unwrap();
+        // 格式错误的文件重试也不会成功，直接标记为失败
+        fs::write(dir.join("broken.docx"), "not a zip").unwrap();
+        worker.submit_index_all_files(&dir).unwrap();
+        while worker.get_tasks_status().unwrap().pending > 0 {
+            worker.process_task().unwrap();
+        }
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!(status.pending, 0);
+        assert_eq!(status.failed, 1);
+        let failed = Worker::get_failed_tasks().unwrap();
+        assert_eq!(failed[0].path, dir.join("broken.docx").to_str().unwrap());
+        assert_eq!(failed[0].attempts, 1);
+        assert!(failed[0].error.starts_with("Read file failed"));
+        // 仍然索引文件名
+        assert!(worker.indexer.get_file(&dir.join("broken.docx")).is_ok());
+
+        // 暂时性的错误等待后重试，次数用完后标记为失败
+        let id = worker
+            .add_task(&PathType::File, &dir.join("busy.txt"), &TaskType::Index)
+            .unwrap();
+        let busy = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        worker.fail_task(id, &busy).unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.failed), (1, 1));
+        // 还没到重试时间
+        worker.process_task().unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+
+        get_conn()
+            .unwrap()
+            .execute(
+                "UPDATE tasks SET attempts = ?1 WHERE id = ?2",
+                params![MAX_TASK_ATTEMPTS - 1, id],
+            )
+            .unwrap();
+        worker.fail_task(id, &busy).unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.failed), (0, 2));
+
+        // 失败的文件移出任务队列
+        assert_eq!(Worker::list_tasks(None, 0, 10).unwrap().total, 0);
+
+        let busy_path = dir.join("busy.txt");
+        assert!(worker.retry_failed(busy_path.to_str().unwrap()).unwrap());
+        assert!(!worker.retry_failed(busy_path.to_str().unwrap()).unwrap());
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.failed), (1, 1));
+        let page = Worker::list_tasks(None, 0, 10).unwrap();
+        assert_eq!(
+            (page.items[0].attempts, page.items[0].error.is_none()),
+            (0, true)
+        );
+
+        assert_eq!(worker.retry_failed_tasks().unwrap(), 1);
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.failed), (2, 0));
+    }
+
+    #[test]
+    fn test_index_unreadable() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let path = env.temp_dir.path().join("a.txt");
+        fs::write(&path, "a").unwrap();
+        let items = vec![Item {
+            content: "已有的内容".into(),
+            ..Default::default()
+        }];
+        worker.indexer.write_file_items(&path, items).unwrap();
+
+        // 还会重试的错误保留已有的内容
+        let busy = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        worker.index_unreadable(&path, &busy).unwrap();
+        assert_eq!(worker.indexer.get_file_items(&path).unwrap().len(), 1);
+        let deferred = anyhow::Error::from(OcrDeferred);
+        worker.index_unreadable(&path, &deferred).unwrap();
+        assert_eq!(worker.indexer.get_file_items(&path).unwrap().len(), 1);
+
+        // 不会再重试的错误只保留文件名
+        let broken = anyhow!("格式错误");
+        worker.index_unreadable(&path, &broken).unwrap();
+        assert!(worker.indexer.get_file(&path).is_ok());
+        assert!(worker.indexer.get_file_items(&path).unwrap().is_empty());
+
+        // 没有索引过的文件先索引文件名
+        let new_path = env.temp_dir.path().join("b.txt");
+        fs::write(&new_path, "b").unwrap();
+        worker.index_unreadable(&new_path, &busy).unwrap();
+        assert!(worker.indexer.get_file(&new_path).is_ok());
+    }
+
+    #[test]
+    fn test_ocr_deferred_task() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let path = env.temp_dir.path().join("scan.png");
+        let id = worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        // 推迟的 OCR 不计入重试次数，也不标记为失败
+        let deferred = anyhow::Error::from(OcrDeferred).context("Read file failed");
+        worker.fail_task(id, &deferred).unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.failed), (1, 0));
+        worker.process_task().unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 1);
+        let task_state = |id: i64| -> (u32, Option<i64>, bool) {
+            get_conn()
+                .unwrap()
+                .query_row(
+                    "SELECT attempts, retry_at, deferred FROM tasks WHERE id = ?1",
+                    params![id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap()
+        };
+        assert!(task_state(id).2);
+
+        // 只按推迟标记重新排队，错误信息相同的其他任务不受影响
+        let other = worker
+            .add_task(
+                &PathType::File,
+                &env.temp_dir.path().join("other.png"),
+                &TaskType::Index,
+            )
+            .unwrap();
+        get_conn()
+            .unwrap()
+            .execute(
+                "UPDATE tasks SET error = ?1, retry_at = ?2 WHERE id = ?3",
+                params![OcrDeferred.to_string(), i64::MAX, other],
+            )
+            .unwrap();
+        assert_eq!(Worker::resume_deferred_tasks().unwrap(), 1);
+        assert_eq!(task_state(id), (0, None, false));
+        assert_eq!(task_state(other).1, Some(i64::MAX));
+
+        // 再次提交时清除推迟标记，不用等到接通电源
+        worker.fail_task(id, &deferred).unwrap();
+        worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        assert_eq!(task_state(id), (0, None, false));
+    }
+
+    #[test]
+    fn test_ocr_required_task() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let path = env.temp_dir.path().join("scan.pdf");
+        let id = worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().ocr_pending, 0);
+        // 普通线程读取到需要 OCR 的页面时转给 OCR 线程，不计入重试次数
+        let required = anyhow::Error::from(OcrRequired).context("Read file failed");
+        worker.fail_task(id, &required).unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.ocr_pending, status.failed), (1, 0));
+        let attempts: u32 = get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT attempts FROM tasks WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(attempts, 0);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_secs(2));
+        assert_eq!(retry_delay(3), Duration::from_secs(8));
+        assert!(is_transient(&anyhow::Error::from(io::Error::from(
+            io::ErrorKind::TimedOut
+        ))));
+        assert!(!is_transient(&anyhow!("File not found")));
+    }
+
+    #[test]
+    fn test_pause_indexing() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        fs::write(env.temp_dir.path().join("a.txt"), "content").unwrap();
+        worker
+            .add_task(
+                &PathType::File,
+                &env.temp_dir.path().join("a.txt"),
+                &TaskType::Index,
+            )
+            .unwrap();
+
+        set_paused(true);
+        worker.process_task().unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        set_paused(false);
+        assert!(status.paused);
+        assert_eq!(status.pending, 1);
+
+        worker.process_task().unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert!(!status.paused);
+        assert_eq!(status.pending, 0);
+    }
+
+    #[test]
+    fn test_cancel_tasks() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let root = env.temp_dir.path().join("root");
+        let other = env.temp_dir.path().join("root2");
+        for dir in [&root, &other] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("a.txt"), "content").unwrap();
+        }
+        worker.submit_index_all_files(&root).unwrap();
+        worker.submit_index_all_files(&other).unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 2);
+
+        // 正在处理的任务处理完后丢弃结果
+        let running = worker
+            .add_task(&PathType::File, &root.join("b.txt"), &TaskType::Index)
+            .unwrap();
+        get_conn()
+            .unwrap()
+            .execute(
+                "UPDATE tasks SET status = ?1 WHERE id = ?2",
+                params![TaskStatus::Running.to_string(), running],
+            )
+            .unwrap();
+
+        let conn = get_conn().unwrap();
+        for dir in [&root, &other] {
+            conn.execute(
+                r"INSERT INTO failed_files (path_type, path, task_type, attempts, error, failed_at)
+                VALUES ('File', ?1, 'Index', 1, 'error', '')",
+                params![path_to_str(&dir.join("c.txt")).unwrap()],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        // 只按完整的路径部分匹配，root2 不受影响
+        assert_eq!(Worker::cancel_tasks(&root).unwrap(), 3);
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.running), (1, 1));
+        let failed: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM failed_files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(failed, 1);
+        CURRENT_TASK.with(|task| task.set(Some(running)));
+        assert!(is_current_task_cancelled());
+        CURRENT_TASK.with(|task| task.set(None));
+        assert!(!is_current_task_cancelled());
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let dir = env.temp_dir.path().join("batch");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        worker.submit_index_all_files(&dir).unwrap();
+        // 目录任务在前，处理完后三个文件任务待处理
+        worker.process_task().unwrap();
+        worker.process_task().unwrap();
+
+        // 还有任务时不提交，任务保持运行状态
+        let (cancelled, first) = {
+            let batch = worker.batch.borrow();
+            let (id, path) = batch
+                .files
+                .iter()
+                .map(|(id, f)| (*id, f.path()))
+                .next()
+                .unwrap();
+            (id, path.to_path_buf())
+        };
+        assert!(worker.indexer.get_file(&first).is_err());
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.running), (2, 1));
+
+        // 提交前被取消的文件不写入
+        get_cancelled_tasks().lock().unwrap().insert(cancelled);
+        worker.process_task().unwrap();
+        worker.process_task().unwrap();
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.running), (0, 0));
+        assert!(worker.indexer.get_file(&first).is_err());
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let path = dir.join(name);
+            if path != first {
+                assert_eq!(
+                    worker.indexer.get_file_items(&path).unwrap()[0].content,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_slow_lane() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path().join("slow");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("small.txt"), "small").unwrap();
+        fs::File::create(dir.join("big.bin"))
+            .unwrap()
+            .set_len(2 * 1024 * 1024)
+            .unwrap();
+        Config::set_slow_lane_threshold_mb(1).unwrap();
+
+        let worker = Worker::new().unwrap();
+        worker.submit_index_all_files(&dir).unwrap();
+        worker.process_task().unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().slow_pending, 1);
+        // 普通线程不处理大文件
+        assert_eq!(process_all_tasks(&worker), 1);
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.slow_pending), (1, 1));
+        assert!(worker.indexer.get_file(&dir.join("small.txt")).is_ok());
+
+        let slow_worker = Worker::new_slow_lane().unwrap();
+        slow_worker.process_task().unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().pending, 0);
+        assert!(worker.indexer.get_file(&dir.join("big.bin")).is_ok());
+    }
+
+    #[test]
+    fn test_ocr_lane() {
+        let env = TestEnv::new();
+        let dir = env.temp_dir.path().join("ocr");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("note.txt"), "note").unwrap();
+        fs::write(dir.join("scan.PNG"), "not a png").unwrap();
+
+        let worker = Worker::new().unwrap();
+        worker.submit_index_all_files(&dir).unwrap();
+        worker.process_task().unwrap();
+        assert_eq!(worker.get_tasks_status().unwrap().ocr_pending, 1);
+        // 普通线程和大文件线程不处理图片
+        assert_eq!(process_all_tasks(&worker), 1);
+        let slow_worker = Worker::new_slow_lane().unwrap();
+        assert_eq!(slow_worker.process_task().unwrap(), Pickup::Idle(None));
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.ocr_pending), (1, 1));
+        assert!(worker.indexer.get_file(&dir.join("note.txt")).is_ok());
+        let page = Worker::list_tasks(None, 0, 10).unwrap();
+        assert!(page.items[0].ocr);
+
+        // OCR 线程只处理图片
+        let ocr_worker = Worker::new_ocr_lane().unwrap();
+        assert_eq!(ocr_worker.process_task().unwrap(), Pickup::Processed);
+        assert_eq!(worker.get_tasks_status().unwrap().ocr_pending, 0);
+        assert!(worker.indexer.get_file(&dir.join("scan.PNG")).is_ok());
+    }
+
+    #[test]
+    fn test_list_tasks() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let dir = env.temp_dir.path().join("list");
+        fs::create_dir_all(&dir).unwrap();
+        let a = worker
+            .add_task(&PathType::File, &dir.join("a.txt"), &TaskType::Index)
+            .unwrap();
+        let b = worker
+            .add_task(&PathType::File, &dir.join("b.txt"), &TaskType::Delete)
+            .unwrap();
+        let busy = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        worker.fail_task(b, &busy).unwrap();
+
+        let page = Worker::list_tasks(None, 0, 1).unwrap();
+        assert_eq!((page.total, page.has_more), (2, true));
+        assert_eq!(page.items[0].id, a);
+        assert_eq!(page.items[0].path, dir.join("a.txt").to_str().unwrap());
+        assert_eq!(page.items[0].task_type, "Index");
+
+        let page = Worker::list_tasks(Some(TaskStatus::Pending), 1, 10).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].id, b);
+        assert_eq!(page.items[0].attempts, 1);
+        assert!(page.items[0].retry_at.is_some());
+        assert!(page.items[0].error.is_some());
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+        let e = catch_panic(|| -> Result<()> { panic!("读取器崩溃") }).unwrap_err();
+        assert_eq!(e.to_string(), "处理任务时崩溃: 读取器崩溃");
+        let name = "坏文件";
+        let e = catch_panic(|| -> Result<()> { panic!("{name}") }).unwrap_err();
+        assert_eq!(e.to_string(), "处理任务时崩溃: 坏文件");
+
+        // 崩溃不会重试成功，直接移到失败的文件
+        let path = env.temp_dir.path().join("panic.txt");
+        let id = worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        worker.fail_task(id, &e).unwrap();
+        let failed = Worker::get_failed_tasks().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].error, "处理任务时崩溃: 坏文件");
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let thread_id = || -> Result<FileRead> {
+            Ok(FileRead {
+                content: FileContent::Read(
+                    vec![Item {
+                        content: format!("{:?}", thread::current().id()),
+                        position: ItemPosition::default(),
+                    }],
+                    None,
+                ),
+                batch_hashes: Vec::new(),
+            })
+        };
+        let content = |read: FileRead| match read.content {
+            FileContent::Read(items, _) => items[0].content.clone(),
+            FileContent::Shared(hash) => hash,
+        };
+        let first = content(
+            worker
+                .read_with_timeout(1, Box::new(thread_id), Duration::from_secs(5))
+                .unwrap(),
+        );
+        // 没有超时时继续使用同一个读取线程
+        let second = content(
+            worker
+                .read_with_timeout(2, Box::new(thread_id), Duration::from_secs(5))
+                .unwrap(),
+        );
+        assert_eq!(first, second);
+
+        // 卡住的读取超时后放弃，任务直接移到失败的文件
+        let stuck = || -> Result<FileRead> {
+            while !is_current_task_cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(anyhow!("读取已取消"))
+        };
+        let e = worker
+            .read_with_timeout(3, Box::new(stuck), Duration::from_millis(100))
+            .unwrap_err();
+        assert!(e.is::<TaskTimeout>());
+        assert!(!is_transient(&e));
+        let path = env.temp_dir.path().join("stuck.pdf");
+        let id = worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        worker.fail_task(id, &e).unwrap();
+        let failed = Worker::get_failed_tasks().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].error.starts_with("timeout"));
+
+        // 之后的读取使用新的线程
+        let third = content(
+            worker
+                .read_with_timeout(4, Box::new(thread_id), Duration::from_secs(5))
+                .unwrap(),
+        );
+        assert_ne!(third, first);
+    }
+
+    #[test]
+    fn test_wait_for_tasks() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        // 没有任务时一直等待
+        assert_eq!(worker.process_task().unwrap(), Pickup::Idle(None));
+
+        // 提交任务时唤醒等待的线程
+        let seen = task_signal_count();
+        let waiter = thread::spawn(move || {
+            let start = Instant::now();
+            wait_for_tasks(seen, None);
+            start.elapsed()
+        });
+        thread::sleep(Duration::from_millis(50));
+        let path = env.temp_dir.path().join("wake.txt");
+        let id = worker
+            .add_task(&PathType::File, &path, &TaskType::Index)
+            .unwrap();
+        assert!(waiter.join().unwrap() < Duration::from_secs(5));
+        // 取任务之后的通知不会漏掉
+        let start = Instant::now();
+        wait_for_tasks(seen, Some(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // 等待重试的任务到时间前醒来
+        let busy = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        worker.fail_task(id, &busy).unwrap();
+        let Pickup::Idle(Some(delay)) = worker.process_task().unwrap() else {
+            panic!("等待重试的任务应该有等待时间");
+        };
+        assert!(delay <= retry_delay(1) && delay > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_drain() {
+        let env = TestEnv::new();
+        let worker = Worker::new().unwrap();
+        let dir = env.temp_dir.path().join("drain");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        worker.submit_index_all_files(&dir).unwrap();
+        worker.process_task().unwrap();
+        worker.process_task().unwrap();
+        let first = worker.batch.borrow().files[0].1.path().to_path_buf();
+        assert!(worker.indexer.get_file(&first).is_err());
+        // 模拟处理到一半的任务
+        get_conn()
+            .unwrap()
+            .execute(
+                "UPDATE tasks SET status = ?1, worker = ?2 WHERE id = (SELECT MIN(id) FROM tasks WHERE status = ?3)",
+                params![
+                    TaskStatus::Running.to_string(),
+                    worker.name,
+                    TaskStatus::Pending.to_string()
+                ],
+            )
+            .unwrap();
+
+        // 停止时提交批次，处理到一半的任务重新排队
+        worker.drain().unwrap();
+        assert!(worker.indexer.get_file(&first).is_ok());
+        let status = worker.get_tasks_status().unwrap();
+        assert_eq!((status.pending, status.running), (2, 0));
+    }
+
     #[test]
     fn test_del_all_files() {
         let (_env, temp_test_data_worker) = prepare_test_data_worker();